@@ -1,39 +1,24 @@
 use criterion::{criterion_group, criterion_main, Criterion};
+use emulator_6502::bus::{Bus, BusBuilder, DeviceHandle};
+use emulator_6502::memory::Ram;
 use emulator_6502::*;
+use std::cell::Cell;
 use std::env::var;
 use std::fs::*;
 use std::io::*;
 use std::path::PathBuf;
+use std::rc::Rc;
 
-struct BasicRam {
-    ram: Box<[u8; u16::max_value() as usize + 1]>,
-    complete: bool,
-}
-
-impl BasicRam {
-    fn load_program(&mut self, start: usize, data: &mut Vec<u8>) {
-        self.ram[start..start + data.len()].clone_from_slice(data);
-    }
-}
-
-impl Interface6502 for BasicRam {
-    fn read(&mut self, address: u16) -> u8 {
-        match address {
-            0xfffe..=0xffff => {
-                self.complete = true; //If brk has been called the test is complete
-                0xff //Keep program in an infinite break loop until test terminates
-            }
-            _ => self.ram[address as usize],
-        }
-    }
-
-    fn write(&mut self, address: u16, data: u8) {
-        self.ram[address as usize] = data
-    }
+/// Bus of 64KB of RAM, with the BRK vector mapped to a device that flags the test as complete when read
+struct TestBus {
+    bus: Bus,
+    #[cfg_attr(not(feature = "binary_coded_decimal"), allow(dead_code))]
+    ram: DeviceHandle<Ram>,
+    complete: Rc<Cell<bool>>,
 }
 
 /// Function for loading test programs
-fn load_test(ram: &mut BasicRam, file_name: &str, location: usize) -> Result<()> {
+fn load_test(file_name: &str, location: usize) -> Result<TestBus> {
     let root_dir = &var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
     let mut source_path = PathBuf::from(root_dir);
     source_path.push("tests/bins");
@@ -43,47 +28,68 @@ fn load_test(ram: &mut BasicRam, file_name: &str, location: usize) -> Result<()>
     let mut buffer = Vec::new();
     file.read_to_end(&mut buffer)?;
 
-    ram.load_program(location, &mut buffer);
+    let complete = Rc::new(Cell::new(false));
+    let brk_complete = Rc::clone(&complete);
+    let mut builder = BusBuilder::new();
+    let ram = builder.add_device(Ram::new(u16::MAX as usize + 1));
+    builder.map(0x0000..=0xffff, &ram);
+    builder.mmio(
+        0xfffe..=0xffff,
+        move |_offset| {
+            brk_complete.set(true); //If brk has been called the test is complete
+            0xff //Keep program in an infinite break loop until test terminates
+        },
+        |_offset, _data| {},
+    );
+    let mut bus = builder.build();
+    bus.device_mut(&ram).load(location, &buffer);
 
-    Ok(())
+    Ok(TestBus { bus, ram, complete })
 }
 
 fn bench_test() {
-    let mut ram = BasicRam {
-        ram: Box::new([0; u16::max_value() as usize + 1]),
-        complete: false,
-    };
-    load_test(&mut ram, "6502_bench.bin", 0x400).unwrap();
+    let mut test = load_test("6502_bench.bin", 0x400).unwrap();
 
     let mut cpu = MOS6502::new_start(0x400);
-    while !ram.complete {
-        cpu.cycle(&mut ram);
+    while !test.complete.get() {
+        cpu.cycle(&mut test.bus);
     }
 }
 
+/// Runs the first million cycles of the decimal test, leaving out loading the program so that only the processor is measured
+fn cycle_bench(c: &mut Criterion) {
+    let mut test = load_test("6502_decimal_test.bin", 0x200).unwrap();
+    c.bench_function("Cycle Bench", |b| {
+        b.iter(|| {
+            let mut cpu = MOS6502::new_start(0x200);
+            for _ in 0..1_000_000 {
+                cpu.cycle(&mut test.bus);
+            }
+        })
+    });
+}
+
 #[cfg(feature = "binary_coded_decimal")]
 fn bcd_bench() -> Result<()> {
     std::env::set_var("RUST_LOG", "trace");
-    let mut ram = BasicRam {
-        ram: Box::new([0; u16::max_value() as usize + 1]),
-        complete: false,
-    };
-    load_test(&mut ram, "6502_decimal_test.bin", 0x200)?;
+    let mut test = load_test("6502_decimal_test.bin", 0x200)?;
 
     let mut cpu = MOS6502::new_start(0x200);
     let mut cycle_timeout = 0;
-    while !ram.complete {
-        cpu.cycle(&mut ram);
+    while !test.complete.get() {
+        cpu.cycle(&mut test.bus);
         cycle_timeout += 1;
         assert!(cycle_timeout < 46089520) //Timeout
     }
-    assert_eq!(ram.ram[0x0b], 0);
+    assert_eq!(test.bus.device(&test.ram).as_slice()[0x0b], 0);
     Ok(())
 }
 
 fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("Loop Bench", |b| b.iter(bench_test));
-    if cfg!(feature = "binary_coded_decimal") {
+    cycle_bench(c);
+    #[cfg(feature = "binary_coded_decimal")]
+    {
         let mut group = c.benchmark_group("Binary Coded Decimal");
         group.sample_size(10);
         group.bench_function("BCD Bench", |b| b.iter(bcd_bench));
@@ -167,6 +167,88 @@ pub(crate) fn zero_page_y(cpu: &mut MOS6502, bus: &mut dyn Interface6502) -> Add
     return AddressModeValue::AbsoluteAddress(u16::from(address));
 }
 
+/// The addressing modes of the 6502, describing how an instruction finds the value it operates on
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AddressMode {
+    /// A 16-bit absolute address
+    Absolute,
+    /// A 16-bit absolute address offset by the x register, taking an extra cycle when a page boundary is crossed
+    AbsoluteX,
+    /// A 16-bit absolute address offset by the x register, taking the same number of cycles regardless of page boundaries
+    AbsoluteXConst,
+    /// A 16-bit absolute address offset by the y register, taking an extra cycle when a page boundary is crossed
+    AbsoluteY,
+    /// A 16-bit absolute address offset by the y register, taking the same number of cycles regardless of page boundaries
+    AbsoluteYConst,
+    /// The byte following the opcode is the value
+    Immediate,
+    /// No operand, or the accumulator
+    Implied,
+    /// A 16-bit address pointing to the actual address
+    Indirect,
+    /// An 8-bit zero page address offset by the x register pointing to the actual address
+    IndirectX,
+    /// An 8-bit zero page address pointing to the actual address, which is offset by the y register,
+    /// taking an extra cycle when a page boundary is crossed
+    IndirectY,
+    /// An 8-bit zero page address pointing to the actual address, which is offset by the y register,
+    /// taking the same number of cycles regardless of page boundaries
+    IndirectYConst,
+    /// An 8-bit signed offset from the program counter, used by branches
+    Relative,
+    /// An 8-bit address on the zero page
+    ZeroPage,
+    /// An 8-bit address on the zero page offset by the x register
+    ZeroPageX,
+    /// An 8-bit address on the zero page offset by the y register
+    ZeroPageY,
+}
+
+impl AddressMode {
+    /// Calls the address mode function to retrieve the addressed location
+    #[inline(always)]
+    pub(crate) fn find_address(self, cpu: &mut MOS6502, bus: &mut dyn Interface6502) -> AddressModeValue {
+        return match self {
+            AddressMode::Absolute => absolute(cpu, bus),
+            AddressMode::AbsoluteX => absolute_x(cpu, bus),
+            AddressMode::AbsoluteXConst => absolute_x_const(cpu, bus),
+            AddressMode::AbsoluteY => absolute_y(cpu, bus),
+            AddressMode::AbsoluteYConst => absolute_y_const(cpu, bus),
+            AddressMode::Immediate => immediate(cpu, bus),
+            AddressMode::Implied => implied(cpu, bus),
+            AddressMode::Indirect => indirect(cpu, bus),
+            AddressMode::IndirectX => indirect_x(cpu, bus),
+            AddressMode::IndirectY => indirect_y(cpu, bus),
+            AddressMode::IndirectYConst => indirect_y_const(cpu, bus),
+            AddressMode::Relative => relative(cpu, bus),
+            AddressMode::ZeroPage => zero_page(cpu, bus),
+            AddressMode::ZeroPageX => zero_page_x(cpu, bus),
+            AddressMode::ZeroPageY => zero_page_y(cpu, bus),
+        };
+    }
+
+    /// Returns the number of operand bytes that follow an opcode using this address mode
+    pub fn operand_length(self) -> u8 {
+        return match self {
+            AddressMode::Implied => 0,
+            AddressMode::Immediate
+            | AddressMode::IndirectX
+            | AddressMode::IndirectY
+            | AddressMode::IndirectYConst
+            | AddressMode::Relative
+            | AddressMode::ZeroPage
+            | AddressMode::ZeroPageX
+            | AddressMode::ZeroPageY => 1,
+            AddressMode::Absolute
+            | AddressMode::AbsoluteX
+            | AddressMode::AbsoluteXConst
+            | AddressMode::AbsoluteY
+            | AddressMode::AbsoluteYConst
+            | AddressMode::Indirect => 2,
+        };
+    }
+}
+
 /// Enum for the return type of Address modes
 #[derive(PartialEq, Clone, Copy)]
 pub(crate) enum AddressModeValue {
@@ -175,6 +257,17 @@ pub(crate) enum AddressModeValue {
     AbsoluteAddress(u16),
 }
 
+impl AddressModeValue {
+    /// Returns the address the instruction operates on, resolving relative offsets from the end of the instruction
+    pub(crate) fn effective_address(self, next: u16) -> Option<u16> {
+        return match self {
+            AddressModeValue::Implied => None,
+            AddressModeValue::AbsoluteAddress(address) => Some(address),
+            AddressModeValue::RelativeAddress(offset) => Some(next.wrapping_add(offset as i8 as u16)),
+        };
+    }
+}
+
 impl fmt::Debug for AddressModeValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -0,0 +1,570 @@
+//! ### BANKING
+//! This module contains devices for bank switching, where windows of a ROM or RAM larger than the
+//! 64KB address space are swapped into it under register control.
+//!
+//! `BankedMemory` divides memory into equal sized banks and selects one into each slot of the window it
+//! is mapped into, and `Banked` pairs it with a closure that handles register writes. The mappers of
+//! some common systems are also provided:
+//! * NES: `Nrom`, `Uxrom` and `Mmc1`, mapped to $8000-$FFFF
+//! * Commodore 64: `Ocean` and `EasyFlash`, mapped with `BusBuilder::map_at_offset` using the `C64_*` offsets
+//! * Atari 2600: F8 and F6 through `AtariHotspot`, mapped to $1000-$1FFF
+//!
+//! Every device reports the bank selected at each offset, which `Bus` passes on through
+//! `Interface6502::bank` so disassemblers and debuggers can show it.
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let mut builder = BusBuilder::new();
+//! let cartridge = builder.add_device(Mmc1::new(prg_rom));
+//! builder.map(0x8000..=0xffff, &cartridge);
+//! let mut bus = builder.build();
+//!
+//! let instruction = disassemble(&mut bus, 0x8000);
+//! println!("{}  {}", instruction.format_address(), instruction);
+//! ```
+
+use super::bus::BusDevice;
+
+/// Memory divided into equal sized banks, with a bank selected into each slot of the window it is mapped into.
+/// Offsets past the end of the window wrap around to the first slot
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BankedMemory {
+    data: Vec<u8>,
+    bank_size: usize,
+    slots: Vec<usize>,
+    writable: bool,
+}
+
+impl BankedMemory {
+    /// Creates read only banked memory with the given bank size and number of slots, with bank 0 selected in every slot
+    pub fn new(data: Vec<u8>, bank_size: usize, slot_count: usize) -> Self {
+        assert!(bank_size > 0 && slot_count > 0, "Banked memory must have a bank size and slots");
+        assert!(
+            !data.is_empty() && data.len() % bank_size == 0,
+            "Banked memory must be a whole number of banks"
+        );
+        return BankedMemory {
+            data,
+            bank_size,
+            slots: vec![0; slot_count],
+            writable: false,
+        };
+    }
+
+    /// Sets whether writes change the memory, for banked RAM
+    pub fn writable(mut self, writable: bool) -> Self {
+        self.writable = writable;
+        return self;
+    }
+
+    /// Returns the size of each bank in bytes
+    pub fn bank_size(&self) -> usize {
+        return self.bank_size;
+    }
+
+    /// Returns the number of banks in the memory
+    pub fn bank_count(&self) -> usize {
+        return self.data.len() / self.bank_size;
+    }
+
+    /// Selects a bank into a slot. Bank numbers past the last bank wrap around, as they would on hardware
+    /// with unconnected bank register bits
+    pub fn select(&mut self, slot: usize, bank: usize) {
+        self.slots[slot] = bank % self.bank_count();
+    }
+
+    /// Returns the bank selected in a slot
+    pub fn selected(&self, slot: usize) -> usize {
+        return self.slots[slot];
+    }
+
+    /// Returns the slot an offset falls in
+    pub fn slot(&self, offset: u16) -> usize {
+        return (offset as usize / self.bank_size) % self.slots.len();
+    }
+
+    /// Returns the contents of the memory
+    pub fn as_slice(&self) -> &[u8] {
+        return &self.data;
+    }
+
+    /// Returns the index into the memory of an offset in the window
+    fn index(&self, offset: u16) -> usize {
+        return self.slots[self.slot(offset)] * self.bank_size + offset as usize % self.bank_size;
+    }
+}
+
+impl BusDevice for BankedMemory {
+    fn read(&mut self, offset: u16) -> u8 {
+        return self.data[self.index(offset)];
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        if self.writable {
+            let index = self.index(offset);
+            self.data[index] = data;
+        }
+    }
+
+    fn bank(&self, offset: u16) -> Option<usize> {
+        return Some(self.selected(self.slot(offset)));
+    }
+}
+
+/// Banked memory with writes handled by a closure, which can select banks and write to the memory
+pub struct Banked<F> {
+    memory: BankedMemory,
+    register: F,
+}
+
+impl<F: FnMut(&mut BankedMemory, u16, u8) + 'static> Banked<F> {
+    /// Creates a banked device that passes every write to the register closure with the offset and data
+    pub fn new(memory: BankedMemory, register: F) -> Self {
+        return Banked { memory, register };
+    }
+
+    /// Returns the banked memory
+    pub fn memory(&self) -> &BankedMemory {
+        return &self.memory;
+    }
+
+    /// Returns the banked memory, mutably
+    pub fn memory_mut(&mut self) -> &mut BankedMemory {
+        return &mut self.memory;
+    }
+}
+
+impl<F: FnMut(&mut BankedMemory, u16, u8) + 'static> BusDevice for Banked<F> {
+    fn read(&mut self, offset: u16) -> u8 {
+        return self.memory.read(offset);
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        (self.register)(&mut self.memory, offset, data)
+    }
+
+    fn bank(&self, offset: u16) -> Option<usize> {
+        return self.memory.bank(offset);
+    }
+}
+
+/// Size of the banks used by the NES mappers
+const NES_PRG_BANK_SIZE: usize = 0x4000;
+
+/// NES mapper 0, with 16KB or 32KB of PRG ROM and no bank switching. 16KB ROMs appear twice
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Nrom {
+    prg: BankedMemory,
+}
+
+impl Nrom {
+    /// Creates the mapper from the PRG ROM
+    pub fn new(prg: Vec<u8>) -> Self {
+        let mut prg = BankedMemory::new(prg, NES_PRG_BANK_SIZE, 2);
+        prg.select(1, 1);
+        return Nrom { prg };
+    }
+}
+
+impl BusDevice for Nrom {
+    fn read(&mut self, offset: u16) -> u8 {
+        return self.prg.read(offset);
+    }
+
+    fn write(&mut self, _offset: u16, _data: u8) {}
+
+    fn bank(&self, offset: u16) -> Option<usize> {
+        return self.prg.bank(offset);
+    }
+}
+
+/// NES mapper 2, with a switchable 16KB bank at $8000 selected by writes to $8000-$FFFF, and the last
+/// bank fixed at $C000
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Uxrom {
+    prg: BankedMemory,
+}
+
+impl Uxrom {
+    /// Creates the mapper from the PRG ROM
+    pub fn new(prg: Vec<u8>) -> Self {
+        let mut prg = BankedMemory::new(prg, NES_PRG_BANK_SIZE, 2);
+        let last = prg.bank_count() - 1;
+        prg.select(1, last);
+        return Uxrom { prg };
+    }
+}
+
+impl BusDevice for Uxrom {
+    fn read(&mut self, offset: u16) -> u8 {
+        return self.prg.read(offset);
+    }
+
+    fn write(&mut self, _offset: u16, data: u8) {
+        self.prg.select(0, data as usize);
+    }
+
+    fn bank(&self, offset: u16) -> Option<usize> {
+        return self.prg.bank(offset);
+    }
+}
+
+/// NES mapper 1, configured through a serial shift register loaded by five writes to $8000-$FFFF.
+///
+/// PRG ROM banking is emulated. The CHR bank and mirroring registers are recorded for the frontend's PPU,
+/// and PRG RAM at $6000-$7FFF should be mapped as a separate device
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Mmc1 {
+    prg: BankedMemory,
+    shift: u8,
+    shift_count: u8,
+    control: u8,
+    chr_banks: [u8; 2],
+    prg_bank: u8,
+}
+
+impl Mmc1 {
+    /// Creates the mapper from the PRG ROM, starting with the last bank fixed at $C000
+    pub fn new(prg: Vec<u8>) -> Self {
+        let mut mmc1 = Mmc1 {
+            prg: BankedMemory::new(prg, NES_PRG_BANK_SIZE, 2),
+            shift: 0,
+            shift_count: 0,
+            control: 0x0c,
+            chr_banks: [0; 2],
+            prg_bank: 0,
+        };
+        mmc1.update_banks();
+        return mmc1;
+    }
+
+    /// Returns the control register, holding the mirroring mode in bits 0-1, PRG ROM bank mode in bits 2-3
+    /// and CHR ROM bank mode in bit 4
+    pub fn control(&self) -> u8 {
+        return self.control;
+    }
+
+    /// Returns the 4KB CHR banks selected at PPU $0000 and $1000
+    pub fn chr_banks(&self) -> (usize, usize) {
+        let [first, second] = self.chr_banks;
+        return if self.control & 0x10 != 0 {
+            (first as usize, second as usize)
+        } else {
+            ((first & !1) as usize, (first | 1) as usize)
+        };
+    }
+
+    fn update_banks(&mut self) {
+        let bank = (self.prg_bank & 0x0f) as usize;
+        let last = self.prg.bank_count() - 1;
+        let (first, second) = match (self.control >> 2) & 0b11 {
+            0 | 1 => (bank & !1, bank | 1), // 32KB mode ignores the lowest bit of the bank
+            2 => (0, bank),
+            _ => (bank, last),
+        };
+        self.prg.select(0, first);
+        self.prg.select(1, second);
+    }
+}
+
+impl BusDevice for Mmc1 {
+    fn read(&mut self, offset: u16) -> u8 {
+        return self.prg.read(offset);
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        if data & 0x80 != 0 {
+            self.shift = 0;
+            self.shift_count = 0;
+            self.control |= 0x0c;
+        } else {
+            self.shift |= (data & 1) << self.shift_count;
+            self.shift_count += 1;
+            if self.shift_count < 5 {
+                return;
+            }
+            match offset >> 13 {
+                0 => self.control = self.shift,
+                1 => self.chr_banks[0] = self.shift,
+                2 => self.chr_banks[1] = self.shift,
+                _ => self.prg_bank = self.shift,
+            }
+            self.shift = 0;
+            self.shift_count = 0;
+        }
+        self.update_banks();
+    }
+
+    fn bank(&self, offset: u16) -> Option<usize> {
+        return self.prg.bank(offset);
+    }
+}
+
+/// Device offset of a C64 cartridge's ROML window, normally mapped at $8000-$9FFF
+pub const C64_ROML: u16 = 0x0000;
+/// Device offset of a C64 cartridge's ROMH window, normally mapped at $A000-$BFFF, or $E000-$FFFF in ultimax mode
+pub const C64_ROMH: u16 = 0x2000;
+/// Device offset of a C64 cartridge's I/O 1 area, mapped at $DE00-$DEFF
+pub const C64_IO1: u16 = 0x4000;
+/// Device offset of a C64 cartridge's I/O 2 area, mapped at $DF00-$DFFF
+pub const C64_IO2: u16 = 0x4100;
+
+/// Size of the banks used by the C64 cartridges
+const C64_BANK_SIZE: usize = 0x2000;
+
+/// C64 Ocean cartridge, with 8KB banks selected by writing to $DE00. The selected bank appears in both the
+/// ROML and ROMH windows, as used by 128KB/256KB and 512KB cartridges respectively
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Ocean {
+    rom: BankedMemory,
+}
+
+impl Ocean {
+    /// Creates the cartridge from its ROM banks in order
+    pub fn new(rom: Vec<u8>) -> Self {
+        return Ocean {
+            rom: BankedMemory::new(rom, C64_BANK_SIZE, 1),
+        };
+    }
+}
+
+impl BusDevice for Ocean {
+    fn read(&mut self, offset: u16) -> u8 {
+        return if offset < C64_IO1 { self.rom.read(offset) } else { 0 };
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        if offset == C64_IO1 {
+            self.rom.select(0, (data & 0x3f) as usize);
+        }
+    }
+
+    fn bank(&self, offset: u16) -> Option<usize> {
+        return if offset < C64_IO1 { self.rom.bank(offset) } else { None };
+    }
+}
+
+/// C64 EasyFlash cartridge, with up to 64 8KB banks in each of its ROML and ROMH chips selected by writing to
+/// $DE00, a control register at $DE02 and 256 bytes of RAM at $DF00-$DFFF.
+///
+/// The memory configuration lines in the control register are recorded for the frontend, and flash
+/// programming is not emulated
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct EasyFlash {
+    roml: BankedMemory,
+    romh: BankedMemory,
+    control: u8,
+    ram: Vec<u8>,
+}
+
+impl EasyFlash {
+    /// Creates the cartridge from the banks of its ROML and ROMH chips in order
+    pub fn new(roml: Vec<u8>, romh: Vec<u8>) -> Self {
+        return EasyFlash {
+            roml: BankedMemory::new(roml, C64_BANK_SIZE, 1),
+            romh: BankedMemory::new(romh, C64_BANK_SIZE, 1),
+            control: 0,
+            ram: vec![0; 0x100],
+        };
+    }
+
+    /// Returns the control register, holding the GAME line in bit 0, EXROM line in bit 1, mode in bit 2 and LED in bit 7
+    pub fn control(&self) -> u8 {
+        return self.control;
+    }
+}
+
+impl BusDevice for EasyFlash {
+    fn read(&mut self, offset: u16) -> u8 {
+        return match offset {
+            C64_ROML..=0x1fff => self.roml.read(offset),
+            C64_ROMH..=0x3fff => self.romh.read(offset),
+            C64_IO2..=0x41ff => self.ram[(offset - C64_IO2) as usize],
+            _ => 0,
+        };
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        match offset {
+            C64_IO1 => {
+                self.roml.select(0, (data & 0x3f) as usize);
+                self.romh.select(0, (data & 0x3f) as usize);
+            }
+            0x4002 => self.control = data & 0x87,
+            C64_IO2..=0x41ff => self.ram[(offset - C64_IO2) as usize] = data,
+            _ => {}
+        }
+    }
+
+    fn bank(&self, offset: u16) -> Option<usize> {
+        return match offset {
+            C64_ROML..=0x1fff => self.roml.bank(offset),
+            C64_ROMH..=0x3fff => self.romh.bank(offset),
+            _ => None,
+        };
+    }
+}
+
+/// Atari 2600 cartridge with 4KB banks selected by accessing hotspot addresses at the end of the cartridge
+/// window. Starts with the last bank selected
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AtariHotspot {
+    rom: BankedMemory,
+    first_hotspot: u16,
+}
+
+impl AtariHotspot {
+    /// Creates an F8 cartridge, with two banks selected by accessing $1FF8 and $1FF9
+    pub fn f8(rom: Vec<u8>) -> Self {
+        assert_eq!(rom.len(), 0x2000, "F8 cartridges hold 8KB");
+        return AtariHotspot::new(rom, 0x0ff8);
+    }
+
+    /// Creates an F6 cartridge, with four banks selected by accessing $1FF6-$1FF9
+    pub fn f6(rom: Vec<u8>) -> Self {
+        assert_eq!(rom.len(), 0x4000, "F6 cartridges hold 16KB");
+        return AtariHotspot::new(rom, 0x0ff6);
+    }
+
+    fn new(rom: Vec<u8>, first_hotspot: u16) -> Self {
+        let mut rom = BankedMemory::new(rom, 0x1000, 1);
+        let last = rom.bank_count() - 1;
+        rom.select(0, last);
+        return AtariHotspot { rom, first_hotspot };
+    }
+
+    /// Switches banks if the offset is a hotspot
+    fn access(&mut self, offset: u16) {
+        let offset = offset & 0x0fff;
+        let bank = offset.wrapping_sub(self.first_hotspot) as usize;
+        if bank < self.rom.bank_count() {
+            self.rom.select(0, bank);
+        }
+    }
+}
+
+impl BusDevice for AtariHotspot {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.access(offset);
+        return self.rom.read(offset);
+    }
+
+    fn write(&mut self, offset: u16, _data: u8) {
+        self.access(offset);
+    }
+
+    fn bank(&self, offset: u16) -> Option<usize> {
+        return self.rom.bank(offset);
+    }
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::BusBuilder;
+    use crate::Interface6502;
+
+    /// Creates ROM of the given number of banks, with each byte holding the number of its bank
+    fn numbered_banks(bank_size: usize, count: usize) -> Vec<u8> {
+        (0..count).flat_map(|bank| vec![bank as u8; bank_size]).collect()
+    }
+
+    #[test]
+    fn test_banked_with_register() {
+        let memory = BankedMemory::new(numbered_banks(0x1000, 4), 0x1000, 2).writable(true);
+        let mut banked = Banked::new(memory, |memory: &mut BankedMemory, offset, data| {
+            if offset == 0x1fff {
+                memory.select(1, data as usize)
+            } else {
+                memory.write(offset, data)
+            }
+        });
+
+        banked.write(0x1fff, 3);
+        assert_eq!(banked.read(0x1000), 3);
+        assert_eq!(banked.bank(0x1000), Some(3));
+        assert_eq!(banked.bank(0x0000), Some(0));
+
+        banked.write(0x0010, 0x99);
+        assert_eq!(banked.memory().as_slice()[0x0010], 0x99);
+    }
+
+    #[test]
+    fn test_nrom_and_uxrom() {
+        let mut nrom = Nrom::new(numbered_banks(0x4000, 1));
+        assert_eq!(nrom.read(0x4000), 0);
+        assert_eq!(nrom.bank(0x7fff), Some(0));
+
+        let mut uxrom = Uxrom::new(numbered_banks(0x4000, 8));
+        uxrom.write(0x1234, 5);
+        assert_eq!(uxrom.read(0x0000), 5);
+        assert_eq!(uxrom.read(0x4000), 7);
+    }
+
+    #[test]
+    fn test_mmc1() {
+        let mut mmc1 = Mmc1::new(numbered_banks(0x4000, 8));
+        assert_eq!(mmc1.read(0x4000), 7);
+
+        // Serially write 3 to the PRG bank register at $E000
+        for bit in [1, 1, 0, 0, 0].iter() {
+            mmc1.write(0x6000, *bit);
+        }
+        assert_eq!(mmc1.read(0x0000), 3);
+        assert_eq!(mmc1.read(0x4000), 7);
+
+        // Switch to 32KB mode through the control register at $8000, which ignores the lowest bank bit
+        for bit in [0, 0, 0, 0, 0].iter() {
+            mmc1.write(0x0000, *bit);
+        }
+        assert_eq!((mmc1.bank(0x0000), mmc1.bank(0x4000)), (Some(2), Some(3)));
+
+        // Writing with bit 7 set resets the shift register and fixes the last bank again
+        mmc1.write(0x0000, 1);
+        mmc1.write(0x0000, 0x80);
+        assert_eq!((mmc1.bank(0x0000), mmc1.bank(0x4000)), (Some(3), Some(7)));
+    }
+
+    #[test]
+    fn test_c64_cartridges() {
+        let mut builder = BusBuilder::new();
+        let cartridge = builder.add_device(EasyFlash::new(numbered_banks(0x2000, 4), numbered_banks(0x2000, 4)));
+        builder
+            .map_at_offset(0x8000..=0x9fff, &cartridge, C64_ROML)
+            .map_at_offset(0xa000..=0xbfff, &cartridge, C64_ROMH)
+            .map_at_offset(0xde00..=0xdeff, &cartridge, C64_IO1)
+            .map_at_offset(0xdf00..=0xdfff, &cartridge, C64_IO2);
+        let mut bus = builder.build();
+
+        bus.write(0xde00, 2);
+        bus.write(0xde02, 0x07);
+        bus.write(0xdf10, 0x55);
+        assert_eq!(bus.read(0x8000), 2);
+        assert_eq!(bus.read(0xbfff), 2);
+        assert_eq!(bus.read(0xdf10), 0x55);
+        assert_eq!(bus.bank(0xa000), Some(2));
+        assert_eq!(bus.bank(0xdf10), None);
+        assert_eq!(bus.device(&cartridge).control(), 0x07);
+
+        let mut ocean = Ocean::new(numbered_banks(0x2000, 16));
+        ocean.write(C64_IO1, 0x4b);
+        assert_eq!(ocean.read(C64_ROML + 0x100), 0x0b);
+        assert_eq!(ocean.read(C64_ROMH), 0x0b);
+    }
+
+    #[test]
+    fn test_atari_hotspots() {
+        let mut f8 = AtariHotspot::f8(numbered_banks(0x1000, 2));
+        assert_eq!(f8.read(0x0000), 1);
+        f8.read(0x0ff8);
+        assert_eq!(f8.read(0x0000), 0);
+        f8.write(0x1ff9, 0);
+        assert_eq!(f8.bank(0x0000), Some(1));
+
+        let mut f6 = AtariHotspot::f6(numbered_banks(0x1000, 4));
+        assert_eq!(f6.read(0x0ff7), 1);
+        assert_eq!(f6.read(0x0ffa), 1);
+    }
+}
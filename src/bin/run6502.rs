@@ -0,0 +1,308 @@
+//! ### RUN6502
+//! Command-line runner that loads a program into 64KB of RAM and runs it until it traps in a loop, reaches an exit
+//! address, jams or runs out of cycles, then prints the registers, cycle count and selected memory. The exit status
+//! reports whether the program stopped where expected, so test ROMs can be run in CI without writing Rust.
+//!
+//! ### Usage Example
+//! ```text
+//! run6502 --load $0400 --success $3469 6502_functional_test.bin
+//! ```
+
+#![allow(clippy::needless_return)]
+
+use emulator_6502::bus::BusBuilder;
+use emulator_6502::loaders::{self, LoadedProgram};
+use emulator_6502::machines::Sim65;
+use emulator_6502::memory::Ram;
+use emulator_6502::monitor::Monitor;
+use emulator_6502::{Interface6502, MOS6502};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+
+const USAGE: &str = "Usage: run6502 [OPTIONS] PROGRAM [ARGUMENTS...]
+
+Loads a 6502 program into 64KB of RAM and runs it until it traps in a loop, reaches an exit address, jams or
+runs out of cycles.
+
+Options:
+  --format FORMAT        raw, prg, hex, srec, xex, apple, o65 or sim65, guessed from the file by default
+  --load ADDRESS         Load address of raw binaries and o65 objects (default $0000)
+  --start ADDRESS        Start at ADDRESS instead of the program's entry point
+  --reset                Start at the address in the reset vector
+  --console ADDRESS      Print bytes written to ADDRESS to stdout
+  --exit ADDRESS         Stop when the program counter reaches ADDRESS
+  --success ADDRESS      Only succeed if the program stops at ADDRESS, by trapping or reaching an exit address
+  --max-cycles COUNT     Stop after COUNT cycles, or each monitor g command after COUNT cycles (default 100000000)
+  --dump ADDRESS:LENGTH  Print LENGTH bytes of memory from ADDRESS at exit, can be repeated
+  --monitor              Start the machine language monitor on stdin and stdout instead of running
+  --help                 Print this message
+
+Addresses are decimal, or hexadecimal with a $ or 0x prefix. The exit status is 0 if the program stopped at the
+success address, or reached an exit address when there is none, 1 if it stopped anywhere else, and 2 if the
+arguments or program were invalid.
+
+Programs built for cc65's sim6502 target run with the sim65 paravirtualization interface, receive the ARGUMENTS
+and exit with their own exit code. Only --format and --max-cycles apply to them.";
+
+/// Where execution begins
+enum Start {
+    Entry,
+    Address(u16),
+    Reset,
+}
+
+/// Options parsed from the command line
+struct Options {
+    program: String,
+    arguments: Vec<String>,
+    format: Option<String>,
+    load: u16,
+    start: Start,
+    console: Option<u16>,
+    exit: Vec<u16>,
+    success: Option<u16>,
+    max_cycles: u64,
+    dumps: Vec<(u16, usize)>,
+    monitor: bool,
+}
+
+/// Why execution stopped
+enum Stop {
+    Trap(u16),
+    Exit(u16),
+    Jammed(u16),
+    CycleLimit,
+}
+
+fn main() {
+    let options = match parse_arguments(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(message) => fail(&format!("{}\n\n{}", message, USAGE)),
+    };
+    process::exit(run(&options));
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("run6502: {}", message);
+    process::exit(2);
+}
+
+fn parse_arguments(mut arguments: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        program: String::new(),
+        arguments: Vec::new(),
+        format: None,
+        load: 0,
+        start: Start::Entry,
+        console: None,
+        exit: Vec::new(),
+        success: None,
+        max_cycles: 100_000_000,
+        dumps: Vec::new(),
+        monitor: false,
+    };
+    let mut program = None;
+    while let Some(argument) = arguments.next() {
+        if program.is_some() {
+            options.arguments.push(argument);
+            continue;
+        }
+        let mut value = || arguments.next().ok_or(format!("{} requires a value", argument));
+        match argument.as_str() {
+            "--help" | "-h" => return Ok(None),
+            "--format" => options.format = Some(value()?),
+            "--load" => options.load = parse_address(&value()?)?,
+            "--start" => options.start = Start::Address(parse_address(&value()?)?),
+            "--reset" => options.start = Start::Reset,
+            "--console" => options.console = Some(parse_address(&value()?)?),
+            "--exit" => options.exit.push(parse_address(&value()?)?),
+            "--success" => options.success = Some(parse_address(&value()?)?),
+            "--max-cycles" => options.max_cycles = parse_number(&value()?)?,
+            "--dump" => {
+                let dump = value()?;
+                let (address, length) = dump.split_once(':').ok_or(format!("Invalid dump range: {}", dump))?;
+                options.dumps.push((parse_address(address)?, parse_number(length)? as usize));
+            }
+            "--monitor" => options.monitor = true,
+            _ if argument.starts_with("--") => return Err(format!("Unknown option: {}", argument)),
+            _ => program = Some(argument),
+        }
+    }
+    options.program = program.ok_or("No program given")?;
+    return Ok(Some(options));
+}
+
+/// Parses a decimal number, or a hexadecimal number with a $ or 0x prefix
+fn parse_number(text: &str) -> Result<u64, String> {
+    let parsed = match text.strip_prefix('$').or_else(|| text.strip_prefix("0x")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    return parsed.map_err(|_| format!("Invalid number: {}", text));
+}
+
+fn parse_address(text: &str) -> Result<u16, String> {
+    let number = parse_number(text)?;
+    if number > u64::from(u16::MAX) {
+        return Err(format!("Address out of range: {}", text));
+    }
+    return Ok(number as u16);
+}
+
+/// Returns the format given on the command line, or guesses it from the file's contents and extension
+fn format(options: &Options, data: &[u8]) -> String {
+    if let Some(format) = &options.format {
+        return format.to_ascii_lowercase();
+    }
+    if data.starts_with(b"sim65") {
+        return "sim65".to_string();
+    }
+    let extension = options.program.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase());
+    let format = match extension.as_deref() {
+        Some("prg") => "prg",
+        Some("hex") | Some("ihx") => "hex",
+        Some("srec") | Some("s19") | Some("mot") => "srec",
+        Some("xex") => "xex",
+        Some("o65") => "o65",
+        _ => "raw",
+    };
+    return format.to_string();
+}
+
+fn load(options: &Options, data: &[u8], format: &str, memory: &mut dyn Interface6502) -> Result<LoadedProgram, String> {
+    let text = || String::from_utf8(data.to_vec()).map_err(|_| loaders::LoadError::InvalidHeader("not a text file"));
+    let loaded = match format {
+        "raw" => loaders::load_raw(memory, data, options.load),
+        "prg" => loaders::load_prg(memory, data),
+        "hex" => text().and_then(|text| loaders::load_intel_hex(memory, &text)),
+        "srec" => text().and_then(|text| loaders::load_srec(memory, &text)),
+        "xex" => loaders::load_xex(memory, data),
+        "apple" => loaders::load_apple_binary(memory, data),
+        "o65" => loaders::load_o65(memory, data, options.load),
+        _ => return Err(format!("Unknown format: {}", format)),
+    };
+    return loaded.map_err(|error| format!("Failed to load {}: {}", options.program, error));
+}
+
+/// Runs a program built for cc65's sim6502 target, returning its exit code
+fn run_sim65(options: &Options, data: &[u8]) -> i32 {
+    let arguments: Vec<&str> = Some(options.program.as_str())
+        .into_iter()
+        .chain(options.arguments.iter().map(String::as_str))
+        .collect();
+    let sim65 = Sim65::new(data).unwrap_or_else(|error| fail(&format!("Failed to load {}: {}", options.program, error)));
+    let mut sim65 = sim65.arguments(&arguments);
+    return match sim65.run(options.max_cycles) {
+        Ok(exit_code) => i32::from(exit_code),
+        Err(error) => {
+            eprintln!("{}", error);
+            print_registers(sim65.cpu());
+            1
+        }
+    };
+}
+
+fn print_registers(cpu: &MOS6502) {
+    let state = cpu.state();
+    eprintln!(
+        "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} SP:{:02X} P:{} CYC:{}",
+        state.program_counter, state.accumulator, state.x_register, state.y_register, state.stack_pointer, state.status, state.total_cycles
+    );
+}
+
+fn run(options: &Options) -> i32 {
+    let data = fs::read(&options.program).unwrap_or_else(|error| fail(&format!("Failed to read {}: {}", options.program, error)));
+    let format = format(options, &data);
+    if format == "sim65" {
+        return run_sim65(options, &data);
+    }
+    if !options.arguments.is_empty() {
+        fail("Arguments can only be passed to sim65 programs");
+    }
+
+    // Load into plain RAM first, so loading does not write to the console
+    let mut builder = BusBuilder::new();
+    let ram = builder.add_device(Ram::new(0x10000));
+    builder.map(0x0000..=0xffff, &ram);
+    let mut loading = builder.build();
+    let program = load(options, &data, &format, &mut loading).unwrap_or_else(|message| fail(&message));
+
+    let mut builder = BusBuilder::new();
+    let ram = builder.add_device(Ram::from_bytes(loading.device(&ram).as_slice().to_vec()));
+    builder.map(0x0000..=0xffff, &ram);
+    if let Some(console) = options.console {
+        builder.mmio(
+            console..=console,
+            |_offset| 0,
+            |_offset, data| {
+                let mut stdout = io::stdout();
+                let _ = stdout.write_all(&[data]).and_then(|_| stdout.flush());
+            },
+        );
+    }
+    let mut bus = builder.build();
+
+    let mut cpu = match options.start {
+        Start::Entry => MOS6502::new_start(program.entry),
+        Start::Address(address) => MOS6502::new_start(address),
+        Start::Reset => MOS6502::new_reset_position(&mut bus),
+    };
+
+    if options.monitor {
+        let stdin = io::stdin();
+        return match Monitor::new()
+            .with_max_cycles(options.max_cycles)
+            .run(&mut cpu, &mut bus, stdin.lock(), io::stdout())
+        {
+            Ok(()) => 0,
+            Err(error) => fail(&format!("Monitor failed: {}", error)),
+        };
+    }
+
+    let stop = loop {
+        let program_counter = cpu.state().program_counter;
+        if options.exit.contains(&program_counter) {
+            break Stop::Exit(program_counter);
+        }
+        if cpu.total_cycles() >= options.max_cycles {
+            break Stop::CycleLimit;
+        }
+        bus.step(&mut cpu);
+        if cpu.is_jammed() {
+            break Stop::Jammed(program_counter);
+        }
+        if cpu.state().program_counter == program_counter {
+            break Stop::Trap(program_counter);
+        }
+    };
+
+    let (description, success) = match stop {
+        Stop::Trap(address) => (format!("Trapped at ${:04X}", address), options.success == Some(address)),
+        Stop::Exit(address) => (
+            format!("Reached exit address ${:04X}", address),
+            options.success.map_or(true, |success| success == address),
+        ),
+        Stop::Jammed(address) => (format!("Jammed at ${:04X}", address), false),
+        Stop::CycleLimit => (format!("Stopped after the cycle limit of {}", options.max_cycles), false),
+    };
+    eprintln!("{}", description);
+    print_registers(&cpu);
+
+    let memory = bus.device(&ram).as_slice();
+    for (address, length) in &options.dumps {
+        let start = *address as usize;
+        let end = (start + length).min(memory.len());
+        for (line, bytes) in memory[start..end].chunks(16).enumerate() {
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            eprintln!("{:04X}: {}", start + line * 16, hex.join(" "));
+        }
+    }
+
+    return if success { 0 } else { 1 };
+}
@@ -0,0 +1,493 @@
+//! ### BUS
+//! This module contains a bus for composing a system's memory map out of devices, instead of writing a
+//! match statement over addresses in a custom `Interface6502` implementation.
+//!
+//! Devices implementing `BusDevice` are added to a `BusBuilder`, which returns a typed handle for
+//! accessing each device after the bus is built. Devices are mapped into one or more address ranges,
+//! and receive addresses as offsets from the start of the range they were accessed through, so mapping
+//! the same device into several ranges mirrors it. Where ranges overlap, the mapping with the highest
+//! priority wins, and later mappings win between equal priorities. Reads from unmapped addresses, or
+//! addresses mapped to the `OpenBus` device, return the last value on the data bus.
+//!
+//! Lookups go through a page table built once by `BusBuilder::build`, so the cost of an access does not
+//! depend on the number of mappings.
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let mut builder = BusBuilder::new();
+//! let ram = builder.add_device(Ram::new(0x0800));
+//! builder.map(0x0000..=0x07ff, &ram);
+//! builder.mmio(0x2000..=0x2007, |offset| ppu_read(offset), |offset, data| ppu_write(offset, data));
+//! let mut bus = builder.build();
+//!
+//! bus.step(&mut cpu);
+//! println!("{:02X}", bus.device(&ram).as_slice()[0x0010]);
+//! ```
+
+use super::memory::OpenBus;
+use super::tracer::Tracer6502;
+use super::{Interface6502, MOS6502};
+use std::any::Any;
+use std::marker::PhantomData;
+use std::ops::RangeInclusive;
+
+/// A device that can be mapped into the address space of a `Bus`
+pub trait BusDevice: Any {
+    /// Returns the value at the offset from the start of the range the device was accessed through
+    fn read(&mut self, offset: u16) -> u8;
+    /// Writes a value to the offset from the start of the range the device was accessed through
+    fn write(&mut self, offset: u16, data: u8);
+    /// Advances the device by the given number of processor cycles
+    fn tick(&mut self, _cycles: u32) {}
+    /// Returns true while the device is asserting the interrupt request line
+    fn irq(&self) -> bool {
+        false
+    }
+    /// Returns true while the device is asserting the non-maskable interrupt line
+    fn nmi(&self) -> bool {
+        false
+    }
+    /// Returns the number of the bank currently selected at the offset, if the device is bank switched
+    fn bank(&self, _offset: u16) -> Option<usize> {
+        None
+    }
+}
+
+/// Typed handle to a device added to a `BusBuilder`, for accessing it after the bus is built
+#[derive(Debug)]
+pub struct DeviceHandle<T> {
+    index: usize,
+    device: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for DeviceHandle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for DeviceHandle<T> {}
+
+/// Memory mapped I/O device made from a pair of closures
+struct Mmio<R, W> {
+    read: R,
+    write: W,
+}
+
+impl<R: FnMut(u16) -> u8 + 'static, W: FnMut(u16, u8) + 'static> BusDevice for Mmio<R, W> {
+    fn read(&mut self, offset: u16) -> u8 {
+        (self.read)(offset)
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        (self.write)(offset, data)
+    }
+}
+
+/// An address range mapped to a device
+#[derive(Debug, Clone, Copy)]
+struct Mapping {
+    start: u16,
+    end: u16,
+    priority: i32,
+    device: usize,
+    offset: u16,
+}
+
+/// Index into the mappings marking an unmapped address
+const UNMAPPED: u16 = u16::MAX;
+
+/// Entry of the page table, covering 256 addresses
+#[derive(Debug, Clone)]
+enum Page {
+    /// Every address in the page uses the same mapping
+    Uniform(u16),
+    /// Addresses in the page use different mappings
+    Split(Box<[u16; 256]>),
+}
+
+/// Returns true if the device is an `OpenBus`, which leaves the addresses it is mapped to unmapped
+fn is_open_bus(device: &dyn BusDevice) -> bool {
+    let device: &dyn Any = device;
+    device.is::<OpenBus>()
+}
+
+/// Builder for composing devices into a `Bus`
+#[derive(Default)]
+pub struct BusBuilder {
+    devices: Vec<Box<dyn BusDevice>>,
+    mappings: Vec<Mapping>,
+}
+
+impl BusBuilder {
+    /// Creates a new builder with no devices
+    pub fn new() -> Self {
+        BusBuilder::default()
+    }
+
+    /// Adds a device to the bus, returning a handle for mapping and accessing it
+    pub fn add_device<T: BusDevice>(&mut self, device: T) -> DeviceHandle<T> {
+        self.devices.push(Box::new(device));
+        DeviceHandle {
+            index: self.devices.len() - 1,
+            device: PhantomData,
+        }
+    }
+
+    /// Maps a device into an address range with the default priority of 0
+    pub fn map<T>(&mut self, range: RangeInclusive<u16>, device: &DeviceHandle<T>) -> &mut Self {
+        self.map_with_priority(range, device, 0)
+    }
+
+    /// Maps a device into an address range, taking precedence over overlapping mappings of lower priority
+    pub fn map_with_priority<T>(&mut self, range: RangeInclusive<u16>, device: &DeviceHandle<T>, priority: i32) -> &mut Self {
+        self.push_mapping(range, device.index, priority, 0)
+    }
+
+    /// Maps a device into an address range with the default priority of 0, adding an offset to the offsets the
+    /// device receives. Devices that appear in several separate ranges, such as cartridges with both ROM and
+    /// I/O registers, use this to tell the ranges apart
+    pub fn map_at_offset<T>(&mut self, range: RangeInclusive<u16>, device: &DeviceHandle<T>, offset: u16) -> &mut Self {
+        self.push_mapping(range, device.index, 0, offset)
+    }
+
+    fn push_mapping(&mut self, range: RangeInclusive<u16>, device: usize, priority: i32, offset: u16) -> &mut Self {
+        assert!(self.mappings.len() < UNMAPPED as usize, "Too many mappings");
+        self.mappings.push(Mapping {
+            start: *range.start(),
+            end: *range.end(),
+            priority,
+            device,
+            offset,
+        });
+        self
+    }
+
+    /// Maps a pair of closures handling reads and writes into an address range, as a memory mapped I/O
+    /// device with the default priority of 0
+    pub fn mmio<R, W>(&mut self, range: RangeInclusive<u16>, read: R, write: W) -> &mut Self
+    where
+        R: FnMut(u16) -> u8 + 'static,
+        W: FnMut(u16, u8) + 'static,
+    {
+        let device = self.add_device(Mmio { read, write });
+        self.map(range, &device)
+    }
+
+    /// Builds the bus and its page table
+    pub fn build(self) -> Bus {
+        let mut pages = Vec::with_capacity(256);
+        for page in 0..=0xff_u16 {
+            let mut entries = [UNMAPPED; 256];
+            for (offset, entry) in entries.iter_mut().enumerate() {
+                let address = page << 8 | offset as u16;
+                let mut best: Option<(usize, &Mapping)> = None;
+                for (index, mapping) in self.mappings.iter().enumerate() {
+                    let covers = mapping.start <= address && address <= mapping.end;
+                    if covers && best.map_or(true, |(_, best)| mapping.priority >= best.priority) {
+                        best = Some((index, mapping));
+                    }
+                }
+                *entry = match best {
+                    Some((_, mapping)) if is_open_bus(self.devices[mapping.device].as_ref()) => UNMAPPED,
+                    Some((index, _)) => index as u16,
+                    None => UNMAPPED,
+                };
+            }
+            if entries.iter().all(|entry| *entry == entries[0]) {
+                pages.push(Page::Uniform(entries[0]));
+            } else {
+                pages.push(Page::Split(Box::new(entries)));
+            }
+        }
+
+        Bus {
+            devices: self.devices,
+            mappings: self.mappings,
+            pages,
+            data_bus: 0,
+            nmi_line: false,
+        }
+    }
+}
+
+/// A 64KB address space composed of devices, built by `BusBuilder`
+pub struct Bus {
+    devices: Vec<Box<dyn BusDevice>>,
+    mappings: Vec<Mapping>,
+    pages: Vec<Page>,
+    data_bus: u8,
+    nmi_line: bool,
+}
+
+impl Bus {
+    /// Returns the device a handle refers to
+    pub fn device<T: BusDevice>(&self, handle: &DeviceHandle<T>) -> &T {
+        let device: &dyn Any = self.devices[handle.index].as_ref();
+        device.downcast_ref().expect("Device handle belongs to this bus")
+    }
+
+    /// Returns the device a handle refers to, mutably
+    pub fn device_mut<T: BusDevice>(&mut self, handle: &DeviceHandle<T>) -> &mut T {
+        let device: &mut dyn Any = self.devices[handle.index].as_mut();
+        device.downcast_mut().expect("Device handle belongs to this bus")
+    }
+
+    /// Returns the mapping an address resolves to, and the offset the device receives
+    #[inline]
+    fn lookup(&self, address: u16) -> Option<(usize, u16)> {
+        let index = match &self.pages[(address >> 8) as usize] {
+            Page::Uniform(index) => *index,
+            Page::Split(entries) => entries[(address & 0xff) as usize],
+        };
+        let mapping = self.mappings.get(index as usize)?;
+        Some((mapping.device, (address - mapping.start).wrapping_add(mapping.offset)))
+    }
+
+    /// Advances every device by the given number of processor cycles
+    pub fn tick(&mut self, cycles: u32) {
+        for device in self.devices.iter_mut() {
+            device.tick(cycles);
+        }
+    }
+
+    /// Returns true if any device is asserting the interrupt request line
+    pub fn irq(&self) -> bool {
+        self.devices.iter().any(|device| device.irq())
+    }
+
+    /// Returns true if any device is asserting the non-maskable interrupt line
+    pub fn nmi(&self) -> bool {
+        self.devices.iter().any(|device| device.nmi())
+    }
+
+    /// Executes an instruction, advances the devices by the cycles it took, and passes any interrupts they
+    /// raise to the processor. Returns the number of cycles the instruction took.
+    ///
+    /// The interrupt request line is level triggered: it is requested after every instruction while it is
+    /// asserted, and a request that has not been serviced is withdrawn once no device asserts it, including one
+    /// made through `MOS6502::interrupt_request`. The non-maskable interrupt line is edge triggered, and is
+    /// requested once each time it becomes asserted
+    pub fn step(&mut self, cpu: &mut MOS6502) -> u64 {
+        let start = cpu.total_cycles();
+        cpu.execute_instruction(self);
+        return self.finish_step(cpu, start);
+    }
+
+    /// Executes an instruction like `step`, passing events to the tracer
+    pub fn step_traced<T: Tracer6502 + ?Sized>(&mut self, cpu: &mut MOS6502, tracer: &mut T) -> u64 {
+        let start = cpu.total_cycles();
+        cpu.execute_instruction_traced(self, tracer);
+        return self.finish_step(cpu, start);
+    }
+
+    /// Runs the function in place of executing an instruction, then advances the devices and passes interrupts to
+    /// the processor like `step`, for drivers that take over steps such as `HostCalls`. Returns the number of cycles
+    /// that passed during the function
+    pub fn step_with<F: FnOnce(&mut MOS6502, &mut Bus)>(&mut self, cpu: &mut MOS6502, step: F) -> u64 {
+        let start = cpu.total_cycles();
+        step(cpu, self);
+        return self.finish_step(cpu, start);
+    }
+
+    fn finish_step(&mut self, cpu: &mut MOS6502, start: u64) -> u64 {
+        let cycles = cpu.total_cycles() - start;
+        self.tick(cycles as u32);
+        cpu.set_interrupt_request_line(self.irq());
+        let nmi = self.nmi();
+        if nmi && !self.nmi_line {
+            cpu.non_maskable_interrupt_request();
+        }
+        self.nmi_line = nmi;
+        return cycles;
+    }
+}
+
+impl Interface6502 for Bus {
+    fn read(&mut self, address: u16) -> u8 {
+        if let Some((device, offset)) = self.lookup(address) {
+            self.data_bus = self.devices[device].read(offset);
+        }
+        self.data_bus
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.data_bus = data;
+        if let Some((device, offset)) = self.lookup(address) {
+            self.devices[device].write(offset, data);
+        }
+    }
+
+    fn bank(&self, address: u16) -> Option<usize> {
+        let (device, offset) = self.lookup(address)?;
+        self.devices[device].bank(offset)
+    }
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// Simple memory device for testing the bus
+    struct Memory(Vec<u8>);
+
+    impl BusDevice for Memory {
+        fn read(&mut self, offset: u16) -> u8 {
+            self.0[offset as usize]
+        }
+
+        fn write(&mut self, offset: u16, data: u8) {
+            self.0[offset as usize] = data
+        }
+    }
+
+    /// Device that asserts its interrupt lines after a number of cycles
+    struct Timer {
+        remaining: u32,
+        nmi: bool,
+    }
+
+    impl BusDevice for Timer {
+        fn read(&mut self, _offset: u16) -> u8 {
+            0
+        }
+
+        fn write(&mut self, _offset: u16, _data: u8) {}
+
+        fn tick(&mut self, cycles: u32) {
+            self.remaining = self.remaining.saturating_sub(cycles);
+        }
+
+        fn irq(&self) -> bool {
+            !self.nmi && self.remaining == 0
+        }
+
+        fn nmi(&self) -> bool {
+            self.nmi && self.remaining == 0
+        }
+    }
+
+    #[test]
+    fn test_mapping() {
+        let mut builder = BusBuilder::new();
+        let ram = builder.add_device(Memory(vec![0; 0x0800]));
+        let rom = builder.add_device(Memory(vec![0xea; 0x10]));
+        builder.map(0x0000..=0x07ff, &ram).map(0x0800..=0x0fff, &ram);
+        builder.map(0xfff0..=0xffff, &rom);
+        let mut bus = builder.build();
+
+        bus.write(0x0010, 0x42);
+        assert_eq!(bus.read(0x0810), 0x42);
+        assert_eq!(bus.read(0xfff5), 0xea);
+        assert_eq!(bus.device(&ram).0[0x10], 0x42);
+
+        bus.device_mut(&rom).0[0x0f] = 0x04;
+        assert_eq!(bus.read(0xffff), 0x04);
+
+        // Unmapped reads return the last value on the data bus
+        assert_eq!(bus.read(0x4000), 0x04);
+    }
+
+    #[test]
+    fn test_priority() {
+        let mut builder = BusBuilder::new();
+        let ram = builder.add_device(Memory(vec![0x11; 0x10000]));
+        let overlay = builder.add_device(Memory(vec![0x22; 0x10]));
+        builder.map_with_priority(0x1234..=0x1243, &overlay, 1);
+        builder.map(0x0000..=0xffff, &ram);
+        let mut bus = builder.build();
+
+        assert_eq!(bus.read(0x1233), 0x11);
+        assert_eq!(bus.read(0x1234), 0x22);
+        assert_eq!(bus.read(0x1243), 0x22);
+        assert_eq!(bus.read(0x1244), 0x11);
+    }
+
+    #[test]
+    fn test_mmio() {
+        let written = Rc::new(Cell::new(None));
+        let written_clone = Rc::clone(&written);
+
+        let mut builder = BusBuilder::new();
+        builder.mmio(
+            0x2000..=0x2007,
+            |offset| offset as u8 * 2,
+            move |offset, data| written_clone.set(Some((offset, data))),
+        );
+        let mut bus = builder.build();
+
+        assert_eq!(bus.read(0x2003), 6);
+        bus.write(0x2005, 0x99);
+        assert_eq!(written.get(), Some((5, 0x99)));
+    }
+
+    /// Device asserting the interrupt request line while the shared flag is set
+    struct Line(Rc<Cell<bool>>);
+
+    impl BusDevice for Line {
+        fn read(&mut self, _offset: u16) -> u8 {
+            0
+        }
+
+        fn write(&mut self, _offset: u16, _data: u8) {}
+
+        fn irq(&self) -> bool {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn test_irq_withdrawn() {
+        let asserted = Rc::new(Cell::new(true));
+        let mut builder = BusBuilder::new();
+        let ram = builder.add_device(Memory(vec![0xea; 0x10000])); // Filled with NOP
+        builder.add_device(Line(Rc::clone(&asserted)));
+        builder.map(0x0000..=0xffff, &ram);
+        let mut bus = builder.build();
+        bus.device_mut(&ram).0[0x0401] = 0x58; // CLI
+        bus.device_mut(&ram).0[0xfffe..=0xffff].clone_from_slice(&[0x00, 0x80]);
+
+        // The interrupt disable flag is set, so the request waits
+        let mut cpu = MOS6502::new_start(0x0400);
+        bus.step(&mut cpu);
+        asserted.set(false);
+        bus.step(&mut cpu);
+        bus.step(&mut cpu);
+        assert_eq!(cpu.state().program_counter, 0x0403, "a withdrawn request should not be serviced");
+    }
+
+    #[test]
+    fn test_step_interrupts() {
+        let mut builder = BusBuilder::new();
+        let ram = builder.add_device(Memory(vec![0xea; 0x10000])); // Filled with NOP
+        let irq_timer = builder.add_device(Timer { remaining: 4, nmi: false });
+        let nmi_timer = builder.add_device(Timer { remaining: 10, nmi: true });
+        builder.map(0x0000..=0xffff, &ram);
+        let mut bus = builder.build();
+        bus.device_mut(&ram).0[0xfffa..=0xffff].clone_from_slice(&[0x00, 0x90, 0x00, 0x04, 0x00, 0x80]);
+
+        let mut cpu = MOS6502::new_start(0x0400);
+        cpu.set_state(&crate::CpuState {
+            status: crate::StatusFlags::UNUSED,
+            ..cpu.state()
+        });
+
+        assert_eq!(bus.step(&mut cpu), 2);
+        assert_eq!(bus.step(&mut cpu), 2);
+        assert!(bus.device(&irq_timer).irq());
+        bus.step(&mut cpu); // Services the interrupt request
+        assert_eq!(cpu.state().program_counter, 0x8000);
+
+        while !bus.device(&nmi_timer).nmi() {
+            bus.step(&mut cpu);
+        }
+        bus.step(&mut cpu); // Services the non-maskable interrupt
+        assert_eq!(cpu.state().program_counter, 0x9000);
+        bus.step(&mut cpu);
+        assert_eq!(cpu.state().program_counter, 0x9001);
+    }
+}
@@ -0,0 +1,434 @@
+//! ### CALL STACK
+//! This module contains a tracer that maintains a shadow call stack as the processor executes
+//! JSR/RTS, BRK/RTI and interrupts, so that a backtrace can be produced at any point.
+//!
+//! Programs for the 6502 frequently manipulate the stack directly, so frames are tracked by the stack
+//! pointer at the time of the call rather than by simply pairing calls with returns:
+//! * A frame is discarded once the stack pointer rises above the return address it pushed, which
+//!   happens when a subroutine pulls its return address and jumps elsewhere
+//! * A return that consumes an address pushed by the program rather than by a call (the RTS jump table
+//!   trick) leaves the shadow stack untouched
+//! * A return to a different address than the one pushed by the call (subroutines that read inline
+//!   parameters and skip over them) still returns from the frame
+//!
+//! Each of these is recorded as a `StackTrick` so they can be inspected while debugging.
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let mut call_stack = CallStack::new();
+//! cpu.execute_instruction_traced(&mut ram, &mut call_stack);
+//! println!("{}", call_stack.backtrace(cpu_program_counter));
+//! ```
+
+use super::symbols::SymbolTable;
+use super::tracer::{InstructionEvent, InterruptEvent, InterruptKind, Tracer6502};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Opcode of the JSR instruction
+const JSR: u8 = 0x20;
+/// Opcode of the BRK instruction
+const BRK: u8 = 0x00;
+/// Opcode of the RTS instruction
+const RTS: u8 = 0x60;
+/// Opcode of the RTI instruction
+const RTI: u8 = 0x40;
+/// The number of stack tricks that are remembered
+const TRICK_CAPACITY: usize = 64;
+
+/// The ways a frame can be entered
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FrameKind {
+    /// A JSR instruction
+    Subroutine,
+    /// A BRK instruction
+    Break,
+    /// A maskable interrupt request
+    Irq,
+    /// A non-maskable interrupt request
+    Nmi,
+}
+
+impl fmt::Display for FrameKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameKind::Subroutine => write!(f, "JSR"),
+            FrameKind::Break => write!(f, "BRK"),
+            FrameKind::Irq => write!(f, "IRQ"),
+            FrameKind::Nmi => write!(f, "NMI"),
+        }
+    }
+}
+
+/// A call that has not yet returned
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct StackFrame {
+    /// How the frame was entered
+    pub kind: FrameKind,
+    /// The address execution moved to when the frame was entered
+    pub entry: u16,
+    /// The address of the calling instruction, or the interrupted instruction for interrupts
+    pub call_site: u16,
+    /// The address execution is expected to return to
+    pub return_address: u16,
+    /// The stack pointer before the call pushed anything
+    pub stack_pointer: u8,
+}
+
+/// Stack manipulation detected while tracking calls
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StackTrick {
+    /// A return instruction at `address` consumed an address pushed by the program and jumped to `target`
+    ReturnJump {
+        /// The address of the return instruction
+        address: u16,
+        /// The address that was jumped to
+        target: u16,
+    },
+    /// The instruction at `address` moved the stack pointer past the return addresses of `count` frames
+    DiscardedFrames {
+        /// The address of the instruction
+        address: u16,
+        /// The number of frames discarded
+        count: usize,
+    },
+    /// A return instruction at `address` returned from a frame to `actual` rather than the `expected` address
+    ModifiedReturn {
+        /// The address of the return instruction
+        address: u16,
+        /// The return address pushed by the call
+        expected: u16,
+        /// The address that was returned to
+        actual: u16,
+    },
+}
+
+/// Tracer maintaining a shadow call stack
+#[derive(Debug, Default, Clone)]
+pub struct CallStack {
+    frames: Vec<StackFrame>,
+    tricks: VecDeque<StackTrick>,
+}
+
+impl CallStack {
+    /// Creates a new, empty call stack
+    pub fn new() -> Self {
+        CallStack::default()
+    }
+
+    /// Returns the active frames, outermost first
+    pub fn frames(&self) -> &[StackFrame] {
+        &self.frames
+    }
+
+    /// Returns the number of active frames
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns the most recently detected stack tricks, oldest first
+    pub fn tricks(&self) -> impl Iterator<Item = &StackTrick> {
+        self.tricks.iter()
+    }
+
+    /// Clears the shadow stack and detected tricks, for use after the processor is reset
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.tricks.clear();
+    }
+
+    /// Creates a backtrace for the processor currently executing at the given address
+    pub fn backtrace(&self, program_counter: u16) -> Backtrace {
+        let mut frames = Vec::with_capacity(self.frames.len() + 1);
+        let mut address = program_counter;
+        for frame in self.frames.iter().rev() {
+            frames.push(BacktraceFrame {
+                address,
+                function: Some(frame.entry),
+                kind: Some(frame.kind),
+            });
+            address = frame.call_site;
+        }
+        frames.push(BacktraceFrame {
+            address,
+            function: None,
+            kind: None,
+        });
+        return Backtrace { frames };
+    }
+
+    fn record(&mut self, trick: StackTrick) {
+        debug!("Stack manipulation detected: {:?}", trick);
+        if self.tricks.len() == TRICK_CAPACITY {
+            self.tricks.pop_front();
+        }
+        self.tricks.push_back(trick);
+    }
+
+    fn push(&mut self, frame: StackFrame) {
+        // A frame at or below the new one can no longer be returned to
+        self.discard(frame.stack_pointer, frame.call_site, |existing, stack_pointer| existing <= stack_pointer);
+        self.frames.push(frame);
+    }
+
+    /// Pops frames whose return addresses are no longer on the stack
+    fn discard(&mut self, stack_pointer: u8, address: u16, discarded: fn(u8, u8) -> bool) {
+        let count = self
+            .frames
+            .iter()
+            .rev()
+            .take_while(|frame| discarded(frame.stack_pointer, stack_pointer))
+            .count();
+        if count > 0 {
+            self.frames.truncate(self.frames.len() - count);
+            self.record(StackTrick::DiscardedFrames { address, count });
+        }
+    }
+
+    fn return_from(&mut self, event: &InstructionEvent) {
+        let stack_pointer = event.after.stack_pointer;
+        let count = self.frames.iter().rev().take_while(|frame| frame.stack_pointer <= stack_pointer).count();
+        if count == 0 {
+            self.record(StackTrick::ReturnJump {
+                address: event.program_counter,
+                target: event.after.program_counter,
+            });
+            return;
+        }
+
+        let frame = self.frames[self.frames.len() - count];
+        self.frames.truncate(self.frames.len() - count);
+        if count > 1 {
+            self.record(StackTrick::DiscardedFrames {
+                address: event.program_counter,
+                count: count - 1,
+            });
+        }
+        if frame.return_address != event.after.program_counter {
+            self.record(StackTrick::ModifiedReturn {
+                address: event.program_counter,
+                expected: frame.return_address,
+                actual: event.after.program_counter,
+            });
+        }
+    }
+}
+
+impl Tracer6502 for CallStack {
+    fn instruction(&mut self, event: &InstructionEvent) {
+        match event.opcode {
+            JSR => self.push(StackFrame {
+                kind: FrameKind::Subroutine,
+                entry: event.after.program_counter,
+                call_site: event.program_counter,
+                return_address: event.program_counter.wrapping_add(3),
+                stack_pointer: event.before.stack_pointer,
+            }),
+            BRK => self.push(StackFrame {
+                kind: FrameKind::Break,
+                entry: event.after.program_counter,
+                call_site: event.program_counter,
+                return_address: event.program_counter.wrapping_add(2),
+                stack_pointer: event.before.stack_pointer,
+            }),
+            RTS | RTI => self.return_from(event),
+            _ => {
+                // Pulling the return address alone keeps the frame alive, as it may be pushed back
+                self.discard(event.after.stack_pointer, event.program_counter, |existing, stack_pointer| {
+                    existing < stack_pointer
+                });
+            }
+        }
+    }
+
+    fn interrupt(&mut self, event: &InterruptEvent) {
+        self.push(StackFrame {
+            kind: match event.kind {
+                InterruptKind::Irq => FrameKind::Irq,
+                InterruptKind::Nmi => FrameKind::Nmi,
+            },
+            entry: event.after.program_counter,
+            call_site: event.before.program_counter,
+            return_address: event.before.program_counter,
+            stack_pointer: event.before.stack_pointer,
+        });
+    }
+}
+
+/// A single frame of a backtrace
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct BacktraceFrame {
+    /// The address execution is at within the frame
+    pub address: u16,
+    /// The entry address of the frame, none for the outermost frame whose entry is unknown
+    pub function: Option<u16>,
+    /// How the frame was entered, none for the outermost frame
+    pub kind: Option<FrameKind>,
+}
+
+/// A snapshot of the call stack, innermost frame first
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Backtrace {
+    /// The frames of the backtrace, innermost first
+    pub frames: Vec<BacktraceFrame>,
+}
+
+impl Backtrace {
+    /// Formats the backtrace, naming addresses with the lookup function where it returns a name
+    pub fn to_string_with<F: Fn(u16) -> Option<String>>(&self, lookup: F) -> String {
+        let name = |address: u16| lookup(address).unwrap_or_else(|| format!("${:04X}", address));
+        let mut output = String::new();
+        for (index, frame) in self.frames.iter().enumerate() {
+            let line = match (frame.function, frame.kind) {
+                (Some(function), Some(kind)) => format!("#{} ${:04X} in {} ({})", index, frame.address, name(function), kind),
+                _ => format!("#{} ${:04X}", index, frame.address),
+            };
+            output.push_str(&line);
+            output.push('\n');
+        }
+        return output;
+    }
+
+    /// Formats the backtrace, naming functions that have a name in the symbol table
+    pub fn to_string_with_symbols(&self, symbols: &SymbolTable) -> String {
+        self.to_string_with(|address| symbols.name(address).map(|name| name.to_string()))
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_string_with(|_| None))
+    }
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utilities::TestRam;
+    use crate::MOS6502;
+
+    fn run(program: &[u8], instructions: usize) -> (CallStack, MOS6502) {
+        let mut ram = TestRam::with_program(0x0400, program);
+        let mut cpu = MOS6502::new_start(0x0400);
+        let mut call_stack = CallStack::new();
+        for _ in 0..instructions {
+            cpu.execute_instruction_traced(&mut ram, &mut call_stack);
+        }
+        (call_stack, cpu)
+    }
+
+    /// Two nested subroutine calls, followed by an infinite loop
+    const NESTED: [u8; 13] = [
+        0x20, 0x06, 0x04, // $0400 JSR $0406
+        0x4c, 0x03, 0x04, // $0403 JMP $0403
+        0x20, 0x0a, 0x04, // $0406 JSR $040a
+        0x60, // $0409 RTS
+        0xea, // $040a NOP
+        0x60, // $040b RTS
+        0x00,
+    ];
+
+    #[test]
+    fn test_nested_calls() {
+        let (call_stack, cpu) = run(&NESTED, 3);
+
+        assert_eq!(call_stack.depth(), 2);
+        assert_eq!(call_stack.frames()[1].entry, 0x040a);
+        assert_eq!(call_stack.frames()[1].return_address, 0x0409);
+        assert_eq!(
+            call_stack.backtrace(cpu.program_counter).to_string(),
+            "#0 $040B in $040A (JSR)\n#1 $0406 in $0406 (JSR)\n#2 $0400\n"
+        );
+
+        let (call_stack, _) = run(&NESTED, 6);
+        assert_eq!(call_stack.depth(), 0);
+        assert_eq!(call_stack.tricks().count(), 0);
+    }
+
+    #[test]
+    fn test_backtrace_symbols() {
+        let (call_stack, cpu) = run(&NESTED, 3);
+        let backtrace = call_stack.backtrace(cpu.program_counter);
+
+        let text = backtrace.to_string_with(|address| if address == 0x040a { Some("inner".to_string()) } else { None });
+        assert_eq!(text, "#0 $040B in inner (JSR)\n#1 $0406 in $0406 (JSR)\n#2 $0400\n");
+
+        let mut symbols = SymbolTable::new();
+        symbols.insert("outer", 0x0406);
+        assert_eq!(
+            backtrace.to_string_with_symbols(&symbols),
+            "#0 $040B in $040A (JSR)\n#1 $0406 in outer (JSR)\n#2 $0400\n"
+        );
+    }
+
+    #[test]
+    fn test_return_jump() {
+        let program = [
+            0xa9, 0x04, // $0400 LDA #$04
+            0x48, // $0402 PHA
+            0xa9, 0x09, // $0403 LDA #$09
+            0x48, // $0405 PHA
+            0x60, // $0406 RTS
+            0xea, // $0407 NOP
+            0xea, // $0408 NOP
+            0xea, // $0409 NOP
+        ];
+        let (call_stack, cpu) = run(&program, 5);
+
+        assert_eq!(cpu.program_counter, 0x040a);
+        assert_eq!(call_stack.depth(), 0);
+        assert_eq!(
+            call_stack.tricks().collect::<Vec<_>>(),
+            vec![&StackTrick::ReturnJump {
+                address: 0x0406,
+                target: 0x040a
+            }]
+        );
+    }
+
+    #[test]
+    fn test_discarded_return() {
+        let program = [
+            0x20, 0x06, 0x04, // $0400 JSR $0406
+            0x4c, 0x03, 0x04, // $0403 JMP $0403
+            0x20, 0x0a, 0x04, // $0406 JSR $040a
+            0x60, // $0409 RTS
+            0x68, // $040a PLA
+            0x68, // $040b PLA
+            0x60, // $040c RTS, returning directly to $0403
+        ];
+        let (call_stack, _) = run(&program, 4);
+        assert_eq!(call_stack.depth(), 2);
+
+        let (call_stack, cpu) = run(&program, 5);
+        assert_eq!(cpu.program_counter, 0x0403);
+        assert_eq!(call_stack.depth(), 0);
+        assert_eq!(
+            call_stack.tricks().collect::<Vec<_>>(),
+            vec![&StackTrick::DiscardedFrames { address: 0x040c, count: 1 }]
+        );
+    }
+
+    #[test]
+    fn test_interrupt_frame() {
+        // CLI; loop: JMP loop; handler at $0500: RTI
+        let mut ram = TestRam::with_program(0x0400, &[0x58, 0x4c, 0x01, 0x04]);
+        ram.ram[0x0500] = 0x40;
+        ram.ram[0xffff] = 0x05;
+        let mut cpu = MOS6502::new_start(0x0400);
+        let mut call_stack = CallStack::new();
+
+        cpu.execute_instruction_traced(&mut ram, &mut call_stack);
+        cpu.interrupt_request();
+        cpu.execute_instruction_traced(&mut ram, &mut call_stack);
+
+        assert_eq!(call_stack.frames()[0].kind, FrameKind::Irq);
+        assert_eq!(call_stack.frames()[0].return_address, 0x0401);
+
+        cpu.execute_instruction_traced(&mut ram, &mut call_stack);
+        assert_eq!(call_stack.depth(), 0);
+        assert_eq!(call_stack.tricks().count(), 0);
+    }
+}
@@ -0,0 +1,428 @@
+//! ### COVERAGE
+//! This module contains a tracer that records which instruction bytes were executed and which ways
+//! each conditional branch went, for measuring how much of a 6502 program its tests exercise.
+//!
+//! Coverage can be exported as an lcov tracefile when a `LineMap` relating addresses to source lines
+//! is available, or as an annotated disassembly listing of a range of memory. Processors with custom
+//! opcodes need their opcode table given to `with_opcode_table`, so their instructions are decoded alike.
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let mut coverage = Coverage::new();
+//! while !ram.complete {
+//!     cpu.execute_instruction_traced(&mut ram, &mut coverage);
+//! }
+//! coverage.write_listing(io::stdout(), &mut ram, 0xc000, 0xc0ff)?;
+//! ```
+
+use super::disassembler::{disassemble_with_table, DisassembledInstruction};
+use super::opcodes::{OpcodeTable, BUILT_IN_TABLE};
+use super::tracer::{InstructionEvent, Tracer6502};
+use super::{AddressMode, Interface6502};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::sync::Arc;
+
+/// Flag marking an address that held the opcode of an executed instruction
+const OPCODE_EXECUTED: u8 = 0b01;
+/// Flag marking an address that held an operand byte of an executed instruction
+const OPERAND_EXECUTED: u8 = 0b10;
+
+/// The number of times a conditional branch went each way
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct BranchCoverage {
+    /// The number of times the branch was taken
+    pub taken: u32,
+    /// The number of times the branch fell through to the next instruction
+    pub not_taken: u32,
+}
+
+/// Tracer recording the coverage of executed instructions and branches
+#[derive(Debug, Clone)]
+pub struct Coverage {
+    hits: Vec<u32>,
+    flags: Vec<u8>,
+    branches: HashMap<u16, BranchCoverage>,
+    opcode_table: Option<Arc<OpcodeTable>>,
+}
+
+impl Coverage {
+    /// Creates a new, empty coverage record
+    pub fn new() -> Self {
+        Coverage {
+            hits: vec![0; u16::MAX as usize + 1],
+            flags: vec![0; u16::MAX as usize + 1],
+            branches: HashMap::new(),
+            opcode_table: None,
+        }
+    }
+
+    /// Decodes instructions with the opcode table of a processor with custom opcodes, when finding branches and
+    /// disassembling
+    pub fn with_opcode_table(mut self, opcode_table: Arc<OpcodeTable>) -> Self {
+        self.opcode_table = Some(opcode_table);
+        self
+    }
+
+    /// Returns the number of times an instruction beginning at the address was executed
+    pub fn hits(&self, address: u16) -> u32 {
+        self.hits[address as usize]
+    }
+
+    /// Returns true if the address held the opcode or an operand byte of an executed instruction
+    pub fn is_executed(&self, address: u16) -> bool {
+        self.flags[address as usize] != 0
+    }
+
+    /// Returns the number of distinct addresses that held part of an executed instruction
+    pub fn executed_bytes(&self) -> usize {
+        self.flags.iter().filter(|flags| **flags != 0).count()
+    }
+
+    /// Returns the branch coverage of the conditional branch at the address, if it was executed
+    pub fn branch(&self, address: u16) -> Option<BranchCoverage> {
+        self.branches.get(&address).copied()
+    }
+
+    /// Clears all recorded coverage, keeping the opcode table
+    pub fn clear(&mut self) {
+        *self = Coverage {
+            opcode_table: self.opcode_table.take(),
+            ..Coverage::new()
+        };
+    }
+
+    /// Writes the coverage as an lcov tracefile, reporting every line in the line map. The instructions of each line
+    /// are disassembled from the interface to find the branches on lines that never ran
+    pub fn write_lcov<W: Write>(&self, mut writer: W, line_map: &LineMap, interface: &mut dyn Interface6502) -> io::Result<()> {
+        writeln!(writer, "TN:")?;
+        for (file_index, file) in line_map.files.iter().enumerate() {
+            let mut lines: BTreeMap<u32, (u32, Vec<Option<BranchCoverage>>)> = BTreeMap::new();
+            for span in line_map.spans.iter().filter(|span| span.file == file_index) {
+                let (hits, branches) = lines.entry(span.line).or_default();
+                for address in span.addresses() {
+                    *hits = (*hits).max(self.hits(address));
+                }
+                let mut offset = 0;
+                while offset < span.length {
+                    let instruction = disassemble_with_table(interface, span.start.wrapping_add(offset), self.opcode_table.as_deref());
+                    if instruction.is_branch() {
+                        branches.push(self.branch(instruction.address));
+                    }
+                    offset = offset.saturating_add(u16::from(instruction.length()));
+                }
+            }
+
+            writeln!(writer, "SF:{}", file)?;
+            let (mut branches_found, mut branches_hit) = (0, 0);
+            for (line, (_, branches)) in &lines {
+                for (block, branch) in branches.iter().enumerate() {
+                    match branch {
+                        Some(branch) => {
+                            writeln!(writer, "BRDA:{},{},0,{}", line, block, branch.taken)?;
+                            writeln!(writer, "BRDA:{},{},1,{}", line, block, branch.not_taken)?;
+                            branches_hit += (branch.taken > 0) as u32 + (branch.not_taken > 0) as u32;
+                        }
+                        None => {
+                            // lcov marks the branches of blocks that never ran with a dash rather than a count
+                            writeln!(writer, "BRDA:{},{},0,-", line, block)?;
+                            writeln!(writer, "BRDA:{},{},1,-", line, block)?;
+                        }
+                    }
+                    branches_found += 2;
+                }
+            }
+            writeln!(writer, "BRF:{}", branches_found)?;
+            writeln!(writer, "BRH:{}", branches_hit)?;
+            for (line, (hits, _)) in &lines {
+                writeln!(writer, "DA:{},{}", line, hits)?;
+            }
+            writeln!(writer, "LF:{}", lines.len())?;
+            writeln!(writer, "LH:{}", lines.values().filter(|(hits, _)| *hits > 0).count())?;
+            writeln!(writer, "end_of_record")?;
+        }
+        Ok(())
+    }
+
+    /// Writes an annotated disassembly of the memory between the start and end addresses, inclusive, marking
+    /// the number of times each instruction was executed and the directions each branch went.
+    ///
+    /// Memory that was never executed is disassembled speculatively, and is shown as data where it would
+    /// overlap an executed instruction
+    pub fn write_listing<W: Write>(&self, mut writer: W, interface: &mut dyn Interface6502, start: u16, end: u16) -> io::Result<()> {
+        let mut address = u32::from(start);
+        while address <= u32::from(end) {
+            let instruction = disassemble_with_table(interface, address as u16, self.opcode_table.as_deref());
+            let executed = self.flags[address as usize] & OPCODE_EXECUTED != 0;
+            let overlaps = (1..u32::from(instruction.length()))
+                .any(|offset| address + offset <= u32::from(u16::MAX) && self.flags[(address + offset) as usize] & OPCODE_EXECUTED != 0);
+
+            if !executed && (overlaps || self.flags[address as usize] & OPERAND_EXECUTED != 0) {
+                writeln!(
+                    writer,
+                    "{:>8}  {:04X}  {:<8}  .BYTE ${:02X}",
+                    "-",
+                    address,
+                    format!("{:02X}", instruction.opcode),
+                    instruction.opcode
+                )?;
+                address += 1;
+                continue;
+            }
+
+            let hits = if executed {
+                self.hits(address as u16).to_string()
+            } else {
+                "-".to_string()
+            };
+            write!(writer, "{:>8}  {:04X}  {:<8}  {}", hits, address, instruction.format_bytes(), instruction)?;
+            writeln!(writer, "{}", self.branch_annotation(&instruction))?;
+            address += u32::from(instruction.length());
+        }
+        Ok(())
+    }
+
+    /// Describes the coverage of a branch instruction for the listing
+    fn branch_annotation(&self, instruction: &DisassembledInstruction) -> String {
+        return match self.branch(instruction.address) {
+            Some(branch) if instruction.is_branch() => {
+                let mut annotation = format!("  ; taken {}, not taken {}", branch.taken, branch.not_taken);
+                if branch.taken == 0 || branch.not_taken == 0 {
+                    annotation.push_str(" (partial)");
+                }
+                annotation
+            }
+            _ => String::new(),
+        };
+    }
+}
+
+impl Tracer6502 for Coverage {
+    fn instruction(&mut self, event: &InstructionEvent) {
+        let address = event.program_counter;
+        self.hits[address as usize] = self.hits[address as usize].saturating_add(1);
+        self.flags[address as usize] |= OPCODE_EXECUTED;
+        for offset in 1..=u16::from(event.operand_length) {
+            self.flags[address.wrapping_add(offset) as usize] |= OPERAND_EXECUTED;
+        }
+
+        // Conditional branches are the instructions using relative addressing, and take an extra cycle when they
+        // branch, even to the next instruction
+        let table = self.opcode_table.as_deref().unwrap_or(&BUILT_IN_TABLE);
+        if table.address_mode(event.opcode) == AddressMode::Relative {
+            let taken = event.cycles > table.cycles(event.opcode);
+            let branch = self.branches.entry(address).or_default();
+            if taken {
+                branch.taken += 1;
+            } else {
+                branch.not_taken += 1;
+            }
+        }
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Coverage::new()
+    }
+}
+
+/// A range of addresses generated by a single line of source code
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct LineSpan {
+    start: u16,
+    length: u16,
+    file: usize,
+    line: u32,
+}
+
+impl LineSpan {
+    fn addresses(&self) -> impl Iterator<Item = u16> {
+        let start = self.start;
+        (0..self.length).map(move |offset| start.wrapping_add(offset))
+    }
+}
+
+/// Mapping from addresses to the source files and lines that generated them
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct LineMap {
+    files: Vec<String>,
+    spans: Vec<LineSpan>,
+}
+
+impl LineMap {
+    /// Creates a new, empty line map
+    pub fn new() -> Self {
+        LineMap::default()
+    }
+
+    /// Records that a line of a source file generated the given number of bytes from the start address
+    pub fn add_span(&mut self, file: &str, line: u32, start: u16, length: u16) {
+        let file = match self.files.iter().position(|existing| existing == file) {
+            Some(index) => index,
+            None => {
+                self.files.push(file.to_string());
+                self.files.len() - 1
+            }
+        };
+        self.spans.push(LineSpan { start, length, file, line });
+    }
+
+    /// Returns the source file and line that generated the byte at an address, if known
+    pub fn lookup(&self, address: u16) -> Option<(&str, u32)> {
+        return self
+            .spans
+            .iter()
+            .find(|span| address.wrapping_sub(span.start) < span.length)
+            .map(|span| (self.files[span.file].as_str(), span.line));
+    }
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utilities::TestRam;
+    use crate::MOS6502;
+
+    /// Counts X down from 2, then jumps over a byte of data, followed by a branch that never runs
+    const PROGRAM: [u8; 12] = [
+        0xa2, 0x02, // $0400 LDX #$02
+        0xca, // $0402 DEX
+        0xd0, 0xfd, // $0403 BNE $0402
+        0x4c, 0x09, 0x04, // $0405 JMP $0409
+        0xff, // $0408 data
+        0xea, // $0409 NOP
+        0xf0, 0x00, // $040A BEQ $040C
+    ];
+
+    fn run() -> (Coverage, TestRam) {
+        let mut ram = TestRam::with_program(0x0400, &PROGRAM);
+        let mut cpu = MOS6502::new_start(0x0400);
+        let mut coverage = Coverage::new();
+        for _ in 0..7 {
+            cpu.execute_instruction_traced(&mut ram, &mut coverage);
+        }
+        (coverage, ram)
+    }
+
+    #[test]
+    fn test_coverage() {
+        let (coverage, _) = run();
+
+        assert_eq!(coverage.hits(0x0400), 1);
+        assert_eq!(coverage.hits(0x0402), 2);
+        assert!(coverage.is_executed(0x0401));
+        assert!(!coverage.is_executed(0x0408));
+        assert_eq!(coverage.executed_bytes(), 9);
+        assert_eq!(coverage.branch(0x0403), Some(BranchCoverage { taken: 1, not_taken: 1 }));
+        assert_eq!(coverage.branch(0x0402), None);
+    }
+
+    #[test]
+    fn test_branch_to_next_instruction() {
+        // BNE $0402; BEQ $0404
+        let mut ram = TestRam::with_program(0x0400, &[0xd0, 0x00, 0xf0, 0x00]);
+        let mut cpu = MOS6502::new_start(0x0400);
+        let mut coverage = Coverage::new();
+        cpu.execute_instruction_traced(&mut ram, &mut coverage);
+        cpu.execute_instruction_traced(&mut ram, &mut coverage);
+
+        assert_eq!(coverage.branch(0x0400), Some(BranchCoverage { taken: 1, not_taken: 0 }));
+        assert_eq!(coverage.branch(0x0402), Some(BranchCoverage { taken: 0, not_taken: 1 }));
+    }
+
+    #[test]
+    fn test_lcov() {
+        let (coverage, mut ram) = run();
+        let mut line_map = LineMap::new();
+        line_map.add_span("loop.s", 1, 0x0400, 2);
+        line_map.add_span("loop.s", 2, 0x0402, 1);
+        line_map.add_span("loop.s", 3, 0x0403, 2);
+        line_map.add_span("loop.s", 4, 0x0405, 3);
+        line_map.add_span("loop.s", 5, 0x0408, 1);
+        line_map.add_span("loop.s", 6, 0x040a, 2);
+        assert_eq!(line_map.lookup(0x0406), Some(("loop.s", 4)));
+
+        let mut output = Vec::new();
+        coverage.write_lcov(&mut output, &line_map, &mut ram).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "TN:\nSF:loop.s\nBRDA:3,0,0,1\nBRDA:3,0,1,1\nBRDA:6,0,0,-\nBRDA:6,0,1,-\nBRF:4\nBRH:2\n\
+             DA:1,1\nDA:2,2\nDA:3,2\nDA:4,1\nDA:5,0\nDA:6,0\nLF:6\nLH:4\nend_of_record\n"
+        );
+    }
+
+    #[test]
+    fn test_listing() {
+        let (coverage, mut ram) = run();
+
+        let mut output = Vec::new();
+        coverage.write_listing(&mut output, &mut ram, 0x0400, 0x040a).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0], "       1  0400  A2 02     LDX #$02");
+        assert_eq!(lines[2], "       2  0403  D0 FD     BNE $0402  ; taken 1, not taken 1");
+        assert_eq!(lines[4], "       -  0408  FF        .BYTE $FF");
+        assert_eq!(lines[6], "       -  040A  F0 00     BEQ $040C");
+    }
+
+    fn trap(_cpu: &mut MOS6502, _interface: &mut dyn Interface6502, _address: Option<u16>) {}
+
+    /// Replaces BNE with an implied opcode
+    const TABLE: OpcodeTable = OpcodeTable::new().with_opcode(
+        0xd0,
+        crate::CustomOpcode {
+            name: "trp",
+            function: trap,
+            address_mode: AddressMode::Implied,
+            cycles: 4,
+        },
+    );
+
+    #[test]
+    fn test_custom_opcodes() {
+        // TRP; NOP
+        let mut ram = TestRam::with_program(0x0400, &[0xd0, 0xea]);
+        let mut cpu = MOS6502::new_start(0x0400).with_opcode_table(Arc::new(TABLE));
+        let mut coverage = Coverage::new().with_opcode_table(Arc::new(TABLE));
+        cpu.execute_instruction_traced(&mut ram, &mut coverage);
+        cpu.execute_instruction_traced(&mut ram, &mut coverage);
+
+        assert_eq!(coverage.branch(0x0400), None);
+        let mut output = Vec::new();
+        coverage.write_listing(&mut output, &mut ram, 0x0400, 0x0401).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "       1  0400  D0        TRP\n       1  0401  EA        NOP\n"
+        );
+
+        coverage.clear();
+        assert_eq!(coverage.opcode_table.as_deref(), Some(&TABLE));
+    }
+
+    #[test]
+    fn test_custom_branch_cycles() {
+        // A relative opcode taking 3 cycles that never branches
+        let table = OpcodeTable::new().with_opcode(
+            0x02,
+            crate::CustomOpcode {
+                name: "nbr",
+                function: trap,
+                address_mode: AddressMode::Relative,
+                cycles: 3,
+            },
+        );
+        let mut ram = TestRam::with_program(0x0400, &[0x02, 0x10]);
+        let mut cpu = MOS6502::new_start(0x0400).with_opcode_table(Arc::new(table.clone()));
+        let mut coverage = Coverage::new().with_opcode_table(Arc::new(table));
+        cpu.execute_instruction_traced(&mut ram, &mut coverage);
+
+        assert_eq!(coverage.branch(0x0400), Some(BranchCoverage { taken: 0, not_taken: 1 }));
+    }
+}
@@ -0,0 +1,254 @@
+//! ### CPU STATE
+//! This module contains a plain struct capturing every part of the processor's state, and a status
+//! flags type with named bits, for frontends and debuggers that need to inspect or modify the processor
+//! without the "implementation_transparency" feature.
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let mut state = cpu.state();
+//! if state.status.contains(StatusFlags::CARRY) {
+//!     state.accumulator = 0xff;
+//! }
+//! state.status.set(StatusFlags::INTERRUPT_DISABLE, false);
+//! cpu.set_state(&state);
+//! ```
+
+use super::MOS6502;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::{BitAnd, BitOr, Not};
+
+/// The flags of the 6502's status register
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StatusFlags(u8);
+
+impl StatusFlags {
+    /// Set when an addition carried or a subtraction did not borrow
+    pub const CARRY: StatusFlags = StatusFlags(0b0000_0001);
+    /// Set when the result of an operation was zero
+    pub const ZERO: StatusFlags = StatusFlags(0b0000_0010);
+    /// Set to prevent interrupt requests from being serviced
+    pub const INTERRUPT_DISABLE: StatusFlags = StatusFlags(0b0000_0100);
+    /// Set to make addition and subtraction use Binary Coded Decimal
+    pub const DECIMAL: StatusFlags = StatusFlags(0b0000_1000);
+    /// Set in the copy of the status register pushed by BRK and PHP, but not by interrupts
+    pub const BREAK: StatusFlags = StatusFlags(0b0001_0000);
+    /// Unused bit, which always reads as set on the original hardware
+    pub const UNUSED: StatusFlags = StatusFlags(0b0010_0000);
+    /// Set when an operation overflowed the signed range
+    pub const OVERFLOW: StatusFlags = StatusFlags(0b0100_0000);
+    /// Set when bit 7 of the result of an operation was set
+    pub const NEGATIVE: StatusFlags = StatusFlags(0b1000_0000);
+
+    /// Creates status flags from the value of the status register
+    pub const fn from_bits(bits: u8) -> Self {
+        StatusFlags(bits)
+    }
+
+    /// Returns the value of the status register
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Returns true if every flag in `flags` is set
+    pub fn contains(self, flags: StatusFlags) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+
+    /// Sets the given flags
+    pub fn insert(&mut self, flags: StatusFlags) {
+        self.0 |= flags.0;
+    }
+
+    /// Clears the given flags
+    pub fn remove(&mut self, flags: StatusFlags) {
+        self.0 &= !flags.0;
+    }
+
+    /// Sets or clears the given flags
+    pub fn set(&mut self, flags: StatusFlags, value: bool) {
+        if value {
+            self.insert(flags)
+        } else {
+            self.remove(flags)
+        }
+    }
+}
+
+impl BitOr for StatusFlags {
+    type Output = StatusFlags;
+
+    fn bitor(self, other: StatusFlags) -> StatusFlags {
+        StatusFlags(self.0 | other.0)
+    }
+}
+
+impl BitAnd for StatusFlags {
+    type Output = StatusFlags;
+
+    fn bitand(self, other: StatusFlags) -> StatusFlags {
+        StatusFlags(self.0 & other.0)
+    }
+}
+
+impl Not for StatusFlags {
+    type Output = StatusFlags;
+
+    fn not(self) -> StatusFlags {
+        StatusFlags(!self.0)
+    }
+}
+
+impl From<u8> for StatusFlags {
+    fn from(bits: u8) -> Self {
+        StatusFlags(bits)
+    }
+}
+
+impl From<StatusFlags> for u8 {
+    fn from(flags: StatusFlags) -> Self {
+        flags.0
+    }
+}
+
+impl fmt::Display for StatusFlags {
+    /// Formats the flags as `NV-BDIZC`, with set flags in uppercase and clear flags in lowercase
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (bit, name) in "NV-BDIZC".chars().enumerate() {
+            let set = self.0 & (0x80 >> bit) != 0;
+            match (name, set) {
+                ('-', _) => write!(f, "-")?,
+                (name, true) => write!(f, "{}", name)?,
+                (name, false) => write!(f, "{}", name.to_ascii_lowercase())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every part of the processor's state
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CpuState {
+    /// The accumulator register
+    pub accumulator: u8,
+    /// The x register
+    pub x_register: u8,
+    /// The y register
+    pub y_register: u8,
+    /// Pointer to the instruction that will be executed next
+    pub program_counter: u16,
+    /// Pointer to the top of the stack
+    pub stack_pointer: u8,
+    /// The status register
+    pub status: StatusFlags,
+    /// The number of cycles before the next opcode is run
+    pub remaining_cycles: u8,
+    /// The total number of cycles that have passed during program execution
+    pub total_cycles: u64,
+    /// Whether a non-maskable interrupt request is waiting to be serviced
+    pub pending_nmi: bool,
+    /// Whether an interrupt request is waiting to be serviced
+    pub pending_irq: bool,
+    /// Whether the processor has been halted by a KIL opcode
+    pub jammed: bool,
+}
+
+impl MOS6502 {
+    /// Returns a copy of every part of the processor's state
+    pub fn state(&self) -> CpuState {
+        CpuState {
+            accumulator: self.accumulator,
+            x_register: self.x_register,
+            y_register: self.y_register,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            status: StatusFlags(self.status_register),
+            remaining_cycles: self.remaining_cycles,
+            total_cycles: self.total_cycles,
+            pending_nmi: self.pending_nmi,
+            pending_irq: self.pending_irq,
+            jammed: self.jammed,
+        }
+    }
+
+    /// Replaces every part of the processor's state, keeping any table of custom opcodes
+    pub fn set_state(&mut self, state: &CpuState) {
+        *self = MOS6502 {
+            opcode_table: self.opcode_table.take(),
+            ..MOS6502::from(*state)
+        };
+    }
+
+    /// Returns the flags of the status register
+    pub fn status_flags(&self) -> StatusFlags {
+        StatusFlags(self.status_register)
+    }
+
+    /// Returns the total number of cycles that have passed during program execution
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+}
+
+impl From<CpuState> for MOS6502 {
+    fn from(state: CpuState) -> Self {
+        MOS6502 {
+            accumulator: state.accumulator,
+            x_register: state.x_register,
+            y_register: state.y_register,
+            program_counter: state.program_counter,
+            stack_pointer: state.stack_pointer,
+            status_register: state.status.0,
+            remaining_cycles: state.remaining_cycles,
+            total_cycles: state.total_cycles,
+            pending_nmi: state.pending_nmi,
+            pending_irq: state.pending_irq,
+            jammed: state.jammed,
+            opcode_table: None,
+        }
+    }
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_status_flags() {
+        let mut flags = StatusFlags::CARRY | StatusFlags::NEGATIVE;
+        assert!(flags.contains(StatusFlags::CARRY));
+        assert!(!flags.contains(StatusFlags::CARRY | StatusFlags::ZERO));
+
+        flags.set(StatusFlags::ZERO, true);
+        flags.remove(StatusFlags::CARRY);
+        assert_eq!(flags.bits(), 0b1000_0010);
+        assert_eq!(flags.to_string(), "Nv-bdiZc");
+        assert_eq!(StatusFlags::from_bits(0xff).to_string(), "NV-BDIZC");
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let mut cpu = MOS6502::new_start(0xc000);
+        cpu.interrupt_request();
+
+        let mut state = cpu.state();
+        assert_eq!(state.program_counter, 0xc000);
+        assert!(state.pending_irq);
+        assert!(state.status.contains(StatusFlags::INTERRUPT_DISABLE | StatusFlags::UNUSED));
+
+        state.accumulator = 0x42;
+        state.status.insert(StatusFlags::CARRY);
+        state.total_cycles = 1000;
+        cpu.set_state(&state);
+
+        assert_eq!(cpu.accumulator, 0x42);
+        assert!(cpu.status_flags().contains(StatusFlags::CARRY));
+        assert_eq!(cpu.total_cycles(), 1000);
+        assert_eq!(cpu.state(), state);
+    }
+}
@@ -0,0 +1,320 @@
+//! ### DISASSEMBLER
+//! This module contains functions for turning machine code back into 6502 assembly, using the same
+//! opcode table the processor executes from. Processors with custom opcodes are disassembled by passing
+//! their table to `disassemble_with_table`.
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! for instruction in disassemble_range(&mut ram, 0xc000, 0xc010) {
+//!     println!("{:04X}  {}", instruction.address, instruction);
+//! }
+//! ```
+
+use super::address_modes::AddressMode;
+use super::opcodes::{OpcodeTable, BUILT_IN_TABLE};
+use super::symbols::SymbolTable;
+use super::Interface6502;
+use std::fmt;
+
+/// A single disassembled instruction
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DisassembledInstruction {
+    /// The address of the opcode
+    pub address: u16,
+    /// The opcode byte
+    pub opcode: u8,
+    /// The lowercase mnemonic of the instruction
+    pub name: &'static str,
+    /// The address mode the instruction uses
+    pub address_mode: AddressMode,
+    /// The operand bytes following the opcode, only the first `address_mode.operand_length()` of which are valid
+    pub operand: [u8; 2],
+    /// Whether the opcode is an illegal opcode outside of the documented instruction set
+    pub illegal: bool,
+    /// The bank selected at the address when it was disassembled, if the address is bank switched
+    pub bank: Option<usize>,
+}
+
+impl DisassembledInstruction {
+    /// Creates a disassembled instruction from its opcode and the bytes that follow it, decoded with the table
+    fn new(address: u16, opcode: u8, operand: [u8; 2], table: &OpcodeTable) -> Self {
+        let address_mode = table.address_mode(opcode);
+        let mut operand = operand;
+        for byte in operand.iter_mut().skip(address_mode.operand_length() as usize) {
+            *byte = 0;
+        }
+        DisassembledInstruction {
+            address,
+            opcode,
+            name: table.name(opcode),
+            address_mode,
+            operand,
+            illegal: table.is_illegal(opcode),
+            bank: None,
+        }
+    }
+
+    /// Returns the number of bytes the instruction occupies, including the opcode
+    pub fn length(&self) -> u8 {
+        1 + self.address_mode.operand_length()
+    }
+
+    /// Returns the valid operand bytes of the instruction
+    pub fn operand_bytes(&self) -> &[u8] {
+        &self.operand[..self.address_mode.operand_length() as usize]
+    }
+
+    /// Returns the address of the instruction following this one
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(u16::from(self.length()))
+    }
+
+    /// Returns the address the operand refers to before any indexing or indirection, or the destination
+    /// of a branch. Immediate and implied instructions have no target
+    pub fn target(&self) -> Option<u16> {
+        let word = u16::from_le_bytes(self.operand);
+        return match self.address_mode {
+            AddressMode::Implied | AddressMode::Immediate => None,
+            AddressMode::Relative => Some(self.next_address().wrapping_add(self.operand[0] as i8 as u16)),
+            AddressMode::IndirectX
+            | AddressMode::IndirectY
+            | AddressMode::IndirectYConst
+            | AddressMode::ZeroPage
+            | AddressMode::ZeroPageX
+            | AddressMode::ZeroPageY => Some(u16::from(self.operand[0])),
+            AddressMode::Absolute
+            | AddressMode::AbsoluteX
+            | AddressMode::AbsoluteXConst
+            | AddressMode::AbsoluteY
+            | AddressMode::AbsoluteYConst
+            | AddressMode::Indirect => Some(word),
+        };
+    }
+
+    /// Returns true if the instruction is a conditional branch
+    pub fn is_branch(&self) -> bool {
+        self.address_mode == AddressMode::Relative
+    }
+
+    /// Formats the operand, using the given text in place of the target address
+    pub(crate) fn format_operand(&self, target: &str) -> String {
+        return match self.address_mode {
+            AddressMode::Implied => match self.name {
+                "asl" | "lsr" | "rol" | "ror" => "A".to_string(),
+                _ => String::new(),
+            },
+            AddressMode::Immediate => format!("#${:02X}", self.operand[0]),
+            AddressMode::Absolute | AddressMode::Relative | AddressMode::ZeroPage => target.to_string(),
+            AddressMode::AbsoluteX | AddressMode::AbsoluteXConst | AddressMode::ZeroPageX => format!("{},X", target),
+            AddressMode::AbsoluteY | AddressMode::AbsoluteYConst | AddressMode::ZeroPageY => format!("{},Y", target),
+            AddressMode::Indirect => format!("({})", target),
+            AddressMode::IndirectX => format!("({},X)", target),
+            AddressMode::IndirectY | AddressMode::IndirectYConst => format!("({}),Y", target),
+        };
+    }
+
+    /// Formats the target address as hexadecimal, using two digits for zero page operands
+    pub(crate) fn format_target(&self) -> String {
+        return match (self.address_mode.operand_length(), self.target()) {
+            (1, Some(target)) if !self.is_branch() => format!("${:02X}", target),
+            (_, Some(target)) => format!("${:04X}", target),
+            (_, None) => String::new(),
+        };
+    }
+
+    /// Formats the instruction like its `Display` implementation, but with the target address replaced by
+    /// its name when it has one in the symbol table
+    pub fn to_string_with_symbols(&self, symbols: &SymbolTable) -> String {
+        let target = match self.target().and_then(|target| symbols.name(target)) {
+            Some(name) => name.to_string(),
+            None => self.format_target(),
+        };
+        let operand = self.format_operand(&target);
+        if operand.is_empty() {
+            self.name.to_uppercase()
+        } else {
+            format!("{} {}", self.name.to_uppercase(), operand)
+        }
+    }
+
+    /// Formats the address of the instruction as hexadecimal, prefixed by the bank number if it has one
+    pub fn format_address(&self) -> String {
+        return match self.bank {
+            Some(bank) => format!("{:02X}:{:04X}", bank, self.address),
+            None => format!("{:04X}", self.address),
+        };
+    }
+
+    /// Formats the raw bytes of the instruction as hexadecimal, separated by spaces
+    pub fn format_bytes(&self) -> String {
+        return self
+            .operand_bytes()
+            .iter()
+            .fold(format!("{:02X}", self.opcode), |bytes, byte| format!("{} {:02X}", bytes, byte));
+    }
+}
+
+impl fmt::Display for DisassembledInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operand = self.format_operand(&self.format_target());
+        if operand.is_empty() {
+            write!(f, "{}", self.name.to_uppercase())
+        } else {
+            write!(f, "{} {}", self.name.to_uppercase(), operand)
+        }
+    }
+}
+
+/// Disassembles the instruction at an address, reading it and the bank selected at the address from the interface.
+///
+/// NOTE: The opcode and operand bytes are read through the interface, which may have side effects for memory mapped devices
+pub fn disassemble(interface: &mut dyn Interface6502, address: u16) -> DisassembledInstruction {
+    return disassemble_with_table(interface, address, None);
+}
+
+/// Disassembles the instruction at an address like `disassemble`, decoding it with the opcode table of a processor
+/// with custom opcodes, as returned by `MOS6502::opcode_table`. The built-in table is used when there is none
+pub fn disassemble_with_table(interface: &mut dyn Interface6502, address: u16, table: Option<&OpcodeTable>) -> DisassembledInstruction {
+    let table = table.unwrap_or(&BUILT_IN_TABLE);
+    let opcode = interface.read(address);
+    let mut operand = [0; 2];
+    for index in 0..table.address_mode(opcode).operand_length() {
+        operand[index as usize] = interface.read(address.wrapping_add(1 + u16::from(index)));
+    }
+    return DisassembledInstruction {
+        bank: interface.bank(address),
+        ..DisassembledInstruction::new(address, opcode, operand, table)
+    };
+}
+
+/// Disassembles the instruction at the start of a byte slice that would be located at the given address,
+/// returning None if the slice ends before the instruction does
+pub fn disassemble_bytes(bytes: &[u8], address: u16) -> Option<DisassembledInstruction> {
+    let opcode = *bytes.first()?;
+    let length = BUILT_IN_TABLE.address_mode(opcode).operand_length() as usize;
+    let mut operand = [0; 2];
+    operand[..length].clone_from_slice(bytes.get(1..=length)?);
+    return Some(DisassembledInstruction::new(address, opcode, operand, &BUILT_IN_TABLE));
+}
+
+/// Disassembles every instruction beginning between the start and end addresses, inclusive
+pub fn disassemble_range(interface: &mut dyn Interface6502, start: u16, end: u16) -> Vec<DisassembledInstruction> {
+    let mut instructions = Vec::new();
+    let mut address = u32::from(start);
+    while address <= u32::from(end) {
+        let instruction = disassemble(interface, address as u16);
+        address += u32::from(instruction.length());
+        instructions.push(instruction);
+    }
+    return instructions;
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::banking::Uxrom;
+    use crate::bus::BusBuilder;
+    use crate::test_utilities::TestRam;
+
+    fn text(bytes: &[u8], address: u16) -> String {
+        disassemble_bytes(bytes, address).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_address_modes() {
+        assert_eq!(text(&[0xa9, 0x10], 0), "LDA #$10");
+        assert_eq!(text(&[0xa5, 0x10], 0), "LDA $10");
+        assert_eq!(text(&[0xb5, 0x10], 0), "LDA $10,X");
+        assert_eq!(text(&[0xb6, 0x10], 0), "LDX $10,Y");
+        assert_eq!(text(&[0xad, 0x34, 0x12], 0), "LDA $1234");
+        assert_eq!(text(&[0xbd, 0x34, 0x12], 0), "LDA $1234,X");
+        assert_eq!(text(&[0x99, 0x34, 0x12], 0), "STA $1234,Y");
+        assert_eq!(text(&[0x6c, 0x34, 0x12], 0), "JMP ($1234)");
+        assert_eq!(text(&[0xa1, 0x10], 0), "LDA ($10,X)");
+        assert_eq!(text(&[0xb1, 0x10], 0), "LDA ($10),Y");
+        assert_eq!(text(&[0x0a], 0), "ASL A");
+        assert_eq!(text(&[0xea], 0), "NOP");
+    }
+
+    #[test]
+    fn test_branch_target() {
+        let instruction = disassemble_bytes(&[0xd0, 0xfe], 0x0400).unwrap();
+        assert!(instruction.is_branch());
+        assert_eq!(instruction.target(), Some(0x0400));
+        assert_eq!(instruction.to_string(), "BNE $0400");
+        assert_eq!(text(&[0x10, 0x10], 0x0400), "BPL $0412");
+    }
+
+    #[test]
+    fn test_symbols() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("init_ppu", 0xc123);
+        symbols.insert("frame", 0x0010);
+
+        let text = |bytes: &[u8]| disassemble_bytes(bytes, 0xc120).unwrap().to_string_with_symbols(&symbols);
+        assert_eq!(text(&[0x20, 0x23, 0xc1]), "JSR init_ppu");
+        assert_eq!(text(&[0xd0, 0x01]), "BNE init_ppu");
+        assert_eq!(text(&[0xb1, 0x10]), "LDA (frame),Y");
+        assert_eq!(text(&[0xa9, 0x10]), "LDA #$10");
+        assert_eq!(text(&[0xad, 0x00, 0x20]), "LDA $2000");
+    }
+
+    #[test]
+    fn test_truncated_bytes() {
+        assert_eq!(disassemble_bytes(&[0xad, 0x34], 0), None);
+        assert_eq!(disassemble_bytes(&[], 0), None);
+    }
+
+    #[test]
+    fn test_disassemble_range() {
+        let mut ram = TestRam::with_program(0x0400, &[0xa2, 0x05, 0xe8, 0x4c, 0x00, 0x04]);
+
+        let instructions = disassemble_range(&mut ram, 0x0400, 0x0403);
+
+        assert_eq!(instructions.len(), 3);
+        assert_eq!(instructions[2].address, 0x0403);
+        assert_eq!(instructions[2].format_bytes(), "4C 00 04");
+        assert_eq!(instructions[2].to_string(), "JMP $0400");
+        assert_eq!(instructions[2].format_address(), "0403");
+        assert!(!instructions[2].illegal);
+    }
+
+    fn trap(_cpu: &mut crate::MOS6502, _interface: &mut dyn Interface6502, _address: Option<u16>) {}
+
+    const TABLE: OpcodeTable = OpcodeTable::new().with_opcode(
+        0x22,
+        crate::CustomOpcode {
+            name: "trp",
+            function: trap,
+            address_mode: AddressMode::Immediate,
+            cycles: 2,
+        },
+    );
+
+    #[test]
+    fn test_custom_opcodes() {
+        let mut ram = TestRam::with_program(0x0400, &[0x22, 0x07, 0xea]);
+
+        let instruction = disassemble_with_table(&mut ram, 0x0400, Some(&TABLE));
+        assert_eq!(instruction.to_string(), "TRP #$07");
+        assert_eq!(instruction.length(), 2);
+        assert!(!instruction.illegal);
+        let instruction = disassemble(&mut ram, 0x0400);
+        assert_eq!(instruction.length(), 1);
+        assert!(instruction.illegal);
+    }
+
+    #[test]
+    fn test_bank() {
+        let mut builder = BusBuilder::new();
+        let cartridge = builder.add_device(Uxrom::new(vec![0xea; 0x10000]));
+        builder.map(0x8000..=0xffff, &cartridge);
+        let mut bus = builder.build();
+        bus.write(0x8000, 2);
+
+        assert_eq!(disassemble(&mut bus, 0x8000).format_address(), "02:8000");
+        assert_eq!(disassemble(&mut bus, 0xc000).format_address(), "03:C000");
+    }
+}
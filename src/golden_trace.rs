@@ -0,0 +1,449 @@
+//! ### GOLDEN TRACE
+//! This module contains tools for comparing the execution of the emulator against a reference
+//! ("golden") trace log produced by another emulator or by real hardware.
+//!
+//! The program is run instruction by instruction, and before each instruction the state of the
+//! processor is compared against the next line of the reference log. Execution stops at the first
+//! divergence, and the returned error holds a window of the preceding instructions for context.
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let reference = BufReader::new(File::open("nestest.log")?);
+//! let mut cpu = MOS6502::new_start(0xc000);
+//!
+//! match GoldenTrace::new(TraceFormat::Nestest).run(&mut cpu, &mut ram, reference) {
+//!     Ok(instructions) => println!("Matched {} instructions", instructions),
+//!     Err(error) => println!("{}", error),
+//! }
+//! ```
+
+use super::cpu_state::{CpuState, StatusFlags};
+use super::{Interface6502, MOS6502};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead};
+
+/// The reference log formats that can be compared against
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TraceFormat {
+    /// The format of the widely used nestest.log, e.g.
+    /// `C000  4C F5 C5  JMP $C5F5    A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7`
+    Nestest,
+    /// The format of the VICE monitor's cpu history, e.g.
+    /// `.C:c000  4C F5 C5    JMP $C5F5      - A:00 X:00 Y:00 SP:fd ..-..I..   7`
+    Vice,
+    /// The format of MAME's `trace` command with a register `tracelog`, e.g.
+    /// `A=00 X=00 Y=00 P=24 SP=FD C000: jmp $c5f5`
+    Mame,
+}
+
+/// Formats the registers of a state the way they are compared, followed by the cycle count if it is known
+fn format_state(state: &CpuState, cycles: Option<u64>) -> String {
+    let registers = format!(
+        "{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+        state.program_counter,
+        state.accumulator,
+        state.x_register,
+        state.y_register,
+        state.status.bits(),
+        state.stack_pointer
+    );
+    return match cycles {
+        Some(cycles) => format!("{} CYC:{}", registers, cycles),
+        None => registers,
+    };
+}
+
+impl TraceFormat {
+    /// Parses a single line of a reference log, returning the registers before the instruction and the total
+    /// number of cycles if the log records them, or None if the line is not a valid trace line. Parts of the state
+    /// that logs don't record are left at their defaults
+    pub fn parse_line(self, line: &str) -> Option<(CpuState, Option<u64>)> {
+        let mut tokens = line.split_whitespace();
+        let program_counter = match self {
+            TraceFormat::Nestest => parse_hex_16(tokens.next()?)?,
+            TraceFormat::Vice => {
+                let token = tokens.next()?;
+                // The memory space prefix (".C:") is optional
+                parse_hex_16(token.rsplit(':').next()?)?
+            }
+            TraceFormat::Mame => parse_hex_16(
+                line.split_whitespace()
+                    .find(|token| token.len() == 5 && token.ends_with(':'))?
+                    .trim_end_matches(':'),
+            )?,
+        };
+
+        let mut accumulator = None;
+        let mut x_register = None;
+        let mut y_register = None;
+        let mut stack_pointer = None;
+        let mut status_register = None;
+        let mut cycles = None;
+
+        let separator = if self == TraceFormat::Mame { '=' } else { ':' };
+        for token in tokens.by_ref() {
+            if let Some(index) = token.find(separator) {
+                let (key, value) = (&token[..index], &token[index + 1..]);
+                match key {
+                    "A" => accumulator = parse_hex_8(value),
+                    "X" => x_register = parse_hex_8(value),
+                    "Y" => y_register = parse_hex_8(value),
+                    "P" => status_register = parse_hex_8(value),
+                    "S" | "SP" => stack_pointer = parse_hex_8(value),
+                    "CYC" => cycles = value.parse().ok(),
+                    _ => {}
+                }
+            } else if self == TraceFormat::Vice && stack_pointer.is_some() && status_register.is_none() {
+                // VICE prints the status register as a string of flag letters directly after the stack pointer
+                status_register = parse_flag_string(token);
+            } else if self == TraceFormat::Vice && status_register.is_some() {
+                cycles = token.parse().ok();
+            }
+        }
+
+        let state = CpuState {
+            program_counter,
+            accumulator: accumulator?,
+            x_register: x_register?,
+            y_register: y_register?,
+            stack_pointer: stack_pointer?,
+            status: StatusFlags::from_bits(status_register?),
+            ..CpuState::default()
+        };
+        return Some((state, cycles));
+    }
+}
+
+/// Parses a status register from a string of flag letters in NV-BDIZC order, where '.' represents a cleared flag
+fn parse_flag_string(flags: &str) -> Option<u8> {
+    if flags.len() != 8 {
+        return None;
+    }
+    return Some(flags.chars().fold(0, |status, flag| (status << 1) | (flag != '.') as u8));
+}
+
+fn parse_hex_16(value: &str) -> Option<u16> {
+    if value.len() != 4 {
+        return None;
+    }
+    return u16::from_str_radix(value, 16).ok();
+}
+
+fn parse_hex_8(value: &str) -> Option<u8> {
+    return u8::from_str_radix(value, 16).ok();
+}
+
+/// Compares the execution of the emulator against a reference trace log
+#[derive(Debug, Clone)]
+pub struct GoldenTrace {
+    format: TraceFormat,
+    context: usize,
+    compare_cycles: bool,
+    status_mask: u8,
+}
+
+impl GoldenTrace {
+    /// Creates a new comparison for logs of the given format that compares all registers and cycle counts,
+    /// keeping 10 instructions of context
+    pub fn new(format: TraceFormat) -> Self {
+        GoldenTrace {
+            format,
+            context: 10,
+            compare_cycles: true,
+            status_mask: 0xff,
+        }
+    }
+
+    /// Sets the number of instructions preceding a divergence that are kept for context
+    pub fn context(mut self, context: usize) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Sets whether cycle counts should be compared when the reference log records them.
+    ///
+    /// Cycle counts are compared relative to the first line of the log, so logs that begin counting
+    /// from a reset sequence (nestest.log begins at 7) can be compared against a fresh processor
+    pub fn compare_cycles(mut self, compare_cycles: bool) -> Self {
+        self.compare_cycles = compare_cycles;
+        self
+    }
+
+    /// Sets a mask applied to both status registers before they are compared, for ignoring bits
+    /// such as the break flag that are not consistently reported by other emulators
+    pub fn status_mask(mut self, status_mask: u8) -> Self {
+        self.status_mask = status_mask;
+        self
+    }
+
+    /// Runs the processor against the reference log until the log is exhausted or a divergence is found,
+    /// returning the number of instructions that matched
+    pub fn run(&self, cpu: &mut MOS6502, interface: &mut dyn Interface6502, reference: impl BufRead) -> Result<u64, GoldenTraceError> {
+        let mut history: VecDeque<ContextLine> = VecDeque::with_capacity(self.context + 1);
+        let mut cycle_start: Option<(u64, u64)> = None;
+        let mut instructions = 0;
+
+        for (index, line) in reference.lines().enumerate() {
+            let line = line.map_err(GoldenTraceError::Io)?;
+            let line_number = index + 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (expected, expected_cycles) = match self.format.parse_line(&line) {
+                Some(parsed) => parsed,
+                None => return Err(GoldenTraceError::Parse { line_number, line }),
+            };
+            let actual = cpu.state();
+
+            if !self.matches(&expected, expected_cycles, &actual, &mut cycle_start) {
+                return Err(GoldenTraceError::Divergence(Box::new(Divergence {
+                    line_number,
+                    line,
+                    expected,
+                    expected_cycles,
+                    actual,
+                    context: history.into_iter().collect(),
+                })));
+            }
+
+            if self.context > 0 {
+                if history.len() == self.context {
+                    history.pop_front();
+                }
+                history.push_back(ContextLine { line_number, line, actual });
+            }
+
+            cpu.execute_instruction(interface);
+            instructions += 1;
+        }
+
+        return Ok(instructions);
+    }
+
+    /// Checks whether the emulator's state matches the expected state
+    fn matches(&self, expected: &CpuState, expected_cycles: Option<u64>, actual: &CpuState, cycle_start: &mut Option<(u64, u64)>) -> bool {
+        let registers_match = expected.program_counter == actual.program_counter
+            && expected.accumulator == actual.accumulator
+            && expected.x_register == actual.x_register
+            && expected.y_register == actual.y_register
+            && expected.stack_pointer == actual.stack_pointer
+            && expected.status.bits() & self.status_mask == actual.status.bits() & self.status_mask;
+
+        let cycles_match = match (self.compare_cycles, expected_cycles) {
+            (true, Some(expected_cycles)) => {
+                let (expected_start, actual_start) = *cycle_start.get_or_insert((expected_cycles, actual.total_cycles));
+                expected_cycles.wrapping_sub(expected_start) == actual.total_cycles.wrapping_sub(actual_start)
+            }
+            _ => true,
+        };
+
+        return registers_match && cycles_match;
+    }
+}
+
+/// A previously matched instruction, kept to give context to a divergence
+#[derive(Debug, Clone)]
+pub struct ContextLine {
+    /// The line number in the reference log
+    pub line_number: usize,
+    /// The text of the line in the reference log
+    pub line: String,
+    /// The state of the emulator before the instruction was executed
+    pub actual: CpuState,
+}
+
+/// Description of the first point at which the emulator and the reference log disagreed
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    /// The line number in the reference log
+    pub line_number: usize,
+    /// The text of the line in the reference log
+    pub line: String,
+    /// The registers recorded in the reference log
+    pub expected: CpuState,
+    /// The total number of cycles recorded in the reference log, if it records them
+    pub expected_cycles: Option<u64>,
+    /// The state of the emulator
+    pub actual: CpuState,
+    /// The instructions preceding the divergence, oldest first
+    pub context: Vec<ContextLine>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Divergence from reference log at line {}", self.line_number)?;
+        for context_line in &self.context {
+            writeln!(f, "  {:>6}: {}", context_line.line_number, context_line.line)?;
+        }
+        writeln!(f, "> {:>6}: {}", self.line_number, self.line)?;
+        writeln!(f, "Expected: {}", format_state(&self.expected, self.expected_cycles))?;
+        write!(f, "Actual:   {}", format_state(&self.actual, Some(self.actual.total_cycles)))
+    }
+}
+
+/// Errors that can end a golden trace comparison
+#[derive(Debug)]
+pub enum GoldenTraceError {
+    /// The reference log could not be read
+    Io(io::Error),
+    /// A line of the reference log could not be parsed in the expected format
+    Parse {
+        /// The line number in the reference log
+        line_number: usize,
+        /// The text of the line that could not be parsed
+        line: String,
+    },
+    /// The emulator's state did not match the reference log
+    Divergence(Box<Divergence>),
+}
+
+impl fmt::Display for GoldenTraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GoldenTraceError::Io(error) => write!(f, "Failed to read reference log: {}", error),
+            GoldenTraceError::Parse { line_number, line } => write!(f, "Failed to parse reference log line {}: {}", line_number, line),
+            GoldenTraceError::Divergence(divergence) => divergence.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for GoldenTraceError {}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utilities::TestRam;
+
+    const PROGRAM: [u8; 5] = [
+        0xa9, 0x01, // LDA #$01
+        0xaa, // TAX
+        0xe8, // INX
+        0xea, // NOP
+    ];
+
+    #[test]
+    fn test_parse_nestest() {
+        let record = TraceFormat::Nestest.parse_line("C000  4C F5 C5  JMP $C5F5                       A:00 X:01 Y:02 P:24 SP:FD PPU:  0, 21 CYC:7");
+        assert_eq!(
+            record,
+            Some((
+                CpuState {
+                    program_counter: 0xc000,
+                    accumulator: 0x00,
+                    x_register: 0x01,
+                    y_register: 0x02,
+                    stack_pointer: 0xfd,
+                    status: StatusFlags::from_bits(0x24),
+                    ..CpuState::default()
+                },
+                Some(7)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_vice() {
+        let record = TraceFormat::Vice.parse_line(".C:e5cf  F0 F7       BEQ $E5C8       - A:00 X:00 Y:0A SP:f3 ..-..IZ.   88327102");
+        assert_eq!(
+            record,
+            Some((
+                CpuState {
+                    program_counter: 0xe5cf,
+                    accumulator: 0x00,
+                    x_register: 0x00,
+                    y_register: 0x0a,
+                    stack_pointer: 0xf3,
+                    status: StatusFlags::from_bits(0x26),
+                    ..CpuState::default()
+                },
+                Some(88327102)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_mame() {
+        let record = TraceFormat::Mame.parse_line("A=10 X=00 Y=00 P=A4 SP=FB C004: sta $0200");
+        assert_eq!(
+            record,
+            Some((
+                CpuState {
+                    program_counter: 0xc004,
+                    accumulator: 0x10,
+                    x_register: 0x00,
+                    y_register: 0x00,
+                    stack_pointer: 0xfb,
+                    status: StatusFlags::from_bits(0xa4),
+                    ..CpuState::default()
+                },
+                None
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert_eq!(TraceFormat::Nestest.parse_line("C000  4C F5 C5  JMP $C5F5  A:00 X:00"), None);
+        assert_eq!(TraceFormat::Nestest.parse_line("Hello friends"), None);
+    }
+
+    #[test]
+    fn test_run_matching() {
+        let reference = "0400  A9 01     LDA #$01  A:00 X:00 Y:00 P:24 SP:FD CYC:7\n\
+                         0402  AA        TAX       A:01 X:00 Y:00 P:24 SP:FD CYC:9\n\
+                         0403  E8        INX       A:01 X:01 Y:00 P:24 SP:FD CYC:11\n";
+        let mut ram = TestRam::with_program(0x0400, &PROGRAM);
+        let mut cpu = MOS6502::new_start(0x0400);
+
+        let result = GoldenTrace::new(TraceFormat::Nestest).run(&mut cpu, &mut ram, reference.as_bytes());
+
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(cpu.x_register, 0x02);
+    }
+
+    #[test]
+    fn test_run_divergence() {
+        let reference = "0400  A9 01     LDA #$01  A:00 X:00 Y:00 P:24 SP:FD CYC:7\n\
+                         0402  AA        TAX       A:01 X:00 Y:00 P:24 SP:FD CYC:9\n\
+                         0403  E8        INX       A:01 X:01 Y:00 P:24 SP:FD CYC:11\n\
+                         0404  EA        NOP       A:01 X:03 Y:00 P:24 SP:FD CYC:13\n";
+        let mut ram = TestRam::with_program(0x0400, &PROGRAM);
+        let mut cpu = MOS6502::new_start(0x0400);
+
+        let result = GoldenTrace::new(TraceFormat::Nestest)
+            .context(2)
+            .run(&mut cpu, &mut ram, reference.as_bytes());
+
+        if let Err(GoldenTraceError::Divergence(divergence)) = result {
+            assert_eq!(divergence.line_number, 4);
+            assert_eq!(divergence.expected.x_register, 0x03);
+            assert_eq!(divergence.actual.x_register, 0x02);
+            assert_eq!(divergence.context.len(), 2);
+            assert_eq!(divergence.context[0].line_number, 2);
+            assert!(divergence
+                .to_string()
+                .ends_with("Expected: 0404 A:01 X:03 Y:00 P:24 SP:FD CYC:13\nActual:   0404 A:01 X:02 Y:00 P:24 SP:FD CYC:6"));
+        } else {
+            panic!("Expected a divergence, got {:?}", result)
+        }
+    }
+
+    #[test]
+    fn test_run_cycle_divergence() {
+        let reference = "0400  A9 01     LDA #$01  A:00 X:00 Y:00 P:24 SP:FD CYC:7\n\
+                         0402  AA        TAX       A:01 X:00 Y:00 P:24 SP:FD CYC:10\n";
+        let mut ram = TestRam::with_program(0x0400, &PROGRAM);
+
+        let result = GoldenTrace::new(TraceFormat::Nestest).run(&mut MOS6502::new_start(0x0400), &mut ram, reference.as_bytes());
+        assert!(matches!(result, Err(GoldenTraceError::Divergence(_))));
+
+        let result =
+            GoldenTrace::new(TraceFormat::Nestest)
+                .compare_cycles(false)
+                .run(&mut MOS6502::new_start(0x0400), &mut ram, reference.as_bytes());
+        assert_eq!(result.unwrap(), 2);
+    }
+}
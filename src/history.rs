@@ -0,0 +1,304 @@
+//! ### HISTORY
+//! This module contains a tracer that records the most recently executed instructions in a ring buffer,
+//! for diagnosing how a program ended up in an unexpected state.
+//!
+//! The history can be dumped on demand, and is dumped automatically to the `log` crate's error level
+//! when the processor jams, executes an illegal opcode without the "illegal_opcodes" feature enabled,
+//! or reaches one of the history's breakpoints, before the instruction there is executed.
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let mut history = InstructionHistory::new(64);
+//! history.add_breakpoint(0xc123);
+//!
+//! while history.triggered().is_none() {
+//!     cpu.execute_instruction_traced(&mut ram, &mut history);
+//! }
+//! println!("{}", history);
+//! ```
+
+use super::tracer::{InstructionEvent, InterruptEvent, Tracer6502};
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+/// A single entry in the instruction history
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum HistoryEntry {
+    /// An executed instruction
+    Instruction(InstructionEvent),
+    /// A serviced interrupt
+    Interrupt(InterruptEvent),
+}
+
+impl fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            HistoryEntry::Instruction(event) => event.fmt(f),
+            HistoryEntry::Interrupt(event) => event.fmt(f),
+        };
+    }
+}
+
+/// The reasons the history can be dumped automatically
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DumpReason {
+    /// A KIL opcode at the given address jammed the processor
+    Jammed(u16),
+    /// An illegal opcode was executed at the given address without the "illegal_opcodes" feature enabled,
+    /// which usually means the program counter has wandered into data
+    IllegalOpcode(u16),
+    /// The program counter reached the given breakpoint address, before the instruction there was executed
+    Breakpoint(u16),
+}
+
+impl fmt::Display for DumpReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            DumpReason::Jammed(address) => write!(f, "processor jammed at {:04X}", address),
+            DumpReason::IllegalOpcode(address) => write!(f, "illegal opcode executed at {:04X}", address),
+            DumpReason::Breakpoint(address) => write!(f, "breakpoint hit at {:04X}", address),
+        };
+    }
+}
+
+/// Tracer recording the last N executed instructions and interrupts
+#[derive(Debug, Clone)]
+pub struct InstructionHistory {
+    entries: VecDeque<HistoryEntry>,
+    capacity: usize,
+    breakpoints: HashSet<u16>,
+    triggered: Option<DumpReason>,
+    log_dumps: bool,
+    started: bool,
+}
+
+impl InstructionHistory {
+    /// Creates a new history that keeps the given number of entries
+    pub fn new(capacity: usize) -> Self {
+        return InstructionHistory {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            breakpoints: HashSet::new(),
+            triggered: None,
+            log_dumps: true,
+            started: false,
+        };
+    }
+
+    /// Adds an address that will trigger a dump when the program counter reaches it, before the instruction there is
+    /// executed. A breakpoint at the address of the first traced instruction, such as the start or reset address,
+    /// triggers once that instruction has executed, as the history has not seen the processor before then
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Removes a breakpoint address
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.remove(&address);
+    }
+
+    /// Sets whether automatic dumps are written to the `log` crate, enabled by default
+    pub fn set_log_dumps(&mut self, log_dumps: bool) {
+        self.log_dumps = log_dumps;
+    }
+
+    /// Returns the reason for the most recent automatic dump, if one has occurred since the last call to `clear_trigger`
+    pub fn triggered(&self) -> Option<DumpReason> {
+        return self.triggered;
+    }
+
+    /// Clears the most recent automatic dump reason, so that execution can continue
+    pub fn clear_trigger(&mut self) {
+        self.triggered = None;
+    }
+
+    /// Returns the recorded entries, oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &HistoryEntry> {
+        return self.entries.iter();
+    }
+
+    /// Returns the number of recorded entries
+    pub fn len(&self) -> usize {
+        return self.entries.len();
+    }
+
+    /// Returns true if no entries have been recorded
+    pub fn is_empty(&self) -> bool {
+        return self.entries.is_empty();
+    }
+
+    /// Removes all recorded entries
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Returns the recorded entries as text, one per line, oldest first
+    pub fn dump(&self) -> String {
+        return self.to_string();
+    }
+
+    fn push(&mut self, entry: HistoryEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Checks the program counter the first traced event started from, which no earlier event ended at
+    fn start(&mut self, program_counter: u16) {
+        if !self.started {
+            self.started = true;
+            if self.breakpoints.contains(&program_counter) {
+                self.trigger(DumpReason::Breakpoint(program_counter));
+            }
+        }
+    }
+
+    fn trigger(&mut self, reason: DumpReason) {
+        self.triggered = Some(reason);
+        if self.log_dumps {
+            error!("Instruction history dump, {}:\n{}", reason, self);
+        }
+    }
+}
+
+impl Tracer6502 for InstructionHistory {
+    fn instruction(&mut self, event: &InstructionEvent) {
+        self.push(HistoryEntry::Instruction(*event));
+        self.start(event.program_counter);
+        if event.illegal && !cfg!(feature = "illegal_opcodes") {
+            self.trigger(DumpReason::IllegalOpcode(event.program_counter));
+        } else if self.breakpoints.contains(&event.after.program_counter) {
+            self.trigger(DumpReason::Breakpoint(event.after.program_counter));
+        }
+    }
+
+    fn interrupt(&mut self, event: &InterruptEvent) {
+        self.push(HistoryEntry::Interrupt(*event));
+        self.start(event.before.program_counter);
+        if self.breakpoints.contains(&event.after.program_counter) {
+            self.trigger(DumpReason::Breakpoint(event.after.program_counter));
+        }
+    }
+
+    fn jammed(&mut self, program_counter: u16) {
+        self.trigger(DumpReason::Jammed(program_counter));
+    }
+}
+
+impl fmt::Display for InstructionHistory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        return Ok(());
+    }
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utilities::TestRam;
+    use crate::MOS6502;
+
+    #[test]
+    fn test_ring_buffer() {
+        // INX; JMP $0400
+        let mut ram = TestRam::with_program(0x0400, &[0xe8, 0x4c, 0x00, 0x04]);
+        let mut cpu = MOS6502::new_start(0x0400);
+        let mut history = InstructionHistory::new(3);
+
+        for _ in 0..10 {
+            cpu.execute_instruction_traced(&mut ram, &mut history);
+        }
+
+        assert_eq!(history.len(), 3);
+        let addresses: Vec<u16> = history
+            .entries()
+            .map(|entry| match entry {
+                HistoryEntry::Instruction(event) => event.program_counter,
+                HistoryEntry::Interrupt(event) => event.before.program_counter,
+            })
+            .collect();
+        assert_eq!(addresses, vec![0x0401, 0x0400, 0x0401]);
+        assert_eq!(history.dump().lines().count(), 3);
+        assert_eq!(history.triggered(), None);
+    }
+
+    #[test]
+    fn test_breakpoint_trigger() {
+        // INX; INX; INX
+        let mut ram = TestRam::with_program(0x0400, &[0xe8, 0xe8, 0xe8]);
+        let mut cpu = MOS6502::new_start(0x0400);
+        let mut history = InstructionHistory::new(8);
+        history.set_log_dumps(false);
+        history.add_breakpoint(0x0401);
+
+        let mut instructions = 0;
+        while history.triggered().is_none() {
+            cpu.execute_instruction_traced(&mut ram, &mut history);
+            instructions += 1;
+        }
+
+        assert_eq!(instructions, 1);
+        assert_eq!(history.triggered(), Some(DumpReason::Breakpoint(0x0401)));
+        assert_eq!(cpu.x_register, 1, "the instruction at the breakpoint should not have executed");
+        history.clear_trigger();
+        assert_eq!(history.triggered(), None);
+    }
+
+    #[test]
+    fn test_breakpoint_at_start() {
+        // INX; INX
+        let mut ram = TestRam::with_program(0x0400, &[0xe8, 0xe8]);
+        let mut cpu = MOS6502::new_start(0x0400);
+        let mut history = InstructionHistory::new(8);
+        history.set_log_dumps(false);
+        history.add_breakpoint(0x0400);
+
+        cpu.execute_instruction_traced(&mut ram, &mut history);
+        assert_eq!(history.triggered(), Some(DumpReason::Breakpoint(0x0400)));
+        history.clear_trigger();
+
+        cpu.execute_instruction_traced(&mut ram, &mut history);
+        assert_eq!(history.triggered(), None, "only the first instruction should check its starting address");
+    }
+
+    #[test]
+    #[cfg(feature = "illegal_opcodes")]
+    fn test_jam_trigger() {
+        // INX; KIL
+        let mut ram = TestRam::with_program(0x0400, &[0xe8, 0x02]);
+        let mut cpu = MOS6502::new_start(0x0400);
+        let mut history = InstructionHistory::new(8);
+        history.set_log_dumps(false);
+
+        cpu.execute_instruction_traced(&mut ram, &mut history);
+        cpu.execute_instruction_traced(&mut ram, &mut history);
+        cpu.execute_instruction_traced(&mut ram, &mut history);
+
+        assert!(cpu.is_jammed());
+        assert_eq!(history.triggered(), Some(DumpReason::Jammed(0x0401)));
+        assert_eq!(history.len(), 2);
+    }
+
+    #[test]
+    #[cfg(not(feature = "illegal_opcodes"))]
+    fn test_illegal_opcode_trigger() {
+        // INX; SLO $10
+        let mut ram = TestRam::with_program(0x0400, &[0xe8, 0x07, 0x10]);
+        let mut cpu = MOS6502::new_start(0x0400);
+        let mut history = InstructionHistory::new(8);
+        history.set_log_dumps(false);
+
+        cpu.execute_instruction_traced(&mut ram, &mut history);
+        assert_eq!(history.triggered(), None);
+        cpu.execute_instruction_traced(&mut ram, &mut history);
+        assert_eq!(history.triggered(), Some(DumpReason::IllegalOpcode(0x0401)));
+    }
+}
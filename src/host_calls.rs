@@ -0,0 +1,240 @@
+//! ### HOST CALLS
+//! This module contains a driver for high level emulation of ROM routines, such as CHROUT at $FFD2 in the C64
+//! KERNAL or COUT in the Apple II monitor. Rust functions are registered for the entry points of routines, and
+//! when the program counter reaches one of them the function runs instead of the 6502 code, then returns to the
+//! caller as though by RTS, taking the number of cycles it was registered with. Programs can then be run and tested
+//! without the original ROMs, and slow routines can be replaced with fast ones.
+//!
+//! With a `Bus`, `step_bus` takes the place of `Bus::step`, so devices are advanced by the cycles of each call and
+//! their interrupts reach the processor.
+//!
+//! NOTE: Functions only run at instruction boundaries where no interrupt is waiting to be serviced, so an interrupt
+//! taken at the entry point runs before the function, as it would before the first instruction of the routine
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let mut host_calls = HostCalls::new();
+//! host_calls.add(0xffd2, 6, |cpu, _memory| print!("{}", cpu.state().accumulator as char));
+//!
+//! while !cpu.is_jammed() {
+//!     host_calls.step_bus(&mut cpu, &mut bus);
+//! }
+//! ```
+
+use super::bus::Bus;
+use super::{Interface6502, StatusFlag, MOS6502};
+use std::collections::HashMap;
+use std::fmt;
+
+/// The type of the functions run in place of routines
+type HostFunction = Box<dyn FnMut(&mut MOS6502, &mut dyn Interface6502)>;
+
+/// A function registered for a routine, and the number of cycles a call to it takes
+struct HostCall {
+    cycles: u64,
+    function: HostFunction,
+}
+
+/// Driver executing instructions, and Rust functions in place of the routines they are registered for
+#[derive(Default)]
+pub struct HostCalls {
+    calls: HashMap<u16, HostCall>,
+}
+
+impl fmt::Debug for HostCalls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut addresses: Vec<&u16> = self.calls.keys().collect();
+        addresses.sort();
+        f.debug_struct("HostCalls").field("addresses", &addresses).finish()
+    }
+}
+
+impl HostCalls {
+    /// Creates a new driver with no functions registered
+    pub fn new() -> Self {
+        HostCalls::default()
+    }
+
+    /// Registers a function to run in place of the routine at the address, replacing any already registered there.
+    /// The function can read and change the registers and memory, and each call takes the given number of cycles,
+    /// including the implicit RTS
+    pub fn add<F>(&mut self, address: u16, cycles: u64, function: F)
+    where
+        F: FnMut(&mut MOS6502, &mut dyn Interface6502) + 'static,
+    {
+        let function = Box::new(function);
+        self.calls.insert(address, HostCall { cycles, function });
+    }
+
+    /// Removes the function registered at the address, so the routine runs as 6502 code again
+    pub fn remove(&mut self, address: u16) {
+        self.calls.remove(&address);
+    }
+
+    /// Returns true if a function is registered at the address
+    pub fn contains(&self, address: u16) -> bool {
+        self.calls.contains_key(&address)
+    }
+
+    /// Runs the function registered at the program counter and returns from it, or executes the instruction there.
+    /// Returns the number of cycles that passed
+    pub fn step(&mut self, cpu: &mut MOS6502, interface: &mut dyn Interface6502) -> u64 {
+        let start = cpu.total_cycles;
+        let interrupt = cpu.pending_nmi || (cpu.pending_irq && !cpu.get_flag(StatusFlag::InterruptDisable));
+        let boundary = cpu.remaining_cycles == 0 && !cpu.jammed && !interrupt;
+        match self.calls.get_mut(&cpu.program_counter) {
+            Some(call) if boundary => {
+                (call.function)(cpu, interface);
+                cpu.program_counter = cpu.pop_stack_16(interface).wrapping_add(1);
+                cpu.total_cycles += call.cycles;
+            }
+            _ => cpu.execute_instruction(interface),
+        }
+        return cpu.total_cycles - start;
+    }
+
+    /// Runs a function or executes an instruction like `step`, then advances the bus's devices and passes their
+    /// interrupts to the processor like `Bus::step`. Returns the number of cycles that passed
+    pub fn step_bus(&mut self, cpu: &mut MOS6502, bus: &mut Bus) -> u64 {
+        return bus.step_with(cpu, |cpu, bus| {
+            self.step(cpu, bus);
+        });
+    }
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::{BusBuilder, BusDevice};
+    use crate::memory::Ram;
+    use crate::test_utilities::TestRam;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_call_and_return() {
+        // LDA #'A'; JSR $FFD2; LDA #'B'; JSR $FFD2; JMP *
+        let program = [0xa9, 0x41, 0x20, 0xd2, 0xff, 0xa9, 0x42, 0x20, 0xd2, 0xff, 0x4c, 0x0a, 0x04];
+        let mut ram = TestRam::with_program(0x0400, &program);
+        let mut cpu = MOS6502::new_start(0x0400);
+        let output = Rc::new(RefCell::new(String::new()));
+        let mut host_calls = HostCalls::new();
+        let chrout = Rc::clone(&output);
+        host_calls.add(0xffd2, 10, move |cpu, _memory| chrout.borrow_mut().push(cpu.state().accumulator as char));
+
+        assert_eq!(host_calls.step(&mut cpu, &mut ram), 2);
+        assert_eq!(host_calls.step(&mut cpu, &mut ram), 6);
+        assert_eq!(host_calls.step(&mut cpu, &mut ram), 10);
+        assert_eq!(cpu.state().program_counter, 0x0405);
+        assert_eq!(cpu.state().stack_pointer, 0xfd);
+        for _ in 0..3 {
+            host_calls.step(&mut cpu, &mut ram);
+        }
+
+        assert_eq!(*output.borrow(), "AB");
+        assert_eq!(cpu.state().program_counter, 0x040a);
+        assert_eq!(cpu.total_cycles(), 2 + 6 + 10 + 2 + 6 + 10);
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        // JSR $C000; JMP *
+        let mut ram = TestRam::with_program(0x0400, &[0x20, 0x00, 0xc0, 0x4c, 0x03, 0x04]);
+        let mut cpu = MOS6502::new_start(0x0400);
+        let mut host_calls = HostCalls::new();
+        host_calls.add(0xc000, 6, |cpu, memory| {
+            let mut state = cpu.state();
+            state.x_register = memory.read(0x0010);
+            cpu.set_state(&state);
+            memory.write(0x0011, 0x99);
+        });
+        ram.ram[0x0010] = 0x42;
+
+        host_calls.step(&mut cpu, &mut ram);
+        host_calls.step(&mut cpu, &mut ram);
+
+        assert_eq!(cpu.state().x_register, 0x42);
+        assert_eq!(ram.ram[0x0011], 0x99);
+        assert_eq!(cpu.state().program_counter, 0x0403);
+    }
+
+    #[test]
+    fn test_interrupts_and_remove() {
+        // JSR $C000, with an NMI handler of RTI at $D000
+        let mut ram = TestRam::with_program(0x0400, &[0x20, 0x00, 0xc0]);
+        ram.ram[0xd000] = 0x40;
+        ram.ram[0xfffa..0xfffc].clone_from_slice(&[0x00, 0xd0]);
+        let mut cpu = MOS6502::new_start(0x0400);
+        let calls = Rc::new(RefCell::new(0));
+        let mut host_calls = HostCalls::new();
+        let counter = Rc::clone(&calls);
+        host_calls.add(0xc000, 6, move |_cpu, _memory| *counter.borrow_mut() += 1);
+        assert!(host_calls.contains(0xc000));
+
+        host_calls.step(&mut cpu, &mut ram);
+        cpu.non_maskable_interrupt_request();
+        host_calls.step(&mut cpu, &mut ram);
+        assert_eq!(*calls.borrow(), 0, "an interrupt waiting at the entry point should be taken first");
+        assert_eq!(cpu.state().program_counter, 0xd000);
+        host_calls.step(&mut cpu, &mut ram);
+        host_calls.step(&mut cpu, &mut ram);
+        assert_eq!(*calls.borrow(), 1);
+        assert_eq!(cpu.state().program_counter, 0x0403);
+
+        host_calls.remove(0xc000);
+        assert!(!host_calls.contains(0xc000));
+    }
+
+    /// Counts the cycles it has been advanced by, asserting the interrupt request line after 15
+    struct Timer(u32);
+
+    impl BusDevice for Timer {
+        fn read(&mut self, _offset: u16) -> u8 {
+            0
+        }
+
+        fn write(&mut self, _offset: u16, _data: u8) {}
+
+        fn tick(&mut self, cycles: u32) {
+            self.0 += cycles;
+        }
+
+        fn irq(&self) -> bool {
+            self.0 >= 15
+        }
+    }
+
+    #[test]
+    fn test_step_bus() {
+        let mut builder = BusBuilder::new();
+        let ram = builder.add_device(Ram::new(0x10000));
+        builder.map(0x0000..=0xffff, &ram);
+        let timer = builder.add_device(Timer(0));
+        let mut bus = builder.build();
+        // CLI; JSR $C000, with an interrupt handler at $D000
+        for (offset, byte) in [0x58, 0x20, 0x00, 0xc0].iter().enumerate() {
+            bus.write(0x0400 + offset as u16, *byte);
+        }
+        bus.write(0xfffe, 0x00);
+        bus.write(0xffff, 0xd0);
+        let mut cpu = MOS6502::new_start(0x0400);
+        let mut host_calls = HostCalls::new();
+        host_calls.add(0xc000, 10, |_cpu, _memory| {});
+
+        assert_eq!(host_calls.step_bus(&mut cpu, &mut bus), 2);
+        assert_eq!(host_calls.step_bus(&mut cpu, &mut bus), 6);
+        assert!(!bus.irq());
+        assert_eq!(host_calls.step_bus(&mut cpu, &mut bus), 10);
+        assert_eq!(bus.device(&timer).0, 18, "the call's cycles should advance the devices");
+        assert_eq!(cpu.state().program_counter, 0x0404);
+
+        host_calls.step_bus(&mut cpu, &mut bus);
+        assert_eq!(
+            cpu.state().program_counter,
+            0xd000,
+            "the interrupt raised during the call should be taken"
+        );
+    }
+}
@@ -0,0 +1,273 @@
+//! ### INES
+//! This module contains a parser for NES ROM images in the iNES and NES 2.0 formats, yielding the PRG and
+//! CHR ROM along with the cartridge's mapper, mirroring and battery flags.
+//!
+//! The PRG ROM of cartridges using the mappers in the `banking` module can be mapped onto a bus, so CPU-only
+//! test ROMs can be run without a PPU or APU.
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let rom = NesRom::parse(&fs::read("nestest.nes")?)?;
+//! let mut bus = rom.cpu_test_bus()?;
+//! let mut cpu = MOS6502::new_start(0xc000); // nestest automation mode
+//! while cpu.get_program_counter() != 0xc66e {
+//!     bus.step(&mut cpu);
+//! }
+//! ```
+
+use super::banking::{Mmc1, Nrom, Uxrom};
+use super::bus::{Bus, BusBuilder};
+use super::memory::{Mirror, Ram};
+use std::fmt;
+
+/// Size of the iNES header
+const HEADER_SIZE: usize = 16;
+/// Size of the trainer some ROMs place before the PRG ROM
+const TRAINER_SIZE: usize = 512;
+/// Units of the PRG ROM size in the header
+const PRG_UNIT: usize = 0x4000;
+/// Units of the CHR ROM size in the header
+const CHR_UNIT: usize = 0x2000;
+
+/// Arrangement of the PPU's nametables, set by the cartridge
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Mirroring {
+    /// Nametables mirrored horizontally, for vertically scrolling games
+    Horizontal,
+    /// Nametables mirrored vertically, for horizontally scrolling games
+    Vertical,
+    /// Four separate nametables provided by the cartridge
+    FourScreen,
+}
+
+/// A parsed NES ROM image
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct NesRom {
+    /// Whether the image used the NES 2.0 header format
+    pub nes2: bool,
+    /// The mapper number
+    pub mapper: u16,
+    /// The submapper number, which is always 0 in iNES images
+    pub submapper: u8,
+    /// The nametable mirroring
+    pub mirroring: Mirroring,
+    /// Whether the cartridge has battery backed PRG RAM
+    pub battery: bool,
+    /// The 512 byte trainer, loaded at $7000 by some copier devices
+    pub trainer: Option<Vec<u8>>,
+    /// The program ROM, seen by the CPU
+    pub prg_rom: Vec<u8>,
+    /// The character ROM, seen by the PPU. Empty when the cartridge uses CHR RAM
+    pub chr_rom: Vec<u8>,
+}
+
+impl NesRom {
+    /// Parses an iNES or NES 2.0 ROM image
+    pub fn parse(data: &[u8]) -> Result<Self, NesRomError> {
+        if data.len() < HEADER_SIZE {
+            return Err(NesRomError::Truncated);
+        }
+        if data[0..4] != *b"NES\x1a" {
+            return Err(NesRomError::InvalidMagic);
+        }
+        let flags6 = data[6];
+        let flags7 = data[7];
+        let nes2 = flags7 & 0x0c == 0x08;
+
+        let (mapper, submapper, prg_size, chr_size) = if nes2 {
+            let mapper = u16::from(flags6 >> 4) | u16::from(flags7 & 0xf0) | u16::from(data[8] & 0x0f) << 8;
+            let prg_size = nes2_rom_size(data[4], data[9] & 0x0f, PRG_UNIT);
+            let chr_size = nes2_rom_size(data[5], data[9] >> 4, CHR_UNIT);
+            (mapper, data[8] >> 4, prg_size, chr_size)
+        } else {
+            // Old images often have junk such as "DiskDude!" in the reserved bytes, which corrupts the upper mapper bits
+            let upper = if data[12..16].iter().all(|byte| *byte == 0) { flags7 & 0xf0 } else { 0 };
+            let mapper = u16::from(flags6 >> 4 | upper);
+            (mapper, 0, data[4] as usize * PRG_UNIT, data[5] as usize * CHR_UNIT)
+        };
+
+        let mirroring = match (flags6 & 0x08 != 0, flags6 & 0x01 != 0) {
+            (true, _) => Mirroring::FourScreen,
+            (false, true) => Mirroring::Vertical,
+            (false, false) => Mirroring::Horizontal,
+        };
+
+        let mut position = HEADER_SIZE;
+        let mut section = |length: usize| -> Result<Vec<u8>, NesRomError> {
+            let end = position.checked_add(length).ok_or(NesRomError::Truncated)?;
+            let bytes = data.get(position..end).ok_or(NesRomError::Truncated)?;
+            position = end;
+            Ok(bytes.to_vec())
+        };
+        let trainer = if flags6 & 0x04 != 0 { Some(section(TRAINER_SIZE)?) } else { None };
+        let prg_rom = section(prg_size)?;
+        let chr_rom = section(chr_size)?;
+        if prg_rom.is_empty() {
+            return Err(NesRomError::Truncated);
+        }
+
+        return Ok(NesRom {
+            nes2,
+            mapper,
+            submapper,
+            mirroring,
+            battery: flags6 & 0x02 != 0,
+            trainer,
+            prg_rom,
+            chr_rom,
+        });
+    }
+
+    /// Maps the PRG ROM to $8000-$FFFF through the cartridge's mapper. Only mappers 0 (NROM), 1 (MMC1) and 2 (UxROM)
+    /// are supported, with PRG ROM made of whole 16KB banks
+    pub fn map_prg(&self, builder: &mut BusBuilder) -> Result<(), NesRomError> {
+        if self.mapper > 2 {
+            return Err(NesRomError::UnsupportedMapper(self.mapper));
+        }
+        if self.prg_rom.is_empty() || self.prg_rom.len() % PRG_UNIT != 0 {
+            return Err(NesRomError::UnsupportedPrgSize(self.prg_rom.len()));
+        }
+        let prg = self.prg_rom.clone();
+        match self.mapper {
+            0 => {
+                let cartridge = builder.add_device(Nrom::new(prg));
+                builder.map(0x8000..=0xffff, &cartridge);
+            }
+            1 => {
+                let cartridge = builder.add_device(Mmc1::new(prg));
+                builder.map(0x8000..=0xffff, &cartridge);
+            }
+            _ => {
+                let cartridge = builder.add_device(Uxrom::new(prg));
+                builder.map(0x8000..=0xffff, &cartridge);
+            }
+        }
+        return Ok(());
+    }
+
+    /// Builds a bus for running the ROM without a PPU or APU, with the console's 2KB of RAM mirrored through
+    /// $0000-$1FFF, 8KB of PRG RAM at $6000-$7FFF and the PRG ROM mapped from $8000. The PPU and APU registers
+    /// are left unmapped
+    pub fn cpu_test_bus(&self) -> Result<Bus, NesRomError> {
+        let mut builder = BusBuilder::new();
+        let ram = builder.add_device(Mirror::new(Ram::new(0x0800), 0x0800));
+        let prg_ram = builder.add_device(Ram::new(0x2000));
+        builder.map(0x0000..=0x1fff, &ram).map(0x6000..=0x7fff, &prg_ram);
+        self.map_prg(&mut builder)?;
+        return Ok(builder.build());
+    }
+}
+
+/// Decodes a NES 2.0 ROM size from its least and most significant parts. A most significant nibble of $F
+/// selects the exponent-multiplier notation, where the size is 2^exponent * (multiplier * 2 + 1) bytes
+fn nes2_rom_size(lsb: u8, msb: u8, unit: usize) -> usize {
+    if msb == 0x0f {
+        let exponent = u32::from(lsb >> 2);
+        let multiplier = (lsb & 0x03) as usize * 2 + 1;
+        return 1_usize
+            .checked_shl(exponent)
+            .and_then(|size| size.checked_mul(multiplier))
+            .unwrap_or(usize::MAX);
+    }
+    return (usize::from(msb) << 8 | usize::from(lsb)) * unit;
+}
+
+/// Errors that can occur while parsing or mapping a NES ROM image
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum NesRomError {
+    /// The image did not begin with "NES" followed by an MS-DOS end of file character
+    InvalidMagic,
+    /// The image was shorter than the sizes in its header
+    Truncated,
+    /// The cartridge's mapper can not be mapped onto a bus
+    UnsupportedMapper(u16),
+    /// The PRG ROM of the given size is not made of whole 16KB banks, so can not be mapped onto a bus
+    UnsupportedPrgSize(usize),
+}
+
+impl fmt::Display for NesRomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NesRomError::InvalidMagic => write!(f, "Not an iNES image"),
+            NesRomError::Truncated => write!(f, "Image is shorter than its header describes"),
+            NesRomError::UnsupportedMapper(mapper) => write!(f, "Unsupported mapper {}", mapper),
+            NesRomError::UnsupportedPrgSize(size) => write!(f, "PRG ROM of {} bytes is not made of whole 16KB banks", size),
+        }
+    }
+}
+
+impl std::error::Error for NesRomError {}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Interface6502, MOS6502};
+
+    fn image(header: [u8; 12], body: &[u8]) -> Vec<u8> {
+        let mut data = b"NES\x1a".to_vec();
+        data.extend_from_slice(&header);
+        data.extend_from_slice(body);
+        data
+    }
+
+    #[test]
+    fn test_parse_ines() {
+        let mut body = vec![0xaa; TRAINER_SIZE];
+        body.extend(vec![0x11; PRG_UNIT * 2]);
+        body.extend(vec![0x22; CHR_UNIT]);
+        let rom = NesRom::parse(&image([2, 1, 0x17, 0x10, 0, 0, 0, 0, 0, 0, 0, 0], &body)).unwrap();
+
+        assert!(!rom.nes2);
+        assert_eq!(rom.mapper, 0x11);
+        assert_eq!(rom.mirroring, Mirroring::Vertical);
+        assert!(rom.battery);
+        assert_eq!(rom.trainer.as_ref().map(Vec::len), Some(TRAINER_SIZE));
+        assert_eq!(rom.prg_rom.len(), PRG_UNIT * 2);
+        assert_eq!(rom.chr_rom, vec![0x22; CHR_UNIT]);
+
+        let dirty = image([1, 0, 0x10, 0x40, 0, 0, 0, 0, b'D', b'u', b'd', b'e'], &vec![0; PRG_UNIT]);
+        assert_eq!(NesRom::parse(&dirty).unwrap().mapper, 1);
+        assert_eq!(
+            NesRom::parse(&image([2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], &[0; 16])),
+            Err(NesRomError::Truncated)
+        );
+        assert_eq!(NesRom::parse(b"NES\x00 and some more bytes"), Err(NesRomError::InvalidMagic));
+    }
+
+    #[test]
+    fn test_parse_nes2() {
+        // Mapper 0x123 submapper 4, with PRG ROM size in exponent-multiplier notation: 2^10 * 3 bytes
+        let rom = NesRom::parse(&image([0x29, 0, 0x38, 0x28, 0x41, 0x0f, 0, 0, 0, 0, 0, 0], &vec![0; 3072])).unwrap();
+
+        assert!(rom.nes2);
+        assert_eq!(rom.mapper, 0x123);
+        assert_eq!(rom.submapper, 4);
+        assert_eq!(rom.mirroring, Mirroring::FourScreen);
+        assert_eq!(rom.prg_rom.len(), 3072);
+        assert!(rom.chr_rom.is_empty());
+        assert_eq!(rom.cpu_test_bus().err(), Some(NesRomError::UnsupportedMapper(0x123)));
+
+        for mapper in 0..=2 {
+            let rom = NesRom { mapper, ..rom.clone() };
+            assert_eq!(rom.cpu_test_bus().err(), Some(NesRomError::UnsupportedPrgSize(3072)));
+        }
+    }
+
+    #[test]
+    fn test_cpu_test_bus() {
+        let mut prg = vec![0; PRG_UNIT];
+        prg[0..7].clone_from_slice(&[0xa9, 0x42, 0x85, 0x02, 0x8d, 0x00, 0x60]); // LDA #$42, STA $02, STA $6000
+        let rom = NesRom::parse(&image([1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], &prg)).unwrap();
+        let mut bus = rom.cpu_test_bus().unwrap();
+        let mut cpu = MOS6502::new_start(0xc000);
+        for _ in 0..3 {
+            bus.step(&mut cpu);
+        }
+
+        assert_eq!(bus.read(0x8000), 0xa9);
+        assert_eq!(bus.read(0x0802), 0x42);
+        assert_eq!(bus.read(0x6000), 0x42);
+    }
+}
@@ -80,25 +80,76 @@
 //! * Emulation of bugs that existed in the original 6502 hardware
 //! * Binary Coded Decimal when the "binary_coded_decimal" compilation feature is enabled
 //! * Illegal undocumented opcodes when the "illegal_opcodes" compilation feature is enabled
+//! * Comparison of execution against reference trace logs (nestest.log, VICE and MAME) in the `golden_trace` module
+//! * Structured per-instruction and interrupt events through the `Tracer6502` trait, with `LogTracer` for logging them
+//! * A ring buffer of recently executed instructions for crash diagnostics in the `history` module
+//! * Per-address and per-subroutine profiling with callgrind and folded stack output in the `profiler` module
+//! * Code and branch coverage with lcov and annotated listing output in the `coverage` module
+//! * Disassembly of instructions in memory in the `disassembler` module
+//! * Shadow call stack tracking and backtraces in the `call_stack` module
+//! * Loading symbols from ca65 debug info, VICE label and llvm-mos ELF files in the `symbols` module, for naming addresses in disassembly, logs, profiles and backtraces
+//! * Reverse stepping and reverse continue to breakpoints through snapshots and a write undo log in the `rewind` module
+//! * Save states in a stable, versioned binary format in the `save_state` module, and serde support when the "serde" compilation feature is enabled
+//! * Reading and writing the complete processor state and individual status flags through `CpuState` and `StatusFlags`
+//! * Composing memory maps from devices with priorities, mirroring and memory mapped I/O closures in the `bus` module
+//! * Ready-made RAM, ROM, mirrored and open bus devices in the `memory` module
+//! * Bank switched memory with NES (NROM, UxROM, MMC1), C64 (Ocean, EasyFlash) and Atari 2600 (F8, F6) mappers in the `banking` module, with banks shown in disassembly
+//! * Program loaders for raw binaries, Commodore PRG, Intel HEX, Motorola S-records, Atari XEX, Apple DOS 3.3 binaries and relocatable cc65 `o65` objects in the `loaders` module
+//! * iNES and NES 2.0 ROM image parsing in the `ines` module, with PRG ROM mapped onto a bus for CPU-only test ROMs such as nestest
+//! * Peripheral chip emulation in the `peripherals` module: the 6522 VIA, the 6526 CIA, the 6532 RIOT and 6530 RRIOT, the 6820 PIA, and the 6551 ACIA connected to host streams such as the terminal
+//! * An Apple-1 reference machine with a 6820 PIA keyboard and display in the `machines` module
+//! * The sim65 paravirtualization interface in the `machines` module, running programs built with `cl65 -t sim6502` with their arguments, host file I/O and exit codes, also available through `run6502`
+//! * A `run6502` command-line runner for test ROMs, stopping on traps, exit addresses or a cycle limit and reporting the result through its exit status
+//! * High level emulation of ROM routines in the `host_calls` module, running Rust functions in place of the routines at registered addresses, followed by an implicit RTS with a configurable cycle cost
+//! * A machine language monitor in the `monitor` module, with memory, register, breakpoint and inline assembly commands over any reader and writer, also available through `run6502 --monitor`
+//! * Custom opcodes through `OpcodeTable`, replacing entries of the opcode table with user functions given register and bus access, an address mode and a cycle count
 //!
 //! If illegal opcodes are called without the "illegal_opcodes" compilation feature enabled, the emulator will log a warning
-//! and run for the appropriate number of cycles without changing state.
+//! and run for the appropriate number of cycles without changing state. With the feature enabled, the KIL opcode jams the
+//! processor until it is reset, which `is_jammed` reports. KIL takes 2 cycles either way.
+//!
+//! NOTE: Up to version 1.1.0, KIL took 0 cycles and panicked when the "illegal_opcodes" feature was enabled.
 
 #![allow(clippy::needless_return)] // My preferred style
 
 mod address_modes;
+pub mod banking;
+pub mod bus;
+pub mod call_stack;
+pub mod coverage;
+mod cpu_state;
+pub mod disassembler;
+pub mod golden_trace;
+pub mod history;
+pub mod host_calls;
+pub mod ines;
+pub mod loaders;
+pub mod machines;
+pub mod memory;
+pub mod monitor;
 mod opcodes;
+pub mod peripherals;
+pub mod profiler;
+pub mod rewind;
+pub mod save_state;
+pub mod symbols;
 #[cfg(test)]
 mod test_utilities;
+pub mod tracer;
 
 #[macro_use]
 extern crate log;
 
+pub use address_modes::AddressMode;
 use address_modes::*;
+pub use cpu_state::{CpuState, StatusFlags};
+pub use opcodes::{CustomOpcode, CustomOpcodeFunction, OpcodeTable};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracer::{InstructionEvent, InterruptEvent, InterruptKind, NoTracer, OperandCapture, Tracer6502};
 
 //Declare some type alias for clarity's sake
-/// The type of all Address Mode functions
-type AddressModeFunction = fn(&mut MOS6502, &mut dyn Interface6502) -> AddressModeValue;
 /// The type of all Opcode functions
 type OpcodeFunction = fn(&mut MOS6502, &mut dyn Interface6502, AddressModeValue);
 
@@ -135,6 +186,7 @@ const IRQ_ADDRESS_LOCATION: u16 = 0xfffe;
 /// }
 /// ```
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MOS6502 {
     // Registers
     /// The accumulator register of the 6502, where the results of arithmetic opcodes are placed
@@ -159,6 +211,11 @@ pub struct MOS6502 {
     pending_nmi: bool,
     /// Boolean tracking whether or not an interrupt request has been made
     pending_irq: bool,
+    /// Boolean tracking whether or not the processor has been halted by a KIL opcode
+    jammed: bool,
+    /// Table of custom opcodes replacing entries of the built-in opcode table
+    #[cfg_attr(feature = "serde", serde(skip))]
+    opcode_table: Option<Arc<OpcodeTable>>,
 }
 
 impl MOS6502 {
@@ -175,6 +232,8 @@ impl MOS6502 {
             total_cycles: 0,
             pending_nmi: false,
             pending_irq: false,
+            jammed: false,
+            opcode_table: None,
         }
     }
 
@@ -189,13 +248,25 @@ impl MOS6502 {
     /// Creates a new MOS6502 emulation with the program counter at the address read from the reset vector (0xfffa-0xfffb).
     ///
     /// This is the standard method used for determining where the program starts on most systems
-    pub fn new_reset_position(interface: &mut (dyn Interface6502)) -> Self {
+    pub fn new_reset_position(interface: &mut dyn Interface6502) -> Self {
         return MOS6502 {
             program_counter: read_16(interface, RESET_ADDRESS_LOCATION),
             ..MOS6502::new()
         };
     }
 
+    /// Replaces the built-in opcode table with a table containing custom opcodes. The table is kept by `set_state`
+    /// and `reset`, but not by save states
+    pub fn with_opcode_table(mut self, opcode_table: Arc<OpcodeTable>) -> Self {
+        self.opcode_table = Some(opcode_table);
+        return self;
+    }
+
+    /// Returns the table of custom opcodes, if one has replaced the built-in opcode table
+    pub fn opcode_table(&self) -> Option<&OpcodeTable> {
+        return self.opcode_table.as_deref();
+    }
+
     /// Force the program counter to a specific address
     pub fn set_program_counter(&mut self, program_counter: u16) {
         self.program_counter = program_counter
@@ -274,61 +345,149 @@ impl MOS6502 {
     }
 
     /// Runs a processor cycle, mutably borrows the reading and writing interface for the duration
-    pub fn cycle(&mut self, interface: &mut (dyn Interface6502)) {
+    pub fn cycle(&mut self, interface: &mut dyn Interface6502) {
+        self.cycle_inner::<NoTracer>(interface, None)
+    }
+
+    /// Runs a processor cycle like `cycle`, passing an event to the tracer for each instruction or interrupt that begins
+    pub fn cycle_traced<T: Tracer6502 + ?Sized>(&mut self, interface: &mut dyn Interface6502, tracer: &mut T) {
+        self.cycle_inner(interface, Some(tracer))
+    }
+
+    /// Runs a processor cycle, building events only when a tracer is provided
+    #[inline(always)]
+    fn cycle_inner<T: Tracer6502 + ?Sized>(&mut self, interface: &mut dyn Interface6502, tracer: Option<&mut T>) {
+        if self.jammed {
+            //A jammed processor does nothing until it is reset
+            self.remaining_cycles = 0;
+            self.total_cycles += 1;
+            return;
+        }
         if self.remaining_cycles == 0 {
             if self.pending_nmi || (self.pending_irq && !self.get_flag(StatusFlag::InterruptDisable)) {
+                let before = self.state();
+
                 //An interrupt will let the executing instruction complete
                 self.push_stack_16(interface, self.program_counter);
                 self.set_flag(StatusFlag::BreakIrq, true);
                 self.push_stack(interface, self.status_register);
                 self.set_flag(StatusFlag::InterruptDisable, true);
 
-                if self.pending_nmi {
+                let kind = if self.pending_nmi {
                     self.program_counter = read_16(interface, NMI_ADDRESS_LOCATION);
                     self.remaining_cycles = 8;
+                    InterruptKind::Nmi
                 } else {
                     self.program_counter = read_16(interface, IRQ_ADDRESS_LOCATION);
                     self.remaining_cycles = 7;
-                }
+                    InterruptKind::Irq
+                };
 
                 self.pending_nmi = false;
                 self.pending_irq = false;
+
+                if let Some(tracer) = tracer {
+                    tracer.interrupt(&InterruptEvent {
+                        kind,
+                        before,
+                        after: self.state(),
+                        cycles: self.remaining_cycles,
+                        total_cycles: self.total_cycles,
+                    });
+                }
             } else {
                 //Proceed normally
-                let instruction = opcodes::OPCODE_TABLE[interface.read(self.program_counter) as usize];
-                let log_program_counter = self.program_counter;
-                self.program_counter += 1;
-                let address_mode_value = instruction.find_address(self, interface);
-
-                trace!(
-                    "0x{:04X} {} {:?} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
-                    log_program_counter,
-                    instruction.get_name(),
-                    address_mode_value,
-                    self.accumulator,
-                    self.x_register,
-                    self.y_register,
-                    self.status_register,
-                    self.stack_pointer,
-                    self.total_cycles + 7,
-                );
-
-                instruction.execute_instruction(self, interface, address_mode_value);
-                self.remaining_cycles += instruction.get_cycles();
+                let opcode = interface.read(self.program_counter);
+                match tracer {
+                    None => {
+                        self.program_counter += 1;
+                        self.execute_opcode(opcode, interface);
+                    }
+                    Some(tracer) => {
+                        let before = self.state();
+                        self.program_counter += 1;
+                        let mut capture = OperandCapture::new(interface, before.program_counter);
+                        let (operand_length, effective_address) = self.execute_opcode(opcode, &mut capture);
+                        let table = self.opcode_table().unwrap_or(&opcodes::BUILT_IN_TABLE);
+                        tracer.instruction(&InstructionEvent {
+                            program_counter: before.program_counter,
+                            opcode,
+                            name: table.name(opcode),
+                            operand: capture.finish(),
+                            operand_length,
+                            effective_address,
+                            before,
+                            after: self.state(),
+                            cycles: self.remaining_cycles,
+                            total_cycles: self.total_cycles,
+                            illegal: table.is_illegal(opcode),
+                        });
+                        if self.jammed {
+                            tracer.jammed(before.program_counter);
+                        }
+                    }
+                }
             }
         }
         self.remaining_cycles -= 1;
         self.total_cycles += 1;
     }
 
+    /// Finds the operand of the opcode and executes it, returning the length of the operand and the effective address
+    #[inline(always)]
+    fn execute_opcode(&mut self, opcode: u8, interface: &mut dyn Interface6502) -> (u8, Option<u16>) {
+        if self.opcode_table.is_some() {
+            if let Some(result) = self.execute_custom_opcode(opcode, interface) {
+                return result;
+            }
+        }
+
+        let instruction = opcodes::OPCODE_TABLE[opcode as usize];
+        let operand_start = self.program_counter;
+        let address_mode_value = instruction.get_address_mode().find_address(self, interface);
+        let operand_end = self.program_counter;
+
+        instruction.execute_instruction(self, interface, address_mode_value);
+        self.remaining_cycles += instruction.get_cycles();
+        return (
+            operand_end.wrapping_sub(operand_start) as u8,
+            address_mode_value.effective_address(operand_end),
+        );
+    }
+
+    /// Executes the opcode if the custom opcode table replaces it, returning the same as `execute_opcode`. Kept out of
+    /// line so that processors without a custom table only pay for the check in `execute_opcode`
+    #[cold]
+    #[inline(never)]
+    fn execute_custom_opcode(&mut self, opcode: u8, interface: &mut dyn Interface6502) -> Option<(u8, Option<u16>)> {
+        let custom = *self.opcode_table()?.get(opcode)?;
+        let operand_start = self.program_counter;
+        let address_mode_value = custom.address_mode.find_address(self, interface);
+        let operand_end = self.program_counter;
+        let effective_address = address_mode_value.effective_address(operand_end);
+
+        (custom.function)(self, interface, effective_address);
+        self.remaining_cycles += custom.cycles;
+        return Some((operand_end.wrapping_sub(operand_start) as u8, effective_address));
+    }
+
     /// Runs as many processor cycles as it takes to complete the instruction at the program counter
-    pub fn execute_instruction(&mut self, interface: &mut (dyn Interface6502)) {
+    pub fn execute_instruction(&mut self, interface: &mut dyn Interface6502) {
         self.cycle(interface); //No do-while loops in Rust
         while self.remaining_cycles != 0 {
             self.cycle(interface)
         }
     }
 
+    /// Runs as many processor cycles as it takes to complete the instruction at the program counter, passing
+    /// events to the tracer like `cycle_traced`
+    pub fn execute_instruction_traced<T: Tracer6502 + ?Sized>(&mut self, interface: &mut dyn Interface6502, tracer: &mut T) {
+        self.cycle_traced(interface, tracer);
+        while self.remaining_cycles != 0 {
+            self.cycle(interface)
+        }
+    }
+
     /// Pushes a byte onto the stack
     fn push_stack(&mut self, interface: &mut dyn Interface6502, data: u8) {
         interface.write(STACK_PAGE + u16::from(self.stack_pointer), data);
@@ -374,14 +533,26 @@ impl MOS6502 {
         self.pending_irq = true;
     }
 
+    /// Sets the interrupt request line to its current level, withdrawing a request that has not been serviced when the
+    /// line is no longer asserted
+    pub(crate) fn set_interrupt_request_line(&mut self, asserted: bool) {
+        self.pending_irq = asserted;
+    }
+
     /// Request that an interrupt occurs after the current instruction completes, even if the interrupt disabled flag is set
     pub fn non_maskable_interrupt_request(&mut self) {
         self.pending_nmi = true;
     }
 
+    /// Returns true if the processor has been halted by a KIL opcode and will do nothing until it is reset
+    pub fn is_jammed(&self) -> bool {
+        self.jammed
+    }
+
     /// Resets the 6502 to a known state
     pub fn reset(&mut self, interface: &mut dyn Interface6502) {
         self.program_counter = read_16(interface, RESET_ADDRESS_LOCATION);
+        self.jammed = false;
 
         self.accumulator = 0x00;
         self.x_register = 0x00;
@@ -429,6 +600,11 @@ pub trait Interface6502 {
     fn read(&mut self, address: u16) -> u8;
     /// Writes a byte to the interface at the given address
     fn write(&mut self, address: u16, data: u8);
+    /// Returns the number of the bank currently selected at the given address, if the address is bank switched,
+    /// for display in disassemblers and debuggers
+    fn bank(&self, _address: u16) -> Option<usize> {
+        None
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -0,0 +1,527 @@
+//! ### LOADERS
+//! This module contains functions for loading programs in common binary formats into memory through
+//! any `Interface6502`:
+//! * Raw binaries at a given load address
+//! * Commodore PRG files, with a 2 byte load address header
+//! * Intel HEX and Motorola S-record text files
+//! * Atari XEX (DOS 2) executables, made up of segments
+//! * Apple DOS 3.3 binary files
+//! * cc65 `o65` relocatable objects, relocated to a given address
+//!
+//! Each loader returns the entry point of the program and the address ranges it wrote.
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let program = load_intel_hex(&mut ram, &fs::read_to_string("program.hex")?)?;
+//! let mut cpu = MOS6502::new_start(program.entry);
+//! ```
+
+use super::Interface6502;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// A program that has been loaded into memory
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct LoadedProgram {
+    /// The address execution should begin at
+    pub entry: u16,
+    /// The address ranges written, in the order they were loaded
+    pub segments: Vec<RangeInclusive<u16>>,
+    /// The initialization routines an Atari XEX file requested to be run while it was loading, in order
+    pub init: Vec<u16>,
+}
+
+impl LoadedProgram {
+    /// Writes a segment of the program to memory and records its address range
+    fn write_segment(&mut self, interface: &mut dyn Interface6502, start: u16, data: &[u8]) -> Result<(), LoadError> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let end = u32::from(start) + data.len() as u32 - 1;
+        if end > u32::from(u16::MAX) {
+            return Err(LoadError::OutOfRange(u32::from(start)));
+        }
+        for (offset, byte) in data.iter().enumerate() {
+            interface.write(start + offset as u16, *byte);
+        }
+        self.segments.push(start..=end as u16);
+        return Ok(());
+    }
+}
+
+/// Loads a raw binary at the load address, which is also the entry point
+pub fn load_raw(interface: &mut dyn Interface6502, data: &[u8], address: u16) -> Result<LoadedProgram, LoadError> {
+    let mut program = LoadedProgram {
+        entry: address,
+        ..LoadedProgram::default()
+    };
+    program.write_segment(interface, address, data)?;
+    return Ok(program);
+}
+
+/// Loads a Commodore PRG file, which begins with its little endian load address. The load address is
+/// also returned as the entry point, although programs loaded at $0801 are usually BASIC programs run with `RUN`
+pub fn load_prg(interface: &mut dyn Interface6502, data: &[u8]) -> Result<LoadedProgram, LoadError> {
+    let address = read_u16(data, 0)?;
+    return load_raw(interface, &data[2..], address);
+}
+
+/// Loads an Apple DOS 3.3 binary file, which begins with its little endian load address and length. The
+/// load address is also the entry point
+pub fn load_apple_binary(interface: &mut dyn Interface6502, data: &[u8]) -> Result<LoadedProgram, LoadError> {
+    let address = read_u16(data, 0)?;
+    let length = read_u16(data, 2)? as usize;
+    let body = data.get(4..4 + length).ok_or(LoadError::Truncated)?;
+    return load_raw(interface, body, address);
+}
+
+/// Atari address the XEX format uses for the run address
+const XEX_RUNAD: u16 = 0x02e0;
+/// Atari address the XEX format uses for initialization routine addresses
+const XEX_INITAD: u16 = 0x02e2;
+
+/// Loads an Atari XEX executable made up of segments, each with a start and inclusive end address.
+///
+/// Segments that write INITAD ($02E2) add an initialization routine to the returned program, which DOS would
+/// run before loading the next segment. The entry point is the address written to RUNAD ($02E0), or the start
+/// of the first segment if it was never written
+pub fn load_xex(interface: &mut dyn Interface6502, data: &[u8]) -> Result<LoadedProgram, LoadError> {
+    if read_u16(data, 0)? != 0xffff {
+        return Err(LoadError::InvalidHeader("missing $FFFF header"));
+    }
+    let mut program = LoadedProgram::default();
+    let mut run_address = None;
+    let mut position = 2;
+    while position < data.len() {
+        let mut start = read_u16(data, position)?;
+        if start == 0xffff {
+            // Segments can optionally repeat the header
+            position += 2;
+            start = read_u16(data, position)?;
+        }
+        let end = read_u16(data, position + 2)?;
+        if end < start {
+            return Err(LoadError::InvalidHeader("segment ends before it starts"));
+        }
+        let length = usize::from(end - start) + 1;
+        let body = data.get(position + 4..position + 4 + length).ok_or(LoadError::Truncated)?;
+        program.write_segment(interface, start, body)?;
+        position += 4 + length;
+
+        let written = |vector: u16| -> Option<u16> {
+            if start <= vector && vector < end {
+                let offset = usize::from(vector - start);
+                Some(u16::from_le_bytes([body[offset], body[offset + 1]]))
+            } else {
+                None
+            }
+        };
+        if let Some(init) = written(XEX_INITAD) {
+            program.init.push(init);
+        }
+        run_address = written(XEX_RUNAD).or(run_address);
+    }
+
+    program.entry = match (run_address, program.segments.first()) {
+        (Some(address), _) => address,
+        (None, Some(segment)) => *segment.start(),
+        (None, None) => return Err(LoadError::Truncated),
+    };
+    return Ok(program);
+}
+
+/// Loads an Intel HEX file. The entry point is the address from a start address record, or the address of the
+/// first data record if there is none. Extended address records are accepted while addresses fit in 16 bits
+pub fn load_intel_hex(interface: &mut dyn Interface6502, text: &str) -> Result<LoadedProgram, LoadError> {
+    let mut program = LoadedProgram::default();
+    let mut base = 0_u32;
+    let mut entry = None;
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let bytes = match line.strip_prefix(':') {
+            Some(hex) => parse_hex_bytes(hex, line_number)?,
+            None => return Err(LoadError::parse(line_number, "record does not begin with ':'")),
+        };
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(LoadError::parse(line_number, "record length does not match its byte count"));
+        }
+        if bytes.iter().fold(0_u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(LoadError::Checksum(line_number));
+        }
+
+        let address = u16::from_be_bytes([bytes[1], bytes[2]]);
+        let record = &bytes[4..bytes.len() - 1];
+        match bytes[3] {
+            0x00 => {
+                let start = base + u32::from(address);
+                if start > u32::from(u16::MAX) {
+                    return Err(LoadError::OutOfRange(start));
+                }
+                program.write_segment(interface, start as u16, record)?;
+            }
+            0x01 => break,
+            0x02 => base = u32::from(read_u16_be(record, line_number)?) << 4,
+            0x04 => base = u32::from(read_u16_be(record, line_number)?) << 16,
+            0x03 => {
+                let segment = u32::from(read_u16_be(record, line_number)?);
+                let offset = u32::from(read_u16_be(record.get(2..).unwrap_or(&[]), line_number)?);
+                entry = Some((segment << 4) + offset);
+            }
+            0x05 => entry = Some(read_u32_be(record, line_number)?),
+            _ => return Err(LoadError::parse(line_number, "unknown record type")),
+        }
+    }
+
+    program.entry = match (entry, program.segments.first()) {
+        (Some(address), _) if address > u32::from(u16::MAX) => return Err(LoadError::OutOfRange(address)),
+        (Some(address), _) => address as u16,
+        (None, Some(segment)) => *segment.start(),
+        (None, None) => 0,
+    };
+    return Ok(program);
+}
+
+/// Loads a Motorola S-record file. The entry point is the address from the termination record, or the address
+/// of the first data record if it is zero or missing. 24 and 32-bit address records are accepted while
+/// addresses fit in 16 bits
+pub fn load_srec(interface: &mut dyn Interface6502, text: &str) -> Result<LoadedProgram, LoadError> {
+    let mut program = LoadedProgram::default();
+    let mut entry = None;
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if !line.starts_with('S') || line.len() < 2 {
+            return Err(LoadError::parse(line_number, "record does not begin with 'S'"));
+        }
+        let record_type = line.as_bytes()[1];
+        let bytes = parse_hex_bytes(&line[2..], line_number)?;
+        if bytes.is_empty() || bytes.len() != bytes[0] as usize + 1 {
+            return Err(LoadError::parse(line_number, "record length does not match its byte count"));
+        }
+        if bytes.iter().fold(0_u8, |sum, byte| sum.wrapping_add(*byte)) != 0xff {
+            return Err(LoadError::Checksum(line_number));
+        }
+
+        let address_length = match record_type {
+            b'0' | b'1' | b'5' | b'9' => 2,
+            b'2' | b'6' | b'8' => 3,
+            b'3' | b'7' => 4,
+            _ => return Err(LoadError::parse(line_number, "unknown record type")),
+        };
+        if bytes.len() < address_length + 2 {
+            return Err(LoadError::parse(line_number, "record is too short for its address"));
+        }
+        let address = bytes[1..=address_length]
+            .iter()
+            .fold(0_u32, |address, byte| address << 8 | u32::from(*byte));
+        let record = &bytes[address_length + 1..bytes.len() - 1];
+        match record_type {
+            b'1' | b'2' | b'3' => {
+                if address > u32::from(u16::MAX) {
+                    return Err(LoadError::OutOfRange(address));
+                }
+                program.write_segment(interface, address as u16, record)?;
+            }
+            b'7' | b'8' | b'9' => entry = Some(address),
+            _ => {} // Header and record count records
+        }
+    }
+
+    program.entry = match (entry, program.segments.first()) {
+        (Some(address), _) if address > u32::from(u16::MAX) => return Err(LoadError::OutOfRange(address)),
+        (Some(address), _) if address != 0 => address as u16,
+        (_, Some(segment)) => *segment.start(),
+        (_, None) => 0,
+    };
+    return Ok(program);
+}
+
+/// Mode flag of an o65 file using 32-bit sizes and addresses
+const O65_MODE_SIZE_32: u16 = 0x2000;
+/// Mode flag of an o65 file for the 65816
+const O65_MODE_65816: u16 = 0x8000;
+/// Mode flag of an o65 file relocated by whole pages, which leaves out the low byte of HIGH relocations
+const O65_MODE_PAGED: u16 = 0x4000;
+
+/// Loads a 16-bit cc65 `o65` relocatable object, relocating its text segment to the given address followed
+/// immediately by its data and bss segments. The zero page segment keeps the address it was assembled for.
+/// The entry point is the start of the text segment.
+///
+/// Objects that import undefined references, or use 65816 segment relocations, are not supported
+pub fn load_o65(interface: &mut dyn Interface6502, data: &[u8], text_base: u16) -> Result<LoadedProgram, LoadError> {
+    if data.get(0..5) != Some(&[0x01, 0x00, b'o', b'6', b'5']) {
+        return Err(LoadError::InvalidHeader("missing o65 marker"));
+    }
+    let mode = read_u16(data, 6)?;
+    if mode & (O65_MODE_SIZE_32 | O65_MODE_65816) != 0 {
+        return Err(LoadError::Unsupported("32-bit and 65816 o65 files"));
+    }
+    let header = |index: usize| read_u16(data, 8 + index * 2);
+    let (old_text, text_length, old_data, data_length, old_bss) = (header(0)?, header(1)?, header(2)?, header(3)?, header(4)?);
+
+    // Skip the header options, each prefixed by its length and ended by a zero length
+    let mut position = 26;
+    loop {
+        let length = *data.get(position).ok_or(LoadError::Truncated)? as usize;
+        if length == 0 {
+            position += 1;
+            break;
+        }
+        position += length;
+    }
+
+    let mut text = data.get(position..position + text_length as usize).ok_or(LoadError::Truncated)?.to_vec();
+    position += text.len();
+    let mut segment_data = data.get(position..position + data_length as usize).ok_or(LoadError::Truncated)?.to_vec();
+    position += segment_data.len();
+    if read_u16(data, position)? != 0 {
+        return Err(LoadError::Unsupported("o65 files with undefined references"));
+    }
+    position += 2;
+
+    let new_data = text_base.wrapping_add(text_length);
+    let new_bss = new_data.wrapping_add(data_length);
+    let deltas = [
+        0,
+        0,
+        text_base.wrapping_sub(old_text),
+        new_data.wrapping_sub(old_data),
+        new_bss.wrapping_sub(old_bss),
+        0,
+    ];
+    let paged = mode & O65_MODE_PAGED != 0;
+    position = relocate_o65(&mut text, data, position, &deltas, paged)?;
+    relocate_o65(&mut segment_data, data, position, &deltas, paged)?;
+
+    let mut program = load_raw(interface, &text, text_base)?;
+    program.write_segment(interface, new_data, &segment_data)?;
+    return Ok(program);
+}
+
+/// Applies an o65 relocation table beginning at the position to a segment, returning the position after the table
+fn relocate_o65(segment: &mut [u8], file: &[u8], mut position: usize, deltas: &[u16; 6], paged: bool) -> Result<usize, LoadError> {
+    let mut address = -1_isize;
+    loop {
+        let offset = *file.get(position).ok_or(LoadError::Truncated)?;
+        position += 1;
+        match offset {
+            0 => return Ok(position),
+            255 => {
+                address += 254;
+                continue;
+            }
+            _ => address += offset as isize,
+        }
+        let type_byte = *file.get(position).ok_or(LoadError::Truncated)?;
+        position += 1;
+        let delta = *deltas
+            .get((type_byte & 0x1f) as usize)
+            .ok_or(LoadError::InvalidHeader("unknown o65 relocation segment"))?;
+        if type_byte & 0x1f == 0 {
+            return Err(LoadError::Unsupported("o65 files with undefined references"));
+        }
+
+        let index = address as usize;
+        let target = |length: usize| -> Result<usize, LoadError> {
+            if address >= 0 && index + length <= segment.len() {
+                Ok(index)
+            } else {
+                Err(LoadError::InvalidHeader("o65 relocation outside of its segment"))
+            }
+        };
+        match type_byte & 0xe0 {
+            0x80 => {
+                let index = target(2)?;
+                let value = u16::from_le_bytes([segment[index], segment[index + 1]]).wrapping_add(delta);
+                segment[index..index + 2].clone_from_slice(&value.to_le_bytes());
+            }
+            0x40 => {
+                let index = target(1)?;
+                let low = if paged {
+                    0
+                } else {
+                    position += 1;
+                    *file.get(position - 1).ok_or(LoadError::Truncated)?
+                };
+                let value = u16::from_be_bytes([segment[index], low]).wrapping_add(delta);
+                segment[index] = (value >> 8) as u8;
+            }
+            0x20 => {
+                let index = target(1)?;
+                segment[index] = segment[index].wrapping_add(delta as u8);
+            }
+            _ => return Err(LoadError::Unsupported("65816 o65 relocation types")),
+        }
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, LoadError> {
+    let bytes = data.get(offset..offset + 2).ok_or(LoadError::Truncated)?;
+    return Ok(u16::from_le_bytes([bytes[0], bytes[1]]));
+}
+
+fn read_u16_be(data: &[u8], line_number: usize) -> Result<u16, LoadError> {
+    let bytes = data.get(0..2).ok_or_else(|| LoadError::parse(line_number, "record is too short"))?;
+    return Ok(u16::from_be_bytes([bytes[0], bytes[1]]));
+}
+
+fn read_u32_be(data: &[u8], line_number: usize) -> Result<u32, LoadError> {
+    let bytes = data.get(0..4).ok_or_else(|| LoadError::parse(line_number, "record is too short"))?;
+    return Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+}
+
+/// Parses a string of hexadecimal digit pairs into bytes
+fn parse_hex_bytes(hex: &str, line_number: usize) -> Result<Vec<u8>, LoadError> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return Err(LoadError::parse(line_number, "odd number of hexadecimal digits"));
+    }
+    return (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).map_err(|_| LoadError::parse(line_number, "invalid hexadecimal digit")))
+        .collect();
+}
+
+/// Errors that can occur while loading a program
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LoadError {
+    /// The file ended before the end of a header or segment
+    Truncated,
+    /// The file did not have a valid header for its format
+    InvalidHeader(&'static str),
+    /// The file used a feature of its format that can not be loaded
+    Unsupported(&'static str),
+    /// Part of the program would be loaded at or past the given address, outside of the 16-bit address space
+    OutOfRange(u32),
+    /// A line of a text format could not be parsed
+    Parse {
+        /// The line number
+        line: usize,
+        /// Description of the problem
+        message: &'static str,
+    },
+    /// The record on the given line of a text format failed its checksum
+    Checksum(usize),
+}
+
+impl LoadError {
+    fn parse(line: usize, message: &'static str) -> Self {
+        LoadError::Parse { line, message }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Truncated => write!(f, "File ended unexpectedly"),
+            LoadError::InvalidHeader(message) => write!(f, "Invalid header: {}", message),
+            LoadError::Unsupported(feature) => write!(f, "Unsupported feature: {}", feature),
+            LoadError::OutOfRange(address) => write!(f, "Program does not fit in the address space at {:X}", address),
+            LoadError::Parse { line, message } => write!(f, "Failed to parse line {}: {}", line, message),
+            LoadError::Checksum(line) => write!(f, "Checksum mismatch on line {}", line),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utilities::TestRam;
+
+    fn ram() -> TestRam {
+        TestRam::with_program(0, &[])
+    }
+
+    #[test]
+    fn test_raw_prg_and_apple() {
+        let mut ram = ram();
+        let program = load_prg(&mut ram, &[0x00, 0xc0, 0xa9, 0x01]).unwrap();
+        assert_eq!(program.entry, 0xc000);
+        assert_eq!(program.segments, vec![0xc000..=0xc001]);
+        assert_eq!(ram.ram[0xc001], 0x01);
+
+        let program = load_apple_binary(&mut ram, &[0x00, 0x03, 0x01, 0x00, 0x60, 0xff]).unwrap();
+        assert_eq!(program.segments, vec![0x0300..=0x0300]);
+        assert_eq!(ram.ram[0x0300], 0x60);
+
+        assert_eq!(load_raw(&mut ram, &[0; 3], 0xfffe), Err(LoadError::OutOfRange(0xfffe)));
+        assert_eq!(load_prg(&mut ram, &[0x00]), Err(LoadError::Truncated));
+    }
+
+    #[test]
+    fn test_xex() {
+        let mut ram = ram();
+        let data = [
+            0xff, 0xff, 0x00, 0x20, 0x01, 0x20, 0xa9, 0x01, // $2000-$2001
+            0xe2, 0x02, 0xe3, 0x02, 0x00, 0x21, // INITAD = $2100
+            0xff, 0xff, 0xe0, 0x02, 0xe1, 0x02, 0x00, 0x20, // RUNAD = $2000
+        ];
+        let program = load_xex(&mut ram, &data).unwrap();
+
+        assert_eq!(program.entry, 0x2000);
+        assert_eq!(program.init, vec![0x2100]);
+        assert_eq!(program.segments.len(), 3);
+        assert_eq!(ram.ram[0x2001], 0x01);
+        assert_eq!(load_xex(&mut ram, &[0x00, 0x20]), Err(LoadError::InvalidHeader("missing $FFFF header")));
+    }
+
+    #[test]
+    fn test_intel_hex() {
+        let mut ram = ram();
+        let text = ":03C00000A9018B08\n:040000050000C00037\n:00000001FF\n";
+        let program = load_intel_hex(&mut ram, text).unwrap();
+
+        assert_eq!(program.entry, 0xc000);
+        assert_eq!(ram.ram[0xc000..0xc003], [0xa9, 0x01, 0x8b]);
+        assert_eq!(load_intel_hex(&mut ram, ":03C00000A9018B09"), Err(LoadError::Checksum(1)));
+    }
+
+    #[test]
+    fn test_srec() {
+        let mut ram = ram();
+        let text = "S00600004844521B\nS106C000A9018B04\nS903C0003C\n";
+        let program = load_srec(&mut ram, text).unwrap();
+
+        assert_eq!(program.entry, 0xc000);
+        assert_eq!(program.segments, vec![0xc000..=0xc002]);
+        assert_eq!(ram.ram[0xc002], 0x8b);
+        assert_eq!(load_srec(&mut ram, "S106C000A9018B05"), Err(LoadError::Checksum(1)));
+    }
+
+    #[test]
+    fn test_o65() {
+        let mut ram = ram();
+        #[rustfmt::skip]
+        let data = [
+            0x01, 0x00, b'o', b'6', b'5', 0x00, // Marker and version
+            0x00, 0x00, // Mode
+            0x00, 0x10, 0x04, 0x00, // Text at $1000, 4 bytes
+            0x04, 0x10, 0x01, 0x00, // Data at $1004, 1 byte
+            0x05, 0x10, 0x00, 0x00, // Bss
+            0x00, 0x00, 0x00, 0x00, // Zero page
+            0x00, 0x00, // Stack
+            0x00, // No header options
+            0x4c, 0x04, 0x10, 0xa9, // JMP $1004, LDA #
+            0x42, // Data
+            0x00, 0x00, // No undefined references
+            0x02, 0x83, // WORD relocation of text offset 1, referring to the data segment
+            0x00, // End of text relocations
+            0x00, // End of data relocations
+        ];
+        let program = load_o65(&mut ram, &data, 0xc000).unwrap();
+
+        assert_eq!(program.entry, 0xc000);
+        assert_eq!(program.segments, vec![0xc000..=0xc003, 0xc004..=0xc004]);
+        assert_eq!(ram.ram[0xc000..0xc005], [0x4c, 0x04, 0xc0, 0xa9, 0x42]);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::bus::{Bus, BusBuilder, BusDevice, DeviceHandle};
+use crate::memory::{Ram, Rom};
+use crate::peripherals::Pia6820;
+use crate::{Interface6502, MOS6502};
+use std::collections::VecDeque;
+
+/// Address of the PIA, with the keyboard on port A and the display on port B
+const PIA_ADDRESS: u16 = 0xd010;
+
+/// The Apple-1, with 8KB of RAM at $0000-$1FFF, 4KB at $E000-$EFFF for Integer BASIC, a monitor ROM at the top of
+/// the address space and a 6820 PIA at $D010 connecting a keyboard and a terminal display:
+///
+/// | Address | Register |
+/// |---|---|
+/// | $D010 | KBD: the last key pressed, with bit 7 set |
+/// | $D011 | KBDCR: bit 7 is set when a key has been pressed, until KBD is read |
+/// | $D012 | DSP: characters written to bits 0-6 are displayed, and bit 7 reads as set while the display is busy |
+/// | $D013 | DSPCR |
+///
+/// Typed text is queued and fed to the keyboard one key at a time as the program reads it, and displayed characters
+/// are collected until taken. As on the original, the PIA's interrupt outputs are not connected
+pub struct Apple1 {
+    cpu: MOS6502,
+    bus: Bus,
+    pia: DeviceHandle<Pia6820>,
+    keyboard: VecDeque<u8>,
+    display: String,
+}
+
+impl Apple1 {
+    /// Creates the machine with a monitor ROM, such as the 256 byte Woz Monitor, mapped so that it ends at $FFFF,
+    /// and resets it
+    pub fn new(rom: Vec<u8>) -> Self {
+        assert!(!rom.is_empty() && rom.len() <= 0x1000, "The monitor ROM must be between 1 byte and 4KB");
+        let rom_start = (0x10000 - rom.len()) as u16;
+
+        let mut builder = BusBuilder::new();
+        let ram = builder.add_device(Ram::new(0x2000));
+        let basic_ram = builder.add_device(Ram::new(0x1000));
+        let pia = builder.add_device(Pia6820::new());
+        let rom = builder.add_device(Rom::new(rom));
+        builder
+            .map(0x0000..=0x1fff, &ram)
+            .map(0xe000..=0xefff, &basic_ram)
+            .map(PIA_ADDRESS..=0xd01f, &pia)
+            .map(rom_start..=0xffff, &rom);
+        let mut bus = builder.build();
+
+        // The display is always ready, and its busy signal on PB7 is never asserted
+        bus.device_mut(&pia).set_port_b(0x00);
+        let cpu = MOS6502::new_reset_position(&mut bus);
+        Apple1 {
+            cpu,
+            bus,
+            pia,
+            keyboard: VecDeque::new(),
+            display: String::new(),
+        }
+    }
+
+    /// Returns the processor
+    pub fn cpu(&self) -> &MOS6502 {
+        &self.cpu
+    }
+
+    /// Returns the processor, mutably
+    pub fn cpu_mut(&mut self) -> &mut MOS6502 {
+        &mut self.cpu
+    }
+
+    /// Returns the bus, for loading programs with the `loaders` module or inspecting memory
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
+    /// Copies a program into memory at the address
+    pub fn load(&mut self, address: u16, program: &[u8]) {
+        for (offset, byte) in program.iter().enumerate() {
+            self.bus.write(address.wrapping_add(offset as u16), *byte);
+        }
+    }
+
+    /// Presses the reset button, restarting the monitor
+    pub fn reset(&mut self) {
+        self.cpu.reset(&mut self.bus);
+    }
+
+    /// Queues text to be typed on the keyboard. Letters are typed in uppercase, and newlines as carriage returns
+    pub fn type_text(&mut self, text: &str) {
+        let keys = text.bytes().filter(u8::is_ascii).map(|key| match key {
+            b'\n' => b'\r',
+            key => key.to_ascii_uppercase(),
+        });
+        self.keyboard.extend(keys);
+    }
+
+    /// Returns the characters displayed since the last call, with carriage returns as newlines
+    pub fn take_output(&mut self) -> String {
+        std::mem::take(&mut self.display)
+    }
+
+    /// Executes an instruction, then services the keyboard and display. Returns the number of cycles the instruction
+    /// took
+    pub fn step(&mut self) -> u64 {
+        let start = self.cpu.total_cycles();
+        self.cpu.execute_instruction(&mut self.bus);
+        let cycles = self.cpu.total_cycles() - start;
+        self.bus.tick(cycles as u32);
+
+        let pia = self.bus.device_mut(&self.pia);
+        if !pia.cb2() {
+            // CB2 goes low when a character is written to the display, and the display acknowledges it on CB1
+            match pia.port_b() & 0x7f {
+                b'\r' => self.display.push('\n'),
+                character => self.display.push(character as char),
+            }
+            pia.set_cb1(false);
+            pia.set_cb1(true);
+        }
+        let key_waiting = pia.read(1) & 0x80 != 0;
+        if !key_waiting {
+            if let Some(key) = self.keyboard.pop_front() {
+                pia.set_port_a(key | 0x80);
+                pia.set_ca1(false);
+                pia.set_ca1(true);
+            }
+        }
+        return cycles;
+    }
+
+    /// Executes instructions until at least the given number of cycles have passed, or the processor jams
+    pub fn run(&mut self, cycles: u64) {
+        let end = self.cpu.total_cycles() + cycles;
+        while self.cpu.total_cycles() < end && !self.cpu.is_jammed() {
+            self.step();
+        }
+    }
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Minimal monitor that initializes the PIA like the Woz Monitor, then echoes typed keys
+    fn echo_rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x100];
+        #[rustfmt::skip]
+        let program = [
+            0xa0, 0x7f,       // LDY #$7F
+            0x8c, 0x12, 0xd0, // STY DSP (data direction)
+            0xa9, 0xa7,       // LDA #$A7
+            0x8d, 0x11, 0xd0, // STA KBDCR
+            0x8d, 0x13, 0xd0, // STA DSPCR
+            0xad, 0x11, 0xd0, // LOOP: LDA KBDCR
+            0x10, 0xfb,       // BPL LOOP
+            0xad, 0x10, 0xd0, // LDA KBD
+            0x2c, 0x12, 0xd0, // ECHO: BIT DSP
+            0x30, 0xfb,       // BMI ECHO
+            0x8d, 0x12, 0xd0, // STA DSP
+            0x4c, 0x0d, 0xff, // JMP LOOP
+        ];
+        rom[..program.len()].clone_from_slice(&program);
+        rom[0xfc..0x100].clone_from_slice(&[0x00, 0xff, 0x00, 0x00]);
+        rom
+    }
+
+    #[test]
+    fn test_keyboard_and_display() {
+        let mut apple = Apple1::new(echo_rom());
+        apple.type_text("Hello\nworld");
+        apple.run(10_000);
+
+        assert_eq!(apple.take_output(), "HELLO\nWORLD");
+        assert_eq!(apple.take_output(), "");
+    }
+
+    #[test]
+    fn test_load_program() {
+        let mut apple = Apple1::new(echo_rom());
+        apple.run(100);
+        // LDA #'A' | $80, STA DSP, JMP $FF0D
+        apple.load(0x0280, &[0xa9, 0xc1, 0x8d, 0x12, 0xd0, 0x4c, 0x0d, 0xff]);
+        apple.load(0xff00, &[0xea]);
+        apple.cpu_mut().set_program_counter(0x0280);
+        apple.type_text("B");
+        apple.run(1000);
+
+        assert_eq!(apple.take_output(), "AB");
+        assert_eq!(apple.bus_mut().read(0xff00), 0xa0, "the monitor ROM should not be writable");
+    }
+}
@@ -0,0 +1,18 @@
+//! ### MACHINES
+//! This module contains complete reference systems assembled from the processor, the `Bus` and the
+//! devices in the `memory` and `peripherals` modules, showing how the parts of the crate fit together,
+//! and the virtual machine of cc65's `sim65` simulator for running compiled C programs.
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let mut apple = Apple1::new(fs::read("wozmon.bin")?);
+//! apple.type_text("FF00.FF0F\n");
+//! apple.run(100_000);
+//! print!("{}", apple.take_output());
+//! ```
+
+mod apple1;
+mod sim65;
+
+pub use apple1::Apple1;
+pub use sim65::{Sim65, Sim65Error, Sim65Header};
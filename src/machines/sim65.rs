@@ -0,0 +1,640 @@
+use crate::bus::{Bus, BusBuilder};
+use crate::host_calls::HostCalls;
+use crate::loaders::{load_raw, LoadError};
+use crate::memory::Ram;
+use crate::{Interface6502, MOS6502};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+
+/// Magic bytes at the start of every sim65 program
+const MAGIC: &[u8; 5] = b"sim65";
+/// The header version written by current versions of ld65
+const VERSION: u8 = 2;
+/// The length of the header before the program data
+const HEADER_LENGTH: usize = 12;
+
+/// Address of the first paravirtualization hook, with one hook at each following address
+const PARAVIRT_BASE: u16 = 0xfff4;
+/// The routines called through the paravirtualization hooks, in address order
+const ROUTINES: [Routine; 6] = [Routine::Open, Routine::Close, Routine::Read, Routine::Write, Routine::Args, Routine::Exit];
+/// The number of cycles each hook takes, those of the RTS returning from it
+const HOOK_CYCLES: u64 = 6;
+
+/// `open` flag bits selecting read only, write only or read and write access
+const OPEN_ACCESS: u16 = 0x03;
+const OPEN_READ: u16 = 0x01;
+const OPEN_WRITE: u16 = 0x02;
+/// `open` flag creating the file if it does not exist
+const OPEN_CREATE: u16 = 0x10;
+/// `open` flag truncating the file
+const OPEN_TRUNCATE: u16 = 0x20;
+/// `open` flag appending writes to the end of the file
+const OPEN_APPEND: u16 = 0x40;
+/// `open` flag failing if the file already exists
+const OPEN_EXCLUSIVE: u16 = 0x80;
+/// The longest path `open` accepts, including its terminating zero
+const PATH_LENGTH: u16 = 1024;
+/// The value returned by hooks when they fail, -1 as a C `int`
+const FAILURE: u16 = 0xffff;
+
+/// Routines the C library of the sim6502 target calls by jumping to the paravirtualization hooks
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Routine {
+    Open,
+    Close,
+    Read,
+    Write,
+    Args,
+    Exit,
+}
+
+/// The header of a program linked for cc65's sim6502 target
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Sim65Header {
+    /// The header version, which must be 2
+    pub version: u8,
+    /// The processor the program was compiled for, 0 for the 6502 and 1 for the 65C02
+    pub cpu: u8,
+    /// The zero page address of the C stack pointer, used to pass arguments to the hooks
+    pub stack_pointer_address: u8,
+    /// The address the program is loaded at
+    pub load_address: u16,
+    /// The address execution begins at
+    pub reset_address: u16,
+}
+
+impl Sim65Header {
+    /// Parses the header at the start of a program
+    pub fn parse(image: &[u8]) -> Result<Self, Sim65Error> {
+        if image.len() < HEADER_LENGTH {
+            return Err(Sim65Error::Load(LoadError::Truncated));
+        }
+        if &image[..5] != MAGIC {
+            return Err(Sim65Error::Load(LoadError::InvalidHeader("missing sim65 signature")));
+        }
+        let header = Sim65Header {
+            version: image[5],
+            cpu: image[6],
+            stack_pointer_address: image[7],
+            load_address: u16::from_le_bytes([image[8], image[9]]),
+            reset_address: u16::from_le_bytes([image[10], image[11]]),
+        };
+        if header.version != VERSION {
+            return Err(Sim65Error::UnsupportedVersion(header.version));
+        }
+        if header.cpu != 0 {
+            return Err(Sim65Error::UnsupportedCpu(header.cpu));
+        }
+        return Ok(header);
+    }
+}
+
+/// The virtual machine of cc65's `sim65` simulator, which runs programs built with `cl65 -t sim6502` in 64KB of
+/// RAM. The target's C library calls the host through paravirtualization hooks, which are registered as `HostCalls`
+/// at the addresses given to them in cc65's `libsrc/sim6502/paravirt.s`:
+///
+/// | Address | Routine |
+/// |---|---|
+/// | $FFF4 | `open` |
+/// | $FFF5 | `close` |
+/// | $FFF6 | `read` |
+/// | $FFF7 | `write` |
+/// | $FFF8 | `args`: fills in `argv` and returns `argc` |
+/// | $FFF9 | `exit`: stops the program with the exit code in the accumulator |
+///
+/// File descriptors 0, 1 and 2 are the standard streams, which default to the host's, and other files are opened
+/// on the host relative to its working directory
+pub struct Sim65 {
+    cpu: MOS6502,
+    bus: Bus,
+    header: Sim65Header,
+    host_calls: HostCalls,
+    host: Rc<RefCell<Host>>,
+}
+
+/// The host side of the paravirtualization interface, shared with the functions registered for the hooks
+struct Host {
+    stack_pointer_address: u8,
+    arguments: Vec<String>,
+    files: BTreeMap<u16, File>,
+    stdin: Box<dyn Read>,
+    stdout: Box<dyn Write>,
+    stderr: Box<dyn Write>,
+    exit_code: Option<u8>,
+}
+
+impl Sim65 {
+    /// Creates the machine with a program in the sim65 format loaded, ready to run from its reset address
+    pub fn new(image: &[u8]) -> Result<Self, Sim65Error> {
+        let header = Sim65Header::parse(image)?;
+        let mut builder = BusBuilder::new();
+        let ram = builder.add_device(Ram::new(0x10000));
+        builder.map(0x0000..=0xffff, &ram);
+        let mut bus = builder.build();
+        load_raw(&mut bus, &image[HEADER_LENGTH..], header.load_address).map_err(Sim65Error::Load)?;
+        write_word(&mut bus, 0xfffc, header.reset_address);
+
+        let host = Rc::new(RefCell::new(Host {
+            stack_pointer_address: header.stack_pointer_address,
+            arguments: Vec::new(),
+            files: BTreeMap::new(),
+            stdin: Box::new(io::stdin()),
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            exit_code: None,
+        }));
+        let mut host_calls = HostCalls::new();
+        for (index, &routine) in ROUTINES.iter().enumerate() {
+            let host = Rc::clone(&host);
+            let function = move |cpu: &mut MOS6502, memory: &mut dyn Interface6502| host.borrow_mut().call(routine, cpu, memory);
+            host_calls.add(PARAVIRT_BASE + index as u16, HOOK_CYCLES, function);
+        }
+
+        return Ok(Sim65 {
+            cpu: MOS6502::new_start(header.reset_address),
+            bus,
+            header,
+            host_calls,
+            host,
+        });
+    }
+
+    /// Sets the command line arguments the program receives, beginning with the program name as `argv[0]`
+    pub fn arguments<S: AsRef<str>>(self, arguments: &[S]) -> Self {
+        self.host.borrow_mut().arguments = arguments.iter().map(|argument| argument.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Replaces the host's standard input as the stream read from file descriptor 0
+    pub fn stdin<R: Read + 'static>(self, stdin: R) -> Self {
+        self.host.borrow_mut().stdin = Box::new(stdin);
+        self
+    }
+
+    /// Replaces the host's standard output as the stream written to file descriptor 1
+    pub fn stdout<W: Write + 'static>(self, stdout: W) -> Self {
+        self.host.borrow_mut().stdout = Box::new(stdout);
+        self
+    }
+
+    /// Replaces the host's standard error as the stream written to file descriptor 2
+    pub fn stderr<W: Write + 'static>(self, stderr: W) -> Self {
+        self.host.borrow_mut().stderr = Box::new(stderr);
+        self
+    }
+
+    /// Returns the header of the loaded program
+    pub fn header(&self) -> &Sim65Header {
+        &self.header
+    }
+
+    /// Returns the processor
+    pub fn cpu(&self) -> &MOS6502 {
+        &self.cpu
+    }
+
+    /// Returns the processor, mutably
+    pub fn cpu_mut(&mut self) -> &mut MOS6502 {
+        &mut self.cpu
+    }
+
+    /// Returns the bus, for inspecting memory
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
+    /// Returns the program's exit code, once it has exited
+    pub fn exit_code(&self) -> Option<u8> {
+        self.host.borrow().exit_code
+    }
+
+    /// Executes an instruction, or the hook at the program counter. Does nothing once the program has exited
+    pub fn step(&mut self) {
+        if self.exit_code().is_none() {
+            self.host_calls.step_bus(&mut self.cpu, &mut self.bus);
+        }
+    }
+
+    /// Runs the program until it exits, returning its exit code. Fails if it is still running after the given
+    /// number of cycles, or the processor jams
+    pub fn run(&mut self, max_cycles: u64) -> Result<u8, Sim65Error> {
+        let end = self.cpu.total_cycles().saturating_add(max_cycles);
+        loop {
+            if let Some(exit_code) = self.exit_code() {
+                return Ok(exit_code);
+            } else if self.cpu.is_jammed() {
+                return Err(Sim65Error::Jammed(self.cpu.state().program_counter));
+            } else if self.cpu.total_cycles() >= end {
+                return Err(Sim65Error::CycleLimit);
+            }
+            self.step();
+        }
+    }
+}
+
+impl Host {
+    /// Runs a routine, returning its result in AX
+    fn call(&mut self, routine: Routine, cpu: &mut MOS6502, memory: &mut dyn Interface6502) {
+        let result = match routine {
+            Routine::Open => self.open(cpu, memory),
+            Routine::Close => self.close(cpu),
+            Routine::Read => self.read(cpu, memory),
+            Routine::Write => self.write(cpu, memory),
+            Routine::Args => self.args(cpu, memory),
+            Routine::Exit => {
+                self.exit_code = Some(cpu.state().accumulator);
+                return;
+            }
+        };
+        let mut state = cpu.state();
+        state.accumulator = result as u8;
+        state.x_register = (result >> 8) as u8;
+        cpu.set_state(&state);
+    }
+
+    /// Returns the 16-bit value in the accumulator and x register, where the last argument of a hook is passed
+    fn ax(cpu: &MOS6502) -> u16 {
+        let state = cpu.state();
+        return u16::from_le_bytes([state.accumulator, state.x_register]);
+    }
+
+    /// Reads the argument at the top of the C stack, then moves the stack pointer up by the increment
+    fn pop_argument(&self, memory: &mut dyn Interface6502, increment: u16) -> u16 {
+        let pointer = u16::from(self.stack_pointer_address);
+        let stack_pointer = read_word(memory, pointer);
+        let value = read_word(memory, stack_pointer);
+        write_word(memory, pointer, stack_pointer.wrapping_add(increment));
+        return value;
+    }
+
+    /// Copies the arguments onto the C stack, below an array of pointers to them, and stores the address of the
+    /// array at the address in AX
+    fn args(&mut self, cpu: &MOS6502, memory: &mut dyn Interface6502) -> u16 {
+        let argv = Host::ax(cpu);
+        let pointer = u16::from(self.stack_pointer_address);
+        let count = self.arguments.len() as u16;
+        let mut array = read_word(memory, pointer).wrapping_sub((count + 1) * 2);
+        write_word(memory, argv, array);
+
+        let mut stack_pointer = array;
+        for argument in &self.arguments {
+            stack_pointer = stack_pointer.wrapping_sub(argument.len() as u16 + 1);
+            for (offset, byte) in argument.bytes().chain(Some(0)).enumerate() {
+                memory.write(stack_pointer.wrapping_add(offset as u16), byte);
+            }
+            write_word(memory, array, stack_pointer);
+            array = array.wrapping_add(2);
+        }
+        write_word(memory, array, 0);
+        write_word(memory, pointer, stack_pointer);
+        return count;
+    }
+
+    /// `int open(const char* name, int flags, ...)`, whose arguments are all on the C stack with their total size
+    /// in the y register. The optional mode is ignored
+    fn open(&mut self, cpu: &MOS6502, memory: &mut dyn Interface6502) -> u16 {
+        let argument_size = u16::from(cpu.state().y_register);
+        self.pop_argument(memory, argument_size.saturating_sub(4));
+        let flags = self.pop_argument(memory, 2);
+        let name = self.pop_argument(memory, 2);
+
+        let mut path = Vec::new();
+        for offset in 0..PATH_LENGTH {
+            match memory.read(name.wrapping_add(offset)) {
+                0 => break,
+                byte => path.push(byte),
+            }
+        }
+        let path = String::from_utf8_lossy(&path).into_owned();
+
+        let mut options = OpenOptions::new();
+        options
+            .read(flags & OPEN_ACCESS != OPEN_WRITE)
+            .write(flags & OPEN_ACCESS != OPEN_READ)
+            .truncate(flags & OPEN_TRUNCATE != 0)
+            .append(flags & OPEN_APPEND != 0);
+        if flags & OPEN_EXCLUSIVE != 0 {
+            options.create_new(true);
+        } else {
+            options.create(flags & OPEN_CREATE != 0);
+        }
+        let descriptor = (3..FAILURE).find(|descriptor| !self.files.contains_key(descriptor));
+        return match (descriptor, options.open(&path)) {
+            (Some(descriptor), Ok(file)) => {
+                self.files.insert(descriptor, file);
+                descriptor
+            }
+            (_, Err(error)) => {
+                warn!("sim65 failed to open {}: {}", path, error);
+                FAILURE
+            }
+            (None, Ok(_)) => FAILURE,
+        };
+    }
+
+    /// `int close(int fd)`
+    fn close(&mut self, cpu: &MOS6502) -> u16 {
+        return match Host::ax(cpu) {
+            0..=2 => 0,
+            descriptor => match self.files.remove(&descriptor) {
+                Some(_) => 0,
+                None => FAILURE,
+            },
+        };
+    }
+
+    /// `int read(int fd, void* buf, unsigned count)`
+    fn read(&mut self, cpu: &MOS6502, memory: &mut dyn Interface6502) -> u16 {
+        let count = Host::ax(cpu);
+        let buffer = self.pop_argument(memory, 2);
+        let descriptor = self.pop_argument(memory, 2);
+        let mut data = vec![0; count as usize];
+        let result = match descriptor {
+            0 => self.stdin.read(&mut data),
+            descriptor => match self.files.get_mut(&descriptor) {
+                Some(file) => file.read(&mut data),
+                None => return FAILURE,
+            },
+        };
+        return match result {
+            Ok(length) => {
+                for (offset, byte) in data[..length].iter().enumerate() {
+                    memory.write(buffer.wrapping_add(offset as u16), *byte);
+                }
+                length as u16
+            }
+            Err(_) => FAILURE,
+        };
+    }
+
+    /// `int write(int fd, const void* buf, unsigned count)`
+    fn write(&mut self, cpu: &MOS6502, memory: &mut dyn Interface6502) -> u16 {
+        let count = Host::ax(cpu);
+        let buffer = self.pop_argument(memory, 2);
+        let descriptor = self.pop_argument(memory, 2);
+        let data: Vec<u8> = (0..count).map(|offset| memory.read(buffer.wrapping_add(offset))).collect();
+        let result = match descriptor {
+            1 => self.stdout.write_all(&data).and_then(|_| self.stdout.flush()),
+            2 => self.stderr.write_all(&data).and_then(|_| self.stderr.flush()),
+            descriptor => match self.files.get_mut(&descriptor) {
+                Some(file) => file.write_all(&data),
+                None => return FAILURE,
+            },
+        };
+        return match result {
+            Ok(()) => count,
+            Err(_) => FAILURE,
+        };
+    }
+}
+
+fn read_word(interface: &mut dyn Interface6502, address: u16) -> u16 {
+    u16::from_le_bytes([interface.read(address), interface.read(address.wrapping_add(1))])
+}
+
+fn write_word(interface: &mut dyn Interface6502, address: u16, value: u16) {
+    let [low, high] = value.to_le_bytes();
+    interface.write(address, low);
+    interface.write(address.wrapping_add(1), high);
+}
+
+/// Errors that can occur while loading or running a sim65 program
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Sim65Error {
+    /// The program could not be loaded
+    Load(LoadError),
+    /// The header was of an unsupported version
+    UnsupportedVersion(u8),
+    /// The program was compiled for a processor other than the 6502
+    UnsupportedCpu(u8),
+    /// The program was still running when the cycle limit was reached
+    CycleLimit,
+    /// The processor jammed at the given address
+    Jammed(u16),
+}
+
+impl fmt::Display for Sim65Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sim65Error::Load(error) => write!(f, "Failed to load sim65 program: {}", error),
+            Sim65Error::UnsupportedVersion(version) => write!(f, "Unsupported sim65 header version {}", version),
+            Sim65Error::UnsupportedCpu(cpu) => write!(f, "Unsupported sim65 processor type {}", cpu),
+            Sim65Error::CycleLimit => write!(f, "The program did not exit before the cycle limit"),
+            Sim65Error::Jammed(address) => write!(f, "The processor jammed at ${:04X}", address),
+        }
+    }
+}
+
+impl std::error::Error for Sim65Error {}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utilities::SharedOutput;
+
+    /// Builds a program loaded at $0200 with the C stack pointer at $02, placing data at addresses after the code
+    fn image(code: &[u8], data: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut image = b"sim65\x02\x00\x02\x00\x02\x00\x02".to_vec();
+        image.extend_from_slice(code);
+        for (address, bytes) in data {
+            let offset = HEADER_LENGTH + (*address as usize - 0x0200);
+            if image.len() < offset + bytes.len() {
+                image.resize(offset + bytes.len(), 0);
+            }
+            image[offset..offset + bytes.len()].clone_from_slice(bytes);
+        }
+        image
+    }
+
+    #[rustfmt::skip]
+    const SET_STACK: [u8; 8] = [
+        0xa9, 0x00,       // LDA #$00
+        0x85, 0x02,       // STA sp
+        0xa9, 0x03,       // LDA #$03
+        0x85, 0x03,       // STA sp+1
+    ];
+
+    #[test]
+    fn test_header() {
+        let header = Sim65Header::parse(&image(&[], &[])).unwrap();
+        assert_eq!(header.load_address, 0x0200);
+        assert_eq!(header.stack_pointer_address, 0x02);
+
+        assert_eq!(Sim65Header::parse(b"sim65\x02"), Err(Sim65Error::Load(LoadError::Truncated)));
+        assert_eq!(
+            Sim65Header::parse(b"sim65\x01\x00\x02\x00\x02\x00\x02"),
+            Err(Sim65Error::UnsupportedVersion(1))
+        );
+        assert_eq!(
+            Sim65Header::parse(b"sim65\x02\x01\x02\x00\x02\x00\x02"),
+            Err(Sim65Error::UnsupportedCpu(1))
+        );
+        assert!(Sim65Header::parse(b"sim66\x02\x00\x02\x00\x02\x00\x02").is_err());
+    }
+
+    #[test]
+    fn test_write_and_exit() {
+        #[rustfmt::skip]
+        let code = [
+            &SET_STACK[..],
+            &[0xa9, 0x05],       // LDA #5
+            &[0xa2, 0x00],       // LDX #0
+            &[0x20, 0xf7, 0xff], // JSR write
+            &[0x8d, 0x20, 0x03], // STA $0320
+            &[0xa9, 0x2a],       // LDA #42
+            &[0x20, 0xf9, 0xff], // JSR exit
+        ].concat();
+        // The buffer is on top of the C stack, above the file descriptor
+        let image = image(&code, &[(0x0300, &[0x10, 0x03, 0x01, 0x00]), (0x0310, b"hello")]);
+        let output = SharedOutput::default();
+        let mut sim65 = Sim65::new(&image).unwrap().stdout(output.clone());
+
+        assert_eq!(sim65.run(10_000), Ok(42));
+        assert_eq!(sim65.exit_code(), Some(42));
+        assert_eq!(&*output.0.borrow(), b"hello");
+        assert_eq!(sim65.bus_mut().read(0x0320), 5);
+        assert_eq!(read_word(sim65.bus_mut(), 0x0002), 0x0304);
+    }
+
+    #[test]
+    fn test_args() {
+        #[rustfmt::skip]
+        let code = [
+            &SET_STACK[..],
+            &[0xa9, 0x40],       // LDA #<$0340
+            &[0xa2, 0x03],       // LDX #>$0340
+            &[0x20, 0xf8, 0xff], // JSR args
+            &[0x20, 0xf9, 0xff], // JSR exit
+        ].concat();
+        let mut sim65 = Sim65::new(&image(&code, &[])).unwrap().arguments(&["test", "a"]);
+
+        assert_eq!(sim65.run(10_000), Ok(2));
+        let bus = sim65.bus_mut();
+        let argv = read_word(bus, 0x0340);
+        assert_eq!(argv, 0x02fa);
+        assert_eq!(read_word(bus, argv + 4), 0);
+        let argument = read_word(bus, argv + 2);
+        assert_eq!((bus.read(argument), bus.read(argument + 1)), (b'a', 0));
+        assert_eq!(read_word(bus, 0x0002), 0x02f3);
+    }
+
+    #[test]
+    fn test_file_io() {
+        let path = std::env::temp_dir().join(format!("sim65_test_{}.txt", std::process::id()));
+        let mut name = path.to_str().unwrap().as_bytes().to_vec();
+        name.push(0);
+        #[rustfmt::skip]
+        let code = [
+            &SET_STACK[..],
+            &[0xa0, 0x04],       // LDY #4
+            &[0x20, 0xf4, 0xff], // JSR open
+            &[0x8d, 0x06, 0x03], // STA $0306
+            &[0x8e, 0x07, 0x03], // STX $0307
+            &[0xa9, 0x03],       // LDA #3
+            &[0xa2, 0x00],       // LDX #0
+            &[0x20, 0xf7, 0xff], // JSR write
+            &[0xad, 0x06, 0x03], // LDA $0306
+            &[0xae, 0x07, 0x03], // LDX $0307
+            &[0x20, 0xf5, 0xff], // JSR close
+            &[0x20, 0xf9, 0xff], // JSR exit
+        ].concat();
+        // open(name, O_WRONLY | O_CREAT | O_TRUNC), then write(fd, "abc", 3)
+        let stack = [0x32, 0x00, 0x00, 0x04, 0x10, 0x03, 0x00, 0x00];
+        let image = image(&code, &[(0x0300, &stack), (0x0310, b"abc"), (0x0400, &name)]);
+        let mut sim65 = Sim65::new(&image).unwrap();
+
+        let result = sim65.run(10_000);
+        let contents = std::fs::read(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result, Ok(0), "closing the file should return 0");
+        assert_eq!(contents.unwrap(), b"abc");
+    }
+
+    /// A program in the layout ld65 links for the sim6502 target: the header from `exehdr.s`, then the startup code
+    /// from `crt0.s` at $0200, `initmainargs` from `mainargs.s`, a `main` calling `write(1, "hello\n", 6)` and
+    /// returning `argc`, and the runtime's `pushax`. The bytes were assembled by hand following those cc65 sources,
+    /// not produced by ld65
+    #[rustfmt::skip]
+    const LD65_PROGRAM: [u8; 103] = [
+        b's', b'i', b'm', b'6', b'5',
+        0x02,             // Header version
+        0x00,             // 6502
+        0x00,             // sp
+        0x00, 0x02,       // Load address
+        0x00, 0x02,       // Reset address
+        // startup:
+        0xd8,             // $0200 CLD
+        0xa2, 0xff,       // $0201 LDX #$FF
+        0x9a,             // $0203 TXS
+        0xa9, 0xf0,       // $0204 LDA #<$FFF0
+        0xa2, 0xff,       // $0206 LDX #>$FFF0
+        0x85, 0x00,       // $0208 STA sp
+        0x86, 0x01,       // $020A STX sp+1
+        0x20, 0x15, 0x02, // $020C JSR initmainargs
+        0x20, 0x23, 0x02, // $020F JSR _main
+        0x4c, 0xf9, 0xff, // $0212 JMP exit
+        // initmainargs:
+        0xa9, 0x5d,       // $0215 LDA #<__argv
+        0xa2, 0x02,       // $0217 LDX #>__argv
+        0x20, 0xf8, 0xff, // $0219 JSR args
+        0x8d, 0x5b, 0x02, // $021C STA __argc
+        0x8e, 0x5c, 0x02, // $021F STX __argc+1
+        0x60,             // $0222 RTS
+        // _main:
+        0xa9, 0x01,       // $0223 LDA #1
+        0xa2, 0x00,       // $0225 LDX #0
+        0x20, 0x3f, 0x02, // $0227 JSR pushax
+        0xa9, 0x55,       // $022A LDA #<message
+        0xa2, 0x02,       // $022C LDX #>message
+        0x20, 0x3f, 0x02, // $022E JSR pushax
+        0xa9, 0x06,       // $0231 LDA #6
+        0xa2, 0x00,       // $0233 LDX #0
+        0x20, 0xf7, 0xff, // $0235 JSR _write
+        0xad, 0x5b, 0x02, // $0238 LDA __argc
+        0xae, 0x5c, 0x02, // $023B LDX __argc+1
+        0x60,             // $023E RTS
+        // pushax:
+        0x48,             // $023F PHA
+        0xa5, 0x00,       // $0240 LDA sp
+        0x38,             // $0242 SEC
+        0xe9, 0x02,       // $0243 SBC #2
+        0x85, 0x00,       // $0245 STA sp
+        0xb0, 0x02,       // $0247 BCS $024B
+        0xc6, 0x01,       // $0249 DEC sp+1
+        0xa0, 0x01,       // $024B LDY #1
+        0x8a,             // $024D TXA
+        0x91, 0x00,       // $024E STA (sp),Y
+        0x68,             // $0250 PLA
+        0x88,             // $0251 DEY
+        0x91, 0x00,       // $0252 STA (sp),Y
+        0x60,             // $0254 RTS
+        // message:
+        b'h', b'e', b'l', b'l', b'o', b'\n',
+        // __argc and __argv follow at $025B and $025D, in the BSS segment that is not stored in the file
+    ];
+
+    #[test]
+    fn test_ld65_layout() {
+        let output = SharedOutput::default();
+        let mut sim65 = Sim65::new(&LD65_PROGRAM).unwrap().arguments(&["hello", "a", "b"]).stdout(output.clone());
+
+        assert_eq!(sim65.run(10_000), Ok(3));
+        assert_eq!(&*output.0.borrow(), b"hello\n");
+        let bus = sim65.bus_mut();
+        let argv = read_word(bus, 0x025d);
+        let argument = read_word(bus, argv + 2);
+        assert_eq!((bus.read(argument), bus.read(argument + 1)), (b'a', 0));
+    }
+
+    #[test]
+    fn test_cycle_limit() {
+        let mut sim65 = Sim65::new(&image(&[0x4c, 0x00, 0x02], &[])).unwrap();
+        assert_eq!(sim65.run(1000), Err(Sim65Error::CycleLimit));
+    }
+}
@@ -0,0 +1,230 @@
+//! ### MEMORY
+//! This module contains ready-made memory devices for mapping into a `Bus`:
+//! * `Ram` of any size
+//! * `Rom` that ignores writes, optionally logging them as warnings
+//! * `Mirror`, which repeats a smaller device across a larger window
+//! * `OpenBus`, which leaves a region unconnected so reads return the last value on the data bus
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let mut builder = BusBuilder::new();
+//! let ram = builder.add_device(Mirror::new(Ram::new(0x0800), 0x0800)); // NES internal RAM
+//! let rom = builder.add_device(Rom::new(prg_rom).report_writes(true));
+//! builder.map(0x0000..=0x1fff, &ram).map(0x8000..=0xffff, &rom);
+//! let mut bus = builder.build();
+//! ```
+
+use super::bus::BusDevice;
+
+/// Read and write memory of any size. Offsets past the end of the memory wrap around to the start
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Ram {
+    data: Vec<u8>,
+}
+
+impl Ram {
+    /// Creates zeroed memory of the given size in bytes
+    pub fn new(size: usize) -> Self {
+        assert!(size > 0, "Memory must have a size");
+        return Ram { data: vec![0; size] };
+    }
+
+    /// Creates memory holding the given bytes
+    pub fn from_bytes(data: Vec<u8>) -> Self {
+        assert!(!data.is_empty(), "Memory must have a size");
+        return Ram { data };
+    }
+
+    /// Copies bytes into the memory, beginning at the offset
+    pub fn load(&mut self, offset: usize, data: &[u8]) {
+        self.data[offset..offset + data.len()].clone_from_slice(data);
+    }
+
+    /// Returns the contents of the memory
+    pub fn as_slice(&self) -> &[u8] {
+        return &self.data;
+    }
+
+    /// Returns the contents of the memory, mutably
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        return &mut self.data;
+    }
+}
+
+impl BusDevice for Ram {
+    fn read(&mut self, offset: u16) -> u8 {
+        return self.data[offset as usize % self.data.len()];
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        let length = self.data.len();
+        self.data[offset as usize % length] = data
+    }
+}
+
+/// Read only memory. Offsets past the end of the memory wrap around to the start
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Rom {
+    data: Vec<u8>,
+    report_writes: bool,
+    ignored_writes: u64,
+}
+
+impl Rom {
+    /// Creates read only memory holding the given bytes
+    pub fn new(data: Vec<u8>) -> Self {
+        assert!(!data.is_empty(), "Memory must have a size");
+        return Rom {
+            data,
+            report_writes: false,
+            ignored_writes: 0,
+        };
+    }
+
+    /// Sets whether writes to the memory are logged as warnings
+    pub fn report_writes(mut self, report_writes: bool) -> Self {
+        self.report_writes = report_writes;
+        return self;
+    }
+
+    /// Returns the number of writes that have been ignored
+    pub fn ignored_writes(&self) -> u64 {
+        return self.ignored_writes;
+    }
+
+    /// Returns the contents of the memory
+    pub fn as_slice(&self) -> &[u8] {
+        return &self.data;
+    }
+}
+
+impl BusDevice for Rom {
+    fn read(&mut self, offset: u16) -> u8 {
+        return self.data[offset as usize % self.data.len()];
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        self.ignored_writes += 1;
+        if self.report_writes {
+            warn!("Ignored write of {:02X} to read only memory at offset {:04X}", data, offset);
+        }
+    }
+}
+
+/// Device wrapper repeating the first bytes of a device across the whole window it is mapped into, like
+/// the NES's 2KB of RAM appearing four times between $0000 and $1FFF
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Mirror<D> {
+    device: D,
+    size: u16,
+}
+
+impl<D: BusDevice> Mirror<D> {
+    /// Wraps a device so offsets repeat every `size` bytes
+    pub fn new(device: D, size: u16) -> Self {
+        assert!(size > 0, "Mirrored window must have a size");
+        return Mirror { device, size };
+    }
+
+    /// Returns the wrapped device
+    pub fn inner(&self) -> &D {
+        return &self.device;
+    }
+
+    /// Returns the wrapped device, mutably
+    pub fn inner_mut(&mut self) -> &mut D {
+        return &mut self.device;
+    }
+}
+
+impl<D: BusDevice> BusDevice for Mirror<D> {
+    fn read(&mut self, offset: u16) -> u8 {
+        return self.device.read(offset % self.size);
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        self.device.write(offset % self.size, data)
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        self.device.tick(cycles)
+    }
+
+    fn irq(&self) -> bool {
+        return self.device.irq();
+    }
+
+    fn nmi(&self) -> bool {
+        return self.device.nmi();
+    }
+
+    fn bank(&self, offset: u16) -> Option<usize> {
+        return self.device.bank(offset % self.size);
+    }
+}
+
+/// An unconnected region. The bus treats regions mapped to this device as unmapped, so reads return the last
+/// value on the data bus and writes are ignored, even where the region overlaps lower priority mappings
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct OpenBus;
+
+impl BusDevice for OpenBus {
+    fn read(&mut self, _offset: u16) -> u8 {
+        return 0;
+    }
+
+    fn write(&mut self, _offset: u16, _data: u8) {}
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::BusBuilder;
+    use crate::Interface6502;
+
+    #[test]
+    fn test_ram_and_rom() {
+        let mut builder = BusBuilder::new();
+        let ram = builder.add_device(Ram::new(0x0800));
+        let rom = builder.add_device(Rom::new(vec![0x4c, 0x00, 0x80]));
+        builder.map(0x0000..=0x07ff, &ram).map(0x8000..=0x8002, &rom);
+        let mut bus = builder.build();
+
+        bus.write(0x0123, 0x45);
+        bus.write(0x8001, 0x45);
+        assert_eq!(bus.read(0x0123), 0x45);
+        assert_eq!(bus.read(0x8001), 0x00);
+        assert_eq!(bus.device(&ram).as_slice()[0x0123], 0x45);
+        assert_eq!(bus.device(&rom).ignored_writes(), 1);
+    }
+
+    #[test]
+    fn test_mirror() {
+        let mut builder = BusBuilder::new();
+        let ram = builder.add_device(Mirror::new(Ram::new(0x0800), 0x0800));
+        builder.map(0x0000..=0x1fff, &ram);
+        let mut bus = builder.build();
+
+        bus.write(0x1801, 0x99);
+        assert_eq!(bus.read(0x0001), 0x99);
+        assert_eq!(bus.read(0x0801), 0x99);
+        assert_eq!(bus.device(&ram).inner().as_slice()[1], 0x99);
+    }
+
+    #[test]
+    fn test_open_bus() {
+        let mut builder = BusBuilder::new();
+        let ram = builder.add_device(Ram::new(0x10000));
+        let open = builder.add_device(OpenBus);
+        builder.map(0x0000..=0xffff, &ram).map_with_priority(0x4000..=0x4fff, &open, 1);
+        let mut bus = builder.build();
+
+        bus.write(0x4000, 0x12);
+        bus.device_mut(&ram).as_mut_slice()[0x3fff] = 0x34;
+        assert_eq!(bus.device(&ram).as_slice()[0x4000], 0x00);
+        assert_eq!(bus.read(0x3fff), 0x34);
+        assert_eq!(bus.read(0x4000), 0x34);
+    }
+}
@@ -0,0 +1,874 @@
+//! ### MONITOR
+//! This module contains a machine language monitor in the style of Supermon and the VICE monitor, for
+//! inspecting and changing the processor and memory through line based commands. Commands are read from
+//! any `BufRead` and their results written to any `Write`, so the monitor can be used interactively over
+//! stdio, or scripted from a file or a test.
+//!
+//! | Command | Description |
+//! |---|---|
+//! | `r [NAME=VALUE...]` | Display the registers, or set A, X, Y, SP, PC or P and display them |
+//! | `m [START [END]]` | Dump memory, continuing from the last dump when no address is given |
+//! | `> ADDRESS BYTE...` | Write bytes to memory |
+//! | `f START END BYTE...` | Fill memory with a repeating pattern |
+//! | `c START END DESTINATION` | Compare memory with the memory at the destination, listing the addresses that differ |
+//! | `h START END BYTE...` | Hunt for a sequence of bytes, listing the addresses it is found at |
+//! | `d [START [END]]` | Disassemble, continuing from the last disassembly or the program counter |
+//! | `a ADDRESS [INSTRUCTION]` | Assemble an instruction, or every following line until an empty one |
+//! | `g [ADDRESS]` | Run until a breakpoint, a BRK instruction, an instruction that jumps to itself, a jam or the cycle limit |
+//! | `z [COUNT]` | Step through instructions |
+//! | `b [ADDRESS]` | Set a breakpoint, or list the breakpoints |
+//! | `bd [ADDRESS]` | Delete a breakpoint, or every breakpoint |
+//! | `l FILE ADDRESS` | Load a binary file into memory |
+//! | `s FILE START END` | Save memory to a binary file |
+//! | `x` | Exit the monitor |
+//!
+//! Numbers are hexadecimal, optionally prefixed by `$`, and addresses can also be names from the symbol
+//! table. Byte lists can include quoted text, and anything following a `;` is ignored, so memory dumps can
+//! be entered again as commands. Errors are reported on lines beginning with `?`.
+//!
+//! NOTE: Memory is read through the interface, which may have side effects for memory mapped devices. This
+//! includes `g`, which reads the opcode at the program counter after every instruction to stop before BRK.
+//! Instructions are executed without servicing interrupt requests from devices
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let mut monitor = Monitor::new().with_symbols(debug_info.symbols);
+//! monitor.add_breakpoint(0xc000);
+//! monitor.run(&mut cpu, &mut bus, io::stdin().lock(), io::stdout())?;
+//! ```
+
+use super::address_modes::AddressMode;
+use super::disassembler::{disassemble_with_table, DisassembledInstruction};
+use super::opcodes::{OpcodeTable, BUILT_IN_TABLE};
+use super::symbols::SymbolTable;
+use super::{Interface6502, StatusFlags, MOS6502};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+/// The number of bytes dumped by `m` when no end address is given
+const DUMP_LENGTH: u32 = 0x80;
+/// The number of instructions disassembled by `d` when no end address is given
+const DISASSEMBLY_LENGTH: usize = 16;
+/// The number of cycles `g` runs for when no cycle limit is set
+const DEFAULT_MAX_CYCLES: u64 = 100_000_000;
+
+const HELP: &str = "r [NAME=VALUE...]          display or set registers
+m [START [END]]            dump memory
+> ADDRESS BYTE...          write memory
+f START END BYTE...        fill memory
+c START END DESTINATION    compare memory
+h START END BYTE...        hunt memory
+d [START [END]]            disassemble
+a ADDRESS [INSTRUCTION]    assemble
+g [ADDRESS]                go
+z [COUNT]                  step
+b [ADDRESS]                set or list breakpoints
+bd [ADDRESS]               delete breakpoints
+l FILE ADDRESS             load memory
+s FILE START END           save memory
+x                          exit";
+
+/// Failure of a command, either reported to the user or from writing the output
+enum CommandError {
+    Invalid(String),
+    Output(io::Error),
+}
+
+impl From<io::Error> for CommandError {
+    fn from(error: io::Error) -> Self {
+        CommandError::Output(error)
+    }
+}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        CommandError::Invalid(message)
+    }
+}
+
+impl From<&str> for CommandError {
+    fn from(message: &str) -> Self {
+        CommandError::Invalid(message.to_string())
+    }
+}
+
+/// Machine language monitor for a processor and the memory it is connected to
+#[derive(Debug, Clone)]
+pub struct Monitor {
+    symbols: SymbolTable,
+    breakpoints: BTreeSet<u16>,
+    max_cycles: u64,
+    next_dump: u16,
+    next_disassembly: Option<u16>,
+    assembling: Option<u16>,
+}
+
+impl Monitor {
+    /// Creates a new monitor with no symbols or breakpoints
+    pub fn new() -> Self {
+        Monitor::default()
+    }
+
+    /// Uses the symbol table to name addresses in disassembly, and to accept names in place of addresses
+    pub fn with_symbols(mut self, symbols: SymbolTable) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    /// Sets the number of cycles `g` runs for before it stops, so programs that never stop do not hang the
+    /// monitor. The default is 100000000
+    pub fn with_max_cycles(mut self, max_cycles: u64) -> Self {
+        self.max_cycles = max_cycles;
+        self
+    }
+
+    /// Sets a breakpoint, stopping `g` before the instruction at the address is executed
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Removes a breakpoint
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.remove(&address);
+    }
+
+    /// Returns the prompt for the next line, which shows the address being assembled while assembling
+    pub fn prompt(&self) -> String {
+        return match self.assembling {
+            Some(address) => format!("A {:04X} ", address),
+            None => ". ".to_string(),
+        };
+    }
+
+    /// Reads and executes commands until the input ends or the `x` command is given, writing a prompt before each
+    pub fn run<R: BufRead, W: Write>(&mut self, cpu: &mut MOS6502, interface: &mut dyn Interface6502, input: R, mut output: W) -> io::Result<()> {
+        write!(output, "{}", self.prompt())?;
+        output.flush()?;
+        for line in input.lines() {
+            if !self.execute(cpu, interface, &line?, &mut output)? {
+                break;
+            }
+            write!(output, "{}", self.prompt())?;
+            output.flush()?;
+        }
+        return Ok(());
+    }
+
+    /// Executes a single line of input, returning false if it was the `x` command. Mistakes in the command are
+    /// reported in the output, so only failures to write the output are returned as errors
+    pub fn execute<W: Write>(&mut self, cpu: &mut MOS6502, interface: &mut dyn Interface6502, line: &str, output: &mut W) -> io::Result<bool> {
+        let line = strip_comment(line).trim();
+        let result = match self.assembling {
+            Some(_) if line.is_empty() => {
+                self.assembling = None;
+                Ok(())
+            }
+            Some(address) => self.assemble_line(cpu, interface, address, line, output),
+            None if line.eq_ignore_ascii_case("x") => return Ok(false),
+            None => match line.strip_prefix('>') {
+                Some(rest) => self.command(cpu, interface, &format!("> {}", rest), output),
+                None => self.command(cpu, interface, line, output),
+            },
+        };
+        return match result {
+            Ok(()) => Ok(true),
+            Err(CommandError::Invalid(message)) => writeln!(output, "? {}", message).map(|_| true),
+            Err(CommandError::Output(error)) => Err(error),
+        };
+    }
+
+    fn command<W: Write>(&mut self, cpu: &mut MOS6502, interface: &mut dyn Interface6502, line: &str, output: &mut W) -> Result<(), CommandError> {
+        let tokens = tokenize(line);
+        let (command, arguments) = match tokens.split_first() {
+            Some((command, arguments)) => (command.to_ascii_lowercase(), arguments),
+            None => return Ok(()),
+        };
+        match command.as_str() {
+            "r" => self.registers(cpu, arguments, output),
+            "m" => self.dump(interface, arguments, output),
+            ">" => self.write_memory(interface, arguments),
+            "f" => self.fill(interface, arguments),
+            "c" => self.compare(interface, arguments, output),
+            "h" => self.hunt(interface, arguments, output),
+            "d" => self.disassemble(cpu, interface, arguments, output),
+            "a" => self.assemble(cpu, interface, arguments, output),
+            "g" => self.go(cpu, interface, arguments, output),
+            "z" => self.step(cpu, interface, arguments, output),
+            "b" => self.breakpoint(arguments, output),
+            "bd" => self.delete_breakpoint(arguments),
+            "l" => self.load(interface, arguments, output),
+            "s" => self.save(interface, arguments),
+            "?" => Ok(writeln!(output, "{}", HELP)?),
+            _ => Err(format!("Unknown command: {}", command).into()),
+        }
+    }
+
+    fn registers<W: Write>(&mut self, cpu: &mut MOS6502, arguments: &[String], output: &mut W) -> Result<(), CommandError> {
+        let mut state = cpu.state();
+        for argument in arguments {
+            let (name, value) = argument.split_once('=').ok_or(format!("Expected NAME=VALUE: {}", argument))?;
+            let value = self.value(value)?;
+            let out_of_range = |_| format!("Value out of range: {:X}", value);
+            let byte = || u8::try_from(value).map_err(out_of_range);
+            match name.to_ascii_uppercase().as_str() {
+                "A" => state.accumulator = byte()?,
+                "X" => state.x_register = byte()?,
+                "Y" => state.y_register = byte()?,
+                "SP" => state.stack_pointer = byte()?,
+                "P" => state.status = StatusFlags::from_bits(byte()?),
+                "PC" => state.program_counter = u16::try_from(value).map_err(out_of_range)?,
+                _ => return Err(format!("Unknown register: {}", name).into()),
+            }
+        }
+        if !arguments.is_empty() {
+            cpu.set_state(&state);
+            self.next_disassembly = None;
+        }
+        return Ok(write_registers(cpu, output)?);
+    }
+
+    fn dump<W: Write>(&mut self, interface: &mut dyn Interface6502, arguments: &[String], output: &mut W) -> Result<(), CommandError> {
+        let start = match arguments.first() {
+            Some(start) => self.address(start)?,
+            None => self.next_dump,
+        };
+        let end = match arguments.get(1) {
+            Some(end) => self.end_address(start, end)?,
+            None => (u32::from(start) + DUMP_LENGTH - 1).min(0xffff) as u16,
+        };
+        for line in (u32::from(start)..=u32::from(end)).step_by(8) {
+            let bytes: Vec<u8> = (line..=(line + 7).min(u32::from(end)))
+                .map(|address| interface.read(address as u16))
+                .collect();
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let text: String = bytes
+                .iter()
+                .map(|&byte| if (0x20..0x7f).contains(&byte) { byte as char } else { '.' })
+                .collect();
+            writeln!(output, ">{:04X} {:<23} ;{}", line, hex.join(" "), text)?;
+        }
+        self.next_dump = end.wrapping_add(1);
+        return Ok(());
+    }
+
+    fn write_memory(&mut self, interface: &mut dyn Interface6502, arguments: &[String]) -> Result<(), CommandError> {
+        let address = self.address(arguments.first().ok_or("Expected an address")?)?;
+        let bytes = self.bytes(&arguments[1..])?;
+        for (offset, byte) in bytes.iter().enumerate() {
+            interface.write(address.wrapping_add(offset as u16), *byte);
+        }
+        return Ok(());
+    }
+
+    fn fill(&mut self, interface: &mut dyn Interface6502, arguments: &[String]) -> Result<(), CommandError> {
+        let (start, end) = self.range(arguments)?;
+        let pattern = self.bytes(&arguments[2..])?;
+        for (address, byte) in (start..=end).zip(pattern.iter().cycle()) {
+            interface.write(address, *byte);
+        }
+        return Ok(());
+    }
+
+    fn compare<W: Write>(&mut self, interface: &mut dyn Interface6502, arguments: &[String], output: &mut W) -> Result<(), CommandError> {
+        let (start, end) = self.range(arguments)?;
+        let destination = self.address(arguments.get(2).ok_or("Expected a destination address")?)?;
+        let differences: Vec<u16> = (start..=end)
+            .filter(|&address| interface.read(address) != interface.read(destination.wrapping_add(address - start)))
+            .collect();
+        return Ok(write_addresses(&differences, output)?);
+    }
+
+    fn hunt<W: Write>(&mut self, interface: &mut dyn Interface6502, arguments: &[String], output: &mut W) -> Result<(), CommandError> {
+        let (start, end) = self.range(arguments)?;
+        let sequence = self.bytes(&arguments[2..])?;
+        let memory: Vec<u8> = (start..=end).map(|address| interface.read(address)).collect();
+        let found: Vec<u16> = memory
+            .windows(sequence.len())
+            .enumerate()
+            .filter(|(_, window)| *window == sequence.as_slice())
+            .map(|(offset, _)| start + offset as u16)
+            .collect();
+        return Ok(write_addresses(&found, output)?);
+    }
+
+    fn disassemble<W: Write>(
+        &mut self,
+        cpu: &MOS6502,
+        interface: &mut dyn Interface6502,
+        arguments: &[String],
+        output: &mut W,
+    ) -> Result<(), CommandError> {
+        let start = match arguments.first() {
+            Some(start) => self.address(start)?,
+            None => self.next_disassembly.unwrap_or(cpu.state().program_counter),
+        };
+        let end = match arguments.get(1) {
+            Some(end) => Some(self.end_address(start, end)?),
+            None => None,
+        };
+        let mut address = u32::from(start);
+        let mut count = 0;
+        while address <= 0xffff && end.map_or(count < DISASSEMBLY_LENGTH, |end| address <= u32::from(end)) {
+            let instruction = disassemble_with_table(interface, address as u16, cpu.opcode_table());
+            write_instruction(&instruction, &self.symbols, output)?;
+            address += u32::from(instruction.length());
+            count += 1;
+        }
+        self.next_disassembly = Some(address as u16);
+        return Ok(());
+    }
+
+    fn assemble<W: Write>(
+        &mut self,
+        cpu: &MOS6502,
+        interface: &mut dyn Interface6502,
+        arguments: &[String],
+        output: &mut W,
+    ) -> Result<(), CommandError> {
+        let address = self.address(arguments.first().ok_or("Expected an address")?)?;
+        if arguments.len() == 1 {
+            self.assembling = Some(address);
+            return Ok(());
+        }
+        return self.assemble_line(cpu, interface, address, &arguments[1..].join(" "), output);
+    }
+
+    /// Assembles an instruction, and moves the assembly address past it if the monitor is assembling
+    fn assemble_line<W: Write>(
+        &mut self,
+        cpu: &MOS6502,
+        interface: &mut dyn Interface6502,
+        address: u16,
+        line: &str,
+        output: &mut W,
+    ) -> Result<(), CommandError> {
+        let bytes = assemble(line, address, &self.symbols, cpu.opcode_table())?;
+        for (offset, byte) in bytes.iter().enumerate() {
+            interface.write(address.wrapping_add(offset as u16), *byte);
+        }
+        let instruction = disassemble_with_table(interface, address, cpu.opcode_table());
+        write_instruction(&instruction, &self.symbols, output)?;
+        if self.assembling.is_some() {
+            self.assembling = Some(instruction.next_address());
+        }
+        return Ok(());
+    }
+
+    fn go<W: Write>(
+        &mut self,
+        cpu: &mut MOS6502,
+        interface: &mut dyn Interface6502,
+        arguments: &[String],
+        output: &mut W,
+    ) -> Result<(), CommandError> {
+        if let Some(address) = arguments.first() {
+            cpu.set_program_counter(self.address(address)?);
+        }
+        let end = cpu.total_cycles().saturating_add(self.max_cycles);
+        let reason = loop {
+            let previous = cpu.state().program_counter;
+            cpu.execute_instruction(interface);
+            let program_counter = cpu.state().program_counter;
+            if cpu.is_jammed() {
+                break format!("Jammed at ${:04X}", previous);
+            } else if program_counter == previous {
+                break format!("Trapped at ${:04X}", program_counter);
+            } else if self.breakpoints.contains(&program_counter) {
+                break format!("Breakpoint at ${:04X}", program_counter);
+            } else if interface.read(program_counter) == 0x00 {
+                break format!("BRK at ${:04X}", program_counter);
+            } else if cpu.total_cycles() >= end {
+                break format!("Cycle limit reached at ${:04X}", program_counter);
+            }
+        };
+        writeln!(output, "{}", reason)?;
+        return self.stopped(cpu, interface, output);
+    }
+
+    fn step<W: Write>(
+        &mut self,
+        cpu: &mut MOS6502,
+        interface: &mut dyn Interface6502,
+        arguments: &[String],
+        output: &mut W,
+    ) -> Result<(), CommandError> {
+        let count = match arguments.first() {
+            Some(count) => self.value(count)?,
+            None => 1,
+        };
+        for _ in 0..count {
+            if cpu.is_jammed() {
+                writeln!(output, "Jammed")?;
+                break;
+            }
+            let instruction = disassemble_with_table(interface, cpu.state().program_counter, cpu.opcode_table());
+            write_instruction(&instruction, &self.symbols, output)?;
+            cpu.execute_instruction(interface);
+        }
+        return Ok(write_registers(cpu, output)?);
+    }
+
+    /// Reports the state of the processor after it stops running, and the instruction it stopped at
+    fn stopped<W: Write>(&mut self, cpu: &MOS6502, interface: &mut dyn Interface6502, output: &mut W) -> Result<(), CommandError> {
+        write_registers(cpu, output)?;
+        let instruction = disassemble_with_table(interface, cpu.state().program_counter, cpu.opcode_table());
+        write_instruction(&instruction, &self.symbols, output)?;
+        self.next_disassembly = None;
+        return Ok(());
+    }
+
+    fn breakpoint<W: Write>(&mut self, arguments: &[String], output: &mut W) -> Result<(), CommandError> {
+        if let Some(address) = arguments.first() {
+            let address = self.address(address)?;
+            self.add_breakpoint(address);
+            return Ok(());
+        }
+        let breakpoints: Vec<u16> = self.breakpoints.iter().copied().collect();
+        return Ok(write_addresses(&breakpoints, output)?);
+    }
+
+    fn delete_breakpoint(&mut self, arguments: &[String]) -> Result<(), CommandError> {
+        match arguments.first() {
+            Some(address) => {
+                let address = self.address(address)?;
+                self.remove_breakpoint(address);
+            }
+            None => self.breakpoints.clear(),
+        }
+        return Ok(());
+    }
+
+    fn load<W: Write>(&mut self, interface: &mut dyn Interface6502, arguments: &[String], output: &mut W) -> Result<(), CommandError> {
+        let file = file_name(arguments.first().ok_or("Expected a file name")?);
+        let address = self.address(arguments.get(1).ok_or("Expected an address")?)?;
+        let data = fs::read(file).map_err(|error| format!("Failed to read {}: {}", file, error))?;
+        if data.is_empty() || u32::from(address) + data.len() as u32 > 0x10000 {
+            return Err(format!("{} does not fit in memory at ${:04X}", file, address).into());
+        }
+        for (offset, byte) in data.iter().enumerate() {
+            interface.write(address + offset as u16, *byte);
+        }
+        writeln!(output, "Loaded ${:04X}-${:04X}", address, address + (data.len() - 1) as u16)?;
+        return Ok(());
+    }
+
+    fn save(&mut self, interface: &mut dyn Interface6502, arguments: &[String]) -> Result<(), CommandError> {
+        let file = file_name(arguments.first().ok_or("Expected a file name")?);
+        let (start, end) = self.range(arguments.get(1..).unwrap_or(&[]))?;
+        let data: Vec<u8> = (start..=end).map(|address| interface.read(address)).collect();
+        fs::write(file, data).map_err(|error| format!("Failed to write {}: {}", file, error))?;
+        return Ok(());
+    }
+
+    /// Parses a hexadecimal number, or the address of a symbol
+    fn value(&self, text: &str) -> Result<u32, String> {
+        if let Some(hex) = text.strip_prefix('$') {
+            return u32::from_str_radix(hex, 16).map_err(|_| format!("Invalid number: {}", text));
+        }
+        return match self.symbols.address(text) {
+            Some(address) => Ok(u32::from(address)),
+            None => u32::from_str_radix(text, 16).map_err(|_| format!("Invalid number or unknown symbol: {}", text)),
+        };
+    }
+
+    fn address(&self, text: &str) -> Result<u16, String> {
+        let value = self.value(text)?;
+        return u16::try_from(value).map_err(|_| format!("Address out of range: {:X}", value));
+    }
+
+    fn end_address(&self, start: u16, text: &str) -> Result<u16, String> {
+        let end = self.address(text)?;
+        if end < start {
+            return Err(format!("The end address ${:04X} is before the start address ${:04X}", end, start));
+        }
+        return Ok(end);
+    }
+
+    /// Parses the start and end addresses at the beginning of the arguments
+    fn range(&self, arguments: &[String]) -> Result<(u16, u16), String> {
+        return match arguments {
+            [start, end, ..] => {
+                let start = self.address(start)?;
+                Ok((start, self.end_address(start, end)?))
+            }
+            _ => Err("Expected a start and end address".to_string()),
+        };
+    }
+
+    /// Parses a list of bytes and quoted text, which must not be empty
+    fn bytes(&self, arguments: &[String]) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+        for argument in arguments {
+            match argument.strip_prefix('"') {
+                Some(text) => bytes.extend(text.bytes()),
+                None => {
+                    let value = self.value(argument)?;
+                    bytes.push(u8::try_from(value).map_err(|_| format!("Byte out of range: {:X}", value))?);
+                }
+            }
+        }
+        if bytes.is_empty() {
+            return Err("Expected one or more bytes".to_string());
+        }
+        return Ok(bytes);
+    }
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Monitor {
+            symbols: SymbolTable::default(),
+            breakpoints: BTreeSet::new(),
+            max_cycles: DEFAULT_MAX_CYCLES,
+            next_dump: 0,
+            next_disassembly: None,
+            assembling: None,
+        }
+    }
+}
+
+fn write_registers<W: Write>(cpu: &MOS6502, output: &mut W) -> io::Result<()> {
+    let state = cpu.state();
+    writeln!(
+        output,
+        "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} SP:{:02X} P:{} CYC:{}",
+        state.program_counter, state.accumulator, state.x_register, state.y_register, state.stack_pointer, state.status, state.total_cycles
+    )
+}
+
+fn write_instruction<W: Write>(instruction: &DisassembledInstruction, symbols: &SymbolTable, output: &mut W) -> io::Result<()> {
+    if let Some(name) = symbols.name(instruction.address) {
+        writeln!(output, "{}:", name)?;
+    }
+    writeln!(
+        output,
+        ".{}  {:<8}  {}",
+        instruction.format_address(),
+        instruction.format_bytes(),
+        instruction.to_string_with_symbols(symbols)
+    )
+}
+
+/// Writes a list of addresses, 8 to a line
+fn write_addresses<W: Write>(addresses: &[u16], output: &mut W) -> io::Result<()> {
+    for line in addresses.chunks(8) {
+        let line: Vec<String> = line.iter().map(|address| format!("{:04X}", address)).collect();
+        writeln!(output, "{}", line.join(" "))?;
+    }
+    return Ok(());
+}
+
+/// Removes anything following a `;` outside of quoted text
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (index, character) in line.char_indices() {
+        match character {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+    return line;
+}
+
+/// Splits a line into words, keeping quoted text together as one word that begins with its opening quote
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut characters = line.chars().peekable();
+    while let Some(&character) = characters.peek() {
+        if character.is_whitespace() {
+            characters.next();
+        } else if character == '"' {
+            characters.next();
+            let text: String = characters.by_ref().take_while(|&character| character != '"').collect();
+            tokens.push(format!("\"{}", text));
+        } else {
+            let mut word = String::new();
+            while let Some(&character) = characters.peek() {
+                if character.is_whitespace() || character == '"' {
+                    break;
+                }
+                word.push(character);
+                characters.next();
+            }
+            tokens.push(word);
+        }
+    }
+    return tokens;
+}
+
+fn file_name(argument: &str) -> &str {
+    argument.strip_prefix('"').unwrap_or(argument)
+}
+
+/// Assembles a single instruction located at the address, using the opcode table the processor executes from,
+/// or the built-in table when it has none. Operands are written as the disassembler formats them, and values of
+/// at most two hexadecimal digits use the zero page address modes where the instruction has them. Documented and
+/// custom opcodes are preferred over illegal opcodes with the same name and address mode
+fn assemble(line: &str, address: u16, symbols: &SymbolTable, table: Option<&OpcodeTable>) -> Result<Vec<u8>, String> {
+    let table = table.unwrap_or(&BUILT_IN_TABLE);
+    let (mnemonic, operand) = match line.trim().split_once(char::is_whitespace) {
+        Some((mnemonic, operand)) => (mnemonic.to_ascii_lowercase(), operand.split_whitespace().collect::<String>()),
+        None => (line.trim().to_ascii_lowercase(), String::new()),
+    };
+    let upper = operand.to_ascii_uppercase();
+    let inner = |prefix: usize, suffix: usize| &operand[prefix..operand.len() - suffix];
+
+    use AddressMode::*;
+    let (modes, value): (&[AddressMode], Option<&str>) = if operand.is_empty() || upper == "A" {
+        (&[Implied], None)
+    } else if upper.starts_with('#') {
+        (&[Immediate], Some(inner(1, 0)))
+    } else if upper.starts_with('(') && upper.ends_with(",X)") {
+        (&[IndirectX], Some(inner(1, 3)))
+    } else if upper.starts_with('(') && upper.ends_with("),Y") {
+        (&[IndirectY, IndirectYConst], Some(inner(1, 3)))
+    } else if upper.starts_with('(') && upper.ends_with(')') {
+        (&[Indirect], Some(inner(1, 1)))
+    } else if upper.ends_with(",X") {
+        (&[ZeroPageX, AbsoluteX, AbsoluteXConst], Some(inner(0, 2)))
+    } else if upper.ends_with(",Y") {
+        (&[ZeroPageY, AbsoluteY, AbsoluteYConst], Some(inner(0, 2)))
+    } else {
+        (&[Relative, ZeroPage, Absolute], Some(inner(0, 0)))
+    };
+
+    let (value, wide) = match value {
+        Some(text) => {
+            let (value, digits) = match (text.strip_prefix('$'), symbols.address(text)) {
+                (None, Some(address)) => (u32::from(address), 0),
+                (hex, _) => {
+                    let hex = hex.unwrap_or(text);
+                    let value = u32::from_str_radix(hex, 16).map_err(|_| format!("Invalid operand: {}", operand))?;
+                    (value, hex.len())
+                }
+            };
+            let value = u16::try_from(value).map_err(|_| format!("Operand out of range: {}", operand))?;
+            (value, value > 0xff || digits > 2)
+        }
+        None => (0, false),
+    };
+    let zero_page = |mode: &AddressMode| mode.operand_length() == 1 && *mode != Relative && *mode != Immediate;
+    let find = |allow_illegal: bool| {
+        modes.iter().filter(|mode| !(wide && zero_page(mode))).find_map(|&mode| {
+            (0..=0xffu8)
+                .find(|&opcode| table.name(opcode) == mnemonic && table.address_mode(opcode) == mode && (allow_illegal || !table.is_illegal(opcode)))
+                .map(|opcode| (opcode, mode))
+        })
+    };
+    let (opcode, mode) = find(false)
+        .or_else(|| find(true))
+        .ok_or(format!("Invalid instruction: {}", line.trim()))?;
+
+    return match mode {
+        Implied => Ok(vec![opcode]),
+        Immediate if value > 0xff => Err(format!("Operand out of range: {}", operand)),
+        Relative => {
+            let offset = value.wrapping_sub(address.wrapping_add(2)) as i16;
+            match i8::try_from(offset) {
+                Ok(offset) => Ok(vec![opcode, offset as u8]),
+                Err(_) => Err(format!("Branch out of range: {}", operand)),
+            }
+        }
+        _ if mode.operand_length() == 1 => Ok(vec![opcode, value as u8]),
+        _ => Ok(vec![opcode, value as u8, (value >> 8) as u8]),
+    };
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utilities::TestRam;
+    use std::sync::Arc;
+
+    fn execute(monitor: &mut Monitor, cpu: &mut MOS6502, ram: &mut TestRam, script: &str) -> String {
+        let mut output = Vec::new();
+        for line in script.lines() {
+            assert!(monitor.execute(cpu, ram, line, &mut output).unwrap());
+        }
+        return String::from_utf8(output).unwrap();
+    }
+
+    fn assembled(line: &str, address: u16) -> Vec<u8> {
+        assemble(line, address, &SymbolTable::new(), None).unwrap()
+    }
+
+    #[test]
+    fn test_assembler() {
+        assert_eq!(assembled("NOP", 0), vec![0xea]);
+        assert_eq!(assembled("asl a", 0), vec![0x0a]);
+        assert_eq!(assembled("LDA #$10", 0), vec![0xa9, 0x10]);
+        assert_eq!(assembled("LDA $10", 0), vec![0xa5, 0x10]);
+        assert_eq!(assembled("LDA $0010", 0), vec![0xad, 0x10, 0x00]);
+        assert_eq!(assembled("LDA $1234,X", 0), vec![0xbd, 0x34, 0x12]);
+        assert_eq!(assembled("LDA $10,Y", 0), vec![0xb9, 0x10, 0x00]);
+        assert_eq!(assembled("STA ($10), Y", 0), vec![0x91, 0x10]);
+        assert_eq!(assembled("LDA ($10,X)", 0), vec![0xa1, 0x10]);
+        assert_eq!(assembled("JMP ($1234)", 0), vec![0x6c, 0x34, 0x12]);
+        assert_eq!(assembled("JMP 10", 0), vec![0x4c, 0x10, 0x00]);
+        assert_eq!(assembled("SBC #1", 0), vec![0xe9, 0x01]);
+        assert_eq!(assembled("LAX $10", 0), vec![0xa7, 0x10]);
+        assert_eq!(assembled("BNE $0400", 0x0400), vec![0xd0, 0xfe]);
+        assert_eq!(assembled("BPL $0382", 0x0400), vec![0x10, 0x80]);
+
+        let symbols = &SymbolTable::new();
+        assert!(assemble("BPL $0381", 0x0400, symbols, None).is_err());
+        assert!(assemble("LDA #$100", 0, symbols, None).is_err());
+        assert!(assemble("STA #$10", 0, symbols, None).is_err());
+        assert!(assemble("FOO", 0, symbols, None).is_err());
+    }
+
+    #[test]
+    fn test_memory_commands() {
+        let (mut monitor, mut cpu, mut ram) = (Monitor::new(), MOS6502::new(), TestRam::with_program(0, &[]));
+        let output = execute(
+            &mut monitor,
+            &mut cpu,
+            &mut ram,
+            "f 0400 040f ea 00\n>0404 41 42 \"C\" ; comment\nm 0400 040f\nm 0410 0411",
+        );
+        assert_eq!(
+            output,
+            ">0400 EA 00 EA 00 41 42 43 00 ;....ABC.\n>0408 EA 00 EA 00 EA 00 EA 00 ;........\n>0410 00 00                   ;..\n"
+        );
+
+        let output = execute(
+            &mut monitor,
+            &mut cpu,
+            &mut ram,
+            ">0500 ea 00 ea 01\nc 0400 0403 0500\nh 0400 040f ea 00\nh 0400 040f \"BC\"",
+        );
+        assert_eq!(output, "0403\n0400 0402 0408 040A 040C 040E\n0405\n");
+    }
+
+    #[test]
+    fn test_assemble_and_disassemble() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("loop", 0x0402);
+        let (mut monitor, mut cpu, mut ram) = (Monitor::new().with_symbols(symbols), MOS6502::new(), TestRam::with_program(0, &[]));
+
+        let output = execute(&mut monitor, &mut cpu, &mut ram, "a 0400 LDX #$05\na 0402\ndex\nbne loop\n\nd 0400 0404");
+        assert_eq!(monitor.prompt(), ". ");
+        assert_eq!(
+            output,
+            ".0400  A2 05     LDX #$05\nloop:\n.0402  CA        DEX\n.0403  D0 FD     BNE loop\n\
+             .0400  A2 05     LDX #$05\nloop:\n.0402  CA        DEX\n.0403  D0 FD     BNE loop\n"
+        );
+
+        let output = execute(&mut monitor, &mut cpu, &mut ram, "a 0410\nlda #$1000");
+        assert!(output.starts_with("? Operand out of range"));
+        assert_eq!(monitor.prompt(), "A 0410 ");
+    }
+
+    fn trap(_cpu: &mut MOS6502, _interface: &mut dyn Interface6502, _address: Option<u16>) {}
+
+    const TABLE: OpcodeTable = OpcodeTable::new().with_opcode(
+        0x22,
+        crate::CustomOpcode {
+            name: "trp",
+            function: trap,
+            address_mode: AddressMode::Immediate,
+            cycles: 2,
+        },
+    );
+
+    #[test]
+    fn test_custom_opcodes() {
+        let (mut monitor, mut cpu, mut ram) = (
+            Monitor::new(),
+            MOS6502::new_start(0x0400).with_opcode_table(Arc::new(TABLE)),
+            TestRam::with_program(0, &[]),
+        );
+
+        let output = execute(&mut monitor, &mut cpu, &mut ram, "a 0400 trp #$07\nd 0400 0401\nz");
+        assert_eq!(ram.ram[0x0400..0x0402], [0x22, 0x07]);
+        assert_eq!(
+            output,
+            ".0400  22 07     TRP #$07\n.0400  22 07     TRP #$07\n.0400  22 07     TRP #$07\n\
+             PC:0402 A:00 X:00 Y:00 SP:FD P:nv-bdIzc CYC:2\n"
+        );
+    }
+
+    #[test]
+    fn test_registers() {
+        let (mut monitor, mut cpu, mut ram) = (Monitor::new(), MOS6502::new(), TestRam::with_program(0, &[]));
+        let output = execute(&mut monitor, &mut cpu, &mut ram, "r a=12 X=$34 pc=c000 p=e3");
+
+        assert_eq!(output, "PC:C000 A:12 X:34 Y:00 SP:FD P:NV-bdiZC CYC:0\n");
+        assert_eq!(cpu.state().accumulator, 0x12);
+        assert!(execute(&mut monitor, &mut cpu, &mut ram, "r a=100").starts_with("? Value out of range"));
+        assert!(execute(&mut monitor, &mut cpu, &mut ram, "r q=1").starts_with("? Unknown register"));
+    }
+
+    #[test]
+    fn test_go_and_step() {
+        // LDX #$00; INX; CPX #$03; BNE $0402; BRK
+        let program = [0xa2, 0x00, 0xe8, 0xe0, 0x03, 0xd0, 0xfb, 0x00];
+        let (mut monitor, mut cpu, mut ram) = (Monitor::new(), MOS6502::new(), TestRam::with_program(0x0400, &program));
+
+        let output = execute(&mut monitor, &mut cpu, &mut ram, "b 0405\ng 0400\nz 2");
+        assert_eq!(
+            output,
+            "Breakpoint at $0405\nPC:0405 A:00 X:01 Y:00 SP:FD P:Nv-bdIzc CYC:6\n.0405  D0 FB     BNE $0402\n\
+             .0405  D0 FB     BNE $0402\n.0402  E8        INX\nPC:0403 A:00 X:02 Y:00 SP:FD P:nv-bdIzc CYC:11\n"
+        );
+
+        let output = execute(&mut monitor, &mut cpu, &mut ram, "bd\ng");
+        assert!(output.starts_with("BRK at $0407\n"));
+        assert_eq!(cpu.state().x_register, 3);
+    }
+
+    #[test]
+    fn test_go_cycle_limit() {
+        // INX; JMP $0400
+        let program = [0xe8, 0x4c, 0x00, 0x04];
+        let (mut monitor, mut cpu, mut ram) = (
+            Monitor::new().with_max_cycles(50),
+            MOS6502::new(),
+            TestRam::with_program(0x0400, &program),
+        );
+
+        let output = execute(&mut monitor, &mut cpu, &mut ram, "g 0400");
+        assert!(output.starts_with("Cycle limit reached at $0400\n"), "{}", output);
+        assert_eq!(cpu.total_cycles(), 50);
+        assert_eq!(cpu.state().x_register, 10);
+    }
+
+    #[test]
+    fn test_errors_and_exit() {
+        let (mut monitor, mut cpu, mut ram) = (Monitor::new(), MOS6502::new(), TestRam::with_program(0, &[]));
+        let output = execute(&mut monitor, &mut cpu, &mut ram, "q\nm 0400 03ff\nf 0400\n> 0400 zz");
+        assert_eq!(
+            output,
+            "? Unknown command: q\n? The end address $03FF is before the start address $0400\n\
+             ? Expected a start and end address\n? Invalid number or unknown symbol: zz\n"
+        );
+
+        let mut output = Vec::new();
+        let input = "r\nx\nr\n".as_bytes();
+        monitor.run(&mut cpu, &mut ram, input, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), ". PC:0400 A:00 X:00 Y:00 SP:FD P:nv-bdIzc CYC:0\n. ");
+    }
+
+    #[test]
+    fn test_load_and_save() {
+        let path = std::env::temp_dir().join(format!("monitor_test_{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        let (mut monitor, mut cpu, mut ram) = (Monitor::new(), MOS6502::new(), TestRam::with_program(0x0400, &[1, 2, 3]));
+
+        let output = execute(
+            &mut monitor,
+            &mut cpu,
+            &mut ram,
+            &format!("s \"{}\" 0400 0402\nl \"{}\" 0600\nl \"{}\" ffff", path, path, path),
+        );
+        std::fs::remove_file(path).unwrap();
+
+        assert!(output.starts_with("Loaded $0600-$0602\n? "));
+        assert_eq!(ram.ram[0x0600..0x0603], [1, 2, 3]);
+    }
+}
@@ -0,0 +1,258 @@
+//! ### CUSTOM OPCODES
+//! This module contains opcode tables, which replace individual entries of the built-in opcode table with user
+//! functions. Unused or illegal opcodes can then act as traps into the host, or as instructions of a modified
+//! processor. A replaced opcode finds its operand with the given address mode, then calls the function with the
+//! effective address and takes the given number of cycles, plus any extra cycle taken by the address mode.
+//!
+//! Processors share their table through an `Arc`, so they stay cheap to clone. Tables can be declared as constants
+//! using the `const` builder, or built at run time with `replace`. The disassembler, monitor and coverage can be
+//! given the same table, so they decode custom opcodes as the processor executes them.
+//!
+//! NOTE: Custom opcode functions are plain function pointers, so they can be used in constants, but cannot capture
+//! state. Functions keep any state of their own in the processor's memory, through the interface, or in statics
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! fn print(cpu: &mut MOS6502, _bus: &mut dyn Interface6502, _address: Option<u16>) {
+//!     print!("{}", cpu.state().accumulator as char);
+//! }
+//!
+//! const TABLE: OpcodeTable = OpcodeTable::new().with_opcode(
+//!     0x02,
+//!     CustomOpcode {
+//!         name: "prt",
+//!         function: print,
+//!         address_mode: AddressMode::Implied,
+//!         cycles: 2,
+//!     },
+//! );
+//!
+//! let mut cpu = MOS6502::new_start(0x0400).with_opcode_table(Arc::new(TABLE));
+//! ```
+
+use super::{is_illegal_opcode, OPCODE_TABLE};
+use crate::{AddressMode, Interface6502, MOS6502};
+
+/// The type of user functions for custom opcodes. The third argument is the effective address found by the address
+/// mode: None for implied, the address of the operand byte for immediate, and the branch target for relative.
+/// Being a function pointer, it cannot capture state
+pub type CustomOpcodeFunction = fn(&mut MOS6502, &mut dyn Interface6502, Option<u16>);
+
+/// A user function replacing an entry of the opcode table. Custom opcodes are equal when every field is, with
+/// functions compared by address
+#[derive(Debug, Clone, Copy)]
+pub struct CustomOpcode {
+    /// The lowercase mnemonic passed to tracers
+    pub name: &'static str,
+    /// The function executing the opcode
+    pub function: CustomOpcodeFunction,
+    /// The address mode used to find the effective address, which also determines the length of the instruction
+    pub address_mode: AddressMode,
+    /// The number of cycles the opcode takes
+    pub cycles: u8,
+}
+
+/// An opcode table with some entries replaced by custom opcodes
+#[derive(Debug, PartialEq, Clone)]
+pub struct OpcodeTable {
+    custom: [Option<CustomOpcode>; 256],
+}
+
+impl OpcodeTable {
+    /// Creates a table matching the built-in opcode table
+    pub const fn new() -> Self {
+        OpcodeTable { custom: [None; 256] }
+    }
+
+    /// Replaces the opcode with a custom opcode, in a form usable in statics
+    pub const fn with_opcode(mut self, opcode: u8, custom: CustomOpcode) -> Self {
+        self.custom[opcode as usize] = Some(custom);
+        return self;
+    }
+
+    /// Replaces the opcode with a custom opcode
+    pub fn replace(&mut self, opcode: u8, custom: CustomOpcode) {
+        self.custom[opcode as usize] = Some(custom);
+    }
+
+    /// Restores the built-in behaviour of the opcode
+    pub fn restore(&mut self, opcode: u8) {
+        self.custom[opcode as usize] = None;
+    }
+
+    /// Returns the custom opcode replacing the opcode, if there is one
+    pub fn get(&self, opcode: u8) -> Option<&CustomOpcode> {
+        return self.custom[opcode as usize].as_ref();
+    }
+
+    /// Returns the lowercase mnemonic of the opcode
+    pub fn name(&self, opcode: u8) -> &'static str {
+        return self.get(opcode).map_or(OPCODE_TABLE[opcode as usize].get_name(), |custom| custom.name);
+    }
+
+    /// Returns the address mode the opcode uses to find its operand
+    pub fn address_mode(&self, opcode: u8) -> AddressMode {
+        return self
+            .get(opcode)
+            .map_or(OPCODE_TABLE[opcode as usize].get_address_mode(), |custom| custom.address_mode);
+    }
+
+    /// Returns the number of cycles the opcode takes, not including extra cycles taken by the address mode
+    pub fn cycles(&self, opcode: u8) -> u8 {
+        return self
+            .get(opcode)
+            .map_or(OPCODE_TABLE[opcode as usize].get_cycles(), |custom| custom.cycles);
+    }
+
+    /// Returns true if the opcode is an illegal opcode outside of the documented instruction set. Custom opcodes
+    /// are never illegal
+    pub fn is_illegal(&self, opcode: u8) -> bool {
+        return self.get(opcode).is_none() && is_illegal_opcode(opcode);
+    }
+}
+
+/// The built-in opcode table, used where no table is given
+pub(crate) static BUILT_IN_TABLE: OpcodeTable = OpcodeTable::new();
+
+impl PartialEq for CustomOpcode {
+    fn eq(&self, other: &Self) -> bool {
+        return self.name == other.name
+            && self.function as usize == other.function as usize
+            && self.address_mode == other.address_mode
+            && self.cycles == other.cycles;
+    }
+}
+
+impl Default for OpcodeTable {
+    fn default() -> Self {
+        OpcodeTable::new()
+    }
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utilities::TestRam;
+    use crate::tracer::{InstructionEvent, Tracer6502};
+    use std::sync::Arc;
+
+    /// Stores the accumulator at the address given by the X register
+    fn trap(cpu: &mut MOS6502, bus: &mut dyn Interface6502, address: Option<u16>) {
+        assert_eq!(address, None);
+        let state = cpu.state();
+        bus.write(u16::from(state.x_register), state.accumulator);
+    }
+
+    /// Loads the X register with the sum of the accumulator and the operand
+    fn add_x(cpu: &mut MOS6502, bus: &mut dyn Interface6502, address: Option<u16>) {
+        let mut state = cpu.state();
+        state.x_register = state.accumulator.wrapping_add(bus.read(address.unwrap()));
+        cpu.set_state(&state);
+    }
+
+    const TABLE: OpcodeTable = OpcodeTable::new()
+        .with_opcode(
+            0x02,
+            CustomOpcode {
+                name: "trp",
+                function: trap,
+                address_mode: AddressMode::Implied,
+                cycles: 4,
+            },
+        )
+        .with_opcode(
+            0xa9,
+            CustomOpcode {
+                name: "adx",
+                function: add_x,
+                address_mode: AddressMode::Immediate,
+                cycles: 3,
+            },
+        );
+
+    struct Names(Vec<(&'static str, bool)>);
+
+    impl Tracer6502 for Names {
+        fn instruction(&mut self, event: &InstructionEvent) {
+            self.0.push((event.name, event.illegal));
+        }
+    }
+
+    #[test]
+    fn test_custom_opcodes() {
+        // LDX #$10; ADX #$05; TRP
+        let mut ram = TestRam::with_program(0x0400, &[0xa2, 0x10, 0xa9, 0x05, 0x02]);
+        let mut cpu = MOS6502::new_start(0x0400).with_opcode_table(Arc::new(TABLE));
+        let mut names = Names(Vec::new());
+        for _ in 0..3 {
+            cpu.execute_instruction_traced(&mut ram, &mut names);
+        }
+
+        assert_eq!(cpu.state().x_register, 0x05);
+        assert_eq!(cpu.state().program_counter, 0x0405);
+        assert_eq!(cpu.total_cycles(), 2 + 3 + 4);
+        assert!(!cpu.is_jammed());
+        assert_eq!(names.0, vec![("ldx", false), ("adx", false), ("trp", false)]);
+    }
+
+    #[test]
+    fn test_trap_writes_memory() {
+        // LDA #$42 is replaced, so load the accumulator with PLA from a prepared stack
+        let mut ram = TestRam::with_program(0x0400, &[0x68, 0xa2, 0x20, 0x02]);
+        ram.ram[0x01fe] = 0x42;
+        let mut cpu = MOS6502::new_start(0x0400).with_opcode_table(Arc::new(TABLE));
+        for _ in 0..3 {
+            cpu.execute_instruction(&mut ram);
+        }
+
+        assert_eq!(ram.ram[0x0020], 0x42);
+    }
+
+    #[test]
+    fn test_table_lookups() {
+        let mut table = TABLE.clone();
+        assert_eq!(table.name(0x02), "trp");
+        assert_eq!(table.address_mode(0xa9), AddressMode::Immediate);
+        assert_eq!(table.cycles(0xa9), 3);
+        assert_eq!(table.name(0xea), "nop");
+        assert_eq!(table.cycles(0xea), 2);
+        assert!(!table.is_illegal(0x02));
+        assert!(table.is_illegal(0x22));
+
+        table.restore(0xa9);
+        assert_eq!(table.name(0xa9), "lda");
+        assert!(table.get(0xa9).is_none());
+        assert_eq!(table.cycles(0xa9), 2);
+    }
+
+    #[test]
+    fn test_set_state_keeps_table() {
+        let mut cpu = MOS6502::new().with_opcode_table(Arc::new(TABLE));
+        cpu.set_state(&MOS6502::new_start(0x0800).state());
+        assert_eq!(cpu.opcode_table(), Some(&TABLE));
+        assert_eq!(MOS6502::new().opcode_table(), None);
+    }
+
+    #[test]
+    fn test_tables_compare_entries() {
+        let cpu = MOS6502::new().with_opcode_table(Arc::new(TABLE));
+        assert_eq!(cpu, MOS6502::new().with_opcode_table(Arc::new(TABLE.clone())));
+        assert_ne!(cpu, MOS6502::new());
+
+        let mut table = TABLE.clone();
+        table.replace(
+            0x02,
+            CustomOpcode {
+                cycles: 5,
+                ..*TABLE.get(0x02).unwrap()
+            },
+        );
+        assert_ne!(table, TABLE);
+        table.restore(0x02);
+        assert_ne!(table, TABLE);
+        table.replace(0x02, *TABLE.get(0x02).unwrap());
+        assert_eq!(table, TABLE);
+    }
+}
@@ -218,16 +218,30 @@ pub(super) fn las(cpu: &mut MOS6502, bus: &mut dyn Interface6502, address_mode_v
     }
 }
 
-/// KIL: Halts the CPU, calling this function will just call a panic!
-pub(super) fn kil(_cpu: &mut MOS6502, _bus: &mut dyn Interface6502, _address_mode_value: AddressModeValue) {
+/// KIL: Halts the CPU, jamming it until it is reset
+pub(super) fn kil(cpu: &mut MOS6502, _bus: &mut dyn Interface6502, _address_mode_value: AddressModeValue) {
     if cfg!(feature = "illegal_opcodes") {
-        error!("KIL opcode called!, crashing the emulator...");
-        panic!("KIL opcode called!");
+        error!("KIL opcode called!, the processor is jammed until reset");
+        cpu.jammed = true;
     } else {
         warn!("Illegal opcode KIL called, ignoring");
     }
 }
 
+/// Returns true if the opcode is not part of the documented instruction set
+pub(crate) fn is_illegal_opcode(opcode: u8) -> bool {
+    return match OPCODE_TABLE[opcode as usize].get_name() {
+        "nop" => opcode != 0xea,
+        "sbc" => opcode == 0xeb,
+        name => ILLEGAL_NAMES.contains(&name),
+    };
+}
+
+/// The names of all opcodes that are exclusively illegal
+const ILLEGAL_NAMES: [&str; 19] = [
+    "kil", "slo", "rla", "sre", "rra", "sax", "lax", "dcp", "isc", "anc", "alr", "arr", "xaa", "axs", "ahx", "shy", "shx", "tas", "las",
+];
+
 #[cfg(all(test, feature = "illegal_opcodes"))]
 mod test {
     #![allow(unused_variables, unused_mut)] // Allow some warnings for test code
@@ -258,7 +272,7 @@ mod test {
         };
         let mut cpu_expected = MOS6502 {
             accumulator: 0x9f,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -289,7 +303,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Carry, true);
         cpu_expected.set_flag(StatusFlag::Zero, true);
@@ -322,7 +336,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x02,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Carry, false);
 
@@ -353,7 +367,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Carry, true);
         cpu_expected.set_flag(StatusFlag::Zero, true);
@@ -385,7 +399,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0xc0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -416,7 +430,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
         cpu_expected.set_flag(StatusFlag::Carry, true);
@@ -448,7 +462,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x99,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Carry, false);
         cpu_expected.set_flag(StatusFlag::Negative, true);
@@ -480,7 +494,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
         cpu_expected.set_flag(StatusFlag::Carry, true);
@@ -512,7 +526,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x80,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Overflow, true);
         cpu_expected.set_flag(StatusFlag::Negative, true);
@@ -546,7 +560,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x18,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
 
         rra(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
@@ -578,7 +592,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, false);
         cpu_expected.set_flag(StatusFlag::Carry, true);
@@ -613,7 +627,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x81,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
         cpu_expected.set_flag(StatusFlag::Overflow, true);
@@ -642,7 +656,7 @@ mod test {
             ..Default::default()
         };
 
-        let cpu_expected = MOS6502 { ..cpu_initial };
+        let cpu_expected = MOS6502 { ..cpu_initial.clone() };
 
         sax(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
 
@@ -668,7 +682,7 @@ mod test {
         let mut cpu_expected = MOS6502 {
             accumulator: 0xff,
             x_register: 0xff,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -695,7 +709,7 @@ mod test {
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
             x_register: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -723,7 +737,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
         cpu_expected.set_flag(StatusFlag::Zero, true);
         cpu_expected.set_flag(StatusFlag::Carry, true);
 
@@ -751,7 +765,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
         dcp(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
@@ -781,7 +795,7 @@ mod test {
 
         let cpu_expected = MOS6502 {
             accumulator: 0x08,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
 
         isc(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
@@ -811,7 +825,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x7f,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Overflow, true);
 
@@ -842,7 +856,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -873,7 +887,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0xff,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Carry, false);
         cpu_expected.set_flag(StatusFlag::Negative, true);
@@ -907,7 +921,7 @@ mod test {
 
         let cpu_expected = MOS6502 {
             accumulator: 0x06,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
 
         isc(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
@@ -944,7 +958,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x94,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Carry, false);
         cpu_expected.set_flag(StatusFlag::Negative, true);
@@ -973,7 +987,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x80,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Carry, true);
         cpu_expected.set_flag(StatusFlag::Negative, true);
@@ -1002,7 +1016,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -1030,7 +1044,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Carry, true);
         cpu_expected.set_flag(StatusFlag::Zero, true);
@@ -1060,7 +1074,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x78,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, false);
 
@@ -1088,7 +1102,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -1117,7 +1131,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0xb8,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Carry, false);
         cpu_expected.set_flag(StatusFlag::Negative, true);
@@ -1148,7 +1162,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x80,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -1178,7 +1192,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -1208,7 +1222,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             x_register: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
         cpu_expected.set_flag(StatusFlag::Carry, true);
@@ -1238,7 +1252,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             x_register: 0xff,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -1266,7 +1280,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
 
         ahx(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x01ff));
         assert_eq!(cpu_initial, cpu_expected);
@@ -1291,7 +1305,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
 
         shx(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x01ff));
         assert_eq!(cpu_initial, cpu_expected);
@@ -1316,7 +1330,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
 
         shy(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x5ff));
         assert_eq!(cpu_initial, cpu_expected);
@@ -1344,7 +1358,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             stack_pointer: 0x11,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
 
         tas(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x01ff));
@@ -1375,7 +1389,7 @@ mod test {
             accumulator: 0x00,
             x_register: 0x00,
             stack_pointer: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -1405,7 +1419,7 @@ mod test {
             accumulator: 0x80,
             x_register: 0x80,
             stack_pointer: 0x80,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -2,10 +2,14 @@
 //!  This module contains all of the opcode functions to prevent the parent module from being primarily full of them
 //!  An opcode function represents one of the 6502's opcodes. An opcode function is passed the
 //!  address mode to use and returns the number of extra cycles that address mode has taken
+mod custom;
 mod illegal;
 
 use super::address_modes::*;
-use super::{AddressModeFunction, Interface6502, OpcodeFunction, StatusFlag, MOS6502};
+use super::{Interface6502, OpcodeFunction, StatusFlag, MOS6502};
+pub(crate) use custom::BUILT_IN_TABLE;
+pub use custom::{CustomOpcode, CustomOpcodeFunction, OpcodeTable};
+pub(crate) use illegal::is_illegal_opcode;
 use illegal::*;
 
 #[derive(Clone, Copy)]
@@ -13,13 +17,13 @@ use illegal::*;
 pub(super) struct Instruction<'a> {
     name: &'a str, //For logging
     function: OpcodeFunction,
-    address_mode: AddressModeFunction,
+    address_mode: AddressMode,
     cycles: u8,
 }
 
-impl Instruction<'_> {
+impl<'a> Instruction<'a> {
     /// Gets the name of the opcode for debug purposes
-    pub(super) fn get_name(&self) -> &str {
+    pub(super) fn get_name(&self) -> &'a str {
         return self.name;
     }
 
@@ -28,9 +32,9 @@ impl Instruction<'_> {
         (self.function)(cpu, interface, address_mode_value);
     }
 
-    /// Calls the address mode function to retrieve the addressed location to be used in the instruction
-    pub(super) fn find_address(&self, cpu: &mut MOS6502, interface: &mut dyn Interface6502) -> AddressModeValue {
-        return (self.address_mode)(cpu, interface);
+    /// Gets the address mode the instruction uses to find its operand
+    pub(crate) fn get_address_mode(&self) -> AddressMode {
+        return self.address_mode;
     }
 
     /// Gets the number of cycles that executing this instruction will take
@@ -46,1537 +50,1537 @@ pub(super) static OPCODE_TABLE: [Instruction; 256] = [
     Instruction {
         name: "brk",
         function: brk,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 7,
     }, //0x0
     Instruction {
         name: "ora",
         function: ora,
-        address_mode: indirect_x,
+        address_mode: AddressMode::IndirectX,
         cycles: 6,
     }, //0x1
     Instruction {
         name: "kil",
         function: kil,
-        address_mode: implied,
-        cycles: 0,
+        address_mode: AddressMode::Implied,
+        cycles: 2,
     }, //0x2
     Instruction {
         name: "slo",
         function: slo,
-        address_mode: indirect_x,
+        address_mode: AddressMode::IndirectX,
         cycles: 8,
     }, //0x3
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0x4
     Instruction {
         name: "ora",
         function: ora,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0x5
     Instruction {
         name: "asl",
         function: asl,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 5,
     }, //0x6
     Instruction {
         name: "slo",
         function: slo,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 5,
     }, //0x7
     Instruction {
         name: "php",
         function: php,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 3,
     }, //0x8
     Instruction {
         name: "ora",
         function: ora,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0x9
     Instruction {
         name: "asl",
         function: asl,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0xa
     Instruction {
         name: "anc",
         function: anc,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0xb
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0xc
     Instruction {
         name: "ora",
         function: ora,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0xd
     Instruction {
         name: "asl",
         function: asl,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 6,
     }, //0xe
     Instruction {
         name: "slo",
         function: slo,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 6,
     }, //0xf
     Instruction {
         name: "bpl",
         function: bpl,
-        address_mode: relative,
+        address_mode: AddressMode::Relative,
         cycles: 2,
     }, //0x10
     Instruction {
         name: "ora",
         function: ora,
-        address_mode: indirect_y,
+        address_mode: AddressMode::IndirectY,
         cycles: 5,
     }, //0x11
     Instruction {
         name: "kil",
         function: kil,
-        address_mode: implied,
-        cycles: 0,
+        address_mode: AddressMode::Implied,
+        cycles: 2,
     }, //0x12
     Instruction {
         name: "slo",
         function: slo,
-        address_mode: indirect_y_const,
+        address_mode: AddressMode::IndirectYConst,
         cycles: 8,
     }, //0x13
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 4,
     }, //0x14
     Instruction {
         name: "ora",
         function: ora,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 4,
     }, //0x15
     Instruction {
         name: "asl",
         function: asl,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 6,
     }, //0x16
     Instruction {
         name: "slo",
         function: slo,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 6,
     }, //0x17
     Instruction {
         name: "clc",
         function: clc,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0x18
     Instruction {
         name: "ora",
         function: ora,
-        address_mode: absolute_y,
+        address_mode: AddressMode::AbsoluteY,
         cycles: 4,
     }, //0x19
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0x1a
     Instruction {
         name: "slo",
         function: slo,
-        address_mode: absolute_y_const,
+        address_mode: AddressMode::AbsoluteYConst,
         cycles: 7,
     }, //0x1b
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: absolute_x,
+        address_mode: AddressMode::AbsoluteX,
         cycles: 4,
     }, //0x1c
     Instruction {
         name: "ora",
         function: ora,
-        address_mode: absolute_x,
+        address_mode: AddressMode::AbsoluteX,
         cycles: 4,
     }, //0x1d
     Instruction {
         name: "asl",
         function: asl,
-        address_mode: absolute_x_const,
+        address_mode: AddressMode::AbsoluteXConst,
         cycles: 7,
     }, //0x1e
     Instruction {
         name: "slo",
         function: slo,
-        address_mode: absolute_x_const,
+        address_mode: AddressMode::AbsoluteXConst,
         cycles: 7,
     }, //0x1f
     Instruction {
         name: "jsr",
         function: jsr,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 6,
     }, //0x20
     Instruction {
         name: "and",
         function: and,
-        address_mode: indirect_x,
+        address_mode: AddressMode::IndirectX,
         cycles: 6,
     }, //0x21
     Instruction {
         name: "kil",
         function: kil,
-        address_mode: implied,
-        cycles: 0,
+        address_mode: AddressMode::Implied,
+        cycles: 2,
     }, //0x22
     Instruction {
         name: "rla",
         function: rla,
-        address_mode: indirect_x,
+        address_mode: AddressMode::IndirectX,
         cycles: 8,
     }, //0x23
     Instruction {
         name: "bit",
         function: bit,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0x24
     Instruction {
         name: "and",
         function: and,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0x25
     Instruction {
         name: "rol",
         function: rol,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 5,
     }, //0x26
     Instruction {
         name: "rla",
         function: rla,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 5,
     }, //0x27
     Instruction {
         name: "plp",
         function: plp,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 4,
     }, //0x28
     Instruction {
         name: "and",
         function: and,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0x29
     Instruction {
         name: "rol",
         function: rol,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0x2a
     Instruction {
         name: "anc",
         function: anc,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0x2b
     Instruction {
         name: "bit",
         function: bit,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0x2c
     Instruction {
         name: "and",
         function: and,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0x2d
     Instruction {
         name: "rol",
         function: rol,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 6,
     }, //0x2e
     Instruction {
         name: "rla",
         function: rla,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 6,
     }, //0x2f
     Instruction {
         name: "bmi",
         function: bmi,
-        address_mode: relative,
+        address_mode: AddressMode::Relative,
         cycles: 2,
     }, //0x30
     Instruction {
         name: "and",
         function: and,
-        address_mode: indirect_y,
+        address_mode: AddressMode::IndirectY,
         cycles: 5,
     }, //0x31
     Instruction {
         name: "kil",
         function: kil,
-        address_mode: implied,
-        cycles: 0,
+        address_mode: AddressMode::Implied,
+        cycles: 2,
     }, //0x32
     Instruction {
         name: "rla",
         function: rla,
-        address_mode: indirect_y_const,
+        address_mode: AddressMode::IndirectYConst,
         cycles: 8,
     }, //0x33
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 4,
     }, //0x34
     Instruction {
         name: "and",
         function: and,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 4,
     }, //0x35
     Instruction {
         name: "rol",
         function: rol,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 6,
     }, //0x36
     Instruction {
         name: "rla",
         function: rla,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 6,
     }, //0x37
     Instruction {
         name: "sec",
         function: sec,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0x38
     Instruction {
         name: "and",
         function: and,
-        address_mode: absolute_y,
+        address_mode: AddressMode::AbsoluteY,
         cycles: 4,
     }, //0x39
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0x3a
     Instruction {
         name: "rla",
         function: rla,
-        address_mode: absolute_y_const,
+        address_mode: AddressMode::AbsoluteYConst,
         cycles: 7,
     }, //0x3b
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: absolute_x,
+        address_mode: AddressMode::AbsoluteX,
         cycles: 4,
     }, //0x3c
     Instruction {
         name: "and",
         function: and,
-        address_mode: absolute_x,
+        address_mode: AddressMode::AbsoluteX,
         cycles: 4,
     }, //0x3d
     Instruction {
         name: "rol",
         function: rol,
-        address_mode: absolute_x_const,
+        address_mode: AddressMode::AbsoluteXConst,
         cycles: 7,
     }, //0x3e
     Instruction {
         name: "rla",
         function: rla,
-        address_mode: absolute_x_const,
+        address_mode: AddressMode::AbsoluteXConst,
         cycles: 7,
     }, //0x3f
     Instruction {
         name: "rti",
         function: rti,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 6,
     }, //0x40
     Instruction {
         name: "eor",
         function: eor,
-        address_mode: indirect_x,
+        address_mode: AddressMode::IndirectX,
         cycles: 6,
     }, //0x41
     Instruction {
         name: "kil",
         function: kil,
-        address_mode: implied,
-        cycles: 0,
+        address_mode: AddressMode::Implied,
+        cycles: 2,
     }, //0x42
     Instruction {
         name: "sre",
         function: sre,
-        address_mode: indirect_x,
+        address_mode: AddressMode::IndirectX,
         cycles: 8,
     }, //0x43
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0x44
     Instruction {
         name: "eor",
         function: eor,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0x45
     Instruction {
         name: "lsr",
         function: lsr,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 5,
     }, //0x46
     Instruction {
         name: "sre",
         function: sre,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 5,
     }, //0x47
     Instruction {
         name: "pha",
         function: pha,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 3,
     }, //0x48
     Instruction {
         name: "eor",
         function: eor,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0x49
     Instruction {
         name: "lsr",
         function: lsr,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0x4a
     Instruction {
         name: "alr",
         function: alr,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0x4b
     Instruction {
         name: "jmp",
         function: jmp,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 3,
     }, //0x4c
     Instruction {
         name: "eor",
         function: eor,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0x4d
     Instruction {
         name: "lsr",
         function: lsr,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 6,
     }, //0x4e
     Instruction {
         name: "sre",
         function: sre,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 6,
     }, //0x4f
     Instruction {
         name: "bvc",
         function: bvc,
-        address_mode: relative,
+        address_mode: AddressMode::Relative,
         cycles: 2,
     }, //0x50
     Instruction {
         name: "eor",
         function: eor,
-        address_mode: indirect_y,
+        address_mode: AddressMode::IndirectY,
         cycles: 5,
     }, //0x51
     Instruction {
         name: "kil",
         function: kil,
-        address_mode: implied,
-        cycles: 0,
+        address_mode: AddressMode::Implied,
+        cycles: 2,
     }, //0x52
     Instruction {
         name: "sre",
         function: sre,
-        address_mode: indirect_y_const,
+        address_mode: AddressMode::IndirectYConst,
         cycles: 8,
     }, //0x53
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 4,
     }, //0x54
     Instruction {
         name: "eor",
         function: eor,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 4,
     }, //0x55
     Instruction {
         name: "lsr",
         function: lsr,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 6,
     }, //0x56
     Instruction {
         name: "sre",
         function: sre,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 6,
     }, //0x57
     Instruction {
         name: "cli",
         function: cli,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0x58
     Instruction {
         name: "eor",
         function: eor,
-        address_mode: absolute_y,
+        address_mode: AddressMode::AbsoluteY,
         cycles: 4,
     }, //0x59
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0x5a
     Instruction {
         name: "sre",
         function: sre,
-        address_mode: absolute_y_const,
+        address_mode: AddressMode::AbsoluteYConst,
         cycles: 7,
     }, //0x5b
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: absolute_x,
+        address_mode: AddressMode::AbsoluteX,
         cycles: 4,
     }, //0x5c
     Instruction {
         name: "eor",
         function: eor,
-        address_mode: absolute_x,
+        address_mode: AddressMode::AbsoluteX,
         cycles: 4,
     }, //0x5d
     Instruction {
         name: "lsr",
         function: lsr,
-        address_mode: absolute_x_const,
+        address_mode: AddressMode::AbsoluteXConst,
         cycles: 7,
     }, //0x5e
     Instruction {
         name: "sre",
         function: sre,
-        address_mode: absolute_x_const,
+        address_mode: AddressMode::AbsoluteXConst,
         cycles: 7,
     }, //0x5f
     Instruction {
         name: "rts",
         function: rts,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 6,
     }, //0x60
     Instruction {
         name: "adc",
         function: adc,
-        address_mode: indirect_x,
+        address_mode: AddressMode::IndirectX,
         cycles: 6,
     }, //0x61
     Instruction {
         name: "kil",
         function: kil,
-        address_mode: implied,
-        cycles: 0,
+        address_mode: AddressMode::Implied,
+        cycles: 2,
     }, //0x62
     Instruction {
         name: "rra",
         function: rra,
-        address_mode: indirect_x,
+        address_mode: AddressMode::IndirectX,
         cycles: 8,
     }, //0x63
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0x64
     Instruction {
         name: "adc",
         function: adc,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0x65
     Instruction {
         name: "ror",
         function: ror,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 5,
     }, //0x66
     Instruction {
         name: "rra",
         function: rra,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 5,
     }, //0x67
     Instruction {
         name: "pla",
         function: pla,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 4,
     }, //0x68
     Instruction {
         name: "adc",
         function: adc,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0x69
     Instruction {
         name: "ror",
         function: ror,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0x6a
     Instruction {
         name: "arr",
         function: arr,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0x6b
     Instruction {
         name: "jmp",
         function: jmp,
-        address_mode: indirect,
+        address_mode: AddressMode::Indirect,
         cycles: 5,
     }, //0x6c
     Instruction {
         name: "adc",
         function: adc,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0x6d
     Instruction {
         name: "ror",
         function: ror,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 6,
     }, //0x6e
     Instruction {
         name: "rra",
         function: rra,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 6,
     }, //0x6f
     Instruction {
         name: "bvs",
         function: bvs,
-        address_mode: relative,
+        address_mode: AddressMode::Relative,
         cycles: 2,
     }, //0x70
     Instruction {
         name: "adc",
         function: adc,
-        address_mode: indirect_y,
+        address_mode: AddressMode::IndirectY,
         cycles: 5,
     }, //0x71
     Instruction {
         name: "kil",
         function: kil,
-        address_mode: implied,
-        cycles: 0,
+        address_mode: AddressMode::Implied,
+        cycles: 2,
     }, //0x72
     Instruction {
         name: "rra",
         function: rra,
-        address_mode: indirect_y_const,
+        address_mode: AddressMode::IndirectYConst,
         cycles: 8,
     }, //0x73
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 4,
     }, //0x74
     Instruction {
         name: "adc",
         function: adc,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 4,
     }, //0x75
     Instruction {
         name: "ror",
         function: ror,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 6,
     }, //0x76
     Instruction {
         name: "rra",
         function: rra,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 6,
     }, //0x77
     Instruction {
         name: "sei",
         function: sei,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0x78
     Instruction {
         name: "adc",
         function: adc,
-        address_mode: absolute_y,
+        address_mode: AddressMode::AbsoluteY,
         cycles: 4,
     }, //0x79
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0x7a
     Instruction {
         name: "rra",
         function: rra,
-        address_mode: absolute_y_const,
+        address_mode: AddressMode::AbsoluteYConst,
         cycles: 7,
     }, //0x7b
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: absolute_x,
+        address_mode: AddressMode::AbsoluteX,
         cycles: 4,
     }, //0x7c
     Instruction {
         name: "adc",
         function: adc,
-        address_mode: absolute_x,
+        address_mode: AddressMode::AbsoluteX,
         cycles: 4,
     }, //0x7d
     Instruction {
         name: "ror",
         function: ror,
-        address_mode: absolute_x_const,
+        address_mode: AddressMode::AbsoluteXConst,
         cycles: 7,
     }, //0x7e
     Instruction {
         name: "rra",
         function: rra,
-        address_mode: absolute_x_const,
+        address_mode: AddressMode::AbsoluteXConst,
         cycles: 7,
     }, //0x7f
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0x80
     Instruction {
         name: "sta",
         function: sta,
-        address_mode: indirect_x,
+        address_mode: AddressMode::IndirectX,
         cycles: 6,
     }, //0x81
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0x82
     Instruction {
         name: "sax",
         function: sax,
-        address_mode: indirect_x,
+        address_mode: AddressMode::IndirectX,
         cycles: 6,
     }, //0x83
     Instruction {
         name: "sty",
         function: sty,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0x84
     Instruction {
         name: "sta",
         function: sta,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0x85
     Instruction {
         name: "stx",
         function: stx,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0x86
     Instruction {
         name: "sax",
         function: sax,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0x87
     Instruction {
         name: "dey",
         function: dey,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0x88
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0x89
     Instruction {
         name: "txa",
         function: txa,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0x8a
     Instruction {
         name: "xaa",
         function: xaa,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0x8b
     Instruction {
         name: "sty",
         function: sty,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0x8c
     Instruction {
         name: "sta",
         function: sta,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0x8d
     Instruction {
         name: "stx",
         function: stx,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0x8e
     Instruction {
         name: "sax",
         function: sax,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0x8f
     Instruction {
         name: "bcc",
         function: bcc,
-        address_mode: relative,
+        address_mode: AddressMode::Relative,
         cycles: 2,
     }, //0x90
     Instruction {
         name: "sta",
         function: sta,
-        address_mode: indirect_y,
+        address_mode: AddressMode::IndirectY,
         cycles: 6,
     }, //0x91
     Instruction {
         name: "kil",
         function: kil,
-        address_mode: implied,
-        cycles: 0,
+        address_mode: AddressMode::Implied,
+        cycles: 2,
     }, //0x92
     Instruction {
         name: "ahx",
         function: ahx,
-        address_mode: indirect_y,
+        address_mode: AddressMode::IndirectY,
         cycles: 6,
     }, //0x93
     Instruction {
         name: "sty",
         function: sty,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 4,
     }, //0x94
     Instruction {
         name: "sta",
         function: sta,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 4,
     }, //0x95
     Instruction {
         name: "stx",
         function: stx,
-        address_mode: zero_page_y,
+        address_mode: AddressMode::ZeroPageY,
         cycles: 4,
     }, //0x96
     Instruction {
         name: "sax",
         function: sax,
-        address_mode: zero_page_y,
+        address_mode: AddressMode::ZeroPageY,
         cycles: 4,
     }, //0x97
     Instruction {
         name: "tya",
         function: tya,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0x98
     Instruction {
         name: "sta",
         function: sta,
-        address_mode: absolute_y_const,
+        address_mode: AddressMode::AbsoluteYConst,
         cycles: 5,
     }, //0x99
     Instruction {
         name: "txs",
         function: txs,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0x9a
     Instruction {
         name: "tas",
         function: tas,
-        address_mode: absolute_y,
+        address_mode: AddressMode::AbsoluteY,
         cycles: 5,
     }, //0x9b
     Instruction {
         name: "shy",
         function: shy,
-        address_mode: absolute_x,
+        address_mode: AddressMode::AbsoluteX,
         cycles: 5,
     }, //0x9c
     Instruction {
         name: "sta",
         function: sta,
-        address_mode: absolute_x_const,
+        address_mode: AddressMode::AbsoluteXConst,
         cycles: 5,
     }, //0x9d
     Instruction {
         name: "shx",
         function: shx,
-        address_mode: absolute_y,
+        address_mode: AddressMode::AbsoluteY,
         cycles: 5,
     }, //0x9e
     Instruction {
         name: "ahx",
         function: ahx,
-        address_mode: absolute_y,
+        address_mode: AddressMode::AbsoluteY,
         cycles: 5,
     }, //0x9f
     Instruction {
         name: "ldy",
         function: ldy,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0xa0
     Instruction {
         name: "lda",
         function: lda,
-        address_mode: indirect_x,
+        address_mode: AddressMode::IndirectX,
         cycles: 6,
     }, //0xa1
     Instruction {
         name: "ldx",
         function: ldx,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0xa2
     Instruction {
         name: "lax",
         function: lax,
-        address_mode: indirect_x,
+        address_mode: AddressMode::IndirectX,
         cycles: 6,
     }, //0xa3
     Instruction {
         name: "ldy",
         function: ldy,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0xa4
     Instruction {
         name: "lda",
         function: lda,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0xa5
     Instruction {
         name: "ldx",
         function: ldx,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0xa6
     Instruction {
         name: "lax",
         function: lax,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0xa7
     Instruction {
         name: "tay",
         function: tay,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0xa8
     Instruction {
         name: "lda",
         function: lda,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0xa9
     Instruction {
         name: "tax",
         function: tax,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0xaa
     Instruction {
         name: "lax",
         function: lax,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0xab
     Instruction {
         name: "ldy",
         function: ldy,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0xac
     Instruction {
         name: "lda",
         function: lda,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0xad
     Instruction {
         name: "ldx",
         function: ldx,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0xae
     Instruction {
         name: "lax",
         function: lax,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0xaf
     Instruction {
         name: "bcs",
         function: bcs,
-        address_mode: relative,
+        address_mode: AddressMode::Relative,
         cycles: 2,
     }, //0xb0
     Instruction {
         name: "lda",
         function: lda,
-        address_mode: indirect_y,
+        address_mode: AddressMode::IndirectY,
         cycles: 5,
     }, //0xb1
     Instruction {
         name: "kil",
         function: kil,
-        address_mode: implied,
-        cycles: 0,
+        address_mode: AddressMode::Implied,
+        cycles: 2,
     }, //0xb2
     Instruction {
         name: "lax",
         function: lax,
-        address_mode: indirect_y,
+        address_mode: AddressMode::IndirectY,
         cycles: 5,
     }, //0xb3
     Instruction {
         name: "ldy",
         function: ldy,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 4,
     }, //0xb4
     Instruction {
         name: "lda",
         function: lda,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 4,
     }, //0xb5
     Instruction {
         name: "ldx",
         function: ldx,
-        address_mode: zero_page_y,
+        address_mode: AddressMode::ZeroPageY,
         cycles: 4,
     }, //0xb6
     Instruction {
         name: "lax",
         function: lax,
-        address_mode: zero_page_y,
+        address_mode: AddressMode::ZeroPageY,
         cycles: 4,
     }, //0xb7
     Instruction {
         name: "clv",
         function: clv,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0xb8
     Instruction {
         name: "lda",
         function: lda,
-        address_mode: absolute_y,
+        address_mode: AddressMode::AbsoluteY,
         cycles: 4,
     }, //0xb9
     Instruction {
         name: "tsx",
         function: tsx,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0xba
     Instruction {
         name: "las",
         function: las,
-        address_mode: absolute_y,
+        address_mode: AddressMode::AbsoluteY,
         cycles: 4,
     }, //0xbb
     Instruction {
         name: "ldy",
         function: ldy,
-        address_mode: absolute_x,
+        address_mode: AddressMode::AbsoluteX,
         cycles: 4,
     }, //0xbc
     Instruction {
         name: "lda",
         function: lda,
-        address_mode: absolute_x,
+        address_mode: AddressMode::AbsoluteX,
         cycles: 4,
     }, //0xbd
     Instruction {
         name: "ldx",
         function: ldx,
-        address_mode: absolute_y,
+        address_mode: AddressMode::AbsoluteY,
         cycles: 4,
     }, //0xbe
     Instruction {
         name: "lax",
         function: lax,
-        address_mode: absolute_y,
+        address_mode: AddressMode::AbsoluteY,
         cycles: 4,
     }, //0xbf
     Instruction {
         name: "cpy",
         function: cpy,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0xc0
     Instruction {
         name: "cmp",
         function: cmp,
-        address_mode: indirect_x,
+        address_mode: AddressMode::IndirectX,
         cycles: 6,
     }, //0xc1
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0xc2
     Instruction {
         name: "dcp",
         function: dcp,
-        address_mode: indirect_x,
+        address_mode: AddressMode::IndirectX,
         cycles: 8,
     }, //0xc3
     Instruction {
         name: "cpy",
         function: cpy,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0xc4
     Instruction {
         name: "cmp",
         function: cmp,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0xc5
     Instruction {
         name: "dec",
         function: dec,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 5,
     }, //0xc6
     Instruction {
         name: "dcp",
         function: dcp,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 5,
     }, //0xc7
     Instruction {
         name: "iny",
         function: iny,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0xc8
     Instruction {
         name: "cmp",
         function: cmp,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0xc9
     Instruction {
         name: "dex",
         function: dex,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0xca
     Instruction {
         name: "axs",
         function: axs,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0xcb
     Instruction {
         name: "cpy",
         function: cpy,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0xcc
     Instruction {
         name: "cmp",
         function: cmp,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0xcd
     Instruction {
         name: "dec",
         function: dec,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 6,
     }, //0xce
     Instruction {
         name: "dcp",
         function: dcp,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 6,
     }, //0xcf
     Instruction {
         name: "bne",
         function: bne,
-        address_mode: relative,
+        address_mode: AddressMode::Relative,
         cycles: 2,
     }, //0xd0
     Instruction {
         name: "cmp",
         function: cmp,
-        address_mode: indirect_y,
+        address_mode: AddressMode::IndirectY,
         cycles: 5,
     }, //0xd1
     Instruction {
         name: "kil",
         function: kil,
-        address_mode: implied,
-        cycles: 0,
+        address_mode: AddressMode::Implied,
+        cycles: 2,
     }, //0xd2
     Instruction {
         name: "dcp",
         function: dcp,
-        address_mode: indirect_y_const,
+        address_mode: AddressMode::IndirectYConst,
         cycles: 8,
     }, //0xd3
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 4,
     }, //0xd4
     Instruction {
         name: "cmp",
         function: cmp,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 4,
     }, //0xd5
     Instruction {
         name: "dec",
         function: dec,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 6,
     }, //0xd6
     Instruction {
         name: "dcp",
         function: dcp,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 6,
     }, //0xd7
     Instruction {
         name: "cld",
         function: cld,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0xd8
     Instruction {
         name: "cmp",
         function: cmp,
-        address_mode: absolute_y,
+        address_mode: AddressMode::AbsoluteY,
         cycles: 4,
     }, //0xd9
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0xda
     Instruction {
         name: "dcp",
         function: dcp,
-        address_mode: absolute_y_const,
+        address_mode: AddressMode::AbsoluteYConst,
         cycles: 7,
     }, //0xdb
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: absolute_x,
+        address_mode: AddressMode::AbsoluteX,
         cycles: 4,
     }, //0xdc
     Instruction {
         name: "cmp",
         function: cmp,
-        address_mode: absolute_x,
+        address_mode: AddressMode::AbsoluteX,
         cycles: 4,
     }, //0xdd
     Instruction {
         name: "dec",
         function: dec,
-        address_mode: absolute_x_const,
+        address_mode: AddressMode::AbsoluteXConst,
         cycles: 7,
     }, //0xde
     Instruction {
         name: "dcp",
         function: dcp,
-        address_mode: absolute_x_const,
+        address_mode: AddressMode::AbsoluteXConst,
         cycles: 7,
     }, //0xdf
     Instruction {
         name: "cpx",
         function: cpx,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0xe0
     Instruction {
         name: "sbc",
         function: sbc,
-        address_mode: indirect_x,
+        address_mode: AddressMode::IndirectX,
         cycles: 6,
     }, //0xe1
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0xe2
     Instruction {
         name: "isc",
         function: isc,
-        address_mode: indirect_x,
+        address_mode: AddressMode::IndirectX,
         cycles: 8,
     }, //0xe3
     Instruction {
         name: "cpx",
         function: cpx,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0xe4
     Instruction {
         name: "sbc",
         function: sbc,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 3,
     }, //0xe5
     Instruction {
         name: "inc",
         function: inc,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 5,
     }, //0xe6
     Instruction {
         name: "isc",
         function: isc,
-        address_mode: zero_page,
+        address_mode: AddressMode::ZeroPage,
         cycles: 5,
     }, //0xe7
     Instruction {
         name: "inx",
         function: inx,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0xe8
     Instruction {
         name: "sbc",
         function: sbc,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0xe9
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0xea
     Instruction {
         name: "sbc",
         function: sbc,
-        address_mode: immediate,
+        address_mode: AddressMode::Immediate,
         cycles: 2,
     }, //0xeb
     Instruction {
         name: "cpx",
         function: cpx,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0xec
     Instruction {
         name: "sbc",
         function: sbc,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 4,
     }, //0xed
     Instruction {
         name: "inc",
         function: inc,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 6,
     }, //0xee
     Instruction {
         name: "isc",
         function: isc,
-        address_mode: absolute,
+        address_mode: AddressMode::Absolute,
         cycles: 6,
     }, //0xef
     Instruction {
         name: "beq",
         function: beq,
-        address_mode: relative,
+        address_mode: AddressMode::Relative,
         cycles: 2,
     }, //0xf0
     Instruction {
         name: "sbc",
         function: sbc,
-        address_mode: indirect_y,
+        address_mode: AddressMode::IndirectY,
         cycles: 5,
     }, //0xf1
     Instruction {
         name: "kil",
         function: kil,
-        address_mode: implied,
-        cycles: 0,
+        address_mode: AddressMode::Implied,
+        cycles: 2,
     }, //0xf2
     Instruction {
         name: "isc",
         function: isc,
-        address_mode: indirect_y_const,
+        address_mode: AddressMode::IndirectYConst,
         cycles: 8,
     }, //0xf3
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 4,
     }, //0xf4
     Instruction {
         name: "sbc",
         function: sbc,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 4,
     }, //0xf5
     Instruction {
         name: "inc",
         function: inc,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 6,
     }, //0xf6
     Instruction {
         name: "isc",
         function: isc,
-        address_mode: zero_page_x,
+        address_mode: AddressMode::ZeroPageX,
         cycles: 6,
     }, //0xf7
     Instruction {
         name: "sed",
         function: sed,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0xf8
     Instruction {
         name: "sbc",
         function: sbc,
-        address_mode: absolute_y,
+        address_mode: AddressMode::AbsoluteY,
         cycles: 4,
     }, //0xf9
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: implied,
+        address_mode: AddressMode::Implied,
         cycles: 2,
     }, //0xfa
     Instruction {
         name: "isc",
         function: isc,
-        address_mode: absolute_y_const,
+        address_mode: AddressMode::AbsoluteYConst,
         cycles: 7,
     }, //0xfb
     Instruction {
         name: "nop",
         function: nop,
-        address_mode: absolute_x,
+        address_mode: AddressMode::AbsoluteX,
         cycles: 4,
     }, //0xfc
     Instruction {
         name: "sbc",
         function: sbc,
-        address_mode: absolute_x,
+        address_mode: AddressMode::AbsoluteX,
         cycles: 4,
     }, //0xfd
     Instruction {
         name: "inc",
         function: inc,
-        address_mode: absolute_x_const,
+        address_mode: AddressMode::AbsoluteXConst,
         cycles: 7,
     }, //0xfe
     Instruction {
         name: "isc",
         function: isc,
-        address_mode: absolute_x_const,
+        address_mode: AddressMode::AbsoluteXConst,
         cycles: 7,
     }, //0xff
 ];
@@ -1608,7 +1612,7 @@ fn adc(cpu: &mut MOS6502, bus: &mut dyn Interface6502, address_mode_value: Addre
             result = sum & 0xff;
         } else {
             //Set the Carry flag for chain adding multi byte numbers
-            cpu.set_flag(StatusFlag::Carry, result > u16::from(u8::max_value()));
+            cpu.set_flag(StatusFlag::Carry, result > u16::from(u8::MAX));
             cpu.set_flag(StatusFlag::Zero, result as u8 == 0);
             //Set the Overflow flag if a signed overflow has occurred
             cpu.set_flag(
@@ -2035,7 +2039,7 @@ fn sbc(cpu: &mut MOS6502, bus: &mut dyn Interface6502, address_mode_value: Addre
 
         let mut result = u16::from(cpu.accumulator) + u16::from(!value) + carry as u16;
         // Set the Carry flag for chain adding multi byte numbers
-        cpu.set_flag(StatusFlag::Carry, result > u16::from(u8::max_value()));
+        cpu.set_flag(StatusFlag::Carry, result > u16::from(u8::MAX));
         cpu.set_flag(StatusFlag::Zero, result as u8 == 0);
         // Set the Overflow flag if a signed overflow has occurred
         cpu.set_flag(
@@ -2228,7 +2232,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x1a,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Carry, false);
 
@@ -2262,7 +2266,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
         cpu_expected.set_flag(StatusFlag::Carry, true);
@@ -2297,7 +2301,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x80,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Overflow, true);
         cpu_expected.set_flag(StatusFlag::Negative, true);
@@ -2335,7 +2339,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x19,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Carry, false);
 
@@ -2372,7 +2376,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, false);
         cpu_expected.set_flag(StatusFlag::Negative, true);
@@ -2410,7 +2414,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: EXPECTED.0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, EXPECTED.1);
         cpu_expected.set_flag(StatusFlag::Overflow, EXPECTED.2);
@@ -2449,7 +2453,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: EXPECTED.0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, EXPECTED.1);
         cpu_expected.set_flag(StatusFlag::Overflow, EXPECTED.2);
@@ -2488,7 +2492,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: EXPECTED.0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, EXPECTED.1);
         cpu_expected.set_flag(StatusFlag::Overflow, EXPECTED.2);
@@ -2527,7 +2531,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: EXPECTED.0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, EXPECTED.1);
         cpu_expected.set_flag(StatusFlag::Overflow, EXPECTED.2);
@@ -2566,7 +2570,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: EXPECTED.0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, EXPECTED.1);
         cpu_expected.set_flag(StatusFlag::Overflow, EXPECTED.2);
@@ -2605,7 +2609,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: EXPECTED.0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, EXPECTED.1);
         cpu_expected.set_flag(StatusFlag::Overflow, EXPECTED.2);
@@ -2644,7 +2648,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: EXPECTED.0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, EXPECTED.1);
         cpu_expected.set_flag(StatusFlag::Overflow, EXPECTED.2);
@@ -2683,7 +2687,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: EXPECTED.0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, EXPECTED.1);
         cpu_expected.set_flag(StatusFlag::Overflow, EXPECTED.2);
@@ -2722,7 +2726,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: EXPECTED.0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, EXPECTED.1);
         cpu_expected.set_flag(StatusFlag::Overflow, EXPECTED.2);
@@ -2761,7 +2765,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: EXPECTED.0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, EXPECTED.1);
         cpu_expected.set_flag(StatusFlag::Overflow, EXPECTED.2);
@@ -2799,7 +2803,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x81,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
         cpu_expected.set_flag(StatusFlag::Overflow, true);
@@ -2844,7 +2848,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x80,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -2878,7 +2882,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -2911,7 +2915,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
         asl(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
@@ -2943,7 +2947,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Carry, true);
         cpu_expected.set_flag(StatusFlag::Zero, true);
@@ -2983,7 +2987,7 @@ mod test {
         let mut cpu_expected = MOS6502 {
             program_counter: 0x8001,
             stack_pointer: 0xfa,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Break, true);
         cpu_expected.set_flag(StatusFlag::InterruptDisable, true);
@@ -3009,7 +3013,7 @@ mod test {
         let mut cpu_expected = MOS6502 {
             program_counter: 0x0100,
             remaining_cycles: 2,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
 
         branch(&mut cpu_initial, true, AddressModeValue::RelativeAddress(0x05));
@@ -3031,7 +3035,7 @@ mod test {
         let mut cpu_expected = MOS6502 {
             program_counter: 0x0005,
             remaining_cycles: 1,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
 
         branch(&mut cpu_initial, true, AddressModeValue::RelativeAddress(0xfb));
@@ -3050,7 +3054,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
 
         branch(&mut cpu_initial, false, AddressModeValue::RelativeAddress(0xfb));
         assert_eq!(cpu_initial, cpu_expected);
@@ -3079,7 +3083,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
         bit(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
@@ -3109,7 +3113,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
         cpu_expected.set_flag(StatusFlag::Zero, true);
         cpu_expected.set_flag(StatusFlag::Negative, true);
         cpu_expected.set_flag(StatusFlag::Overflow, true);
@@ -3141,7 +3145,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
         cpu_expected.set_flag(StatusFlag::Zero, true);
         cpu_expected.set_flag(StatusFlag::Carry, true);
 
@@ -3172,7 +3176,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
         compare(&mut cpu_initial, &mut stub_bus, 0x0f, AddressModeValue::AbsoluteAddress(0x00ff));
@@ -3203,7 +3207,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
         dec(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
@@ -3234,7 +3238,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
         dec(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
@@ -3265,7 +3269,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             x_register: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -3297,7 +3301,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             x_register: 0xff,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -3329,7 +3333,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             y_register: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -3361,7 +3365,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             y_register: 0xff,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -3394,7 +3398,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x90,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -3428,7 +3432,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -3460,7 +3464,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
         inc(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
@@ -3491,7 +3495,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
         inc(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
@@ -3522,7 +3526,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             x_register: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -3554,7 +3558,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             x_register: 0x80,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -3586,7 +3590,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             y_register: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -3618,7 +3622,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             y_register: 0x80,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -3650,7 +3654,7 @@ mod test {
 
         let cpu_expected = MOS6502 {
             program_counter: 0x00ff,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
 
         jmp(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
@@ -3684,7 +3688,7 @@ mod test {
         let cpu_expected = MOS6502 {
             program_counter: 0x00ff,
             stack_pointer: 0xfb,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
 
         jsr(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
@@ -3713,7 +3717,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0xff,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -3743,7 +3747,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -3773,7 +3777,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             x_register: 0xff,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -3803,7 +3807,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             x_register: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -3833,7 +3837,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             y_register: 0xff,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -3863,7 +3867,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             y_register: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -3895,7 +3899,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
         cpu_expected.set_flag(StatusFlag::Carry, true);
 
         lsr(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
@@ -3927,7 +3931,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
         cpu_expected.set_flag(StatusFlag::Carry, true);
@@ -3962,7 +3966,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x90,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -3996,7 +4000,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -4029,7 +4033,7 @@ mod test {
 
         let cpu_expected = MOS6502 {
             stack_pointer: 0xfc,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
 
         pha(&mut cpu_initial, &mut stub_bus, AddressModeValue::Implied);
@@ -4062,7 +4066,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             stack_pointer: 0xfc,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         //cpu_expected.set_flag(StatusFlag::Break, true);
 
@@ -4098,7 +4102,7 @@ mod test {
             accumulator: 0xff,
             stack_pointer: 0xfd,
             status_register: 0x80,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
 
         pla(&mut cpu_initial, &mut stub_bus, AddressModeValue::Implied);
@@ -4132,7 +4136,7 @@ mod test {
         let cpu_expected = MOS6502 {
             status_register: 0xa1,
             stack_pointer: 0xfd,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
 
         plp(&mut cpu_initial, &mut stub_bus, AddressModeValue::Implied);
@@ -4165,7 +4169,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
         cpu_expected.set_flag(StatusFlag::Negative, true);
         cpu_expected.set_flag(StatusFlag::Carry, false);
 
@@ -4198,7 +4202,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
         cpu_expected.set_flag(StatusFlag::Carry, true);
@@ -4233,7 +4237,7 @@ mod test {
             ..Default::default()
         };
 
-        let mut cpu_expected = MOS6502 { ..cpu_initial };
+        let mut cpu_expected = MOS6502 { ..cpu_initial.clone() };
         cpu_expected.set_flag(StatusFlag::Negative, true);
         cpu_expected.set_flag(StatusFlag::Carry, false);
 
@@ -4266,7 +4270,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
         cpu_expected.set_flag(StatusFlag::Carry, true);
@@ -4305,7 +4309,7 @@ mod test {
             program_counter: 0x4001,
             status_register: 0xe1,
             stack_pointer: 0xfd,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
 
         rti(&mut cpu_initial, &mut stub_bus, AddressModeValue::Implied);
@@ -4340,7 +4344,7 @@ mod test {
         let cpu_expected = MOS6502 {
             program_counter: 0x1001,
             stack_pointer: 0xfd,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
 
         rts(&mut cpu_initial, &mut stub_bus, AddressModeValue::Implied);
@@ -4370,7 +4374,7 @@ mod test {
 
         let cpu_expected = MOS6502 {
             accumulator: 0x08,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
 
         sbc(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
@@ -4400,7 +4404,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x7f,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Overflow, true);
 
@@ -4431,7 +4435,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -4462,7 +4466,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0xff,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Carry, false);
         cpu_expected.set_flag(StatusFlag::Negative, true);
@@ -4496,7 +4500,7 @@ mod test {
 
         let cpu_expected = MOS6502 {
             accumulator: 0x06,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
 
         sbc(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
@@ -4528,7 +4532,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x94,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Carry, false);
         cpu_expected.set_flag(StatusFlag::Negative, true);
@@ -4565,7 +4569,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: EXPECTED.0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, EXPECTED.1);
         cpu_expected.set_flag(StatusFlag::Overflow, EXPECTED.2);
@@ -4604,7 +4608,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: EXPECTED.0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, EXPECTED.1);
         cpu_expected.set_flag(StatusFlag::Overflow, EXPECTED.2);
@@ -4643,7 +4647,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: EXPECTED.0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, EXPECTED.1);
         cpu_expected.set_flag(StatusFlag::Overflow, EXPECTED.2);
@@ -4682,7 +4686,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: EXPECTED.0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, EXPECTED.1);
         cpu_expected.set_flag(StatusFlag::Overflow, EXPECTED.2);
@@ -4721,7 +4725,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: EXPECTED.0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, EXPECTED.1);
         cpu_expected.set_flag(StatusFlag::Overflow, EXPECTED.2);
@@ -4760,7 +4764,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: EXPECTED.0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, EXPECTED.1);
         cpu_expected.set_flag(StatusFlag::Overflow, EXPECTED.2);
@@ -4799,7 +4803,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: EXPECTED.0,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, EXPECTED.1);
         cpu_expected.set_flag(StatusFlag::Overflow, EXPECTED.2);
@@ -4833,7 +4837,7 @@ mod test {
             ..Default::default()
         };
 
-        let cpu_expected = MOS6502 { ..cpu_initial };
+        let cpu_expected = MOS6502 { ..cpu_initial.clone() };
 
         sta(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
 
@@ -4863,7 +4867,7 @@ mod test {
             ..Default::default()
         };
 
-        let cpu_expected = MOS6502 { ..cpu_initial };
+        let cpu_expected = MOS6502 { ..cpu_initial.clone() };
 
         stx(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
 
@@ -4893,7 +4897,7 @@ mod test {
             ..Default::default()
         };
 
-        let cpu_expected = MOS6502 { ..cpu_initial };
+        let cpu_expected = MOS6502 { ..cpu_initial.clone() };
 
         sty(&mut cpu_initial, &mut stub_bus, AddressModeValue::AbsoluteAddress(0x00ff));
 
@@ -4924,7 +4928,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             x_register: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -4957,7 +4961,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             y_register: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Zero, true);
 
@@ -4990,7 +4994,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             x_register: 0xfd,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -5023,7 +5027,7 @@ mod test {
 
         let mut cpu_expected = MOS6502 {
             accumulator: 0x80,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
         cpu_expected.set_flag(StatusFlag::Negative, true);
 
@@ -5056,7 +5060,7 @@ mod test {
 
         let cpu_expected = MOS6502 {
             stack_pointer: 0x00,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
 
         txs(&mut cpu_initial, &mut stub_bus, AddressModeValue::Implied);
@@ -5088,7 +5092,7 @@ mod test {
 
         let cpu_expected = MOS6502 {
             accumulator: 0x01,
-            ..cpu_initial
+            ..cpu_initial.clone()
         };
 
         tya(&mut cpu_initial, &mut stub_bus, AddressModeValue::Implied);
@@ -0,0 +1,308 @@
+use crate::bus::BusDevice;
+use std::fmt;
+use std::io::{self, BufReader, Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// Status bit set when a received byte overwrote one that had not been read
+const STATUS_OVERRUN: u8 = 0x04;
+/// Status bit set while the receive data register holds a byte
+const STATUS_RECEIVE_FULL: u8 = 0x08;
+/// Status bit set while the transmit data register can accept a byte
+const STATUS_TRANSMIT_EMPTY: u8 = 0x10;
+/// Status bit set while the chip is asserting the interrupt request line
+const STATUS_IRQ: u8 = 0x80;
+
+/// Command bit enabling the receiver and interrupts (data terminal ready)
+const COMMAND_DTR: u8 = 0x01;
+/// Command bit disabling receive interrupts
+const COMMAND_RECEIVE_IRQ_DISABLE: u8 = 0x02;
+/// Command bits selecting the transmitter mode
+const COMMAND_TRANSMIT_CONTROL: u8 = 0x0c;
+/// Transmitter mode with transmit interrupts enabled
+const COMMAND_TRANSMIT_IRQ: u8 = 0x04;
+/// Command bit echoing received bytes back to the transmitter
+const COMMAND_ECHO: u8 = 0x10;
+/// Command bit enabling the parity bit
+const COMMAND_PARITY: u8 = 0x20;
+
+/// Baud rates selected by the low nibble of the control register. Rate 0 uses an external 16x clock,
+/// which is treated as 115200 baud
+const BAUD_RATES: [u32; 16] = [115_200, 50, 75, 110, 135, 150, 300, 600, 1200, 1800, 2400, 3600, 4800, 7200, 9600, 19200];
+
+/// MOS 6551 Asynchronous Communications Interface Adapter, a serial port whose transmitted bytes are written to a
+/// host stream and whose received bytes are read from another, such as stdin and stdout or a TCP socket. Its 4
+/// registers repeat every 4 bytes of the range it is mapped to:
+///
+/// | Offset | Read | Write |
+/// |---|---|---|
+/// | 0 | Receive data | Transmit data |
+/// | 1 | Status | Programmed reset |
+/// | 2 | Command | Command |
+/// | 3 | Control | Control |
+///
+/// Each byte takes as long to send or receive as a frame at the baud rate and format in the control and command
+/// registers, based on the processor's clock rate. Input is read from the host stream by a background thread and
+/// buffered until the program reads it, so bytes are never lost to overruns. As on the chip, a byte written to the
+/// transmit data register waits there until the shift register is free, and the register reports empty once the
+/// byte moves on, so a program can write the next byte while one is being sent. Bytes are sent to the host stream
+/// as soon as they finish transmitting, and errors writing them are logged as warnings
+pub struct Acia6551 {
+    input: Receiver<u8>,
+    output: Box<dyn Write>,
+    clock_rate: u32,
+    receive_data: u8,
+    transmit_data: u8,
+    transmit_shift: u8,
+    status: u8,
+    command: u8,
+    control: u8,
+    transmit_cycles: u32,
+    receive_cycles: u32,
+}
+
+impl Acia6551 {
+    /// Creates the chip connected to host streams. A background thread reads the input stream until it ends
+    pub fn new<R, W>(input: R, output: W) -> Self
+    where
+        R: Read + Send + 'static,
+        W: Write + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in BufReader::new(input).bytes() {
+                match byte {
+                    Ok(byte) if sender.send(byte).is_ok() => {}
+                    _ => break,
+                }
+            }
+        });
+        return Acia6551::with_receiver(receiver, output);
+    }
+
+    /// Creates the chip receiving the bytes sent through a channel
+    fn with_receiver<W: Write + 'static>(input: Receiver<u8>, output: W) -> Self {
+        return Acia6551 {
+            input,
+            output: Box::new(output),
+            clock_rate: 1_000_000,
+            receive_data: 0,
+            transmit_data: 0,
+            transmit_shift: 0,
+            status: STATUS_TRANSMIT_EMPTY,
+            command: 0,
+            control: 0,
+            transmit_cycles: 0,
+            receive_cycles: 0,
+        };
+    }
+
+    /// Creates the chip connected to the terminal through stdin and stdout
+    pub fn stdio() -> Self {
+        return Acia6551::new(io::stdin(), io::stdout());
+    }
+
+    /// Sets the clock rate of the processor in hertz, used to convert the baud rate into cycles. The default is 1MHz
+    pub fn clock_rate(mut self, clock_rate: u32) -> Self {
+        self.clock_rate = clock_rate;
+        return self;
+    }
+
+    /// Returns the number of processor cycles a frame takes at the current baud rate and format
+    fn frame_cycles(&self) -> u32 {
+        let data_bits = 8 - u32::from((self.control >> 5) & 0x03);
+        let stop_bits = if self.control & 0x80 != 0 { 2 } else { 1 };
+        let parity_bits = if self.command & COMMAND_PARITY != 0 { 1 } else { 0 };
+        let frame_bits = 1 + data_bits + parity_bits + stop_bits;
+        let baud = BAUD_RATES[(self.control & 0x0f) as usize];
+        return (u64::from(self.clock_rate) * u64::from(frame_bits) / u64::from(baud)) as u32;
+    }
+
+    /// Returns the mask of the bits in a byte used by the current word length
+    fn word_mask(&self) -> u8 {
+        return 0xff >> ((self.control >> 5) & 0x03);
+    }
+
+    fn send(&mut self, data: u8) {
+        if let Err(error) = self.output.write_all(&[data]).and_then(|_| self.output.flush()) {
+            warn!("Failed to write serial output: {}", error);
+        }
+    }
+}
+
+impl BusDevice for Acia6551 {
+    fn read(&mut self, offset: u16) -> u8 {
+        return match offset & 0x03 {
+            0 => {
+                self.status &= !(STATUS_RECEIVE_FULL | STATUS_OVERRUN);
+                self.receive_data
+            }
+            1 => {
+                let status = self.status;
+                self.status &= !STATUS_IRQ;
+                status
+            }
+            2 => self.command,
+            _ => self.control,
+        };
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        match offset & 0x03 {
+            0 => {
+                self.transmit_data = data & self.word_mask();
+                self.status &= !STATUS_TRANSMIT_EMPTY;
+            }
+            1 => {
+                self.command &= 0xe0;
+                self.status &= !STATUS_OVERRUN;
+            }
+            2 => self.command = data,
+            _ => self.control = data,
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        if self.transmit_cycles > 0 {
+            self.transmit_cycles = self.transmit_cycles.saturating_sub(cycles);
+            if self.transmit_cycles == 0 {
+                self.send(self.transmit_shift);
+            }
+        }
+        if self.transmit_cycles == 0 && self.status & STATUS_TRANSMIT_EMPTY == 0 {
+            self.transmit_shift = self.transmit_data;
+            self.transmit_cycles = self.frame_cycles().max(1);
+            self.status |= STATUS_TRANSMIT_EMPTY;
+            if self.command & COMMAND_TRANSMIT_CONTROL == COMMAND_TRANSMIT_IRQ {
+                self.status |= STATUS_IRQ;
+            }
+        }
+
+        self.receive_cycles = self.receive_cycles.saturating_sub(cycles);
+        if self.receive_cycles == 0 && self.status & STATUS_RECEIVE_FULL == 0 && self.command & COMMAND_DTR != 0 {
+            if let Ok(data) = self.input.try_recv() {
+                self.receive_data = data & self.word_mask();
+                self.status |= STATUS_RECEIVE_FULL;
+                self.receive_cycles = self.frame_cycles();
+                if self.command & COMMAND_RECEIVE_IRQ_DISABLE == 0 {
+                    self.status |= STATUS_IRQ;
+                }
+                if self.command & COMMAND_ECHO != 0 {
+                    self.send(self.receive_data);
+                }
+            }
+        }
+    }
+
+    fn irq(&self) -> bool {
+        return self.status & STATUS_IRQ != 0;
+    }
+}
+
+impl fmt::Debug for Acia6551 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f
+            .debug_struct("Acia6551")
+            .field("status", &self.status)
+            .field("command", &self.command)
+            .field("control", &self.control)
+            .finish();
+    }
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utilities::SharedOutput;
+    use std::sync::mpsc::Sender;
+
+    /// Creates the chip with input sent through a channel, so tests do not depend on the reader thread
+    fn channel_acia(output: SharedOutput) -> (Sender<u8>, Acia6551) {
+        let (sender, receiver) = mpsc::channel();
+        (sender, Acia6551::with_receiver(receiver, output))
+    }
+
+    #[test]
+    fn test_transmit() {
+        let output = SharedOutput::default();
+        let (_input, mut acia) = channel_acia(output.clone());
+        acia.write(3, 0x1e); // 9600 baud, 8 data bits, 1 stop bit
+        acia.write(2, 0x05); // Transmit interrupts
+        acia.write(0, b'A');
+
+        assert_eq!(acia.read(1) & STATUS_TRANSMIT_EMPTY, 0);
+        acia.tick(1);
+        assert!(acia.irq(), "the byte should move to the shift register");
+        assert_eq!(acia.read(1), STATUS_IRQ | STATUS_TRANSMIT_EMPTY);
+        assert!(!acia.irq());
+        acia.tick(1000);
+        assert!(output.0.borrow().is_empty(), "a frame at 9600 baud should take over 1000 cycles");
+        acia.tick(100);
+        assert_eq!(*output.0.borrow(), b"A");
+    }
+
+    #[test]
+    fn test_transmit_while_sending() {
+        let output = SharedOutput::default();
+        let (_input, mut acia) = channel_acia(output.clone());
+        acia.write(3, 0x1e);
+        acia.write(0, b'A');
+        acia.tick(1);
+        acia.write(0, b'B');
+
+        assert_eq!(acia.read(1) & STATUS_TRANSMIT_EMPTY, 0, "B should wait for A to finish");
+        acia.tick(1100);
+        assert_eq!(*output.0.borrow(), b"A");
+        assert_ne!(acia.read(1) & STATUS_TRANSMIT_EMPTY, 0);
+        acia.tick(1100);
+        assert_eq!(*output.0.borrow(), b"AB");
+    }
+
+    #[test]
+    fn test_receive() {
+        let output = SharedOutput::default();
+        let (input, mut acia) = channel_acia(output.clone());
+        acia.write(2, 0x09); // Receive interrupts
+        acia.write(3, 0x1f); // 19200 baud
+        input.send(b'h').unwrap();
+        input.send(b'i').unwrap();
+
+        acia.tick(1);
+        assert!(acia.irq());
+        assert_eq!(acia.read(1) & STATUS_RECEIVE_FULL, STATUS_RECEIVE_FULL);
+        assert_eq!(acia.read(0), b'h');
+        acia.tick(100);
+        assert_eq!(acia.read(1) & STATUS_RECEIVE_FULL, 0, "a frame at 19200 baud should take over 100 cycles");
+        acia.tick(500);
+        assert_eq!(acia.read(0), b'i');
+        acia.tick(1000);
+        assert_eq!(acia.read(1) & STATUS_RECEIVE_FULL, 0);
+        assert!(output.0.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_receiver_disabled_without_dtr() {
+        let (input, mut acia) = channel_acia(SharedOutput::default());
+        input.send(b'x').unwrap();
+        acia.tick(100_000);
+        assert_eq!(acia.read(1) & STATUS_RECEIVE_FULL, 0);
+        assert!(!acia.irq());
+    }
+
+    #[test]
+    fn test_reader_thread() {
+        let mut acia = Acia6551::new(&b"x"[..], io::sink());
+        acia.write(2, 0x01);
+        for _ in 0..1000 {
+            acia.tick(1000);
+            if acia.read(1) & STATUS_RECEIVE_FULL != 0 {
+                assert_eq!(acia.read(0), b'x');
+                return;
+            }
+            thread::sleep(std::time::Duration::from_millis(1));
+        }
+        panic!("No byte received");
+    }
+}
@@ -0,0 +1,641 @@
+use crate::bus::BusDevice;
+
+/// Interrupt control bit for timer A underflows
+const ICR_TIMER_A: u8 = 0x01;
+/// Interrupt control bit for timer B underflows
+const ICR_TIMER_B: u8 = 0x02;
+/// Interrupt control bit for the time of day clock matching the alarm
+const ICR_ALARM: u8 = 0x04;
+/// Interrupt control bit for the serial shift register completing a byte
+const ICR_SERIAL: u8 = 0x08;
+/// Interrupt control bit for negative edges on the FLAG pin
+const ICR_FLAG: u8 = 0x10;
+/// Bit of the interrupt control register set while the chip is asserting its interrupt line
+const ICR_IRQ: u8 = 0x80;
+
+/// Timer control bit starting the timer
+const CONTROL_START: u8 = 0x01;
+/// Timer control bit outputting underflows on PB6 or PB7
+const CONTROL_PB_OUTPUT: u8 = 0x02;
+/// Timer control bit toggling the port B output on underflows, rather than pulsing it for a cycle
+const CONTROL_TOGGLE: u8 = 0x04;
+/// Timer control bit stopping the timer after it underflows
+const CONTROL_ONE_SHOT: u8 = 0x08;
+/// Timer control bit loading the counter from the latch, which is not stored
+const CONTROL_FORCE_LOAD: u8 = 0x10;
+/// Control register A bit making timer A count positive edges on CNT
+const CRA_COUNT_CNT: u8 = 0x20;
+/// Control register A bit making the serial port an output
+const CRA_SERIAL_OUTPUT: u8 = 0x40;
+/// Control register A bit selecting a 50Hz time of day input, rather than 60Hz
+const CRA_TOD_50HZ: u8 = 0x80;
+/// Control register B bit directing time of day writes to the alarm
+const CRB_ALARM: u8 = 0x80;
+
+/// Revision of the 6526, which differ in when the interrupt line is asserted
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CiaRevision {
+    /// The original 6526, found in early C64s, which asserts its interrupt line one cycle after the
+    /// interrupt is flagged
+    Old,
+    /// The 6526A and 8521, found in later C64s and the C128, which assert their interrupt line in the same cycle
+    New,
+}
+
+/// The processor interrupt line a CIA is connected to
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InterruptLine {
+    /// The interrupt request line, like the C64's CIA 1
+    Irq,
+    /// The non-maskable interrupt line, like the C64's CIA 2
+    Nmi,
+}
+
+/// One of the CIA's two interval timers
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Timer {
+    counter: u16,
+    latch: u16,
+    control: u8,
+    output: bool,
+}
+
+impl Timer {
+    fn new() -> Self {
+        Timer {
+            counter: 0xffff,
+            latch: 0xffff,
+            control: 0,
+            output: false,
+        }
+    }
+
+    fn running(&self) -> bool {
+        self.control & CONTROL_START != 0
+    }
+
+    /// Counts down once, returning true if the timer underflowed and reloaded from the latch
+    fn count(&mut self) -> bool {
+        if !self.running() {
+            return false;
+        }
+        if self.counter > 0 {
+            self.counter -= 1;
+            return false;
+        }
+        self.counter = self.latch;
+        if self.control & CONTROL_ONE_SHOT != 0 {
+            self.control &= !CONTROL_START;
+        }
+        self.output = if self.control & CONTROL_TOGGLE != 0 { !self.output } else { true };
+        return true;
+    }
+
+    fn write_control(&mut self, data: u8) {
+        if data & CONTROL_START != 0 && !self.running() {
+            self.output = true;
+        }
+        if data & CONTROL_FORCE_LOAD != 0 {
+            self.counter = self.latch;
+        }
+        self.control = data & !CONTROL_FORCE_LOAD;
+    }
+
+    /// Writes the latch high byte, which also loads a stopped timer and starts it in one-shot mode
+    fn write_high(&mut self, data: u8) {
+        self.latch = self.latch & 0x00ff | u16::from(data) << 8;
+        if !self.running() {
+            self.counter = self.latch;
+            if self.control & CONTROL_ONE_SHOT != 0 {
+                self.control |= CONTROL_START;
+            }
+        }
+    }
+
+    /// Returns the level the timer drives onto its port B pin, or None when it does not drive it
+    fn port_output(&self) -> Option<bool> {
+        if self.control & CONTROL_PB_OUTPUT != 0 {
+            Some(self.output)
+        } else {
+            None
+        }
+    }
+
+    /// Ends an underflow pulse on the port B pin after a cycle
+    fn end_pulse(&mut self) {
+        if self.control & CONTROL_TOGGLE == 0 {
+            self.output = false;
+        }
+    }
+}
+
+/// MOS 6526 Complex Interface Adapter, with two 8-bit ports, two chainable 16-bit timers, a time of day clock
+/// with an alarm and a serial shift register. Its 16 registers repeat every 16 bytes of the range it is mapped to:
+///
+/// | Offset | Register |
+/// |---|---|
+/// | 0, 1 | Port A, B data |
+/// | 2, 3 | Port A, B data direction |
+/// | 4, 5 | Timer A counter low, high (read) or latch low, high (write) |
+/// | 6, 7 | Timer B counter low, high (read) or latch low, high (write) |
+/// | 8-B | Time of day tenths of seconds, seconds, minutes, hours and AM/PM, in BCD |
+/// | C | Serial data |
+/// | D | Interrupt flags (read, clearing them) or mask (write) |
+/// | E, F | Control A, B |
+///
+/// Timers underflow N + 1 cycles after being loaded with N, then reload from their latch. The time of day clock is
+/// driven by a simulated mains frequency derived from the processor clock rate. Input pins float high until driven
+/// by the frontend
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Cia6526 {
+    revision: CiaRevision,
+    line: InterruptLine,
+    pra: u8,
+    prb: u8,
+    ddra: u8,
+    ddrb: u8,
+    port_a_input: u8,
+    port_b_input: u8,
+
+    timer_a: Timer,
+    timer_b: Timer,
+
+    icr_data: u8,
+    icr_mask: u8,
+    interrupt_asserted: bool,
+    interrupt_delay: bool,
+
+    tod: [u8; 4],
+    alarm: [u8; 4],
+    tod_latch: Option<[u8; 4]>,
+    tod_stopped: bool,
+    clock_rate: u32,
+    mains_frequency: u32,
+    tod_cycles: u32,
+    tod_pulses: u8,
+
+    sdr: u8,
+    shift: u8,
+    shift_bits: u8,
+    sdr_pending: bool,
+    serial_phase: bool,
+    sp_input: bool,
+    sp_output: bool,
+    cnt: bool,
+}
+
+impl Cia6526 {
+    /// Creates the chip in its reset state, connected to the interrupt request line
+    pub fn new(revision: CiaRevision) -> Self {
+        Cia6526 {
+            revision,
+            line: InterruptLine::Irq,
+            pra: 0,
+            prb: 0,
+            ddra: 0,
+            ddrb: 0,
+            port_a_input: 0xff,
+            port_b_input: 0xff,
+            timer_a: Timer::new(),
+            timer_b: Timer::new(),
+            icr_data: 0,
+            icr_mask: 0,
+            interrupt_asserted: false,
+            interrupt_delay: false,
+            tod: [0, 0, 0, 0x01],
+            alarm: [0; 4],
+            tod_latch: None,
+            tod_stopped: false,
+            clock_rate: 1_000_000,
+            mains_frequency: 60,
+            tod_cycles: 0,
+            tod_pulses: 0,
+            sdr: 0,
+            shift: 0,
+            shift_bits: 0,
+            sdr_pending: false,
+            serial_phase: false,
+            sp_input: true,
+            sp_output: true,
+            cnt: true,
+        }
+    }
+
+    /// Sets the processor interrupt line the chip asserts
+    pub fn interrupt_line(mut self, line: InterruptLine) -> Self {
+        self.line = line;
+        self
+    }
+
+    /// Sets the clock rate of the processor in hertz and the frequency of the mains supply driving the time of
+    /// day clock. The defaults are 1MHz and 60Hz
+    pub fn clock_rate(mut self, clock_rate: u32, mains_frequency: u32) -> Self {
+        assert!(mains_frequency > 0 && clock_rate >= mains_frequency, "Invalid clock rates");
+        self.clock_rate = clock_rate;
+        self.mains_frequency = mains_frequency;
+        self
+    }
+
+    /// Returns the levels of the port A pins, driven by the data register where the data direction bits are set
+    pub fn port_a(&self) -> u8 {
+        self.pra & self.ddra | self.port_a_input & !self.ddra
+    }
+
+    /// Returns the levels of the port B pins, driven by the data register where the data direction bits are set,
+    /// with PB6 and PB7 driven by the timers when enabled
+    pub fn port_b(&self) -> u8 {
+        let mut pins = self.prb & self.ddrb | self.port_b_input & !self.ddrb;
+        if let Some(level) = self.timer_a.port_output() {
+            pins = pins & !0x40 | (level as u8) << 6;
+        }
+        if let Some(level) = self.timer_b.port_output() {
+            pins = pins & !0x80 | (level as u8) << 7;
+        }
+        pins
+    }
+
+    /// Sets the levels external devices drive onto the port A pins
+    pub fn set_port_a(&mut self, value: u8) {
+        self.port_a_input = value;
+    }
+
+    /// Sets the levels external devices drive onto the port B pins
+    pub fn set_port_b(&mut self, value: u8) {
+        self.port_b_input = value;
+    }
+
+    /// Signals a negative edge on the FLAG pin, such as from the C64's cassette or serial bus
+    pub fn trigger_flag(&mut self) {
+        self.interrupt(ICR_FLAG);
+    }
+
+    /// Sets the level of the CNT pin, whose positive edges can clock the timers and the serial port input
+    pub fn set_cnt(&mut self, level: bool) {
+        let rising = level && !self.cnt;
+        self.cnt = level;
+        if !rising {
+            return;
+        }
+        if self.timer_a.control & CRA_COUNT_CNT != 0 {
+            self.count_timer_a();
+        }
+        if (self.timer_b.control >> 5) & 0x03 == 0b01 {
+            self.count_timer_b();
+        }
+        if self.timer_a.control & CRA_SERIAL_OUTPUT == 0 {
+            self.shift = self.shift << 1 | self.sp_input as u8;
+            self.shift_bits += 1;
+            if self.shift_bits == 8 {
+                self.sdr = self.shift;
+                self.shift_bits = 0;
+                self.interrupt(ICR_SERIAL);
+            }
+        }
+    }
+
+    /// Sets the level external devices drive onto the SP pin, shifted in while the serial port is an input
+    pub fn set_sp(&mut self, level: bool) {
+        self.sp_input = level;
+    }
+
+    /// Returns the level of the SP pin while the serial port is an output
+    pub fn sp(&self) -> bool {
+        self.sp_output
+    }
+
+    /// Flags an interrupt, asserting the interrupt line if it is enabled in the mask
+    fn interrupt(&mut self, flag: u8) {
+        self.icr_data |= flag;
+        if self.icr_data & self.icr_mask != 0 && !self.interrupt_asserted {
+            match self.revision {
+                CiaRevision::Old => self.interrupt_delay = true,
+                CiaRevision::New => self.interrupt_asserted = true,
+            }
+        }
+    }
+
+    fn count_timer_a(&mut self) {
+        if !self.timer_a.count() {
+            return;
+        }
+        self.interrupt(ICR_TIMER_A);
+        if self.timer_a.control & CRA_SERIAL_OUTPUT != 0 {
+            self.clock_serial_output();
+        }
+        match (self.timer_b.control >> 5) & 0x03 {
+            0b10 => self.count_timer_b(),
+            0b11 if self.cnt => self.count_timer_b(),
+            _ => {}
+        }
+    }
+
+    fn count_timer_b(&mut self) {
+        if self.timer_b.count() {
+            self.interrupt(ICR_TIMER_B);
+        }
+    }
+
+    /// Shifts a bit out every second underflow of timer A, most significant bit first
+    fn clock_serial_output(&mut self) {
+        self.serial_phase = !self.serial_phase;
+        if self.serial_phase {
+            return;
+        }
+        if self.shift_bits == 0 {
+            if !self.sdr_pending {
+                return;
+            }
+            self.shift = self.sdr;
+            self.shift_bits = 8;
+            self.sdr_pending = false;
+        }
+        self.sp_output = self.shift & 0x80 != 0;
+        self.shift <<= 1;
+        self.shift_bits -= 1;
+        if self.shift_bits == 0 {
+            self.interrupt(ICR_SERIAL);
+        }
+    }
+
+    /// Advances the time of day clock by a tenth of a second
+    fn advance_tod(&mut self) {
+        let [tenths, seconds, minutes, hours] = &mut self.tod;
+        *tenths = (*tenths + 1) % 10;
+        if *tenths != 0 {
+            return;
+        }
+        if !increment_bcd(seconds, 0x59) || !increment_bcd(minutes, 0x59) {
+            return;
+        }
+        let pm = *hours & 0x80;
+        *hours = match *hours & 0x1f {
+            0x11 => 0x12 | (pm ^ 0x80),
+            0x12 => 0x01 | pm,
+            hour => {
+                let mut hour = hour;
+                increment_bcd(&mut hour, 0x12);
+                hour | pm
+            }
+        };
+    }
+
+    /// Advances the chip by a single cycle
+    fn cycle(&mut self) {
+        if self.interrupt_delay {
+            self.interrupt_delay = false;
+            self.interrupt_asserted = true;
+        }
+        self.timer_a.end_pulse();
+        self.timer_b.end_pulse();
+
+        if self.timer_a.control & CRA_COUNT_CNT == 0 {
+            self.count_timer_a();
+        }
+        if (self.timer_b.control >> 5) & 0x03 == 0 {
+            self.count_timer_b();
+        }
+
+        self.tod_cycles += 1;
+        if self.tod_cycles >= self.clock_rate / self.mains_frequency {
+            self.tod_cycles = 0;
+            self.tod_pulses += 1;
+            let divider = if self.timer_a.control & CRA_TOD_50HZ != 0 { 5 } else { 6 };
+            if self.tod_pulses >= divider {
+                self.tod_pulses = 0;
+                if !self.tod_stopped {
+                    self.advance_tod();
+                    if self.tod == self.alarm {
+                        self.interrupt(ICR_ALARM);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Increments a BCD value, wrapping to zero after the maximum. Returns true if it wrapped
+fn increment_bcd(value: &mut u8, maximum: u8) -> bool {
+    if *value >= maximum {
+        *value = 0;
+        return true;
+    }
+    *value = if *value & 0x0f >= 9 { (*value & 0xf0) + 0x10 } else { *value + 1 };
+    return false;
+}
+
+impl BusDevice for Cia6526 {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset & 0x0f {
+            0x0 => self.port_a(),
+            0x1 => self.port_b(),
+            0x2 => self.ddra,
+            0x3 => self.ddrb,
+            0x4 => self.timer_a.counter as u8,
+            0x5 => (self.timer_a.counter >> 8) as u8,
+            0x6 => self.timer_b.counter as u8,
+            0x7 => (self.timer_b.counter >> 8) as u8,
+            0x8 => {
+                let tod = self.tod_latch.take().unwrap_or(self.tod);
+                tod[0]
+            }
+            register @ 0x9..=0xa => self.tod_latch.unwrap_or(self.tod)[register as usize - 0x8],
+            0xb => {
+                // Reading the hours freezes the values read until the tenths of seconds are read
+                let tod = *self.tod_latch.get_or_insert(self.tod);
+                tod[3]
+            }
+            0xc => self.sdr,
+            0xd => {
+                let value = if self.interrupt_asserted {
+                    self.icr_data | ICR_IRQ
+                } else {
+                    self.icr_data
+                };
+                self.icr_data = 0;
+                self.interrupt_asserted = false;
+                self.interrupt_delay = false;
+                value
+            }
+            0xe => self.timer_a.control,
+            _ => self.timer_b.control,
+        }
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        match offset & 0x0f {
+            0x0 => self.pra = data,
+            0x1 => self.prb = data,
+            0x2 => self.ddra = data,
+            0x3 => self.ddrb = data,
+            0x4 => self.timer_a.latch = self.timer_a.latch & 0xff00 | u16::from(data),
+            0x5 => self.timer_a.write_high(data),
+            0x6 => self.timer_b.latch = self.timer_b.latch & 0xff00 | u16::from(data),
+            0x7 => self.timer_b.write_high(data),
+            register @ 0x8..=0xb => {
+                let index = register as usize - 0x8;
+                let value = data & [0x0f, 0x7f, 0x7f, 0x9f][index];
+                if self.timer_b.control & CRB_ALARM != 0 {
+                    self.alarm[index] = value;
+                } else {
+                    // Writing the hours stops the clock until the tenths of seconds are written
+                    self.tod[index] = value;
+                    match index {
+                        0 => self.tod_stopped = false,
+                        3 => self.tod_stopped = true,
+                        _ => {}
+                    }
+                }
+            }
+            0xc => {
+                self.sdr = data;
+                if self.timer_a.control & CRA_SERIAL_OUTPUT != 0 {
+                    self.sdr_pending = true;
+                }
+            }
+            0xd => {
+                if data & 0x80 != 0 {
+                    self.icr_mask |= data & 0x1f;
+                } else {
+                    self.icr_mask &= !data;
+                }
+                self.interrupt(0);
+            }
+            0xe => self.timer_a.write_control(data),
+            _ => self.timer_b.write_control(data),
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            self.cycle();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.line == InterruptLine::Irq && self.interrupt_asserted
+    }
+
+    fn nmi(&self) -> bool {
+        self.line == InterruptLine::Nmi && self.interrupt_asserted
+    }
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn start_timer_a(cia: &mut Cia6526, count: u16, control: u8) {
+        cia.write(0x4, count as u8);
+        cia.write(0x5, (count >> 8) as u8);
+        cia.write(0xe, control | CONTROL_START | CONTROL_FORCE_LOAD);
+    }
+
+    #[test]
+    fn test_timer_a_and_interrupt_control() {
+        let mut cia = Cia6526::new(CiaRevision::New);
+        cia.write(0xd, 0x80 | ICR_TIMER_A);
+        start_timer_a(&mut cia, 4, 0);
+
+        cia.tick(4);
+        assert!(!cia.irq());
+        cia.tick(1);
+        assert!(cia.irq());
+        assert_eq!(cia.read(0x4), 4);
+        assert_eq!(cia.read(0xd), ICR_IRQ | ICR_TIMER_A);
+        assert!(!cia.irq());
+        assert_eq!(cia.read(0xd), 0);
+
+        cia.tick(5);
+        assert!(cia.irq(), "continuous mode should reload and underflow again");
+    }
+
+    #[test]
+    fn test_old_revision_delays_interrupt() {
+        let mut cia = Cia6526::new(CiaRevision::Old).interrupt_line(InterruptLine::Nmi);
+        cia.write(0xd, 0x80 | ICR_TIMER_A);
+        start_timer_a(&mut cia, 4, CONTROL_ONE_SHOT);
+
+        cia.tick(5);
+        assert!(!cia.nmi());
+        cia.tick(1);
+        assert!(cia.nmi());
+        assert!(!cia.irq());
+        assert_eq!(cia.read(0xe) & CONTROL_START, 0);
+    }
+
+    #[test]
+    fn test_timer_chaining_and_port_output() {
+        let mut cia = Cia6526::new(CiaRevision::New);
+        start_timer_a(&mut cia, 1, CONTROL_PB_OUTPUT | CONTROL_TOGGLE);
+        cia.write(0x6, 2);
+        cia.write(0x7, 0);
+        cia.write(0xf, 0b10 << 5 | CONTROL_START);
+
+        assert_eq!(cia.port_b() & 0x40, 0x40);
+        cia.tick(2);
+        assert_eq!(cia.port_b() & 0x40, 0x00);
+        cia.tick(2);
+        assert_eq!(cia.read(0x6), 0);
+        cia.tick(2);
+        assert_eq!(cia.read(0xd), ICR_TIMER_B | ICR_TIMER_A);
+    }
+
+    #[test]
+    fn test_time_of_day() {
+        let mut cia = Cia6526::new(CiaRevision::New).clock_rate(600, 60);
+        cia.write(0xb, 0x91); // 11:59:59.9 PM
+        cia.write(0xa, 0x59);
+        cia.write(0x9, 0x59);
+        cia.tick(1000);
+        assert_eq!(cia.read(0x9), 0x59, "writing the hours should stop the clock");
+        cia.write(0x8, 0x09);
+        cia.write(0xf, CRB_ALARM);
+        cia.write(0xb, 0x12);
+        cia.write(0xd, 0x80 | ICR_ALARM);
+
+        cia.tick(60);
+        assert!(cia.irq());
+        assert_eq!(cia.read(0xb), 0x12);
+        cia.tick(120);
+        assert_eq!(cia.read(0x8), 0x00, "reading the hours should latch the time");
+        assert_eq!(cia.read(0x8), 0x02);
+    }
+
+    #[test]
+    fn test_time_of_day_seconds_write() {
+        let mut cia = Cia6526::new(CiaRevision::New).clock_rate(600, 60);
+        cia.write(0x9, 0x30);
+        cia.tick(60);
+        assert_eq!(cia.read(0x8), 0x01, "writing the seconds should not stop the clock");
+        assert_eq!(cia.read(0x9), 0x30);
+
+        cia.write(0xb, 0x01);
+        cia.write(0xa, 0x15);
+        cia.tick(60);
+        assert_eq!(cia.read(0x8), 0x01, "writing the minutes should not restart the clock");
+    }
+
+    #[test]
+    fn test_serial_port() {
+        let mut cia = Cia6526::new(CiaRevision::New);
+        start_timer_a(&mut cia, 0, CRA_SERIAL_OUTPUT);
+        cia.write(0xc, 0b1000_0000);
+        cia.tick(2);
+        assert!(cia.sp());
+        cia.tick(2);
+        assert!(!cia.sp());
+        cia.tick(12);
+        assert_eq!(cia.read(0xd), ICR_SERIAL | ICR_TIMER_A);
+
+        cia.write(0xe, 0);
+        cia.set_sp(true);
+        for _ in 0..8 {
+            cia.set_cnt(false);
+            cia.set_cnt(true);
+        }
+        assert_eq!(cia.read(0xc), 0xff);
+        assert_eq!(cia.read(0xd), ICR_SERIAL);
+    }
+}
@@ -0,0 +1,30 @@
+//! ### PERIPHERALS
+//! This module contains emulations of the peripheral chips found alongside the 6502 in most systems.
+//! Each chip is a `BusDevice`, so it is mapped into a `Bus` like memory, advanced by the cycles of each
+//! instruction as the bus steps the processor, and drives the processor's interrupt lines through the bus.
+//!
+//! The chips' ports and control lines are exposed as methods, for the frontend to connect to keyboards,
+//! displays or other chips.
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let mut builder = BusBuilder::new();
+//! let via = builder.add_device(Via6522::new());
+//! builder.map(0x6000..=0x600f, &via);
+//! let mut bus = builder.build();
+//!
+//! bus.step(&mut cpu);
+//! let leds = bus.device(&via).port_b();
+//! ```
+
+mod acia;
+mod cia;
+mod pia;
+mod riot;
+mod via;
+
+pub use acia::Acia6551;
+pub use cia::{Cia6526, CiaRevision, InterruptLine};
+pub use pia::Pia6820;
+pub use riot::{Riot6532, Rriot6530, RIOT_IO, RRIOT_IO, RRIOT_RAM, RRIOT_ROM};
+pub use via::Via6522;
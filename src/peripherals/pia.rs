@@ -0,0 +1,309 @@
+use crate::bus::BusDevice;
+
+/// Control register bit enabling the interrupt from the line 1 flag
+const CONTROL_C1_IRQ: u8 = 0x01;
+/// Control register bit making line 1 active on positive edges, rather than negative edges
+const CONTROL_C1_POSITIVE: u8 = 0x02;
+/// Control register bit selecting the data register, rather than the data direction register
+const CONTROL_DATA: u8 = 0x04;
+/// Control register bit enabling the interrupt from the line 2 flag, or the pulse or manual level in output modes
+const CONTROL_C2_BIT3: u8 = 0x08;
+/// Control register bit making line 2 active on positive edges in input mode, or selecting manual output mode
+const CONTROL_C2_BIT4: u8 = 0x10;
+/// Control register bit making line 2 an output
+const CONTROL_C2_OUTPUT: u8 = 0x20;
+/// Control register flag set by an active transition of line 2
+const CONTROL_IRQ2: u8 = 0x40;
+/// Control register flag set by an active transition of line 1
+const CONTROL_IRQ1: u8 = 0x80;
+
+/// One side of the PIA: a port, its two control lines and its control register
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Side {
+    output: u8,
+    direction: u8,
+    input: u8,
+    control: u8,
+    c1: bool,
+    c2: bool,
+    c2_output: bool,
+    c2_pulse: bool,
+}
+
+impl Side {
+    fn new() -> Self {
+        Side {
+            output: 0,
+            direction: 0,
+            input: 0xff,
+            control: 0,
+            c1: true,
+            c2: true,
+            c2_output: true,
+            c2_pulse: false,
+        }
+    }
+
+    fn pins(&self) -> u8 {
+        self.output & self.direction | self.input & !self.direction
+    }
+
+    fn set_c1(&mut self, level: bool) {
+        if level != self.c1 && level == (self.control & CONTROL_C1_POSITIVE != 0) {
+            self.control |= CONTROL_IRQ1;
+            if self.control & (CONTROL_C2_OUTPUT | CONTROL_C2_BIT4) == CONTROL_C2_OUTPUT {
+                self.c2_output = true;
+            }
+        }
+        self.c1 = level;
+    }
+
+    fn set_c2(&mut self, level: bool) {
+        let input = self.control & CONTROL_C2_OUTPUT == 0;
+        if input && level != self.c2 && level == (self.control & CONTROL_C2_BIT4 != 0) {
+            self.control |= CONTROL_IRQ2;
+        }
+        self.c2 = level;
+    }
+
+    fn c2(&self) -> bool {
+        match self.control & (CONTROL_C2_OUTPUT | CONTROL_C2_BIT4) {
+            0x30 => self.control & CONTROL_C2_BIT3 != 0,
+            CONTROL_C2_OUTPUT => self.c2_output,
+            _ => self.c2,
+        }
+    }
+
+    /// Brings line 2 low for the handshake or pulse output modes
+    fn handshake(&mut self) {
+        if self.control & (CONTROL_C2_OUTPUT | CONTROL_C2_BIT4) == CONTROL_C2_OUTPUT {
+            self.c2_output = false;
+            self.c2_pulse = self.control & CONTROL_C2_BIT3 != 0;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        let irq1 = self.control & CONTROL_IRQ1 != 0 && self.control & CONTROL_C1_IRQ != 0;
+        let c2_interrupt = self.control & (CONTROL_C2_OUTPUT | CONTROL_C2_BIT3) == CONTROL_C2_BIT3;
+        let irq2 = self.control & CONTROL_IRQ2 != 0 && c2_interrupt;
+        irq1 || irq2
+    }
+
+    fn read_data(&mut self) -> u8 {
+        if self.control & CONTROL_DATA == 0 {
+            return self.direction;
+        }
+        self.control &= !(CONTROL_IRQ1 | CONTROL_IRQ2);
+        self.pins()
+    }
+
+    fn write_data(&mut self, data: u8) {
+        if self.control & CONTROL_DATA == 0 {
+            self.direction = data;
+        } else {
+            self.output = data;
+        }
+    }
+}
+
+/// Motorola 6820 / 6821 Peripheral Interface Adapter, with two 8-bit ports and two control lines for each, as used
+/// by the Apple-1. Its 4 registers repeat every 4 bytes of the range it is mapped to:
+///
+/// | Offset | Register |
+/// |---|---|
+/// | 0 | Port A data, or data direction when bit 2 of control register A is clear |
+/// | 1 | Control register A |
+/// | 2 | Port B data, or data direction when bit 2 of control register B is clear |
+/// | 3 | Control register B |
+///
+/// CA2 handshakes on reads of port A and CB2 on writes to port B. The two sides have separate interrupt outputs,
+/// and the device asserts the interrupt request line when either does. Input pins float high until driven by the
+/// frontend
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Pia6820 {
+    a: Side,
+    b: Side,
+}
+
+impl Default for Pia6820 {
+    fn default() -> Self {
+        Pia6820::new()
+    }
+}
+
+impl Pia6820 {
+    /// Creates the chip in its reset state
+    pub fn new() -> Self {
+        Pia6820 {
+            a: Side::new(),
+            b: Side::new(),
+        }
+    }
+
+    /// Returns the levels of the port A pins, driven by the data register where the data direction bits are set
+    pub fn port_a(&self) -> u8 {
+        self.a.pins()
+    }
+
+    /// Returns the levels of the port B pins, driven by the data register where the data direction bits are set
+    pub fn port_b(&self) -> u8 {
+        self.b.pins()
+    }
+
+    /// Sets the levels external devices drive onto the port A pins
+    pub fn set_port_a(&mut self, value: u8) {
+        self.a.input = value;
+    }
+
+    /// Sets the levels external devices drive onto the port B pins
+    pub fn set_port_b(&mut self, value: u8) {
+        self.b.input = value;
+    }
+
+    /// Sets the level of the CA1 control line
+    pub fn set_ca1(&mut self, level: bool) {
+        self.a.set_c1(level);
+    }
+
+    /// Sets the level of the CA2 control line, which is only an input in the input modes
+    pub fn set_ca2(&mut self, level: bool) {
+        self.a.set_c2(level);
+    }
+
+    /// Sets the level of the CB1 control line
+    pub fn set_cb1(&mut self, level: bool) {
+        self.b.set_c1(level);
+    }
+
+    /// Sets the level of the CB2 control line, which is only an input in the input modes
+    pub fn set_cb2(&mut self, level: bool) {
+        self.b.set_c2(level);
+    }
+
+    /// Returns the level of the CA2 control line
+    pub fn ca2(&self) -> bool {
+        self.a.c2()
+    }
+
+    /// Returns the level of the CB2 control line
+    pub fn cb2(&self) -> bool {
+        self.b.c2()
+    }
+
+    /// Returns true while the port A side is asserting its interrupt output
+    pub fn irq_a(&self) -> bool {
+        self.a.irq()
+    }
+
+    /// Returns true while the port B side is asserting its interrupt output
+    pub fn irq_b(&self) -> bool {
+        self.b.irq()
+    }
+}
+
+impl BusDevice for Pia6820 {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset & 0x03 {
+            0 => {
+                let data = self.a.read_data();
+                if self.a.control & CONTROL_DATA != 0 {
+                    self.a.handshake();
+                }
+                data
+            }
+            1 => self.a.control,
+            2 => self.b.read_data(),
+            _ => self.b.control,
+        }
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        // The interrupt flags are read only
+        let control = |side: &Side| side.control & (CONTROL_IRQ1 | CONTROL_IRQ2) | data & 0x3f;
+        match offset & 0x03 {
+            0 => self.a.write_data(data),
+            1 => self.a.control = control(&self.a),
+            2 => {
+                self.b.write_data(data);
+                if self.b.control & CONTROL_DATA != 0 {
+                    self.b.handshake();
+                }
+            }
+            _ => self.b.control = control(&self.b),
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        if cycles == 0 {
+            return;
+        }
+        for side in [&mut self.a, &mut self.b] {
+            if side.c2_pulse {
+                side.c2_output = true;
+                side.c2_pulse = false;
+            }
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_a() || self.irq_b()
+    }
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_data_direction_and_ports() {
+        let mut pia = Pia6820::new();
+        pia.write(0, 0x0f);
+        pia.write(1, CONTROL_DATA);
+        pia.write(0, 0x55);
+        pia.set_port_a(0xa0);
+
+        assert_eq!(pia.port_a(), 0xa5);
+        assert_eq!(pia.read(0), 0xa5);
+        pia.write(1, 0);
+        assert_eq!(pia.read(0), 0x0f);
+    }
+
+    #[test]
+    fn test_c1_interrupt() {
+        let mut pia = Pia6820::new();
+        pia.write(1, CONTROL_DATA | CONTROL_C1_POSITIVE | CONTROL_C1_IRQ);
+        pia.set_ca1(false);
+        assert!(!pia.irq());
+        pia.set_ca1(true);
+
+        assert!(pia.irq_a());
+        assert!(pia.irq());
+        assert_eq!(pia.read(1) & CONTROL_IRQ1, CONTROL_IRQ1);
+        pia.write(1, CONTROL_DATA | CONTROL_C1_POSITIVE | CONTROL_C1_IRQ);
+        assert!(pia.irq_a(), "writing the control register should not clear the flags");
+        pia.read(0);
+        assert!(!pia.irq());
+    }
+
+    #[test]
+    fn test_c2_handshake_and_pulse() {
+        let mut pia = Pia6820::new();
+        pia.write(3, CONTROL_DATA | CONTROL_C1_POSITIVE | CONTROL_C2_OUTPUT);
+        pia.write(2, 0x41);
+        assert!(!pia.cb2());
+        pia.set_cb1(false);
+        pia.set_cb1(true);
+        assert!(pia.cb2());
+
+        pia.write(1, CONTROL_DATA | CONTROL_C2_OUTPUT | CONTROL_C2_BIT3);
+        pia.read(0);
+        assert!(!pia.ca2());
+        pia.tick(1);
+        assert!(pia.ca2());
+
+        pia.write(1, CONTROL_DATA | CONTROL_C2_OUTPUT | CONTROL_C2_BIT4);
+        assert!(!pia.ca2());
+    }
+}
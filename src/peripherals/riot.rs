@@ -0,0 +1,413 @@
+use crate::bus::BusDevice;
+
+/// Offset of the 6532's I/O and timer registers, selected by address line 9 like on the Atari 2600. Offsets
+/// below it access the RAM
+pub const RIOT_IO: u16 = 0x0200;
+/// Offset of the 6530's 1KB ROM
+pub const RRIOT_ROM: u16 = 0x0000;
+/// Offset of the 6530's 64 bytes of RAM
+pub const RRIOT_RAM: u16 = 0x0400;
+/// Offset of the 6530's I/O and timer registers
+pub const RRIOT_IO: u16 = 0x0440;
+
+/// Interrupt flag set when the timer passes zero
+const FLAG_TIMER: u8 = 0x80;
+/// Interrupt flag set by an active edge on PA7
+const FLAG_PA7: u8 = 0x40;
+
+/// Interval timer shared by the 6530 and 6532, counting down once every 1, 8, 64 or 1024 cycles until it passes
+/// zero, and once every cycle afterwards
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct IntervalTimer {
+    value: u8,
+    prescale: u16,
+    prescale_count: u16,
+    expired: bool,
+    flag: bool,
+    interrupt_enabled: bool,
+}
+
+impl IntervalTimer {
+    fn new() -> Self {
+        IntervalTimer {
+            value: 0xff,
+            prescale: 1024,
+            prescale_count: 1024,
+            expired: false,
+            flag: false,
+            interrupt_enabled: false,
+        }
+    }
+
+    /// Starts the timer from a register write, where address lines 0 and 1 select the prescaler and line 3
+    /// enables the interrupt
+    fn write(&mut self, offset: u16, data: u8) {
+        self.prescale = [1, 8, 64, 1024][(offset & 0x03) as usize];
+        self.prescale_count = self.prescale;
+        self.value = data;
+        self.expired = false;
+        self.flag = false;
+        self.interrupt_enabled = offset & 0x08 != 0;
+    }
+
+    /// Reads the timer, where address line 3 enables the interrupt
+    fn read(&mut self, offset: u16) -> u8 {
+        self.interrupt_enabled = offset & 0x08 != 0;
+        self.flag = false;
+        self.value
+    }
+
+    fn cycle(&mut self) {
+        if self.expired {
+            self.value = self.value.wrapping_sub(1);
+            return;
+        }
+        self.prescale_count -= 1;
+        if self.prescale_count == 0 {
+            self.prescale_count = self.prescale;
+            if self.value == 0 {
+                self.value = 0xff;
+                self.expired = true;
+                self.flag = true;
+            } else {
+                self.value -= 1;
+            }
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.flag && self.interrupt_enabled
+    }
+}
+
+/// A pair of 8-bit ports with data direction registers
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Ports {
+    output: [u8; 2],
+    direction: [u8; 2],
+    input: [u8; 2],
+}
+
+impl Ports {
+    fn new() -> Self {
+        Ports {
+            output: [0; 2],
+            direction: [0; 2],
+            input: [0xff; 2],
+        }
+    }
+
+    fn pins(&self, port: usize) -> u8 {
+        self.output[port] & self.direction[port] | self.input[port] & !self.direction[port]
+    }
+
+    /// Reads the data or data direction register selected by address lines 0 and 1
+    fn read(&self, offset: u16) -> u8 {
+        let port = (offset as usize >> 1) & 0x01;
+        if offset & 0x01 == 0 {
+            self.pins(port)
+        } else {
+            self.direction[port]
+        }
+    }
+
+    /// Writes the data or data direction register selected by address lines 0 and 1
+    fn write(&mut self, offset: u16, data: u8) {
+        let port = (offset as usize >> 1) & 0x01;
+        if offset & 0x01 == 0 {
+            self.output[port] = data;
+        } else {
+            self.direction[port] = data;
+        }
+    }
+}
+
+/// MOS 6532 RAM-I/O-Timer, with 128 bytes of RAM, two 8-bit ports, an interval timer and an edge detector on PA7,
+/// as used by the Atari 2600. Offsets below `RIOT_IO` access the RAM, repeating every 128 bytes, and offsets from
+/// `RIOT_IO` access the registers, repeating every 32 bytes:
+///
+/// | Offset | Read | Write |
+/// |---|---|---|
+/// | 0-3 | Port A data, data direction, port B data, data direction | The same |
+/// | 4, C | Timer, with the timer interrupt disabled or enabled by bit 3 | PA7 edge detection, see below |
+/// | 5, D | Interrupt flags: bit 7 timer, bit 6 PA7 | PA7 edge detection, see below |
+/// | 14-17, 1C-1F | | Timer, counting every 1, 8, 64 or 1024 cycles, interrupt enabled by bit 3 |
+///
+/// Writes to offsets 4-7 configure PA7 edge detection: bit 0 selects positive rather than negative edges, and
+/// bit 1 enables the interrupt. Input pins float high until driven by the frontend
+///
+/// For the Atari 2600 the chip is mapped with `map_at_offset(0x0080..=0x00ff, &riot, 0)` and
+/// `map_at_offset(0x0280..=0x029f, &riot, RIOT_IO)`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Riot6532 {
+    ram: [u8; 128],
+    ports: Ports,
+    timer: IntervalTimer,
+    pa7_flag: bool,
+    pa7_positive_edge: bool,
+    pa7_interrupt_enabled: bool,
+}
+
+impl Default for Riot6532 {
+    fn default() -> Self {
+        Riot6532::new()
+    }
+}
+
+impl Riot6532 {
+    /// Creates the chip in its reset state
+    pub fn new() -> Self {
+        Riot6532 {
+            ram: [0; 128],
+            ports: Ports::new(),
+            timer: IntervalTimer::new(),
+            pa7_flag: false,
+            pa7_positive_edge: false,
+            pa7_interrupt_enabled: false,
+        }
+    }
+
+    /// Returns the levels of the port A pins, driven by the data register where the data direction bits are set
+    pub fn port_a(&self) -> u8 {
+        self.ports.pins(0)
+    }
+
+    /// Returns the levels of the port B pins, driven by the data register where the data direction bits are set
+    pub fn port_b(&self) -> u8 {
+        self.ports.pins(1)
+    }
+
+    /// Sets the levels external devices drive onto the port A pins, such as the 2600's joysticks. Active edges
+    /// on PA7 are flagged
+    pub fn set_port_a(&mut self, value: u8) {
+        let before = self.port_a() & 0x80 != 0;
+        self.ports.input[0] = value;
+        let after = self.port_a() & 0x80 != 0;
+        if before != after && after == self.pa7_positive_edge {
+            self.pa7_flag = true;
+        }
+    }
+
+    /// Sets the levels external devices drive onto the port B pins, such as the 2600's console switches
+    pub fn set_port_b(&mut self, value: u8) {
+        self.ports.input[1] = value;
+    }
+
+    /// Returns the contents of the RAM
+    pub fn ram(&self) -> &[u8; 128] {
+        &self.ram
+    }
+}
+
+impl BusDevice for Riot6532 {
+    fn read(&mut self, offset: u16) -> u8 {
+        if offset & RIOT_IO == 0 {
+            return self.ram[(offset & 0x7f) as usize];
+        }
+        match (offset & 0x04 != 0, offset & 0x01 != 0) {
+            (false, _) => self.ports.read(offset),
+            (true, false) => self.timer.read(offset),
+            (true, true) => {
+                let mut flags = 0;
+                if self.timer.flag {
+                    flags |= FLAG_TIMER;
+                }
+                if self.pa7_flag {
+                    flags |= FLAG_PA7;
+                }
+                self.pa7_flag = false;
+                flags
+            }
+        }
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        if offset & RIOT_IO == 0 {
+            self.ram[(offset & 0x7f) as usize] = data;
+            return;
+        }
+        match (offset & 0x04 != 0, offset & 0x10 != 0) {
+            (false, _) => self.ports.write(offset, data),
+            (true, true) => self.timer.write(offset, data),
+            (true, false) => {
+                self.pa7_positive_edge = offset & 0x01 != 0;
+                self.pa7_interrupt_enabled = offset & 0x02 != 0;
+            }
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            self.timer.cycle();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.timer.irq() || (self.pa7_flag && self.pa7_interrupt_enabled)
+    }
+}
+
+/// MOS 6530 ROM-RAM-I/O-Timer, with 1KB of mask ROM, 64 bytes of RAM, two 8-bit ports and an interval timer, as
+/// used by the KIM-1. The ROM, RAM and registers are at the offsets `RRIOT_ROM`, `RRIOT_RAM` and `RRIOT_IO`, and the
+/// registers repeat every 16 bytes:
+///
+/// | Offset | Read | Write |
+/// |---|---|---|
+/// | 0-3 | Port A data, data direction, port B data, data direction | The same |
+/// | 6, E | Timer, with the timer interrupt disabled or enabled by bit 3 | |
+/// | 7, F | Interrupt flags: bit 7 timer | |
+/// | 4-7, C-F | | Timer, counting every 1, 8, 64 or 1024 cycles, interrupt enabled by bit 3 |
+///
+/// The timer interrupt is output on PB7, which only asserts the interrupt line while PB7 is an input.
+///
+/// For the KIM-1's 6530-002 the chip is mapped with `map_at_offset(0x1c00..=0x1fff, &rriot, RRIOT_ROM)`,
+/// `map_at_offset(0x17c0..=0x17ff, &rriot, RRIOT_RAM)` and `map_at_offset(0x1740..=0x177f, &rriot, RRIOT_IO)`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Rriot6530 {
+    rom: Vec<u8>,
+    ram: [u8; 64],
+    ports: Ports,
+    timer: IntervalTimer,
+}
+
+impl Rriot6530 {
+    /// Creates the chip in its reset state, with the contents of its mask ROM. ROMs shorter than 1KB repeat
+    pub fn new(rom: Vec<u8>) -> Self {
+        assert!(!rom.is_empty() && rom.len() <= 0x0400, "The 6530 has up to 1KB of ROM");
+        Rriot6530 {
+            rom,
+            ram: [0; 64],
+            ports: Ports::new(),
+            timer: IntervalTimer::new(),
+        }
+    }
+
+    /// Returns the levels of the port A pins, driven by the data register where the data direction bits are set
+    pub fn port_a(&self) -> u8 {
+        self.ports.pins(0)
+    }
+
+    /// Returns the levels of the port B pins, driven by the data register where the data direction bits are set,
+    /// with PB7 pulled low by a pending timer interrupt
+    pub fn port_b(&self) -> u8 {
+        let pins = self.ports.pins(1);
+        if self.timer.irq() {
+            pins & 0x7f
+        } else {
+            pins
+        }
+    }
+
+    /// Sets the levels external devices drive onto the port A pins, such as the KIM-1's keypad
+    pub fn set_port_a(&mut self, value: u8) {
+        self.ports.input[0] = value;
+    }
+
+    /// Sets the levels external devices drive onto the port B pins
+    pub fn set_port_b(&mut self, value: u8) {
+        self.ports.input[1] = value;
+    }
+
+    /// Returns the contents of the RAM
+    pub fn ram(&self) -> &[u8; 64] {
+        &self.ram
+    }
+}
+
+impl BusDevice for Rriot6530 {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset {
+            RRIOT_ROM..=0x03ff => self.rom[offset as usize % self.rom.len()],
+            RRIOT_RAM..=0x043f => self.ram[(offset - RRIOT_RAM) as usize],
+            _ => match (offset & 0x04 != 0, offset & 0x01 != 0) {
+                (false, _) => self.ports.read(offset),
+                (true, false) => self.timer.read(offset),
+                (true, true) if self.timer.flag => FLAG_TIMER,
+                (true, true) => 0,
+            },
+        }
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        match offset {
+            RRIOT_ROM..=0x03ff => {}
+            RRIOT_RAM..=0x043f => self.ram[(offset - RRIOT_RAM) as usize] = data,
+            _ if offset & 0x04 == 0 => self.ports.write(offset, data),
+            _ => self.timer.write(offset, data),
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            self.timer.cycle();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.timer.irq() && self.ports.direction[1] & 0x80 == 0
+    }
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_riot_ram_and_ports() {
+        let mut riot = Riot6532::new();
+        riot.write(0x0080, 0x12);
+        assert_eq!(riot.read(0x0000), 0x12);
+        assert_eq!(riot.ram()[0], 0x12);
+
+        riot.write(RIOT_IO | 0x03, 0xf0);
+        riot.write(RIOT_IO | 0x02, 0xa5);
+        riot.set_port_b(0x0f);
+        assert_eq!(riot.read(RIOT_IO | 0x02), 0xaf);
+        assert_eq!(riot.port_b(), 0xaf);
+    }
+
+    #[test]
+    fn test_riot_timer() {
+        let mut riot = Riot6532::new();
+        riot.write(RIOT_IO | 0x1d, 2); // 8 cycle prescaler, interrupt enabled
+        riot.tick(8);
+        assert_eq!(riot.read(RIOT_IO | 0x0c), 1);
+        riot.tick(16);
+        assert!(riot.irq());
+        assert_eq!(riot.read(RIOT_IO | 0x05), FLAG_TIMER);
+        riot.tick(3);
+        assert_eq!(riot.read(RIOT_IO | 0x04), 0xfc, "the timer should count every cycle after passing zero");
+        assert!(!riot.irq());
+    }
+
+    #[test]
+    fn test_riot_pa7_edge() {
+        let mut riot = Riot6532::new();
+        riot.write(RIOT_IO | 0x06, 0); // Negative edge, interrupt enabled
+        riot.set_port_a(0xff);
+        assert!(!riot.irq());
+        riot.set_port_a(0x7f);
+        assert!(riot.irq());
+        assert_eq!(riot.read(RIOT_IO | 0x05), FLAG_PA7);
+        assert!(!riot.irq());
+    }
+
+    #[test]
+    fn test_rriot() {
+        let mut rriot = Rriot6530::new(vec![0xea; 0x0400]);
+        rriot.write(RRIOT_ROM, 0x00);
+        rriot.write(RRIOT_RAM + 0x3f, 0x34);
+        assert_eq!(rriot.read(RRIOT_ROM + 0x3ff), 0xea);
+        assert_eq!(rriot.read(RRIOT_RAM + 0x3f), 0x34);
+
+        rriot.write(RRIOT_IO | 0x0c, 0); // Single cycle prescaler, interrupt enabled
+        rriot.tick(1);
+        assert!(rriot.irq());
+        assert_eq!(rriot.port_b() & 0x80, 0);
+        assert_eq!(rriot.read(RRIOT_IO | 0x07), FLAG_TIMER);
+        rriot.write(RRIOT_IO | 0x03, 0x80);
+        assert!(!rriot.irq(), "the interrupt is only output while PB7 is an input");
+    }
+}
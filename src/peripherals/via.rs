@@ -0,0 +1,604 @@
+use crate::bus::BusDevice;
+
+/// Interrupt flag set by an active transition of CA2
+const IFR_CA2: u8 = 0x01;
+/// Interrupt flag set by an active transition of CA1
+const IFR_CA1: u8 = 0x02;
+/// Interrupt flag set after the shift register shifts eight bits
+const IFR_SR: u8 = 0x04;
+/// Interrupt flag set by an active transition of CB2
+const IFR_CB2: u8 = 0x08;
+/// Interrupt flag set by an active transition of CB1
+const IFR_CB1: u8 = 0x10;
+/// Interrupt flag set when timer 2 times out
+const IFR_T2: u8 = 0x20;
+/// Interrupt flag set when timer 1 times out
+const IFR_T1: u8 = 0x40;
+/// Bit of the interrupt flag register set while any enabled interrupt is flagged
+const IFR_IRQ: u8 = 0x80;
+
+/// Auxiliary control bit latching port A on active transitions of CA1
+const ACR_PA_LATCH: u8 = 0x01;
+/// Auxiliary control bit latching port B on active transitions of CB1
+const ACR_PB_LATCH: u8 = 0x02;
+/// Auxiliary control bit making timer 2 count negative pulses on PB6
+const ACR_T2_PULSE_COUNT: u8 = 0x20;
+/// Auxiliary control bit making timer 1 free running
+const ACR_T1_CONTINUOUS: u8 = 0x40;
+/// Auxiliary control bit outputting timer 1 on PB7
+const ACR_T1_PB7: u8 = 0x80;
+
+/// Shift register mode shifting out continuously at the rate of timer 2, without interrupts
+const SR_OUT_FREE_RUNNING: u8 = 0b100;
+
+/// MOS 6522 Versatile Interface Adapter, with two 8-bit ports, two 16-bit timers, a shift register and
+/// handshaking control lines. Its 16 registers repeat every 16 bytes of the range it is mapped to:
+///
+/// | Offset | Read | Write |
+/// |---|---|---|
+/// | 0 | Port B input | Port B output |
+/// | 1 | Port A input, with handshake | Port A output, with handshake |
+/// | 2, 3 | Data direction B, A | Data direction B, A |
+/// | 4, 5 | Timer 1 counter low, high | Timer 1 latch low, latch high and start |
+/// | 6, 7 | Timer 1 latch low, high | Timer 1 latch low, high |
+/// | 8, 9 | Timer 2 counter low, high | Timer 2 latch low, counter high and start |
+/// | A | Shift register | Shift register |
+/// | B, C | Auxiliary, peripheral control | Auxiliary, peripheral control |
+/// | D, E | Interrupt flags, enable | Interrupt flags to clear, enable |
+/// | F | Port A input, no handshake | Port A output, no handshake |
+///
+/// Input pins float high until driven by the frontend. Timer 1 interrupts N + 1 cycles after it is started
+/// with a count of N, and in free running mode reloads every N + 2 cycles
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Via6522 {
+    ora: u8,
+    orb: u8,
+    ddra: u8,
+    ddrb: u8,
+    port_a_input: u8,
+    port_b_input: u8,
+    ira_latch: u8,
+    irb_latch: u8,
+
+    t1_counter: u16,
+    t1_latch: u16,
+    t1_armed: bool,
+    t1_reload: bool,
+    pb7: bool,
+    t2_counter: u16,
+    t2_latch_low: u8,
+    t2_armed: bool,
+
+    sr: u8,
+    sr_bits: u8,
+    sr_divider: u16,
+    sr_output: bool,
+
+    acr: u8,
+    pcr: u8,
+    ifr: u8,
+    ier: u8,
+
+    ca1: bool,
+    ca2: bool,
+    cb1: bool,
+    cb2: bool,
+    ca2_output: bool,
+    cb2_output: bool,
+    ca2_pulse: bool,
+    cb2_pulse: bool,
+}
+
+impl Default for Via6522 {
+    fn default() -> Self {
+        return Via6522::new();
+    }
+}
+
+impl Via6522 {
+    /// Creates the chip in its reset state
+    pub fn new() -> Self {
+        return Via6522 {
+            ora: 0,
+            orb: 0,
+            ddra: 0,
+            ddrb: 0,
+            port_a_input: 0xff,
+            port_b_input: 0xff,
+            ira_latch: 0,
+            irb_latch: 0,
+            t1_counter: 0xffff,
+            t1_latch: 0xffff,
+            t1_armed: false,
+            t1_reload: false,
+            pb7: true,
+            t2_counter: 0xffff,
+            t2_latch_low: 0xff,
+            t2_armed: false,
+            sr: 0,
+            sr_bits: 0,
+            sr_divider: 0,
+            sr_output: true,
+            acr: 0,
+            pcr: 0,
+            ifr: 0,
+            ier: 0,
+            ca1: true,
+            ca2: true,
+            cb1: true,
+            cb2: true,
+            ca2_output: true,
+            cb2_output: true,
+            ca2_pulse: false,
+            cb2_pulse: false,
+        };
+    }
+
+    /// Returns the levels of the port A pins, driven by the output register where the data direction bits are set
+    pub fn port_a(&self) -> u8 {
+        return self.ora & self.ddra | self.port_a_input & !self.ddra;
+    }
+
+    /// Returns the levels of the port B pins, driven by the output register where the data direction bits are set,
+    /// with PB7 driven by timer 1 when enabled
+    pub fn port_b(&self) -> u8 {
+        let pins = self.orb & self.ddrb | self.port_b_input & !self.ddrb;
+        return self.with_pb7(pins);
+    }
+
+    /// Sets the levels external devices drive onto the port A pins
+    pub fn set_port_a(&mut self, value: u8) {
+        self.port_a_input = value;
+    }
+
+    /// Sets the levels external devices drive onto the port B pins. Falling edges on PB6 are counted by
+    /// timer 2 in pulse counting mode
+    pub fn set_port_b(&mut self, value: u8) {
+        let falling = self.port_b_input & !value & 0x40 != 0;
+        self.port_b_input = value;
+        if falling && self.acr & ACR_T2_PULSE_COUNT != 0 {
+            self.t2_counter = self.t2_counter.wrapping_sub(1);
+            if self.t2_counter == 0 && self.t2_armed {
+                self.ifr |= IFR_T2;
+                self.t2_armed = false;
+            }
+        }
+    }
+
+    /// Sets the level of the CA1 control line
+    pub fn set_ca1(&mut self, level: bool) {
+        let active = level != self.ca1 && level == (self.pcr & 0x01 != 0);
+        self.ca1 = level;
+        if active {
+            self.ifr |= IFR_CA1;
+            if self.acr & ACR_PA_LATCH != 0 {
+                self.ira_latch = self.port_a();
+            }
+            if self.ca2_mode() == 0b100 {
+                self.ca2_output = true;
+            }
+        }
+    }
+
+    /// Sets the level of the CA2 control line, which is only an input in the input modes
+    pub fn set_ca2(&mut self, level: bool) {
+        let mode = self.ca2_mode();
+        if level != self.ca2 && mode & 0b100 == 0 && level == (mode & 0b010 != 0) {
+            self.ifr |= IFR_CA2;
+        }
+        self.ca2 = level;
+    }
+
+    /// Sets the level of the CB1 control line, which also clocks the shift register in its external clock modes
+    pub fn set_cb1(&mut self, level: bool) {
+        let rising = level && !self.cb1;
+        let active = level != self.cb1 && level == (self.pcr & 0x10 != 0);
+        self.cb1 = level;
+        if active {
+            self.ifr |= IFR_CB1;
+            if self.acr & ACR_PB_LATCH != 0 {
+                self.irb_latch = self.port_b();
+            }
+            if self.cb2_mode() == 0b100 {
+                self.cb2_output = true;
+            }
+        }
+        if rising && self.sr_mode() & 0b011 == 0b011 {
+            self.shift();
+        }
+    }
+
+    /// Sets the level of the CB2 control line, which is only an input in the input modes. Also the data
+    /// shifted in by the shift register
+    pub fn set_cb2(&mut self, level: bool) {
+        let mode = self.cb2_mode();
+        if level != self.cb2 && mode & 0b100 == 0 && level == (mode & 0b010 != 0) {
+            self.ifr |= IFR_CB2;
+        }
+        self.cb2 = level;
+    }
+
+    /// Returns the level of the CA2 control line
+    pub fn ca2(&self) -> bool {
+        return match self.ca2_mode() {
+            0b110 => false,
+            0b111 => true,
+            0b100 | 0b101 => self.ca2_output,
+            _ => self.ca2,
+        };
+    }
+
+    /// Returns the level of the CB2 control line, which carries the data shifted out by the shift register
+    pub fn cb2(&self) -> bool {
+        if self.acr & 0x10 != 0 {
+            return self.sr_output;
+        }
+        return match self.cb2_mode() {
+            0b110 => false,
+            0b111 => true,
+            0b100 | 0b101 => self.cb2_output,
+            _ => self.cb2,
+        };
+    }
+
+    fn ca2_mode(&self) -> u8 {
+        return (self.pcr >> 1) & 0b111;
+    }
+
+    fn cb2_mode(&self) -> u8 {
+        return (self.pcr >> 5) & 0b111;
+    }
+
+    fn sr_mode(&self) -> u8 {
+        return (self.acr >> 2) & 0b111;
+    }
+
+    fn with_pb7(&self, value: u8) -> u8 {
+        return if self.acr & ACR_T1_PB7 != 0 {
+            value & 0x7f | (self.pb7 as u8) << 7
+        } else {
+            value
+        };
+    }
+
+    /// Clears the control line interrupt flags after an access to a port, leaving line 2's flag set in the
+    /// independent interrupt modes
+    fn clear_port_flags(&mut self, line1: u8, line2: u8, mode: u8) {
+        self.ifr &= !line1;
+        if mode & 0b101 != 0b001 {
+            self.ifr &= !line2;
+        }
+    }
+
+    fn read_port_a(&mut self, handshake: bool) -> u8 {
+        if handshake {
+            self.clear_port_flags(IFR_CA1, IFR_CA2, self.ca2_mode());
+            self.ca2_handshake();
+        }
+        return if self.acr & ACR_PA_LATCH != 0 { self.ira_latch } else { self.port_a() };
+    }
+
+    fn write_port_a(&mut self, data: u8, handshake: bool) {
+        self.ora = data;
+        if handshake {
+            self.clear_port_flags(IFR_CA1, IFR_CA2, self.ca2_mode());
+            self.ca2_handshake();
+        }
+    }
+
+    fn ca2_handshake(&mut self) {
+        match self.ca2_mode() {
+            0b100 => self.ca2_output = false,
+            0b101 => {
+                self.ca2_output = false;
+                self.ca2_pulse = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// Shifts one bit in from or out to CB2, flagging an interrupt after eight bits
+    fn shift(&mut self) {
+        let mode = self.sr_mode();
+        if mode == 0 || (self.sr_bits >= 8 && mode != SR_OUT_FREE_RUNNING) {
+            return;
+        }
+        if mode & 0b100 != 0 {
+            self.sr = self.sr.rotate_left(1);
+            self.sr_output = self.sr & 0x01 != 0;
+        } else {
+            self.sr = self.sr << 1 | self.cb2 as u8;
+        }
+        self.sr_bits += 1;
+        if self.sr_bits == 8 {
+            if mode == SR_OUT_FREE_RUNNING {
+                self.sr_bits = 0;
+            } else {
+                self.ifr |= IFR_SR;
+            }
+        }
+    }
+
+    /// Advances the chip by a single cycle
+    fn cycle(&mut self) {
+        if self.ca2_pulse {
+            self.ca2_output = true;
+            self.ca2_pulse = false;
+        }
+        if self.cb2_pulse {
+            self.cb2_output = true;
+            self.cb2_pulse = false;
+        }
+
+        if self.t1_reload {
+            self.t1_counter = self.t1_latch;
+            self.t1_reload = false;
+        } else {
+            self.t1_counter = self.t1_counter.wrapping_sub(1);
+            if self.t1_counter == 0xffff {
+                if self.t1_armed {
+                    self.ifr |= IFR_T1;
+                    if self.acr & ACR_T1_CONTINUOUS != 0 {
+                        self.pb7 = !self.pb7;
+                    } else {
+                        self.t1_armed = false;
+                        self.pb7 = true;
+                    }
+                }
+                self.t1_reload = true;
+            }
+        }
+
+        if self.acr & ACR_T2_PULSE_COUNT == 0 {
+            self.t2_counter = self.t2_counter.wrapping_sub(1);
+            if self.t2_counter == 0xffff && self.t2_armed {
+                self.ifr |= IFR_T2;
+                self.t2_armed = false;
+            }
+        }
+
+        match self.sr_mode() {
+            0b010 | 0b110 => self.shift(),
+            0b001 | 0b100 | 0b101 => {
+                // Each bit takes two periods of the low byte of timer 2, which each last N + 2 cycles
+                self.sr_divider += 1;
+                if self.sr_divider >= (u16::from(self.t2_latch_low) + 2) * 2 {
+                    self.sr_divider = 0;
+                    self.shift();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl BusDevice for Via6522 {
+    fn read(&mut self, offset: u16) -> u8 {
+        return match offset & 0x0f {
+            0x0 => {
+                self.clear_port_flags(IFR_CB1, IFR_CB2, self.cb2_mode());
+                let input = if self.acr & ACR_PB_LATCH != 0 {
+                    self.irb_latch
+                } else {
+                    self.port_b_input
+                };
+                self.with_pb7(self.orb & self.ddrb | input & !self.ddrb)
+            }
+            0x1 => self.read_port_a(true),
+            0x2 => self.ddrb,
+            0x3 => self.ddra,
+            0x4 => {
+                self.ifr &= !IFR_T1;
+                self.t1_counter as u8
+            }
+            0x5 => (self.t1_counter >> 8) as u8,
+            0x6 => self.t1_latch as u8,
+            0x7 => (self.t1_latch >> 8) as u8,
+            0x8 => {
+                self.ifr &= !IFR_T2;
+                self.t2_counter as u8
+            }
+            0x9 => (self.t2_counter >> 8) as u8,
+            0xa => {
+                self.ifr &= !IFR_SR;
+                self.sr_bits = 0;
+                self.sr
+            }
+            0xb => self.acr,
+            0xc => self.pcr,
+            0xd => {
+                if self.irq() {
+                    self.ifr | IFR_IRQ
+                } else {
+                    self.ifr
+                }
+            }
+            0xe => self.ier | 0x80,
+            _ => self.read_port_a(false),
+        };
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        match offset & 0x0f {
+            0x0 => {
+                self.orb = data;
+                self.clear_port_flags(IFR_CB1, IFR_CB2, self.cb2_mode());
+                match self.cb2_mode() {
+                    0b100 => self.cb2_output = false,
+                    0b101 => {
+                        self.cb2_output = false;
+                        self.cb2_pulse = true;
+                    }
+                    _ => {}
+                }
+            }
+            0x1 => self.write_port_a(data, true),
+            0x2 => self.ddrb = data,
+            0x3 => self.ddra = data,
+            0x4 | 0x6 => self.t1_latch = self.t1_latch & 0xff00 | u16::from(data),
+            0x5 => {
+                self.t1_latch = self.t1_latch & 0x00ff | u16::from(data) << 8;
+                self.t1_counter = self.t1_latch;
+                self.t1_reload = false;
+                self.t1_armed = true;
+                self.ifr &= !IFR_T1;
+                self.pb7 = false;
+            }
+            0x7 => {
+                self.t1_latch = self.t1_latch & 0x00ff | u16::from(data) << 8;
+                self.ifr &= !IFR_T1;
+            }
+            0x8 => self.t2_latch_low = data,
+            0x9 => {
+                self.t2_counter = u16::from(data) << 8 | u16::from(self.t2_latch_low);
+                self.t2_armed = true;
+                self.ifr &= !IFR_T2;
+            }
+            0xa => {
+                self.sr = data;
+                self.sr_bits = 0;
+                self.ifr &= !IFR_SR;
+            }
+            0xb => self.acr = data,
+            0xc => self.pcr = data,
+            0xd => self.ifr &= !data,
+            0xe => {
+                if data & 0x80 != 0 {
+                    self.ier |= data & 0x7f;
+                } else {
+                    self.ier &= !data;
+                }
+            }
+            _ => self.write_port_a(data, false),
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            self.cycle();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        return self.ifr & self.ier & 0x7f != 0;
+    }
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ports() {
+        let mut via = Via6522::new();
+        via.write(0x3, 0x0f);
+        via.write(0x1, 0xa5);
+        via.set_port_a(0x30);
+
+        assert_eq!(via.port_a(), 0x35);
+        assert_eq!(via.read(0x1), 0x35);
+        via.write(0x2, 0xff);
+        via.write(0x0, 0x42);
+        assert_eq!(via.port_b(), 0x42);
+        assert_eq!(via.read(0x0), 0x42);
+    }
+
+    #[test]
+    fn test_timer1_one_shot_and_interrupts() {
+        let mut via = Via6522::new();
+        via.write(0xe, 0x80 | IFR_T1);
+        via.write(0xb, ACR_T1_PB7);
+        via.write(0x4, 10);
+        via.write(0x5, 0);
+        assert_eq!(via.port_b() & 0x80, 0);
+
+        via.tick(10);
+        assert!(!via.irq());
+        via.tick(1);
+        assert!(via.irq());
+        assert_eq!(via.read(0xd), 0x80 | IFR_T1);
+        assert_eq!(via.port_b() & 0x80, 0x80);
+
+        via.read(0x4);
+        assert!(!via.irq());
+        via.tick(100);
+        assert!(!via.irq(), "one-shot mode should only interrupt once");
+    }
+
+    #[test]
+    fn test_timer1_free_running() {
+        let mut via = Via6522::new();
+        via.write(0xb, ACR_T1_CONTINUOUS | ACR_T1_PB7);
+        via.write(0x4, 4);
+        via.write(0x5, 0);
+        via.tick(5);
+        assert_eq!(via.read(0xd), IFR_T1);
+        assert_eq!(via.port_b() & 0x80, 0x80);
+
+        via.write(0xd, IFR_T1);
+        via.tick(6);
+        assert_eq!(via.read(0xd), IFR_T1);
+        assert_eq!(via.port_b() & 0x80, 0x00);
+    }
+
+    #[test]
+    fn test_timer2() {
+        let mut via = Via6522::new();
+        via.write(0x8, 3);
+        via.write(0x9, 0);
+        via.tick(4);
+        assert_eq!(via.read(0xd), IFR_T2);
+        via.read(0x8);
+
+        via.write(0xb, ACR_T2_PULSE_COUNT);
+        via.write(0x8, 2);
+        via.write(0x9, 0);
+        via.set_port_b(0xbf);
+        via.set_port_b(0xff);
+        assert_eq!(via.read(0xd), 0);
+        via.set_port_b(0xbf);
+        assert_eq!(via.read(0xd), IFR_T2);
+    }
+
+    #[test]
+    fn test_ca1_latch_and_handshake() {
+        let mut via = Via6522::new();
+        via.write(0xc, 0b0000_1001); // CA1 positive edge, CA2 handshake output
+        via.write(0xb, ACR_PA_LATCH);
+        via.write(0xe, 0x80 | IFR_CA1);
+        via.set_ca1(false);
+        via.set_port_a(0x5a);
+        via.set_ca1(true);
+        via.set_port_a(0x00);
+
+        assert!(via.irq());
+        assert!(via.ca2());
+        assert_eq!(via.read(0x1), 0x5a);
+        assert!(!via.irq());
+        assert!(!via.ca2());
+    }
+
+    #[test]
+    fn test_shift_register() {
+        let mut via = Via6522::new();
+        via.write(0xb, 0b110 << 2);
+        via.write(0xa, 0b1000_0000);
+        via.tick(1);
+        assert!(via.cb2());
+        via.tick(1);
+        assert!(!via.cb2());
+        via.tick(6);
+        assert_eq!(via.read(0xd), IFR_SR);
+        assert_eq!(via.read(0xa), 0b1000_0000);
+
+        via.write(0xb, 0b011 << 2);
+        via.set_cb2(true);
+        for _ in 0..8 {
+            via.set_cb1(false);
+            via.set_cb1(true);
+        }
+        assert_eq!(via.read(0xa), 0xff);
+    }
+}
@@ -0,0 +1,499 @@
+//! ### PROFILER
+//! This module contains a tracer that counts the instructions and cycles spent at each address, and
+//! attributes them to subroutines by following JSR/RTS, BRK/RTI and interrupts.
+//!
+//! Subroutines are identified by their entry address. The code running before the first call is
+//! attributed to a root "subroutine" at the address the profiler first saw executing. A subroutine
+//! frame is considered to have returned once the stack pointer rises back to where it was before the
+//! call, so frames discarded by pulling the return address off the stack are also handled.
+//!
+//! Results can be exported in the callgrind format for tools like KCachegrind, or as folded stacks
+//! for flamegraph tools.
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let mut profiler = Profiler::new();
+//! for _ in 0..1_000_000 {
+//!     cpu.execute_instruction_traced(&mut ram, &mut profiler);
+//! }
+//! profiler.write_callgrind(File::create("callgrind.out")?)?;
+//! profiler.write_folded(File::create("game.folded")?)?;
+//! ```
+
+use super::symbols::SymbolTable;
+use super::tracer::{InstructionEvent, InterruptEvent, Tracer6502};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Opcode of the JSR instruction
+const JSR: u8 = 0x20;
+/// Opcode of the BRK instruction
+const BRK: u8 = 0x00;
+
+/// A count of executed instructions and the cycles they took
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Cost {
+    /// The number of instructions executed
+    pub instructions: u64,
+    /// The number of cycles taken
+    pub cycles: u64,
+}
+
+impl Cost {
+    fn add(&mut self, instructions: u64, cycles: u64) {
+        self.instructions += instructions;
+        self.cycles += cycles;
+    }
+
+    fn since(self, start: Cost) -> Cost {
+        Cost {
+            instructions: self.instructions - start.instructions,
+            cycles: self.cycles - start.cycles,
+        }
+    }
+}
+
+/// The profile of a single subroutine
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct SubroutineProfile {
+    /// The entry address of the subroutine
+    pub entry: u16,
+    /// The number of times the subroutine was called
+    pub calls: u64,
+    /// The cost of the instructions within the subroutine itself
+    pub exclusive: Cost,
+    /// The cost of the subroutine including the subroutines it called
+    pub inclusive: Cost,
+}
+
+/// A subroutine that has been called but has not returned
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    /// Entry address of the subroutine
+    entry: u16,
+    /// The subroutine that made the call and the address it was made from, none for root frames
+    caller: Option<(u16, u16)>,
+    /// The stack pointer before the call was made
+    stack_pointer: u8,
+    /// The node in the calling context tree for this frame
+    node: usize,
+    /// The total cost at the moment the call was made
+    start: Cost,
+}
+
+/// A node in the calling context tree, used for building folded stacks
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    parent: Option<usize>,
+    entry: u16,
+    exclusive: u64,
+}
+
+/// Cost of all calls from a call site to a subroutine
+#[derive(Debug, Default, Clone, Copy)]
+struct CallCost {
+    calls: u64,
+    inclusive: Cost,
+}
+
+/// Tracer that profiles the time spent at each address and in each subroutine
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    total: Cost,
+    addresses: Vec<Cost>,
+    subroutine_addresses: HashMap<(u16, u16), Cost>,
+    subroutines: HashMap<u16, SubroutineProfile>,
+    calls: HashMap<(u16, u16, u16), CallCost>,
+    nodes: Vec<Node>,
+    node_children: HashMap<(Option<usize>, u16), usize>,
+    stack: Vec<Frame>,
+    symbols: Option<SymbolTable>,
+}
+
+impl Profiler {
+    /// Creates a new, empty profiler
+    pub fn new() -> Self {
+        Profiler {
+            total: Cost::default(),
+            addresses: vec![Cost::default(); u16::MAX as usize + 1],
+            subroutine_addresses: HashMap::new(),
+            subroutines: HashMap::new(),
+            calls: HashMap::new(),
+            nodes: Vec::new(),
+            node_children: HashMap::new(),
+            stack: Vec::new(),
+            symbols: None,
+        }
+    }
+
+    /// Names subroutines in the callgrind and folded stack output using the symbol table
+    pub fn with_symbols(mut self, symbols: SymbolTable) -> Self {
+        self.symbols = Some(symbols);
+        self
+    }
+
+    /// Returns the total cost of everything profiled
+    pub fn total(&self) -> Cost {
+        self.total
+    }
+
+    /// Returns the cost of the instructions executed at an address
+    pub fn address_cost(&self, address: u16) -> Cost {
+        self.addresses[address as usize]
+    }
+
+    /// Returns the profile of the subroutine with the given entry address, if it was executed.
+    ///
+    /// Subroutines that have not yet returned have their inclusive cost counted up to the present
+    pub fn subroutine(&self, entry: u16) -> Option<SubroutineProfile> {
+        self.subroutines().into_iter().find(|profile| profile.entry == entry)
+    }
+
+    /// Returns the profiles of all executed subroutines, sorted by descending exclusive cycles
+    pub fn subroutines(&self) -> Vec<SubroutineProfile> {
+        let mut subroutines = self.subroutines.clone();
+        for (index, frame) in self.stack.iter().enumerate() {
+            if !self.stack[..index].iter().any(|outer| outer.entry == frame.entry) {
+                let profile = subroutines.get_mut(&frame.entry).expect("Active subroutine has no profile");
+                let inclusive = self.total.since(frame.start);
+                profile.inclusive.add(inclusive.instructions, inclusive.cycles);
+            }
+        }
+        let mut subroutines: Vec<SubroutineProfile> = subroutines.into_values().collect();
+        subroutines.sort_by(|a, b| b.exclusive.cycles.cmp(&a.exclusive.cycles).then(a.entry.cmp(&b.entry)));
+        return subroutines;
+    }
+
+    /// Clears all recorded data, keeping the symbol table
+    pub fn clear(&mut self) {
+        *self = Profiler {
+            symbols: self.symbols.take(),
+            ..Profiler::new()
+        };
+    }
+
+    /// Writes the profile in the callgrind format, using the instruction address as the position
+    pub fn write_callgrind<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "# callgrind format")?;
+        writeln!(writer, "version: 1")?;
+        writeln!(writer, "creator: emulator_6502")?;
+        writeln!(writer, "positions: instr")?;
+        writeln!(writer, "events: Instructions Cycles")?;
+        writeln!(writer, "summary: {} {}", self.total.instructions, self.total.cycles)?;
+
+        // Include the costs of calls that have not yet returned
+        let mut calls = self.calls.clone();
+        for frame in &self.stack {
+            if let Some((caller, call_site)) = frame.caller {
+                let call = calls.entry((caller, call_site, frame.entry)).or_default();
+                let inclusive = self.total.since(frame.start);
+                call.inclusive.add(inclusive.instructions, inclusive.cycles);
+            }
+        }
+
+        let mut entries: Vec<u16> = self.subroutines.keys().copied().collect();
+        entries.sort_unstable();
+        for entry in entries {
+            writeln!(writer)?;
+            writeln!(writer, "fn={}", self.name(entry))?;
+
+            let mut addresses: Vec<(u16, Cost)> = self
+                .subroutine_addresses
+                .iter()
+                .filter(|((subroutine, _), _)| *subroutine == entry)
+                .map(|((_, address), cost)| (*address, *cost))
+                .collect();
+            addresses.sort_unstable_by_key(|(address, _)| *address);
+            for (address, cost) in addresses {
+                writeln!(writer, "0x{:04X} {} {}", address, cost.instructions, cost.cycles)?;
+            }
+
+            let mut outgoing: Vec<(&(u16, u16, u16), &CallCost)> = calls.iter().filter(|((caller, _, _), _)| *caller == entry).collect();
+            outgoing.sort_unstable_by_key(|(key, _)| **key);
+            for ((_, call_site, callee), call) in outgoing {
+                writeln!(writer, "cfn={}", self.name(*callee))?;
+                writeln!(writer, "calls={} 0x{:04X}", call.calls, callee)?;
+                writeln!(writer, "0x{:04X} {} {}", call_site, call.inclusive.instructions, call.inclusive.cycles)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the profile as folded stacks, one line per distinct call stack with its exclusive cycles
+    pub fn write_folded<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for (index, node) in self.nodes.iter().enumerate() {
+            if node.exclusive == 0 {
+                continue;
+            }
+            let mut names = Vec::new();
+            let mut current = Some(index);
+            while let Some(node_index) = current {
+                names.push(self.name(self.nodes[node_index].entry));
+                current = self.nodes[node_index].parent;
+            }
+            names.reverse();
+            writeln!(writer, "{} {}", names.join(";"), node.exclusive)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the display name of the subroutine at an address
+    fn name(&self, entry: u16) -> String {
+        return match self.symbols.as_ref().and_then(|symbols| symbols.name(entry)) {
+            Some(name) => name.to_string(),
+            None => format!("${:04X}", entry),
+        };
+    }
+
+    /// Pushes a new frame for a subroutine call
+    fn call(&mut self, entry: u16, caller: Option<(u16, u16)>, stack_pointer: u8) {
+        let parent = caller.and_then(|_| self.stack.last()).map(|frame| frame.node);
+        let next_node = self.nodes.len();
+        let node = *self.node_children.entry((parent, entry)).or_insert(next_node);
+        if node == next_node {
+            self.nodes.push(Node { parent, entry, exclusive: 0 });
+        }
+
+        let profile = self.subroutines.entry(entry).or_insert(SubroutineProfile { entry, ..Default::default() });
+        if let Some((caller_entry, call_site)) = caller {
+            profile.calls += 1;
+            self.calls.entry((caller_entry, call_site, entry)).or_default().calls += 1;
+        }
+
+        self.stack.push(Frame {
+            entry,
+            caller,
+            stack_pointer,
+            node,
+            start: self.total,
+        });
+    }
+
+    /// Pops every frame whose return address has been pulled from the stack
+    fn unwind(&mut self, stack_pointer: u8) {
+        while let Some(frame) = self.stack.last().copied() {
+            if frame.caller.is_none() || stack_pointer < frame.stack_pointer {
+                break;
+            }
+            self.stack.pop();
+            let inclusive = self.total.since(frame.start);
+            if !self.stack.iter().any(|outer| outer.entry == frame.entry) {
+                let profile = self.subroutines.get_mut(&frame.entry).expect("Returning subroutine has no profile");
+                profile.inclusive.add(inclusive.instructions, inclusive.cycles);
+            }
+            if let Some((caller, call_site)) = frame.caller {
+                let call = self.calls.get_mut(&(caller, call_site, frame.entry)).expect("Returning call has no cost");
+                call.inclusive.add(inclusive.instructions, inclusive.cycles);
+            }
+        }
+    }
+
+    /// Attributes the cost of an instruction or interrupt at an address to the current subroutine
+    fn attribute(&mut self, address: u16, instructions: u64, cycles: u64) {
+        if self.stack.is_empty() {
+            self.call(address, None, 0);
+        }
+        let frame = *self.stack.last().expect("Profiler stack is empty");
+
+        self.total.add(instructions, cycles);
+        self.addresses[address as usize].add(instructions, cycles);
+        self.subroutine_addresses
+            .entry((frame.entry, address))
+            .or_default()
+            .add(instructions, cycles);
+        self.subroutines
+            .get_mut(&frame.entry)
+            .expect("Active subroutine has no profile")
+            .exclusive
+            .add(instructions, cycles);
+        self.nodes[frame.node].exclusive += cycles;
+    }
+}
+
+impl Tracer6502 for Profiler {
+    fn instruction(&mut self, event: &InstructionEvent) {
+        self.attribute(event.program_counter, 1, u64::from(event.cycles));
+        let caller = self.stack.last().map(|frame| frame.entry).expect("Profiler stack is empty");
+
+        match event.opcode {
+            JSR | BRK => self.call(
+                event.after.program_counter,
+                Some((caller, event.program_counter)),
+                event.before.stack_pointer,
+            ),
+            _ => self.unwind(event.after.stack_pointer),
+        }
+    }
+
+    fn interrupt(&mut self, event: &InterruptEvent) {
+        let caller = match self.stack.last() {
+            Some(frame) => frame.entry,
+            None => {
+                self.call(event.before.program_counter, None, 0);
+                event.before.program_counter
+            }
+        };
+        self.call(
+            event.after.program_counter,
+            Some((caller, event.before.program_counter)),
+            event.before.stack_pointer,
+        );
+        self.attribute(event.after.program_counter, 0, u64::from(event.cycles));
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler::new()
+    }
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utilities::TestRam;
+    use crate::MOS6502;
+
+    /// Calls a subroutine that calls another subroutine, then loops forever
+    const PROGRAM: [u8; 15] = [
+        0x20, 0x06, 0x04, // $0400 JSR $0406
+        0x4c, 0x03, 0x04, // $0403 JMP $0403
+        0xe8, // $0406 INX
+        0x20, 0x0b, 0x04, // $0407 JSR $040b
+        0x60, // $040a RTS
+        0xc8, // $040b INY
+        0xc8, // $040c INY
+        0x60, // $040d RTS
+        0x00,
+    ];
+
+    fn run(instructions: usize) -> Profiler {
+        let mut ram = TestRam::with_program(0x0400, &PROGRAM);
+        let mut cpu = MOS6502::new_start(0x0400);
+        let mut profiler = Profiler::new();
+        for _ in 0..instructions {
+            cpu.execute_instruction_traced(&mut ram, &mut profiler);
+        }
+        profiler
+    }
+
+    #[test]
+    fn test_subroutine_costs() {
+        let profiler = run(10);
+
+        // JSR(6) INX(2) JSR(6) INY(2) INY(2) RTS(6) RTS(6) JMP(3) JMP(3) JMP(3)
+        assert_eq!(
+            profiler.total(),
+            Cost {
+                instructions: 10,
+                cycles: 39
+            }
+        );
+        assert_eq!(profiler.address_cost(0x0403), Cost { instructions: 3, cycles: 9 });
+
+        let root = profiler.subroutine(0x0400).unwrap();
+        assert_eq!(root.calls, 0);
+        assert_eq!(root.exclusive, Cost { instructions: 4, cycles: 15 });
+
+        let outer = profiler.subroutine(0x0406).unwrap();
+        assert_eq!(outer.calls, 1);
+        assert_eq!(outer.exclusive, Cost { instructions: 3, cycles: 14 });
+        assert_eq!(outer.inclusive, Cost { instructions: 6, cycles: 24 });
+
+        let inner = profiler.subroutine(0x040b).unwrap();
+        assert_eq!(inner.calls, 1);
+        assert_eq!(inner.exclusive, Cost { instructions: 3, cycles: 10 });
+        assert_eq!(inner.inclusive, inner.exclusive);
+    }
+
+    #[test]
+    fn test_open_frames() {
+        // Stop inside the inner subroutine
+        let profiler = run(4);
+
+        let outer = profiler.subroutine(0x0406).unwrap();
+        assert_eq!(outer.inclusive, Cost { instructions: 3, cycles: 10 });
+    }
+
+    #[test]
+    fn test_folded() {
+        let profiler = run(10);
+        let mut output = Vec::new();
+        profiler.write_folded(&mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines, vec!["$0400 15", "$0400;$0406 14", "$0400;$0406;$040B 10"]);
+    }
+
+    #[test]
+    fn test_folded_symbols() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("outer", 0x0406);
+        symbols.insert("inner", 0x040b);
+        let profiler = run(10).with_symbols(symbols);
+        let mut output = Vec::new();
+        profiler.write_folded(&mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines, vec!["$0400 15", "$0400;outer 14", "$0400;outer;inner 10"]);
+    }
+
+    #[test]
+    fn test_clear_keeps_symbols() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("outer", 0x0406);
+        let mut profiler = run(10).with_symbols(symbols);
+        profiler.clear();
+        assert_eq!(profiler.total(), Cost::default());
+
+        let mut ram = TestRam::with_program(0x0400, &PROGRAM);
+        let mut cpu = MOS6502::new_start(0x0400);
+        for _ in 0..3 {
+            cpu.execute_instruction_traced(&mut ram, &mut profiler);
+        }
+        let mut output = Vec::new();
+        profiler.write_folded(&mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("$0400;outer "), "symbols were lost: {}", output);
+    }
+
+    #[test]
+    fn test_callgrind() {
+        let profiler = run(10);
+        let mut output = Vec::new();
+        profiler.write_callgrind(&mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("# callgrind format\n"));
+        assert!(output.contains("summary: 10 39\n"));
+        assert!(output.contains("fn=$0406\n0x0406 1 2\n0x0407 1 6\n0x040A 1 6\ncfn=$040B\ncalls=1 0x040B\n0x0407 3 10\n"));
+    }
+
+    #[test]
+    fn test_interrupt_frames() {
+        // CLI; loop: JMP loop; handler at $0500: INX; RTI
+        let mut ram = TestRam::with_program(0x0400, &[0x58, 0x4c, 0x01, 0x04]);
+        ram.ram[0x0500] = 0xe8;
+        ram.ram[0x0501] = 0x40;
+        ram.ram[0xfffe] = 0x00;
+        ram.ram[0xffff] = 0x05;
+        let mut cpu = MOS6502::new_start(0x0400);
+        let mut profiler = Profiler::new();
+
+        cpu.execute_instruction_traced(&mut ram, &mut profiler);
+        cpu.interrupt_request();
+        for _ in 0..4 {
+            cpu.execute_instruction_traced(&mut ram, &mut profiler);
+        }
+
+        let handler = profiler.subroutine(0x0500).unwrap();
+        assert_eq!(handler.calls, 1);
+        assert_eq!(handler.exclusive, Cost { instructions: 2, cycles: 15 });
+        assert_eq!(profiler.subroutine(0x0400).unwrap().exclusive, Cost { instructions: 2, cycles: 5 });
+    }
+}
@@ -0,0 +1,419 @@
+//! ### REWIND
+//! This module contains a driver for stepping the processor backwards while debugging.
+//!
+//! Instructions are executed through the `Rewind` driver, which takes periodic snapshots of the processor
+//! state and records the previous value of every byte written through the interface in an undo log. The
+//! processor and memory can then be rewound to any earlier instruction within the configured window, by
+//! undoing writes back to the nearest snapshot and replaying forward from it.
+//!
+//! NOTE: Replaying reads memory through the interface again, so devices whose reads have side effects,
+//! or interrupts requested between snapshots, are only reproduced exactly with a snapshot interval of 1.
+//! The previous value of each written byte is also read back through the interface before the write, except
+//! in address ranges added with `add_io_range`. Peripheral registers that clear flags when read, such as the
+//! status registers of the ACIA, VIA, CIA and RIOT, should be added so recording does not change the
+//! program's behaviour. Writes to them are not undone when rewinding
+//!
+//! Bank switching mappers such as `Uxrom` and `Mmc1` take their register writes at the same addresses as their
+//! ROM, from $8000 up, so those ranges must be added with `add_io_range` too. Otherwise undoing a write would write
+//! the ROM byte read back from there into the mapper's registers, switching banks.
+//!
+//! Only the processor and the bytes written through the interface are rewound. The state of devices and mappers,
+//! such as timers, interrupt flags and selected banks, stays as it was at the newest instruction
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let mut rewind = Rewind::new(10_000);
+//! rewind.add_breakpoint(0xc123);
+//! rewind.add_io_range(0xd000..=0xd00f);
+//!
+//! for _ in 0..5000 {
+//!     rewind.step(&mut cpu, &mut ram);
+//! }
+//! rewind.reverse_step(&mut cpu, &mut ram)?;
+//! rewind.reverse_continue(&mut cpu, &mut ram);
+//! ```
+
+use super::tracer::{NoTracer, Tracer6502};
+use super::{Interface6502, MOS6502};
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// The previous value of a byte written by an instruction
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct UndoRecord {
+    position: u64,
+    address: u16,
+    previous: u8,
+}
+
+/// Interface wrapper recording the previous value of every write in the undo log
+struct RecordingInterface<'a> {
+    interface: &'a mut dyn Interface6502,
+    undo_log: &'a mut VecDeque<UndoRecord>,
+    io_ranges: &'a [RangeInclusive<u16>],
+    position: u64,
+}
+
+impl Interface6502 for RecordingInterface<'_> {
+    fn read(&mut self, address: u16) -> u8 {
+        self.interface.read(address)
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        if !self.io_ranges.iter().any(|range| range.contains(&address)) {
+            let previous = self.interface.read(address);
+            self.undo_log.push_back(UndoRecord {
+                position: self.position,
+                address,
+                previous,
+            });
+        }
+        self.interface.write(address, data);
+    }
+
+    fn bank(&self, address: u16) -> Option<usize> {
+        self.interface.bank(address)
+    }
+}
+
+/// Driver executing instructions while recording enough history to rewind them
+#[derive(Debug, Clone)]
+pub struct Rewind {
+    window: u64,
+    snapshot_interval: u64,
+    position: u64,
+    snapshots: VecDeque<(u64, MOS6502)>,
+    undo_log: VecDeque<UndoRecord>,
+    program_counters: VecDeque<u16>,
+    breakpoints: HashSet<u16>,
+    io_ranges: Vec<RangeInclusive<u16>>,
+}
+
+impl Rewind {
+    /// Creates a new rewind driver that can rewind at least the given number of instructions, taking a
+    /// snapshot every 64 instructions
+    pub fn new(window: u64) -> Self {
+        Rewind {
+            window,
+            snapshot_interval: 64,
+            position: 0,
+            snapshots: VecDeque::new(),
+            undo_log: VecDeque::new(),
+            program_counters: VecDeque::new(),
+            breakpoints: HashSet::new(),
+            io_ranges: Vec::new(),
+        }
+    }
+
+    /// Sets the number of instructions between snapshots. Shorter intervals use more memory but replay
+    /// fewer instructions when rewinding
+    pub fn snapshot_interval(mut self, instructions: u64) -> Self {
+        self.snapshot_interval = instructions.max(1);
+        self
+    }
+
+    /// Adds an address that `reverse_continue` stops at
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Removes a breakpoint address
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.remove(&address);
+    }
+
+    /// Marks an address range as I/O, so writes there are passed through without reading the previous value first.
+    /// Writes to I/O ranges are not undone when rewinding
+    pub fn add_io_range(&mut self, range: RangeInclusive<u16>) {
+        self.io_ranges.push(range);
+    }
+
+    /// Returns the number of instructions executed through the driver, which identifies the current
+    /// position in the recorded history
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Returns the earliest position that can be rewound to
+    pub fn oldest(&self) -> u64 {
+        self.snapshots.front().map_or(self.position, |(position, _)| *position)
+    }
+
+    /// Discards all recorded history, keeping the current position
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.undo_log.clear();
+        self.program_counters.clear();
+    }
+
+    /// Executes the instruction at the program counter, recording it so it can be rewound.
+    ///
+    /// Executing after rewinding discards the rewound instructions
+    pub fn step(&mut self, cpu: &mut MOS6502, interface: &mut dyn Interface6502) {
+        self.step_inner(cpu, interface, None::<&mut NoTracer>);
+    }
+
+    /// Executes the instruction at the program counter like `step`, passing events to the tracer
+    pub fn step_traced<T: Tracer6502 + ?Sized>(&mut self, cpu: &mut MOS6502, interface: &mut dyn Interface6502, tracer: &mut T) {
+        self.step_inner(cpu, interface, Some(tracer));
+    }
+
+    fn step_inner<T: Tracer6502 + ?Sized>(&mut self, cpu: &mut MOS6502, interface: &mut dyn Interface6502, tracer: Option<&mut T>) {
+        let snapshot_due = self
+            .snapshots
+            .back()
+            .map_or(true, |(position, _)| self.position - position >= self.snapshot_interval);
+        if snapshot_due {
+            self.snapshots.push_back((self.position, cpu.clone()));
+        }
+        self.program_counters.push_back(cpu.program_counter);
+
+        let mut recording = RecordingInterface {
+            interface,
+            undo_log: &mut self.undo_log,
+            io_ranges: &self.io_ranges,
+            position: self.position,
+        };
+        match tracer {
+            Some(tracer) => cpu.execute_instruction_traced(&mut recording, tracer),
+            None => cpu.execute_instruction(&mut recording),
+        }
+        self.position += 1;
+
+        // Drop the oldest snapshot once the next one alone covers the window
+        while self.snapshots.len() > 1 && self.position - self.snapshots[1].0 >= self.window {
+            self.snapshots.pop_front();
+            let oldest = self.oldest();
+            while self.undo_log.front().is_some_and(|record| record.position < oldest) {
+                self.undo_log.pop_front();
+            }
+            let excess = self.program_counters.len() - (self.position - oldest) as usize;
+            self.program_counters.drain(..excess);
+        }
+    }
+
+    /// Rewinds the processor and memory to the state before the instruction at the given position was executed
+    pub fn rewind_to(&mut self, cpu: &mut MOS6502, interface: &mut dyn Interface6502, position: u64) -> Result<(), RewindError> {
+        if position > self.position {
+            return Err(RewindError::Future(position));
+        }
+        if position < self.oldest() || self.snapshots.is_empty() {
+            return Err(RewindError::OutsideWindow {
+                position,
+                oldest: self.oldest(),
+            });
+        }
+
+        while self.snapshots.back().is_some_and(|(snapshot, _)| *snapshot > position) {
+            self.snapshots.pop_back();
+        }
+        let (snapshot_position, snapshot) = self.snapshots.back().cloned().expect("Oldest snapshot is before the position");
+
+        while let Some(record) = self.undo_log.back() {
+            if record.position < snapshot_position {
+                break;
+            }
+            interface.write(record.address, record.previous);
+            self.undo_log.pop_back();
+        }
+        *cpu = snapshot;
+        self.program_counters.truncate((snapshot_position - self.oldest()) as usize);
+        self.position = snapshot_position;
+
+        while self.position < position {
+            self.step(cpu, interface);
+        }
+        return Ok(());
+    }
+
+    /// Rewinds the processor and memory by a single instruction
+    pub fn reverse_step(&mut self, cpu: &mut MOS6502, interface: &mut dyn Interface6502) -> Result<(), RewindError> {
+        let position = self.position.checked_sub(1).ok_or(RewindError::OutsideWindow {
+            position: 0,
+            oldest: self.oldest(),
+        })?;
+        return self.rewind_to(cpu, interface, position);
+    }
+
+    /// Rewinds to the most recent earlier instruction that began at a breakpoint, returning its address.
+    ///
+    /// If no breakpoint was reached within the window, rewinds to the oldest position and returns None
+    pub fn reverse_continue(&mut self, cpu: &mut MOS6502, interface: &mut dyn Interface6502) -> Option<u16> {
+        let oldest = self.oldest();
+        let found = self
+            .program_counters
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, program_counter)| self.breakpoints.contains(program_counter))
+            .map(|(index, program_counter)| (oldest + index as u64, *program_counter));
+
+        let target = found.map_or(oldest, |(position, _)| position);
+        self.rewind_to(cpu, interface, target).ok()?;
+        return found.map(|(_, program_counter)| program_counter);
+    }
+}
+
+/// Errors that can occur when rewinding
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RewindError {
+    /// The position has not been executed yet
+    Future(u64),
+    /// The position is older than the recorded history
+    OutsideWindow {
+        /// The requested position
+        position: u64,
+        /// The earliest position that can be rewound to
+        oldest: u64,
+    },
+}
+
+impl fmt::Display for RewindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RewindError::Future(position) => write!(f, "Position {} has not been executed yet", position),
+            RewindError::OutsideWindow { position, oldest } => {
+                write!(f, "Position {} is outside the rewind window, which begins at {}", position, oldest)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RewindError {}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utilities::TestRam;
+
+    /// Stores an increasing counter to successive addresses forever
+    const PROGRAM: [u8; 7] = [
+        0xe8, // $0400 INX
+        0x8a, // $0401 TXA
+        0x95, 0x10, // $0402 STA $10,X
+        0x4c, 0x00, 0x04, // $0404 JMP $0400
+    ];
+
+    fn run(rewind: &mut Rewind, instructions: usize) -> (MOS6502, TestRam) {
+        let mut ram = TestRam::with_program(0x0400, &PROGRAM);
+        let mut cpu = MOS6502::new_start(0x0400);
+        for _ in 0..instructions {
+            rewind.step(&mut cpu, &mut ram);
+        }
+        (cpu, ram)
+    }
+
+    #[test]
+    fn test_reverse_step() {
+        let mut rewind = Rewind::new(100).snapshot_interval(1);
+        let (mut cpu, mut ram) = run(&mut rewind, 12);
+        assert_eq!(ram.ram[0x13], 3);
+
+        rewind.reverse_step(&mut cpu, &mut ram).unwrap();
+        rewind.reverse_step(&mut cpu, &mut ram).unwrap();
+
+        assert_eq!(rewind.position(), 10);
+        assert_eq!(cpu.program_counter, 0x0402);
+        assert_eq!(cpu.x_register, 3);
+        assert_eq!(ram.ram[0x13], 0);
+        assert_eq!(ram.ram[0x12], 2);
+    }
+
+    #[test]
+    fn test_rewind_replays_from_snapshot() {
+        let mut rewind = Rewind::new(100).snapshot_interval(5);
+        let (mut cpu, mut ram) = run(&mut rewind, 20);
+        let (expected, _) = run(&mut Rewind::new(100), 7);
+
+        rewind.rewind_to(&mut cpu, &mut ram, 7).unwrap();
+
+        assert_eq!(cpu, expected);
+        assert_eq!(ram.ram[0x12], 2);
+        assert_eq!(ram.ram[0x13], 0);
+
+        // Executing again continues from the rewound position
+        rewind.step(&mut cpu, &mut ram);
+        assert_eq!(rewind.position(), 8);
+        assert_eq!(ram.ram[0x13], 0);
+    }
+
+    #[test]
+    fn test_reverse_continue() {
+        let mut rewind = Rewind::new(100);
+        rewind.add_breakpoint(0x0402);
+        let (mut cpu, mut ram) = run(&mut rewind, 10);
+
+        assert_eq!(rewind.reverse_continue(&mut cpu, &mut ram), Some(0x0402));
+        assert_eq!(rewind.position(), 6);
+        assert_eq!(cpu.x_register, 2);
+        assert_eq!(ram.ram[0x12], 0);
+
+        assert_eq!(rewind.reverse_continue(&mut cpu, &mut ram), Some(0x0402));
+        assert_eq!(rewind.position(), 2);
+
+        assert_eq!(rewind.reverse_continue(&mut cpu, &mut ram), None);
+        assert_eq!(rewind.position(), 0);
+        assert_eq!(cpu.program_counter, 0x0400);
+    }
+
+    /// Memory with a status register at $D000 that counts its reads, like a flag cleared on read
+    struct StatusRegister {
+        ram: TestRam,
+        status_reads: u32,
+    }
+
+    impl Interface6502 for StatusRegister {
+        fn read(&mut self, address: u16) -> u8 {
+            if address == 0xd000 {
+                self.status_reads += 1;
+            }
+            return self.ram.read(address);
+        }
+
+        fn write(&mut self, address: u16, data: u8) {
+            self.ram.write(address, data)
+        }
+    }
+
+    #[test]
+    fn test_io_range() {
+        // STA $D000; STA $10
+        let ram = TestRam::with_program(0x0400, &[0x8d, 0x00, 0xd0, 0x85, 0x10]);
+        let mut memory = StatusRegister { ram, status_reads: 0 };
+        let mut cpu = MOS6502::new_start(0x0400);
+        cpu.accumulator = 0x42;
+        let mut rewind = Rewind::new(100);
+        rewind.add_io_range(0xd000..=0xd00f);
+
+        rewind.step(&mut cpu, &mut memory);
+        rewind.step(&mut cpu, &mut memory);
+        assert_eq!(memory.status_reads, 0);
+        assert_eq!(memory.ram.ram[0xd000], 0x42);
+
+        rewind.rewind_to(&mut cpu, &mut memory, 0).unwrap();
+        assert_eq!(memory.ram.ram[0x10], 0x00);
+        assert_eq!(memory.ram.ram[0xd000], 0x42, "writes to I/O are not undone");
+    }
+
+    #[test]
+    fn test_window() {
+        let mut rewind = Rewind::new(10).snapshot_interval(4);
+        let (mut cpu, mut ram) = run(&mut rewind, 40);
+
+        assert!(rewind.oldest() <= 30);
+        assert!(rewind.oldest() > 20);
+        assert_eq!(rewind.rewind_to(&mut cpu, &mut ram, 41), Err(RewindError::Future(41)));
+        assert_eq!(
+            rewind.rewind_to(&mut cpu, &mut ram, 0),
+            Err(RewindError::OutsideWindow {
+                position: 0,
+                oldest: rewind.oldest()
+            })
+        );
+        rewind.rewind_to(&mut cpu, &mut ram, 30).unwrap();
+        assert_eq!(cpu.x_register, 8);
+    }
+}
@@ -0,0 +1,249 @@
+//! ### SAVE STATES
+//! This module contains methods for saving the complete state of the processor to a stable, versioned
+//! binary format and loading it back, for emulator save states.
+//!
+//! The saved state includes the registers, remaining and total cycles, pending interrupts, jammed state
+//! and which variant features the emulator was compiled with. States saved by a build with different
+//! variant features are rejected, since the processor would behave differently after loading them.
+//!
+//! When the "serde" compilation feature is enabled, `MOS6502` also implements serde's `Serialize` and
+//! `Deserialize` traits.
+//!
+//! NOTE: A custom opcode table set with `with_opcode_table` is not part of the state, by either method. Loaded
+//! processors use the built-in opcode table, and the custom table must be set on them again
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let state = cpu.save_state();
+//! fs::write("game.state", &state)?;
+//!
+//! let cpu = MOS6502::load_state(&fs::read("game.state")?)?;
+//! ```
+
+use super::MOS6502;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+/// The bytes every save state begins with
+const MAGIC: [u8; 4] = *b"6502";
+/// The version of the save state format written by this version of the crate
+pub const SAVE_STATE_VERSION: u8 = 1;
+
+/// Variant flag set when the emulator was compiled with the "binary_coded_decimal" feature
+const VARIANT_BINARY_CODED_DECIMAL: u8 = 0b01;
+/// Variant flag set when the emulator was compiled with the "illegal_opcodes" feature
+const VARIANT_ILLEGAL_OPCODES: u8 = 0b10;
+
+/// Flag set when a non-maskable interrupt is pending
+const STATE_PENDING_NMI: u8 = 0b001;
+/// Flag set when an interrupt request is pending
+const STATE_PENDING_IRQ: u8 = 0b010;
+/// Flag set when the processor is jammed
+const STATE_JAMMED: u8 = 0b100;
+
+/// Returns the variant flags of the features this build was compiled with
+fn variant() -> u8 {
+    let mut variant = 0;
+    if cfg!(feature = "binary_coded_decimal") {
+        variant |= VARIANT_BINARY_CODED_DECIMAL;
+    }
+    if cfg!(feature = "illegal_opcodes") {
+        variant |= VARIANT_ILLEGAL_OPCODES;
+    }
+    return variant;
+}
+
+impl MOS6502 {
+    /// Returns the complete state of the processor in the versioned save state format
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::new();
+        self.write_state(&mut state).expect("Writing to a Vec can not fail");
+        return state;
+    }
+
+    /// Writes the complete state of the processor in the versioned save state format.
+    ///
+    /// Version 1 of the format is 23 bytes long, with multiple byte values in little endian order:
+    /// magic number "6502", version, variant flags, A, X, Y, PC (2 bytes), SP, P, remaining cycles,
+    /// total cycles (8 bytes) and state flags
+    pub fn write_state<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut flags = 0;
+        if self.pending_nmi {
+            flags |= STATE_PENDING_NMI;
+        }
+        if self.pending_irq {
+            flags |= STATE_PENDING_IRQ;
+        }
+        if self.jammed {
+            flags |= STATE_JAMMED;
+        }
+
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&[SAVE_STATE_VERSION, variant(), self.accumulator, self.x_register, self.y_register])?;
+        writer.write_all(&self.program_counter.to_le_bytes())?;
+        writer.write_all(&[self.stack_pointer, self.status_register, self.remaining_cycles])?;
+        writer.write_all(&self.total_cycles.to_le_bytes())?;
+        writer.write_all(&[flags])?;
+        Ok(())
+    }
+
+    /// Creates a processor from a state returned by `save_state`, which must not be followed by any other data.
+    /// The processor uses the built-in opcode table, so a custom table must be set again with `with_opcode_table`
+    pub fn load_state(mut state: &[u8]) -> Result<Self, SaveStateError> {
+        let cpu = MOS6502::read_state(&mut state)?;
+        if !state.is_empty() {
+            return Err(SaveStateError::TrailingData(state.len()));
+        }
+        return Ok(cpu);
+    }
+
+    /// Creates a processor from a state written by `write_state`, leaving anything after the state unread. Like
+    /// `load_state`, the processor uses the built-in opcode table
+    pub fn read_state<R: Read>(mut reader: R) -> Result<Self, SaveStateError> {
+        let mut header = [0; 6];
+        reader.read_exact(&mut header).map_err(SaveStateError::Io)?;
+        if header[..4] != MAGIC {
+            return Err(SaveStateError::InvalidMagic);
+        }
+        if header[4] != SAVE_STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion(header[4]));
+        }
+        if header[5] != variant() {
+            return Err(SaveStateError::VariantMismatch {
+                saved: header[5],
+                current: variant(),
+            });
+        }
+
+        let mut body = [0; 17];
+        reader.read_exact(&mut body).map_err(SaveStateError::Io)?;
+        let flags = body[16];
+        if flags & !(STATE_PENDING_NMI | STATE_PENDING_IRQ | STATE_JAMMED) != 0 {
+            return Err(SaveStateError::InvalidFlags(flags));
+        }
+
+        let mut total_cycles = [0; 8];
+        total_cycles.clone_from_slice(&body[8..16]);
+        return Ok(MOS6502 {
+            accumulator: body[0],
+            x_register: body[1],
+            y_register: body[2],
+            program_counter: u16::from_le_bytes([body[3], body[4]]),
+            stack_pointer: body[5],
+            status_register: body[6],
+            remaining_cycles: body[7],
+            total_cycles: u64::from_le_bytes(total_cycles),
+            pending_nmi: flags & STATE_PENDING_NMI != 0,
+            pending_irq: flags & STATE_PENDING_IRQ != 0,
+            jammed: flags & STATE_JAMMED != 0,
+            opcode_table: None,
+        });
+    }
+}
+
+/// Errors that can occur while loading a save state
+#[derive(Debug)]
+pub enum SaveStateError {
+    /// The state could not be read, or ended early
+    Io(io::Error),
+    /// The state did not begin with the save state magic number
+    InvalidMagic,
+    /// The state was saved in a format version this version of the crate does not support
+    UnsupportedVersion(u8),
+    /// The state was saved by a build compiled with different variant features
+    VariantMismatch {
+        /// The variant flags of the build that saved the state
+        saved: u8,
+        /// The variant flags of this build
+        current: u8,
+    },
+    /// The state flags byte had unknown bits set
+    InvalidFlags(u8),
+    /// The given number of bytes followed the end of the state
+    TrailingData(usize),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::Io(error) => write!(f, "Failed to read save state: {}", error),
+            SaveStateError::InvalidMagic => write!(f, "Not a 6502 save state"),
+            SaveStateError::UnsupportedVersion(version) => write!(f, "Unsupported save state version {}", version),
+            SaveStateError::VariantMismatch { saved, current } => write!(
+                f,
+                "Save state variant flags {:02b} do not match this build's variant flags {:02b}",
+                saved, current
+            ),
+            SaveStateError::InvalidFlags(flags) => write!(f, "Invalid save state flags {:08b}", flags),
+            SaveStateError::TrailingData(length) => write!(f, "{} unexpected bytes after the end of the save state", length),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cpu() -> MOS6502 {
+        MOS6502 {
+            accumulator: 0x12,
+            x_register: 0x34,
+            y_register: 0x56,
+            program_counter: 0xc123,
+            stack_pointer: 0xf0,
+            status_register: 0xa5,
+            remaining_cycles: 3,
+            total_cycles: 0x0123_4567_89ab,
+            pending_nmi: false,
+            pending_irq: true,
+            jammed: true,
+            opcode_table: None,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let state = cpu().save_state();
+        assert_eq!(state.len(), 23);
+        assert_eq!(state[..5], [b'6', b'5', b'0', b'2', SAVE_STATE_VERSION]);
+
+        let loaded = MOS6502::load_state(&state).unwrap();
+        assert_eq!(loaded, cpu());
+    }
+
+    #[test]
+    fn test_invalid_states() {
+        let state = cpu().save_state();
+
+        assert!(matches!(MOS6502::load_state(&state[..10]), Err(SaveStateError::Io(_))));
+        assert!(matches!(MOS6502::load_state(b"NES\x1a\x01\x00"), Err(SaveStateError::InvalidMagic)));
+
+        let mut future = state.clone();
+        future[4] = SAVE_STATE_VERSION + 1;
+        assert!(matches!(MOS6502::load_state(&future), Err(SaveStateError::UnsupportedVersion(_))));
+
+        let mut other_variant = state.clone();
+        other_variant[5] ^= VARIANT_BINARY_CODED_DECIMAL;
+        assert!(matches!(MOS6502::load_state(&other_variant), Err(SaveStateError::VariantMismatch { .. })));
+
+        let mut bad_flags = state.clone();
+        bad_flags[22] = 0xff;
+        assert!(matches!(MOS6502::load_state(&bad_flags), Err(SaveStateError::InvalidFlags(0xff))));
+
+        let mut trailing = state;
+        trailing.extend_from_slice(&[0, 0]);
+        assert!(matches!(MOS6502::load_state(&trailing), Err(SaveStateError::TrailingData(2))));
+        assert_eq!(MOS6502::read_state(&trailing[..]).unwrap(), cpu());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_traits() {
+        fn assert_serde<T: serde::Serialize + serde::de::DeserializeOwned>() {}
+        assert_serde::<MOS6502>();
+    }
+}
@@ -0,0 +1,457 @@
+//! ### SYMBOLS
+//! This module contains a symbol table for naming addresses, and loaders for the label and debug
+//! files produced by common 6502 toolchains:
+//! * ca65/ld65 debug info files (`ld65 --dbgfile`), which also provide source line information
+//! * VICE monitor label files (`al C:c123 .label`)
+//! * ELF executables produced by llvm-mos
+//!
+//! Once loaded, the symbol table can be given to the disassembler, `LogTracer`, profiler and backtraces
+//! so they display `JSR init_ppu` instead of `JSR $C123`.
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! let debug_info = DebugInfo::parse_ca65(&fs::read_to_string("game.dbg")?)?;
+//! let instruction = disassemble(&mut ram, 0xc000);
+//! println!("{}", instruction.to_string_with_symbols(&debug_info.symbols));
+//! ```
+
+use super::coverage::LineMap;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Table of names for addresses
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct SymbolTable {
+    names: BTreeMap<u16, Vec<String>>,
+    addresses: HashMap<String, u16>,
+}
+
+impl SymbolTable {
+    /// Creates a new, empty symbol table
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    /// Adds a name for an address. An address can have several names, the first of which is used for display
+    pub fn insert(&mut self, name: &str, address: u16) {
+        if self.addresses.insert(name.to_string(), address).is_none() {
+            self.names.entry(address).or_default().push(name.to_string());
+        }
+    }
+
+    /// Adds every symbol from another table to this one
+    pub fn merge(&mut self, other: &SymbolTable) {
+        for (address, names) in &other.names {
+            for name in names {
+                self.insert(name, *address);
+            }
+        }
+    }
+
+    /// Returns the display name of an address, if it has one
+    pub fn name(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).and_then(|names| names.first()).map(|name| name.as_str())
+    }
+
+    /// Returns every name of an address
+    pub fn names(&self, address: u16) -> &[String] {
+        self.names.get(&address).map_or(&[], |names| names.as_slice())
+    }
+
+    /// Returns the address of a name, if it is in the table
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    /// Returns the closest named address at or before the given address, and the offset from it
+    pub fn nearest(&self, address: u16) -> Option<(&str, u16)> {
+        let (symbol_address, names) = self.names.range(..=address).next_back()?;
+        return Some((names[0].as_str(), address - symbol_address));
+    }
+
+    /// Returns the number of names in the table
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    /// Returns true if the table has no names
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// Parses a VICE monitor label file, made up of lines like `al C:c123 .init_ppu`
+    pub fn parse_vice_labels(text: &str) -> Result<Self, SymbolError> {
+        let mut table = SymbolTable::new();
+        for (index, line) in text.lines().enumerate() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.is_empty() {
+                continue;
+            }
+            if tokens[0] != "al" || tokens.len() < 3 {
+                return Err(SymbolError::parse(index + 1, "expected `al <address> .<label>`"));
+            }
+            let address = tokens[1].rsplit(':').next().unwrap_or_default();
+            let address = u32::from_str_radix(address, 16).map_err(|_| SymbolError::parse(index + 1, "invalid address"))?;
+            let address = u16::try_from(address).map_err(|_| SymbolError::parse(index + 1, "address out of range"))?;
+            table.insert(tokens[2].trim_start_matches('.'), address);
+        }
+        return Ok(table);
+    }
+
+    /// Parses the symbol table of a 32-bit little endian ELF file, such as those produced by llvm-mos.
+    ///
+    /// Only defined function, object and untyped symbols within the 16-bit address space are included
+    pub fn parse_elf(bytes: &[u8]) -> Result<Self, SymbolError> {
+        if bytes.get(0..4) != Some(b"\x7fELF") {
+            return Err(SymbolError::InvalidElf("missing ELF magic number"));
+        }
+        if bytes.get(4) != Some(&1) || bytes.get(5) != Some(&1) {
+            return Err(SymbolError::InvalidElf("only 32-bit little endian ELF files are supported"));
+        }
+
+        let section_offset = read_u32(bytes, 0x20)? as usize;
+        let section_size = read_u16(bytes, 0x2e)? as usize;
+        let section_count = read_u16(bytes, 0x30)? as usize;
+        let section = |index: usize| -> Result<ElfSection, SymbolError> {
+            let offset = section_offset + index * section_size;
+            Ok(ElfSection {
+                section_type: read_u32(bytes, offset + 4)?,
+                offset: read_u32(bytes, offset + 16)? as usize,
+                size: read_u32(bytes, offset + 20)? as usize,
+                link: read_u32(bytes, offset + 24)? as usize,
+            })
+        };
+
+        let mut table = SymbolTable::new();
+        for index in 0..section_count {
+            let symbols = section(index)?;
+            if symbols.section_type != SHT_SYMTAB {
+                continue;
+            }
+            let strings = section(symbols.link)?;
+            for entry in (symbols.offset..symbols.offset + symbols.size).step_by(16) {
+                let name_offset = read_u32(bytes, entry)? as usize;
+                let value = read_u32(bytes, entry + 4)?;
+                let symbol_type = *bytes.get(entry + 12).ok_or(SymbolError::InvalidElf("truncated symbol table"))? & 0xf;
+                let section_index = read_u16(bytes, entry + 14)?;
+                let address = match u16::try_from(value) {
+                    Ok(address) if section_index != 0 && symbol_type <= STT_FUNC => address,
+                    _ => continue,
+                };
+                let name = read_string(bytes, strings.offset + name_offset)?;
+                if !name.is_empty() {
+                    table.insert(name, address);
+                }
+            }
+        }
+        return Ok(table);
+    }
+}
+
+/// ELF section type of a symbol table
+const SHT_SYMTAB: u32 = 2;
+/// ELF symbol type of a function, the highest of the included untyped, object and function types
+const STT_FUNC: u8 = 2;
+
+/// The parts of an ELF section header needed for reading symbols
+struct ElfSection {
+    section_type: u32,
+    offset: usize,
+    size: usize,
+    link: usize,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, SymbolError> {
+    let slice = bytes.get(offset..offset + 2).ok_or(SymbolError::InvalidElf("unexpected end of file"))?;
+    return Ok(u16::from_le_bytes([slice[0], slice[1]]));
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, SymbolError> {
+    let slice = bytes.get(offset..offset + 4).ok_or(SymbolError::InvalidElf("unexpected end of file"))?;
+    return Ok(u32::from_le_bytes([slice[0], slice[1], slice[2], slice[3]]));
+}
+
+fn read_string(bytes: &[u8], offset: usize) -> Result<&str, SymbolError> {
+    let slice = bytes.get(offset..).ok_or(SymbolError::InvalidElf("unexpected end of file"))?;
+    let end = slice
+        .iter()
+        .position(|byte| *byte == 0)
+        .ok_or(SymbolError::InvalidElf("unterminated string"))?;
+    return std::str::from_utf8(&slice[..end]).map_err(|_| SymbolError::InvalidElf("invalid symbol name"));
+}
+
+/// Symbols and source line information loaded from a debug info file
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct DebugInfo {
+    /// The labels defined in the program
+    pub symbols: SymbolTable,
+    /// The source lines that generated each address
+    pub lines: LineMap,
+}
+
+impl DebugInfo {
+    /// Parses a debug info file produced by ld65's `--dbgfile` option.
+    ///
+    /// Labels and `=` or `.set` constants become symbols, and assembly and C source lines become line map entries.
+    /// Constants outside the 16-bit address space are left out, as they can't name an address. Lines generated
+    /// by macro expansion are attributed to the line that invoked the macro instead
+    pub fn parse_ca65(text: &str) -> Result<Self, SymbolError> {
+        let mut files: HashMap<u32, String> = HashMap::new();
+        let mut segments: HashMap<u32, u32> = HashMap::new();
+        let mut spans: HashMap<u32, (u32, u32, u32)> = HashMap::new();
+        let mut lines: Vec<(u32, u32, Vec<u32>)> = Vec::new();
+        let mut symbols = SymbolTable::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let (record, fields) = match line.find(|c: char| c.is_whitespace()) {
+                Some(split) => (&line[..split], parse_fields(line[split..].trim(), line_number)?),
+                None => continue,
+            };
+            let field = |key: &str| -> Result<&str, SymbolError> {
+                fields
+                    .get(key)
+                    .map(|value| value.as_str())
+                    .ok_or_else(|| SymbolError::parse(line_number, &format!("missing field `{}`", key)))
+            };
+            let number = |key: &str| -> Result<u32, SymbolError> {
+                parse_number(field(key)?).ok_or_else(|| SymbolError::parse(line_number, &format!("invalid number in field `{}`", key)))
+            };
+
+            match record {
+                "file" => {
+                    files.insert(number("id")?, field("name")?.to_string());
+                }
+                "seg" => {
+                    segments.insert(number("id")?, number("start")?);
+                }
+                "span" => {
+                    spans.insert(number("id")?, (number("seg")?, number("start")?, number("size")?));
+                }
+                "line" if fields.contains_key("span") && fields.get("type").map_or(true, |line_type| line_type != "2") => {
+                    let span_ids = field("span")?
+                        .split('+')
+                        .map(|id| parse_number(id).ok_or_else(|| SymbolError::parse(line_number, "invalid span list")))
+                        .collect::<Result<Vec<u32>, SymbolError>>()?;
+                    lines.push((number("file")?, number("line")?, span_ids));
+                }
+                "sym" if fields.contains_key("val") => match fields.get("type").map(|symbol_type| symbol_type.as_str()) {
+                    Some("lab") => {
+                        let address = u16::try_from(number("val")?).map_err(|_| SymbolError::parse(line_number, "label address out of range"))?;
+                        symbols.insert(field("name")?, address);
+                    }
+                    Some("equ") => {
+                        if let Ok(address) = u16::try_from(number("val")?) {
+                            symbols.insert(field("name")?, address);
+                        }
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        let mut line_map = LineMap::new();
+        for (file, line, span_ids) in lines {
+            let file = files
+                .get(&file)
+                .ok_or_else(|| SymbolError::parse(0, &format!("line refers to unknown file {}", file)))?;
+            for span_id in span_ids {
+                let (segment, start, size) = spans
+                    .get(&span_id)
+                    .ok_or_else(|| SymbolError::parse(0, &format!("line refers to unknown span {}", span_id)))?;
+                let segment_start = segments
+                    .get(segment)
+                    .ok_or_else(|| SymbolError::parse(0, &format!("span refers to unknown segment {}", segment)))?;
+                let out_of_range = || SymbolError::parse(0, &format!("span {} is outside the address space", span_id));
+                let address = u16::try_from(segment_start + start).map_err(|_| out_of_range())?;
+                let size = u16::try_from(*size).map_err(|_| out_of_range())?;
+                line_map.add_span(file, line, address, size);
+            }
+        }
+
+        return Ok(DebugInfo { symbols, lines: line_map });
+    }
+}
+
+/// Parses the comma separated `key=value` fields of a ca65 debug info record
+fn parse_fields(text: &str, line_number: usize) -> Result<HashMap<String, String>, SymbolError> {
+    let mut fields = HashMap::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let equals = rest.find('=').ok_or_else(|| SymbolError::parse(line_number, "expected `key=value`"))?;
+        let key = rest[..equals].to_string();
+        rest = &rest[equals + 1..];
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').ok_or_else(|| SymbolError::parse(line_number, "unterminated string"))?;
+            rest = &quoted[end + 1..];
+            quoted[..end].to_string()
+        } else {
+            let end = rest.find(',').unwrap_or(rest.len());
+            let value = rest[..end].to_string();
+            rest = &rest[end..];
+            value
+        };
+        rest = rest.strip_prefix(',').unwrap_or(rest);
+        fields.insert(key, value);
+    }
+    return Ok(fields);
+}
+
+/// Parses a decimal or 0x prefixed hexadecimal number
+fn parse_number(text: &str) -> Option<u32> {
+    return match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    };
+}
+
+/// Errors that can occur while loading symbols
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SymbolError {
+    /// A line of a text file could not be parsed
+    Parse {
+        /// The line number, or 0 if the error was found after reading the whole file
+        line: usize,
+        /// Description of the problem
+        message: String,
+    },
+    /// An ELF file was malformed or of an unsupported kind
+    InvalidElf(&'static str),
+}
+
+impl SymbolError {
+    fn parse(line: usize, message: &str) -> Self {
+        SymbolError::Parse {
+            line,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymbolError::Parse { line, message } => write!(f, "Failed to parse line {}: {}", line, message),
+            SymbolError::InvalidElf(message) => write!(f, "Invalid ELF file: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CA65_DEBUG_INFO: &str = "version\tmajor=2,minor=0
+info\tcsym=0,file=1,lib=0,line=3,mod=1,scope=1,seg=1,span=2,sym=2,type=2
+file\tid=0,name=\"game.s\",size=120,mtime=0x5C3E0A8C,mod=0
+line\tid=0,file=0,line=4,span=0
+line\tid=1,file=0,line=5,span=1
+line\tid=2,file=0,line=9,type=2,span=1
+mod\tid=0,name=\"game.o\",file=0
+seg\tid=0,name=\"CODE\",start=0x00C000,size=0x0005,addrsize=absolute,type=ro,oname=\"game.bin\",ooffs=16
+span\tid=0,seg=0,start=0,size=2
+span\tid=1,seg=0,start=2,size=3
+scope\tid=0,name=\"\",mod=0,size=5
+sym\tid=0,name=\"init_ppu\",addrsize=absolute,scope=0,def=0,val=0xC000,seg=0,type=lab
+sym\tid=1,name=\"PPUCTRL\",addrsize=absolute,scope=0,def=1,val=0x2000,type=equ
+";
+
+    #[test]
+    fn test_symbol_table() {
+        let mut table = SymbolTable::new();
+        table.insert("init_ppu", 0xc123);
+        table.insert("reset", 0xc000);
+        table.insert("start", 0xc000);
+
+        assert_eq!(table.name(0xc000), Some("reset"));
+        assert_eq!(table.names(0xc000).len(), 2);
+        assert_eq!(table.address("init_ppu"), Some(0xc123));
+        assert_eq!(table.nearest(0xc125), Some(("init_ppu", 2)));
+        assert_eq!(table.nearest(0x8000), None);
+        assert_eq!(table.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_ca65() {
+        let debug_info = DebugInfo::parse_ca65(CA65_DEBUG_INFO).unwrap();
+
+        assert_eq!(debug_info.symbols.name(0xc000), Some("init_ppu"));
+        assert_eq!(debug_info.symbols.address("PPUCTRL"), Some(0x2000));
+        assert_eq!(debug_info.lines.lookup(0xc001), Some(("game.s", 4)));
+        assert_eq!(debug_info.lines.lookup(0xc004), Some(("game.s", 5)));
+        assert_eq!(debug_info.lines.lookup(0xc005), None);
+    }
+
+    #[test]
+    fn test_parse_ca65_error() {
+        let result = DebugInfo::parse_ca65("span\tid=0,seg=0,start=zero,size=2");
+        assert_eq!(result, Err(SymbolError::parse(1, "invalid number in field `start`")));
+
+        let result = DebugInfo::parse_ca65("sym\tid=0,name=\"far\",val=0x10000,type=lab");
+        assert_eq!(result, Err(SymbolError::parse(1, "label address out of range")));
+        let result = DebugInfo::parse_ca65("sym\tid=0,name=\"BIG\",val=100000,type=equ");
+        assert_eq!(result.map(|debug_info| debug_info.symbols.len()), Ok(0));
+
+        let result = DebugInfo::parse_ca65(
+            "file\tid=0,name=\"a.s\"\nseg\tid=0,start=0xFFFF\nspan\tid=0,seg=0,start=1,size=1\nline\tid=0,file=0,line=1,span=0",
+        );
+        assert_eq!(result, Err(SymbolError::parse(0, "span 0 is outside the address space")));
+    }
+
+    #[test]
+    fn test_parse_vice_labels() {
+        let table = SymbolTable::parse_vice_labels("al C:c123 .init_ppu\n\nal 00c000 .reset\n").unwrap();
+        assert_eq!(table.name(0xc123), Some("init_ppu"));
+        assert_eq!(table.name(0xc000), Some("reset"));
+
+        assert!(SymbolTable::parse_vice_labels("break c000").is_err());
+        assert_eq!(
+            SymbolTable::parse_vice_labels("al C:1c000 .far"),
+            Err(SymbolError::parse(1, "address out of range"))
+        );
+    }
+
+    #[test]
+    fn test_parse_elf() {
+        // A minimal ELF file with a null section, a symbol table and its string table
+        let strings = b"\0init_ppu\0.text\0";
+        let mut symbols = vec![0; 16]; // Null symbol
+        symbols.extend_from_slice(&[1, 0, 0, 0, 0x23, 0xc1, 0, 0, 0, 0, 0, 0, 0x12, 0, 1, 0]); // init_ppu, global function
+        symbols.extend_from_slice(&[10, 0, 0, 0, 0x00, 0xc0, 0, 0, 0, 0, 0, 0, 0x03, 0, 1, 0]); // .text, section symbol
+
+        let mut elf = vec![0; 0x34];
+        elf[0..6].clone_from_slice(b"\x7fELF\x01\x01");
+        let symbols_offset = elf.len();
+        elf.extend_from_slice(&symbols);
+        let strings_offset = elf.len();
+        elf.extend_from_slice(strings);
+        let section_offset = elf.len();
+        elf[0x20..0x24].clone_from_slice(&(section_offset as u32).to_le_bytes());
+        elf[0x2e..0x30].clone_from_slice(&40u16.to_le_bytes());
+        elf[0x30..0x32].clone_from_slice(&3u16.to_le_bytes());
+
+        let mut section = |section_type: u32, offset: usize, size: usize, link: u32| {
+            let mut header = vec![0; 40];
+            header[4..8].clone_from_slice(&section_type.to_le_bytes());
+            header[16..20].clone_from_slice(&(offset as u32).to_le_bytes());
+            header[20..24].clone_from_slice(&(size as u32).to_le_bytes());
+            header[24..28].clone_from_slice(&link.to_le_bytes());
+            elf.extend_from_slice(&header);
+        };
+        section(0, 0, 0, 0);
+        section(2, symbols_offset, symbols.len(), 2);
+        section(3, strings_offset, strings.len(), 0);
+
+        let table = SymbolTable::parse_elf(&elf).unwrap();
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.name(0xc123), Some("init_ppu"));
+
+        assert_eq!(SymbolTable::parse_elf(b"MZ"), Err(SymbolError::InvalidElf("missing ELF magic number")));
+    }
+}
@@ -1,6 +1,9 @@
 //! Module of test utility types
 
 use super::*;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
 
 pub(crate) struct StubInterface6502 {
     pub(crate) read: fn(u16, u8) -> u8,
@@ -40,3 +43,44 @@ impl Default for StubInterface6502 {
         )
     }
 }
+
+/// Writer whose output can be inspected after it has been moved into a device or machine
+#[derive(Clone, Default)]
+pub(crate) struct SharedOutput(pub(crate) Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Flat 64KB memory interface for tests that need to run small programs
+pub(crate) struct TestRam {
+    pub(crate) ram: Box<[u8; u16::MAX as usize + 1]>,
+}
+
+impl TestRam {
+    /// Creates a zeroed ram with the given program copied to the start address
+    pub(crate) fn with_program(start: u16, program: &[u8]) -> Self {
+        let mut ram = TestRam {
+            ram: Box::new([0; u16::MAX as usize + 1]),
+        };
+        ram.ram[start as usize..start as usize + program.len()].clone_from_slice(program);
+        ram
+    }
+}
+
+impl Interface6502 for TestRam {
+    fn read(&mut self, address: u16) -> u8 {
+        self.ram[address as usize]
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.ram[address as usize] = data
+    }
+}
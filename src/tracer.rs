@@ -0,0 +1,379 @@
+//! ### TRACER
+//! This module contains the trait used for observing the execution of the processor.
+//!
+//! A tracer is passed to `MOS6502::cycle_traced` or `MOS6502::execute_instruction_traced` and receives
+//! a structured event for every instruction and interrupt. The untraced `cycle` and `execute_instruction`
+//! functions never build these events, so there is no cost when no tracer is in use.
+//!
+//! ### Usage Example
+//! ```rust,ignore
+//! struct JsrCounter(u32);
+//!
+//! impl Tracer6502 for JsrCounter {
+//!     fn instruction(&mut self, event: &InstructionEvent) {
+//!         if event.opcode == 0x20 {
+//!             self.0 += 1
+//!         }
+//!     }
+//! }
+//!
+//! let mut counter = JsrCounter(0);
+//! cpu.execute_instruction_traced(&mut ram, &mut counter);
+//! ```
+
+use super::cpu_state::CpuState;
+use super::symbols::SymbolTable;
+use super::Interface6502;
+use std::fmt;
+
+/// Trait for receiving events from the processor as it executes
+pub trait Tracer6502 {
+    /// Called after each instruction has been executed
+    fn instruction(&mut self, event: &InstructionEvent);
+
+    /// Called after the processor has responded to an interrupt request
+    fn interrupt(&mut self, _event: &InterruptEvent) {}
+
+    /// Called after an instruction at the given address has jammed the processor
+    fn jammed(&mut self, _program_counter: u16) {}
+}
+
+impl<T: Tracer6502 + ?Sized> Tracer6502 for &mut T {
+    fn instruction(&mut self, event: &InstructionEvent) {
+        (**self).instruction(event)
+    }
+
+    fn interrupt(&mut self, event: &InterruptEvent) {
+        (**self).interrupt(event)
+    }
+
+    fn jammed(&mut self, program_counter: u16) {
+        (**self).jammed(program_counter)
+    }
+}
+
+/// Tracer that receives no events, used when the processor is run without a tracer
+pub(crate) struct NoTracer;
+
+impl Tracer6502 for NoTracer {
+    fn instruction(&mut self, _event: &InstructionEvent) {}
+}
+
+/// Interface wrapper keeping the first value read from each operand byte of an instruction, so events report the
+/// bytes the processor actually used without reading them from the interface again. It only observes, so traced
+/// and untraced instructions make the same accesses
+pub(crate) struct OperandCapture<'a> {
+    interface: &'a mut dyn Interface6502,
+    start: u16,
+    operand: [u8; 2],
+    captured: [bool; 2],
+}
+
+impl<'a> OperandCapture<'a> {
+    /// Wraps the interface to capture the operand bytes following the opcode at the given address
+    pub(crate) fn new(interface: &'a mut dyn Interface6502, opcode_address: u16) -> Self {
+        OperandCapture {
+            interface,
+            start: opcode_address.wrapping_add(1),
+            operand: [0; 2],
+            captured: [false; 2],
+        }
+    }
+
+    /// Returns the operand bytes the instruction read, with any it never read left as 0
+    pub(crate) fn finish(self) -> [u8; 2] {
+        return self.operand;
+    }
+}
+
+impl Interface6502 for OperandCapture<'_> {
+    fn read(&mut self, address: u16) -> u8 {
+        let data = self.interface.read(address);
+        let index = address.wrapping_sub(self.start) as usize;
+        if index < 2 && !self.captured[index] {
+            self.operand[index] = data;
+            self.captured[index] = true;
+        }
+        return data;
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.interface.write(address, data)
+    }
+
+    fn bank(&self, address: u16) -> Option<usize> {
+        self.interface.bank(address)
+    }
+}
+
+/// Event describing a single executed instruction
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct InstructionEvent {
+    /// The address the instruction was read from
+    pub program_counter: u16,
+    /// The opcode byte of the instruction
+    pub opcode: u8,
+    /// The lowercase mnemonic of the instruction
+    pub name: &'static str,
+    /// The operand bytes following the opcode, only the first `operand_length` of which are valid. Bytes the
+    /// instruction never read, such as the operand of an immediate NOP, are 0, as the tracer does not read memory
+    pub operand: [u8; 2],
+    /// The number of operand bytes following the opcode
+    pub operand_length: u8,
+    /// The address the instruction operated on, or the destination of a branch, if it had one
+    pub effective_address: Option<u16>,
+    /// The state of the processor before the instruction was executed, with the program counter pointing at the opcode
+    pub before: CpuState,
+    /// The state of the processor after the instruction was executed
+    pub after: CpuState,
+    /// The number of cycles the instruction took, including any page crossing or branch penalties
+    pub cycles: u8,
+    /// The total number of cycles that had passed when the instruction began
+    pub total_cycles: u64,
+    /// Whether the opcode is an illegal opcode outside of the documented instruction set
+    pub illegal: bool,
+}
+
+impl InstructionEvent {
+    /// Returns the valid operand bytes of the instruction
+    pub fn operand_bytes(&self) -> &[u8] {
+        &self.operand[..self.operand_length as usize]
+    }
+}
+
+impl fmt::Display for InstructionEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = self
+            .operand_bytes()
+            .iter()
+            .fold(format!("{:02X}", self.opcode), |bytes, byte| format!("{} {:02X}", bytes, byte));
+        write!(
+            f,
+            "{:04X}  {:<8}  {}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            self.program_counter,
+            bytes,
+            self.name,
+            self.before.accumulator,
+            self.before.x_register,
+            self.before.y_register,
+            self.before.status.bits(),
+            self.before.stack_pointer,
+            self.total_cycles
+        )
+    }
+}
+
+/// The kinds of interrupts the processor can respond to
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InterruptKind {
+    /// A maskable interrupt request
+    Irq,
+    /// A non-maskable interrupt request
+    Nmi,
+}
+
+/// Event describing the processor's response to an interrupt request
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct InterruptEvent {
+    /// The kind of interrupt that was serviced
+    pub kind: InterruptKind,
+    /// The state of the processor before the interrupt was serviced, with the program counter holding the return address
+    pub before: CpuState,
+    /// The state of the processor after the interrupt was serviced, with the program counter at the interrupt handler
+    pub after: CpuState,
+    /// The number of cycles servicing the interrupt took
+    pub cycles: u8,
+    /// The total number of cycles that had passed when the interrupt began
+    pub total_cycles: u64,
+}
+
+impl fmt::Display for InterruptEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04X}  {:?} -> {:04X}  CYC:{}",
+            self.before.program_counter, self.kind, self.after.program_counter, self.total_cycles
+        )
+    }
+}
+
+/// Tracer that writes each instruction to the `log` crate's trace level, in the format the emulator
+/// previously logged in. Effective addresses are shown by name when a symbol table is given
+#[derive(Debug, Default, Clone)]
+pub struct LogTracer {
+    symbols: Option<SymbolTable>,
+}
+
+impl LogTracer {
+    /// Creates a new log tracer showing addresses in hexadecimal
+    pub fn new() -> Self {
+        LogTracer::default()
+    }
+
+    /// Shows addresses that have a name in the symbol table by name
+    pub fn with_symbols(mut self, symbols: SymbolTable) -> Self {
+        self.symbols = Some(symbols);
+        self
+    }
+
+    /// Formats an effective address, using its name if it has one
+    fn format_address(&self, address: u16) -> String {
+        return match self.symbols.as_ref().and_then(|symbols| symbols.name(address)) {
+            Some(name) => name.to_string(),
+            None => format!("{:04X}", address),
+        };
+    }
+}
+
+impl Tracer6502 for LogTracer {
+    fn instruction(&mut self, event: &InstructionEvent) {
+        trace!(
+            "0x{:04X} {} {} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            event.program_counter,
+            event.name,
+            event
+                .effective_address
+                .map_or_else(|| "Implied".to_string(), |address| self.format_address(address)),
+            event.before.accumulator,
+            event.before.x_register,
+            event.before.y_register,
+            event.before.status.bits(),
+            event.before.stack_pointer,
+            event.total_cycles + 7,
+        );
+    }
+
+    fn interrupt(&mut self, event: &InterruptEvent) {
+        trace!(
+            "0x{:04X} {:?} -> 0x{:04X} CYC:{}",
+            event.before.program_counter,
+            event.kind,
+            event.after.program_counter,
+            event.total_cycles + 7
+        );
+    }
+}
+
+//TESTS---------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utilities::TestRam;
+    use crate::MOS6502;
+
+    #[derive(Default)]
+    struct RecordingTracer {
+        instructions: Vec<InstructionEvent>,
+        interrupts: Vec<InterruptEvent>,
+    }
+
+    impl Tracer6502 for RecordingTracer {
+        fn instruction(&mut self, event: &InstructionEvent) {
+            self.instructions.push(*event)
+        }
+
+        fn interrupt(&mut self, event: &InterruptEvent) {
+            self.interrupts.push(*event)
+        }
+    }
+
+    #[test]
+    fn test_instruction_events() {
+        let mut ram = TestRam::with_program(
+            0x0400,
+            &[
+                0xa2, 0x05, // LDX #$05
+                0xbd, 0xff, 0x10, // LDA $10ff,X
+                0xd0, 0xfe, // BNE -2
+            ],
+        );
+        ram.ram[0x1104] = 0x42;
+        let mut cpu = MOS6502::new_start(0x0400);
+        let mut tracer = RecordingTracer::default();
+
+        for _ in 0..3 {
+            cpu.execute_instruction_traced(&mut ram, &mut tracer);
+        }
+
+        let events = &tracer.instructions;
+        assert_eq!(events.len(), 3);
+
+        assert_eq!(events[0].opcode, 0xa2);
+        assert_eq!(events[0].name, "ldx");
+        assert_eq!(events[0].operand_bytes(), &[0x05]);
+        assert_eq!(events[0].after.x_register, 0x05);
+        assert_eq!(events[0].total_cycles, 0);
+
+        assert_eq!(events[1].program_counter, 0x0402);
+        assert_eq!(events[1].operand_bytes(), &[0xff, 0x10]);
+        assert_eq!(events[1].effective_address, Some(0x1104));
+        assert_eq!(events[1].before.accumulator, 0x00);
+        assert_eq!(events[1].after.accumulator, 0x42);
+        assert_eq!(events[1].cycles, 5); //Page boundary crossed
+        assert_eq!(events[1].total_cycles, 2);
+
+        assert_eq!(events[2].effective_address, Some(0x0405));
+        assert_eq!(events[2].after.program_counter, 0x0405);
+        assert_eq!(events[2].cycles, 3); //Branch taken
+    }
+
+    #[test]
+    fn test_interrupt_events() {
+        let mut ram = TestRam::with_program(0x0400, &[0xea]);
+        ram.ram[0xfffa] = 0x00;
+        ram.ram[0xfffb] = 0x80;
+        let mut cpu = MOS6502::new_start(0x0400);
+        let mut tracer = RecordingTracer::default();
+
+        cpu.execute_instruction_traced(&mut ram, &mut tracer);
+        cpu.non_maskable_interrupt_request();
+        cpu.execute_instruction_traced(&mut ram, &mut tracer);
+
+        assert_eq!(tracer.instructions.len(), 1);
+        assert_eq!(tracer.interrupts.len(), 1);
+        assert_eq!(tracer.interrupts[0].kind, InterruptKind::Nmi);
+        assert_eq!(tracer.interrupts[0].before.program_counter, 0x0401);
+        assert_eq!(tracer.interrupts[0].after.program_counter, 0x8000);
+        assert_eq!(tracer.interrupts[0].after.stack_pointer, 0xfa);
+        assert_eq!(tracer.interrupts[0].cycles, 8);
+    }
+
+    /// Memory that counts reads and bank switches on any read of $1000, like a hotspot
+    struct Hotspot {
+        ram: TestRam,
+        reads: u32,
+    }
+
+    impl Interface6502 for Hotspot {
+        fn read(&mut self, address: u16) -> u8 {
+            self.reads += 1;
+            if address == 0x1000 {
+                // Switching banks replaces the instruction's operand
+                self.ram.ram[0x0401] = 0xff;
+            }
+            return self.ram.read(address);
+        }
+
+        fn write(&mut self, address: u16, data: u8) {
+            self.ram.write(address, data)
+        }
+    }
+
+    #[test]
+    fn test_operand_read_once() {
+        // LDA $1000; NOP #$12
+        let ram = TestRam::with_program(0x0400, &[0xad, 0x00, 0x10, 0x80, 0x12]);
+        let mut memory = Hotspot { ram, reads: 0 };
+        let mut cpu = MOS6502::new_start(0x0400);
+        let mut tracer = RecordingTracer::default();
+
+        cpu.execute_instruction_traced(&mut memory, &mut tracer);
+        assert_eq!(memory.reads, 4, "opcode, two operand bytes and the value");
+        assert_eq!(tracer.instructions[0].operand_bytes(), &[0x00, 0x10]);
+
+        cpu.execute_instruction_traced(&mut memory, &mut tracer);
+        assert_eq!(tracer.instructions[1].operand_bytes(), &[0x00], "the operand NOP never reads is not read");
+        assert_eq!(memory.reads, 5, "tracing should not read more than the untraced instruction");
+    }
+}
@@ -1,38 +1,22 @@
+use emulator_6502::bus::{Bus, BusBuilder, DeviceHandle};
+use emulator_6502::memory::Ram;
 use emulator_6502::*;
+use std::cell::Cell;
 use std::env::var;
 use std::fs::*;
 use std::io::*;
 use std::path::PathBuf;
+use std::rc::Rc;
 
-struct BasicRam {
-    ram: Box<[u8; u16::max_value() as usize + 1]>,
-    complete: bool,
-}
-
-impl BasicRam {
-    fn load_program(&mut self, start: usize, data: &mut Vec<u8>) {
-        self.ram[start..start + data.len()].clone_from_slice(data);
-    }
-}
-
-impl Interface6502 for BasicRam {
-    fn read(&mut self, address: u16) -> u8 {
-        match address {
-            0xfffe..=0xffff => {
-                self.complete = true; //If brk has been called the test is complete
-                0xff //Keep program in an infinite break loop until test terminates
-            }
-            _ => self.ram[address as usize],
-        }
-    }
-
-    fn write(&mut self, address: u16, data: u8) {
-        self.ram[address as usize] = data
-    }
+/// Bus of 64KB of RAM, with the BRK vector mapped to a device that flags the test as complete when read
+struct TestBus {
+    bus: Bus,
+    ram: DeviceHandle<Ram>,
+    complete: Rc<Cell<bool>>,
 }
 
 /// Function for loading test programs
-fn load_test(ram: &mut BasicRam, file_name: &str, location: usize) -> Result<()> {
+fn load_test(file_name: &str, location: usize) -> Result<TestBus> {
     let root_dir = &var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
     let mut source_path = PathBuf::from(root_dir);
     source_path.push("tests/bins");
@@ -42,29 +26,39 @@ fn load_test(ram: &mut BasicRam, file_name: &str, location: usize) -> Result<()>
     let mut buffer = Vec::new();
     file.read_to_end(&mut buffer)?;
 
-    ram.load_program(location, &mut buffer);
+    let complete = Rc::new(Cell::new(false));
+    let brk_complete = Rc::clone(&complete);
+    let mut builder = BusBuilder::new();
+    let ram = builder.add_device(Ram::new(u16::MAX as usize + 1));
+    builder.map(0x0000..=0xffff, &ram);
+    builder.mmio(
+        0xfffe..=0xffff,
+        move |_offset| {
+            brk_complete.set(true); //If brk has been called the test is complete
+            0xff //Keep program in an infinite break loop until test terminates
+        },
+        |_offset, _data| {},
+    );
+    let mut bus = builder.build();
+    bus.device_mut(&ram).load(location, &buffer);
 
-    Ok(())
+    Ok(TestBus { bus, ram, complete })
 }
 
 #[test]
 fn loop_test() -> Result<()> {
     std::env::set_var("RUST_LOG", "error");
-    let mut ram = BasicRam {
-        ram: Box::new([0; u16::max_value() as usize + 1]),
-        complete: false,
-    };
-    load_test(&mut ram, "6502_loop_test.bin", 0x400)?;
+    let mut test = load_test("6502_loop_test.bin", 0x400)?;
 
     let mut cpu = MOS6502::new_start(0x400);
     let mut cycle_timeout = 0;
-    while !ram.complete {
-        cpu.cycle(&mut ram);
+    while !test.complete.get() {
+        cpu.cycle(&mut test.bus);
         cycle_timeout += 1;
         assert!(cycle_timeout < 5000) //Timeout
     }
 
-    assert_eq!(ram.ram[0], 100);
+    assert_eq!(test.bus.device(&test.ram).as_slice()[0], 100);
 
     Ok(())
 }
@@ -73,19 +67,90 @@ fn loop_test() -> Result<()> {
 #[cfg(feature = "binary_coded_decimal")]
 fn bcd_test() -> Result<()> {
     std::env::set_var("RUST_LOG", "trace");
-    let mut ram = BasicRam {
-        ram: Box::new([0; u16::max_value() as usize + 1]),
-        complete: false,
-    };
-    load_test(&mut ram, "6502_decimal_test.bin", 0x200)?;
+    let mut test = load_test("6502_decimal_test.bin", 0x200)?;
 
     let mut cpu = MOS6502::new_start(0x200);
     let mut cycle_timeout = 0;
-    while !ram.complete {
-        cpu.cycle(&mut ram);
+    while !test.complete.get() {
+        cpu.cycle(&mut test.bus);
         cycle_timeout += 1;
         assert!(cycle_timeout < 46089520) //Timeout
     }
-    assert_eq!(ram.ram[0x0b], 0);
+    assert_eq!(test.bus.device(&test.ram).as_slice()[0x0b], 0);
     Ok(())
 }
+
+#[test]
+fn runner_test() {
+    let root_dir = &var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_run6502"))
+        .args([
+            "--load",
+            "$0400",
+            "--start",
+            "0x400",
+            "--exit",
+            "$0000",
+            "--max-cycles",
+            "10000",
+            "--dump",
+            "$0000:1",
+        ])
+        .arg(PathBuf::from(root_dir).join("tests/bins/6502_loop_test.bin"))
+        .output()
+        .expect("Failed to run run6502");
+    let report = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "{}", report);
+    assert!(report.contains("Reached exit address $0000"), "{}", report);
+    assert!(report.contains("0000: 64"), "{}", report);
+}
+
+#[test]
+fn runner_monitor_test() {
+    let root_dir = &var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+    let mut runner = std::process::Command::new(env!("CARGO_BIN_EXE_run6502"))
+        .args(["--load", "$0400", "--start", "$0400", "--monitor"])
+        .arg(PathBuf::from(root_dir).join("tests/bins/6502_loop_test.bin"))
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to run run6502");
+    runner.stdin.take().unwrap().write_all(b"g\nm 0000 0000\nx\n").unwrap();
+    let output = runner.wait_with_output().unwrap();
+    let transcript = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert!(transcript.contains("BRK at $040A"), "{}", transcript);
+    assert!(transcript.contains(">0000 64"), "{}", transcript);
+}
+
+#[test]
+fn runner_sim65_test() {
+    #[rustfmt::skip]
+    let mut image = vec![
+        b's', b'i', b'm', b'6', b'5', 2, 0, 0x02, 0x00, 0x02, 0x00, 0x02,
+        0xa9, 0x00, 0x85, 0x02, // LDA #$00, STA sp
+        0xa9, 0x03, 0x85, 0x03, // LDA #$03, STA sp+1
+        0xa9, 0x02, 0xa2, 0x00, // LDA #2, LDX #0
+        0x20, 0xf7, 0xff,       // JSR write
+        0xa9, 0x03,             // LDA #3
+        0x20, 0xf9, 0xff,       // JSR exit
+    ];
+    // write(1, $0310, 2) takes the buffer and file descriptor from the C stack at $0300
+    image.resize(12 + 0x110, 0);
+    image[12 + 0x100..12 + 0x104].clone_from_slice(&[0x10, 0x03, 0x01, 0x00]);
+    image.extend_from_slice(b"hi");
+    let path = std::env::temp_dir().join(format!("runner_sim65_test_{}", std::process::id()));
+    std::fs::write(&path, &image).unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_run6502"))
+        .arg(&path)
+        .arg("argument")
+        .output();
+    std::fs::remove_file(&path).unwrap();
+    let output = output.expect("Failed to run run6502");
+
+    assert_eq!(output.status.code(), Some(3), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(output.stdout, b"hi");
+}